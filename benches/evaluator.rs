@@ -0,0 +1,59 @@
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use rmcp::serde_json::{Value, json};
+use rs_cel_mcp::evaluator::{self, Evaluator};
+use rs_cel_mcp::profile::Profile;
+use std::hint::black_box;
+
+/// A context with a list of records, the shape that stresses list/map conversion.
+fn records(n: usize) -> Value {
+    let items: Vec<Value> = (0..n)
+        .map(|i| json!({"id": format!("r{}", i), "tags": ["a", "b"], "score": i as f64}))
+        .collect();
+    json!({ "items": items })
+}
+
+fn bench_evaluate(c: &mut Criterion) {
+    let evaluator = Evaluator::new();
+    let context = json!({"user": {"roles": ["dev", "admin"]}});
+    c.bench_function("evaluate/exists", |b| {
+        b.iter(|| {
+            evaluator.evaluate(
+                black_box("user.roles.exists(r, r == 'admin')"),
+                black_box(&context),
+                Profile::Default,
+            )
+        })
+    });
+
+    let context = records(1000);
+    c.bench_function("evaluate/filter_1000", |b| {
+        b.iter(|| {
+            evaluator.evaluate(
+                black_box("items.filter(i, i.score > 500.0)"),
+                black_box(&context),
+                Profile::Default,
+            )
+        })
+    });
+}
+
+fn bench_conversion(c: &mut Criterion) {
+    let context = records(1000);
+    c.bench_function("json_to_cel/records_1000", |b| {
+        b.iter(|| cel::to_value(black_box(&context)))
+    });
+
+    let result = Evaluator::new()
+        .execute("items", &context, Profile::Default)
+        .unwrap_or(cel::Value::Null);
+    c.bench_function("cel_to_json/records_1000", |b| {
+        b.iter_batched(
+            || result.clone(),
+            |result| evaluator::to_json(black_box(result)),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_evaluate, bench_conversion);
+criterion_main!(benches);
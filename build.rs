@@ -0,0 +1,33 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    conformance_service();
+}
+
+/// Generates the `ConformanceService` server stub against the prost messages shipped by the
+/// `googleapis-tonic-*` crates, so no `protoc` is needed at build time.
+#[cfg(feature = "grpc")]
+fn conformance_service() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    const PB: &str = "googleapis_tonic_google_api_expr_conformance_v1alpha1::google::api::expr::conformance::v1alpha1";
+
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("{PB}::{input}"))
+            .output_type(format!("{PB}::{output}"))
+            .codec_path("tonic_prost::ProstCodec")
+            .build()
+    };
+
+    let service = Service::builder()
+        .name("ConformanceService")
+        .package("google.api.expr.conformance.v1alpha1")
+        .method(method("parse", "Parse", "ParseRequest", "ParseResponse"))
+        .method(method("check", "Check", "CheckRequest", "CheckResponse"))
+        .method(method("eval", "Eval", "EvalRequest", "EvalResponse"))
+        .build();
+
+    Builder::new().build_client(false).compile(&[service]);
+}
@@ -0,0 +1,212 @@
+//! A Kubernetes validating admission webhook: `POST /admission` takes an `AdmissionReview`,
+//! evaluates the configured validations against the object and answers with its `response`.
+//!
+//! Expressions see `self` and `object` (the new object), `oldSelf` and `oldObject` (the
+//! previous object, `null` on create) and `request` (the rest of the admission request).
+
+use crate::cel_tool::{CelTool, REQUEST_ID_HEADER, client_request_id};
+use crate::evaluator::Query;
+use crate::merge::MergeStrategy;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::{Json, Router};
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{Map, Value, json};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct AdmissionConfig {
+    /// Checks that must all hold for the object to be admitted.
+    pub validations: Vec<Validation>,
+    /// What happens when a validation fails to evaluate or returns a non-bool.
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct Validation {
+    /// A CEL expression returning `true` when the object is acceptable.
+    pub expression: String,
+    /// Returned when the expression is false; defaults to naming the expression.
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rmcp::serde")]
+pub enum FailurePolicy {
+    /// Reject the object.
+    #[default]
+    Fail,
+    /// Admit the object.
+    Ignore,
+}
+
+/// Routes `POST /admission`.
+pub fn router(tool: CelTool, config: AdmissionConfig) -> Router {
+    Router::new()
+        .route("/admission", post(admission))
+        .with_state((tool, Arc::new(config)))
+}
+
+async fn admission(
+    State((tool, config)): State<(CelTool, Arc<AdmissionConfig>)>,
+    headers: HeaderMap,
+    Json(review): Json<Value>,
+) -> Json<Value> {
+    let request_id = client_request_id(
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    );
+    Json(review_response(&tool, &config, &request_id, review).await)
+}
+
+/// Answers an `AdmissionReview`, echoing its `apiVersion` and `request.uid`.
+async fn review_response(
+    tool: &CelTool,
+    config: &AdmissionConfig,
+    request_id: &str,
+    mut review: Value,
+) -> Value {
+    let api_version = review
+        .get("apiVersion")
+        .cloned()
+        .unwrap_or_else(|| json!("admission.k8s.io/v1"));
+    let request = review
+        .get_mut("request")
+        .map(Value::take)
+        .unwrap_or_default();
+    let uid = request.get("uid").cloned().unwrap_or_default();
+    let denials = validate(tool, config, request_id, request).await;
+    let mut response = json!({ "uid": uid, "allowed": denials.is_empty() });
+    if !denials.is_empty() {
+        response["status"] = json!({
+            "code": 403,
+            "reason": "Forbidden",
+            "message": denials.join("; "),
+        });
+    }
+    json!({
+        "apiVersion": api_version,
+        "kind": "AdmissionReview",
+        "response": response,
+    })
+}
+
+/// Returns the messages of the failed validations; empty when the object is admitted.
+async fn validate(
+    tool: &CelTool,
+    config: &AdmissionConfig,
+    request_id: &str,
+    mut request: Value,
+) -> Vec<String> {
+    let object = request
+        .get_mut("object")
+        .map(Value::take)
+        .unwrap_or_default();
+    let old_object = request
+        .get_mut("oldObject")
+        .map(Value::take)
+        .unwrap_or_default();
+    let mut context = Map::new();
+    context.insert("self".to_string(), object.clone());
+    context.insert("object".to_string(), object);
+    context.insert("oldSelf".to_string(), old_object.clone());
+    context.insert("oldObject".to_string(), old_object);
+    context.insert("request".to_string(), request);
+
+    let outputs = config
+        .validations
+        .iter()
+        .enumerate()
+        .map(|(i, validation)| (i.to_string(), validation.expression.clone()))
+        .collect();
+    let outcome = tool
+        .query(
+            "admission",
+            request_id,
+            Query::Projection(outputs),
+            context,
+            MergeStrategy::Replace,
+        )
+        .await;
+    let failed = |message: String| match config.failure_policy {
+        FailurePolicy::Fail => vec![message],
+        FailurePolicy::Ignore => Vec::new(),
+    };
+    let values = match outcome {
+        Ok(values) => values,
+        Err(e) => return failed(format!("Validation failed to evaluate: {}", e.message)),
+    };
+    let mut denials = Vec::new();
+    for (i, validation) in config.validations.iter().enumerate() {
+        match values.get(i.to_string()) {
+            Some(Value::Bool(true)) => {}
+            Some(Value::Bool(false)) => denials.push(
+                validation
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| format!("failed expression: {}", validation.expression)),
+            ),
+            _ => denials.extend(failed(format!(
+                "expression did not return a bool: {}",
+                validation.expression
+            ))),
+        }
+    }
+    denials
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_review_response() {
+        let tool = CelTool::builder().build();
+        let config: AdmissionConfig = rmcp::serde_json::from_value(json!({
+            "validations": [
+                {"expression": "self.spec.replicas <= 5", "message": "too many replicas"},
+                {"expression": "oldSelf == null || self.spec.replicas >= oldSelf.spec.replicas"},
+                {"expression": "request.operation != 'DELETE'"}
+            ]
+        }))
+        .unwrap();
+        let review = |replicas: u64, old: Value| {
+            json!({
+                "apiVersion": "admission.k8s.io/v1",
+                "kind": "AdmissionReview",
+                "request": {
+                    "uid": "u-1",
+                    "operation": "UPDATE",
+                    "object": {"spec": {"replicas": replicas}},
+                    "oldObject": old,
+                }
+            })
+        };
+
+        let allowed = review_response(&tool, &config, "req-7", review(3, Value::Null)).await;
+        assert_eq!(
+            allowed,
+            json!({
+                "apiVersion": "admission.k8s.io/v1",
+                "kind": "AdmissionReview",
+                "response": {"uid": "u-1", "allowed": true},
+            })
+        );
+
+        let old = json!({"spec": {"replicas": 7}});
+        let denied = review_response(&tool, &config, "req-7", review(6, old)).await;
+        assert_eq!(denied["response"]["allowed"], json!(false));
+        assert_eq!(
+            denied["response"]["status"]["message"],
+            json!(
+                "too many replicas; failed expression: oldSelf == null || self.spec.replicas >= oldSelf.spec.replicas"
+            )
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use crate::macros;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use cel::common::ast::{EntryExpr, Expr};
+use cel::common::value::CelVal;
+use cel::parser::{Expression, ParseErrors, Parser};
+use rmcp::serde_json::{Map, Value, json};
+use std::collections::BTreeSet;
+
+/// Parses a CEL expression into its AST without building a `Program`.
+pub(crate) fn parse(expression: &str) -> Result<Expression, ParseErrors> {
+    Parser::default().parse(expression)
+}
+
+/// Visits `expr` and every sub-expression in pre-order.
+pub(crate) fn walk<'a>(expr: &'a Expression, f: &mut impl FnMut(&'a Expression)) {
+    f(expr);
+    match &expr.expr {
+        Expr::Call(call) => {
+            if let Some(target) = &call.target {
+                walk(target, f);
+            }
+            for arg in &call.args {
+                walk(arg, f);
+            }
+        }
+        Expr::Comprehension(comp) => {
+            walk(&comp.iter_range, f);
+            walk(&comp.accu_init, f);
+            walk(&comp.loop_cond, f);
+            walk(&comp.loop_step, f);
+            walk(&comp.result, f);
+        }
+        Expr::List(list) => {
+            for element in &list.elements {
+                walk(element, f);
+            }
+        }
+        Expr::Map(map) => {
+            for entry in &map.entries {
+                if let EntryExpr::MapEntry(e) = &entry.expr {
+                    walk(&e.key, f);
+                    walk(&e.value, f);
+                }
+            }
+        }
+        Expr::Struct(st) => {
+            for entry in &st.entries {
+                if let EntryExpr::StructField(field) = &entry.expr {
+                    walk(&field.value, f);
+                }
+            }
+        }
+        Expr::Select(select) => walk(&select.operand, f),
+        Expr::Ident(_) | Expr::Literal(_) | Expr::Unspecified => {}
+    }
+}
+
+/// The variables `expr` reads: identifiers not bound by a macro such as `all` or `map`. An
+/// identifier a function is called on, such as `kv` in `kv.get(k)`, is left out when
+/// `is_function` says the qualified name (`kv.get`) is a function.
+pub(crate) fn variables(expr: &Expression, is_function: impl Fn(&str) -> bool) -> BTreeSet<String> {
+    fn visit(
+        expr: &Expression,
+        bound: &mut Vec<String>,
+        is_function: &dyn Fn(&str) -> bool,
+        found: &mut BTreeSet<String>,
+    ) {
+        match &expr.expr {
+            Expr::Ident(name) => {
+                if !bound.contains(name) {
+                    found.insert(name.clone());
+                }
+            }
+            Expr::Comprehension(comp) => {
+                visit(&comp.iter_range, bound, is_function, found);
+                visit(&comp.accu_init, bound, is_function, found);
+                let scope = bound.len();
+                bound.extend(comp.iter_var2.iter().cloned());
+                bound.push(comp.iter_var.clone());
+                bound.push(comp.accu_var.clone());
+                visit(&comp.loop_cond, bound, is_function, found);
+                visit(&comp.loop_step, bound, is_function, found);
+                visit(&comp.result, bound, is_function, found);
+                bound.truncate(scope);
+            }
+            Expr::Call(call) => {
+                if let Some(target) = &call.target {
+                    let namespace = match &target.expr {
+                        Expr::Ident(name) => {
+                            !bound.contains(name)
+                                && is_function(&format!("{}.{}", name, call.func_name))
+                        }
+                        _ => false,
+                    };
+                    if !namespace {
+                        visit(target, bound, is_function, found);
+                    }
+                }
+                for arg in &call.args {
+                    visit(arg, bound, is_function, found);
+                }
+            }
+            Expr::List(list) => {
+                for element in &list.elements {
+                    visit(element, bound, is_function, found);
+                }
+            }
+            Expr::Map(map) => {
+                for entry in &map.entries {
+                    if let EntryExpr::MapEntry(e) = &entry.expr {
+                        visit(&e.key, bound, is_function, found);
+                        visit(&e.value, bound, is_function, found);
+                    }
+                }
+            }
+            Expr::Struct(st) => {
+                for entry in &st.entries {
+                    if let EntryExpr::StructField(field) = &entry.expr {
+                        visit(&field.value, bound, is_function, found);
+                    }
+                }
+            }
+            Expr::Select(select) => visit(&select.operand, bound, is_function, found),
+            Expr::Literal(_) | Expr::Unspecified => {}
+        }
+    }
+    let mut found = BTreeSet::new();
+    visit(expr, &mut Vec::new(), &is_function, &mut found);
+    found
+}
+
+/// The number of nodes in `expr`, what evaluating it costs when no macro loops.
+pub(crate) fn node_count(expr: &Expression) -> u64 {
+    let mut count = 0;
+    walk(expr, &mut |_| count += 1);
+    count
+}
+
+/// Renders `expr` as JSON with macros expanded; each expansion is tagged with its `macro`.
+pub(crate) fn describe(expr: &Expression) -> Value {
+    let mut node = match &expr.expr {
+        Expr::Ident(name) => json!({ "ident": name }),
+        Expr::Literal(literal) => json!({ "literal": describe_literal(literal) }),
+        Expr::Select(select) => json!({
+            "select": {
+                "operand": describe(&select.operand),
+                "field": select.field,
+                "test_only": select.test,
+            }
+        }),
+        Expr::Call(call) => {
+            let mut described = Map::new();
+            described.insert("function".to_string(), json!(call.func_name));
+            if let Some(target) = &call.target {
+                described.insert("target".to_string(), describe(target));
+            }
+            described.insert("args".to_string(), call.args.iter().map(describe).collect());
+            json!({ "call": described })
+        }
+        Expr::Comprehension(comp) => json!({
+            "comprehension": {
+                "iter_var": comp.iter_var,
+                "iter_range": describe(&comp.iter_range),
+                "accu_var": comp.accu_var,
+                "accu_init": describe(&comp.accu_init),
+                "loop_condition": describe(&comp.loop_cond),
+                "loop_step": describe(&comp.loop_step),
+                "result": describe(&comp.result),
+            }
+        }),
+        Expr::List(list) => {
+            json!({ "list": list.elements.iter().map(describe).collect::<Vec<_>>() })
+        }
+        Expr::Map(map) => json!({
+            "map": map
+                .entries
+                .iter()
+                .filter_map(|entry| match &entry.expr {
+                    EntryExpr::MapEntry(e) => Some(json!({
+                        "key": describe(&e.key),
+                        "value": describe(&e.value),
+                    })),
+                    EntryExpr::StructField(_) => None,
+                })
+                .collect::<Vec<_>>()
+        }),
+        Expr::Struct(st) => json!({
+            "struct": {
+                "type": st.type_name,
+                "fields": st
+                    .entries
+                    .iter()
+                    .filter_map(|entry| match &entry.expr {
+                        EntryExpr::StructField(field) => {
+                            Some((field.field.clone(), describe(&field.value)))
+                        }
+                        EntryExpr::MapEntry(_) => None,
+                    })
+                    .collect::<Map<String, Value>>(),
+            }
+        }),
+        Expr::Unspecified => json!({ "unspecified": null }),
+    };
+    if let (Some(found), Value::Object(node)) = (macros::classify(&expr.expr), &mut node) {
+        node.insert("macro".to_string(), json!(found));
+    }
+    node
+}
+
+/// A hash of `expr`'s syntax tree, so formatting, comments and redundant parentheses do not
+/// change it.
+pub(crate) fn fingerprint(expr: &Expression) -> String {
+    hash(&describe(expr).to_string())
+}
+
+/// A stable 64-bit FNV-1a hash of `text`, in hex.
+pub(crate) fn hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+fn describe_literal(literal: &CelVal) -> Value {
+    match literal {
+        CelVal::Null => Value::Null,
+        CelVal::Boolean(b) => json!(b),
+        CelVal::Int(i) => json!(i),
+        CelVal::UInt(u) => json!({ "uint": u }),
+        CelVal::Double(d) => json!(d),
+        CelVal::String(s) => json!(s),
+        CelVal::Bytes(bytes) => json!({ "bytes": BASE64_STANDARD.encode(bytes) }),
+        other => json!(format!("{:?}", other)),
+    }
+}
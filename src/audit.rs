@@ -0,0 +1,271 @@
+use crate::redact::Redactor;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rmcp::serde_json::{Value, json};
+use std::fs::{File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The JSON shape of each audit line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AuditFormat {
+    /// One flat record per evaluation: expression, context, result or error, duration.
+    #[default]
+    Native,
+    /// OPA decision-log records (`decision_id`, `input`, `result`, `timestamp`, `metrics`),
+    /// so existing decision-log pipelines can ingest them.
+    Opa,
+}
+
+/// Everything recorded about one evaluation.
+#[derive(Debug)]
+pub struct AuditRecord<'a> {
+    pub decision_id: &'a str,
+    pub tool: &'a str,
+    pub expression: &'a str,
+    /// The expression's canonical fingerprint, if it parses.
+    pub fingerprint: Option<&'a str>,
+    pub context: &'a Value,
+    pub outcome: Result<&'a Value, &'a str>,
+    pub timestamp: DateTime<Utc>,
+    pub duration: Duration,
+}
+
+impl AuditRecord<'_> {
+    /// Renders the record in the requested format.
+    pub fn to_json(&self, format: AuditFormat) -> Value {
+        let timestamp = self.timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true);
+        match format {
+            AuditFormat::Native => {
+                let mut record = json!({
+                    "timestamp": timestamp,
+                    "decision_id": self.decision_id,
+                    "tool": self.tool,
+                    "expression": self.expression,
+                    "context": self.context,
+                    "duration_ms": self.duration.as_secs_f64() * 1000.0,
+                });
+                if let Some(fingerprint) = self.fingerprint {
+                    record["fingerprint"] = fingerprint.into();
+                }
+                match self.outcome {
+                    Ok(result) => record["result"] = result.clone(),
+                    Err(e) => record["error"] = e.into(),
+                }
+                record
+            }
+            AuditFormat::Opa => {
+                let mut record = json!({
+                    "decision_id": self.decision_id,
+                    "path": format!("cel/{}", self.tool),
+                    "query": self.expression,
+                    "input": self.context,
+                    "timestamp": timestamp,
+                    "labels": {
+                        "app": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                    "metrics": {
+                        "timer_cel_eval_ns": u64::try_from(self.duration.as_nanos()).unwrap_or(u64::MAX),
+                    },
+                });
+                if let Some(fingerprint) = self.fingerprint {
+                    record["labels"]["fingerprint"] = fingerprint.into();
+                }
+                match self.outcome {
+                    Ok(result) => record["result"] = result.clone(),
+                    Err(e) => record["error"] = json!({ "message": e }),
+                }
+                record
+            }
+        }
+    }
+}
+
+/// An evaluation that took longer than the slow-evaluation threshold.
+#[derive(Debug)]
+pub struct SlowEvaluation<'a> {
+    pub decision_id: &'a str,
+    pub tool: &'a str,
+    /// The expression's fingerprint (or a hash of its text if it does not parse), to group
+    /// evaluations of the same policy.
+    pub expression_hash: &'a str,
+    /// Size of the JSON-encoded context.
+    pub context_bytes: usize,
+    pub timestamp: DateTime<Utc>,
+    /// Time from enqueueing to receiving the result.
+    pub total: Duration,
+    /// Time the evaluator spent on the request; the rest was spent waiting in the queue.
+    pub evaluation: Duration,
+}
+
+impl SlowEvaluation<'_> {
+    /// Renders the record; `type` tells it apart from evaluation records in the same log.
+    pub fn to_json(&self) -> Value {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        json!({
+            "type": "slow_evaluation",
+            "timestamp": self.timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true),
+            "decision_id": self.decision_id,
+            "tool": self.tool,
+            "expression_hash": self.expression_hash,
+            "context_bytes": self.context_bytes,
+            "duration_ms": ms(self.total),
+            "queue_ms": ms(self.total.saturating_sub(self.evaluation)),
+            "eval_ms": ms(self.evaluation),
+        })
+    }
+}
+
+/// A decision where a policy set's shadow version disagreed with the active one.
+#[derive(Debug)]
+pub struct ShadowDivergence<'a> {
+    pub request_id: &'a str,
+    pub policy: &'a str,
+    pub context: &'a Value,
+    /// The decision returned to the client.
+    pub active: &'a Value,
+    /// The shadow version's decision, or the error it failed with.
+    pub shadow: Result<&'a Value, &'a str>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ShadowDivergence<'_> {
+    /// Renders the record; `type` tells it apart from evaluation records in the same log.
+    pub fn to_json(&self) -> Value {
+        let mut record = json!({
+            "type": "shadow_divergence",
+            "timestamp": self.timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true),
+            "request_id": self.request_id,
+            "policy": self.policy,
+            "context": self.context,
+            "active": self.active,
+        });
+        match self.shadow {
+            Ok(result) => record["shadow"] = result.clone(),
+            Err(e) => record["shadow_error"] = e.into(),
+        }
+        record
+    }
+}
+
+/// An append-only JSON Lines audit sink.
+pub struct AuditLog {
+    format: AuditFormat,
+    redactor: Redactor,
+    sink: Mutex<LineWriter<File>>,
+}
+
+impl AuditLog {
+    /// Opens (creating if needed) `path` for appending audit records.
+    pub fn open(path: impl AsRef<Path>, format: AuditFormat) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            format,
+            redactor: Redactor::default(),
+            sink: Mutex::new(LineWriter::new(file)),
+        })
+    }
+
+    /// Masks sensitive context values (and their echoes in results and errors) before writing.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Appends one record; failures are logged rather than failing the evaluation.
+    pub fn record(&self, record: &AuditRecord<'_>) {
+        let line = if self.redactor.is_empty() {
+            record.to_json(self.format).to_string()
+        } else {
+            let context = self.redactor.redact_value(record.context);
+            let outcome = record
+                .outcome
+                .map(|result| self.redactor.redact_value(result))
+                .map_err(|e| self.redactor.redact_message(e, record.context));
+            AuditRecord {
+                context: &context,
+                outcome: outcome.as_ref().map_err(String::as_str),
+                ..*record
+            }
+            .to_json(self.format)
+            .to_string()
+        };
+        self.write_line(&line);
+    }
+
+    /// Appends a slow-evaluation record.
+    pub fn record_slow(&self, slow: &SlowEvaluation<'_>) {
+        self.write_line(&slow.to_json().to_string());
+    }
+
+    /// Appends a shadow-divergence record, redacting the context like evaluation records.
+    pub fn record_divergence(&self, divergence: &ShadowDivergence<'_>) {
+        let line = if self.redactor.is_empty() {
+            divergence.to_json().to_string()
+        } else {
+            let context = self.redactor.redact_value(divergence.context);
+            let shadow = divergence
+                .shadow
+                .map_err(|e| self.redactor.redact_message(e, divergence.context));
+            ShadowDivergence {
+                context: &context,
+                shadow: shadow.as_ref().map_err(String::as_str).copied(),
+                ..*divergence
+            }
+            .to_json()
+            .to_string()
+        };
+        self.write_line(&line);
+    }
+
+    fn write_line(&self, line: &str) {
+        let Ok(mut sink) = self.sink.lock() else {
+            tracing::error!("Audit log lock poisoned, dropping record.");
+            return;
+        };
+        if let Err(e) = writeln!(sink, "{}", line) {
+            tracing::error!("Failed to write audit record: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record<'a>(context: &'a Value, outcome: Result<&'a Value, &'a str>) -> AuditRecord<'a> {
+        AuditRecord {
+            decision_id: "d-1",
+            tool: "evaluate",
+            expression: "a > 1",
+            fingerprint: Some("0123456789abcdef"),
+            context,
+            outcome,
+            timestamp: DateTime::from_timestamp(0, 0).unwrap_or_default(),
+            duration: Duration::from_micros(1500),
+        }
+    }
+
+    #[test]
+    fn test_opa_format() {
+        let context = json!({"a": 2});
+        let result = json!(true);
+        let line = record(&context, Ok(&result)).to_json(AuditFormat::Opa);
+        assert_eq!(line["decision_id"], json!("d-1"));
+        assert_eq!(line["input"], context);
+        assert_eq!(line["result"], json!(true));
+        assert_eq!(line["timestamp"], json!("1970-01-01T00:00:00.000000000Z"));
+        assert_eq!(line["metrics"]["timer_cel_eval_ns"], json!(1_500_000));
+        assert_eq!(line["labels"]["fingerprint"], json!("0123456789abcdef"));
+    }
+
+    #[test]
+    fn test_native_format_error() {
+        let context = json!({});
+        let line = record(&context, Err("CEL execution error: boom")).to_json(AuditFormat::Native);
+        assert_eq!(line["error"], json!("CEL execution error: boom"));
+        assert!(line.get("result").is_none());
+    }
+}
@@ -0,0 +1,156 @@
+//! Latency measurement for the `bench` subcommand.
+
+use crate::evaluator::{self, Evaluator};
+use crate::profile::Profile;
+use rmcp::serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// Latency percentiles over a series of timed runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Latencies {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Latencies {
+    /// Summarizes `samples` by nearest rank; `None` when there are none.
+    pub fn from_samples(mut samples: Vec<Duration>) -> Option<Self> {
+        samples.sort_unstable();
+        let max = *samples.last()?;
+        let rank = |p: usize| samples[(samples.len() * p).div_ceil(100).max(1) - 1];
+        Some(Self {
+            p50: rank(50),
+            p95: rank(95),
+            p99: rank(99),
+            max,
+        })
+    }
+}
+
+/// Allocation totals reported by a counting allocator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocCount {
+    pub allocations: usize,
+    pub bytes: usize,
+}
+
+/// Reads the process-wide allocation totals, e.g. from a counting global allocator.
+pub type AllocProbe = fn() -> AllocCount;
+
+/// One benchmark report line: what was timed and how long it took.
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub name: &'static str,
+    pub latencies: Latencies,
+    /// Average allocations per iteration, when an [`AllocProbe`] was given.
+    pub allocations: Option<AllocCount>,
+}
+
+/// Times `iterations` cold compiles, evaluations with a warm program cache, and conversions of
+/// the result to JSON, plus (when `compare_cache` is set) evaluations that recompile every time.
+pub fn run(
+    expression: &str,
+    context: &Value,
+    profile: Profile,
+    iterations: usize,
+    compare_cache: bool,
+    probe: Option<AllocProbe>,
+) -> Result<Vec<Measurement>, String> {
+    if iterations == 0 {
+        return Err("--iterations must be at least 1".to_string());
+    }
+    let uncached = Evaluator::new().with_cache_capacity(0);
+    let cached = Evaluator::new();
+    // Surface compile and runtime errors once instead of timing them.
+    let result = cached.execute(expression, context, profile)?;
+
+    let mut measurements = vec![
+        measure("compile", iterations, probe, || {
+            uncached.compile(expression).map(drop)
+        })?,
+        measure("evaluate (cached)", iterations, probe, || {
+            cached.evaluate(expression, context, profile).map(drop)
+        })?,
+        measure("convert", iterations, probe, || {
+            drop(evaluator::to_json(result.clone()));
+            Ok(())
+        })?,
+    ];
+    if compare_cache {
+        measurements.push(measure("evaluate (uncached)", iterations, probe, || {
+            uncached.evaluate(expression, context, profile).map(drop)
+        })?);
+    }
+    Ok(measurements)
+}
+
+fn measure(
+    name: &'static str,
+    iterations: usize,
+    probe: Option<AllocProbe>,
+    mut f: impl FnMut() -> Result<(), String>,
+) -> Result<Measurement, String> {
+    let mut samples = Vec::with_capacity(iterations);
+    let before = probe.map(|probe| probe());
+    for _ in 0..iterations {
+        let started = Instant::now();
+        f()?;
+        samples.push(started.elapsed());
+    }
+    let allocations = probe.zip(before).map(|(probe, before)| {
+        let after = probe();
+        AllocCount {
+            allocations: after.allocations.saturating_sub(before.allocations) / iterations,
+            bytes: after.bytes.saturating_sub(before.bytes) / iterations,
+        }
+    });
+    let latencies = Latencies::from_samples(samples).ok_or("no samples")?;
+    Ok(Measurement {
+        name,
+        latencies,
+        allocations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_percentiles_by_nearest_rank() {
+        let samples = (1..=100).map(Duration::from_millis).collect();
+        let latencies = Latencies::from_samples(samples).unwrap();
+        assert_eq!(latencies.p50, Duration::from_millis(50));
+        assert_eq!(latencies.p95, Duration::from_millis(95));
+        assert_eq!(latencies.p99, Duration::from_millis(99));
+        assert_eq!(latencies.max, Duration::from_millis(100));
+        assert_eq!(Latencies::from_samples(Vec::new()), None);
+    }
+
+    #[test]
+    fn test_run_reports_each_mode() {
+        let measurements = run(
+            "x + '!'",
+            &json!({"x": "a"}),
+            Profile::Default,
+            3,
+            true,
+            None,
+        )
+        .unwrap();
+        let names: Vec<_> = measurements.iter().map(|m| m.name).collect();
+        assert_eq!(
+            names,
+            [
+                "compile",
+                "evaluate (cached)",
+                "convert",
+                "evaluate (uncached)"
+            ]
+        );
+        assert!(run("x +", &json!({}), Profile::Default, 3, false, None).is_err());
+    }
+}
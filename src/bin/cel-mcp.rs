@@ -1,19 +1,41 @@
-use clap::Parser;
-use rmcp::{
-    ServiceExt,
-    transport::{
-        stdio,
-        streamable_http_server::{StreamableHttpService, session::local::LocalSessionManager},
-    },
-};
-use rs_cel_mcp::cel_tool::{CelTool, evaluator_service};
+use clap::{ArgGroup, Parser, Subcommand};
+use rmcp::ServiceExt;
+use rs_cel_mcp::audit::{AuditFormat, AuditLog};
+use rs_cel_mcp::bench::{self, AllocProbe};
+use rs_cel_mcp::cel_tool::{CelTool, Encoding, Limits, NonFinite};
+use rs_cel_mcp::config::{self, Config};
+use rs_cel_mcp::conformance::{self, Outcome};
+use rs_cel_mcp::ingest::JsonNumbers;
+use rs_cel_mcp::ip_filter::{self, Cidr, IpFilter};
+use rs_cel_mcp::job::{self, JobLimits};
+use rs_cel_mcp::keys::{KeyStore, Scope};
+use rs_cel_mcp::kv_store::KvStore;
+use rs_cel_mcp::library::Library;
+use rs_cel_mcp::macros::Macro;
+use rs_cel_mcp::mcp_http;
+use rs_cel_mcp::messages::ErrorMessages;
+use rs_cel_mcp::profile::Profile;
+use rs_cel_mcp::program_cache::{self, ProgramCache};
+use rs_cel_mcp::quota::{self, Quota};
+use rs_cel_mcp::redact::{RedactingMakeWriter, Redactor};
+use rs_cel_mcp::reload::{Settings, SettingsLoader};
+use rs_cel_mcp::resolver;
+use rs_cel_mcp::result_cache::{self, ResultCache};
+use rs_cel_mcp::rewrite::Rewriter;
+use rs_cel_mcp::size_limit;
 use std::net::SocketAddr;
-use tokio::sync::mpsc;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+#[command(group(ArgGroup::new("profile").multiple(false)))]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Use stdio for transport. This is the default if --http is not specified.
     #[arg(long)]
     stdio: bool,
@@ -21,55 +43,895 @@ struct Args {
     /// Use HTTP for transport, specifying the listen address (e.g., "127.0.0.1:8080").
     #[arg(long)]
     http: Option<String>,
+
+    /// Pre-declare the Envoy RBAC/ext_authz attributes (request.*, source.*, destination.*)
+    /// and expose a tool evaluating against an Envoy check request.
+    #[arg(long, group = "profile")]
+    envoy_compat: bool,
+
+    /// Pre-declare the Google Cloud IAM Conditions attributes (request.time, resource.name, ...)
+    /// and reject expressions outside IAM's restrictions.
+    #[arg(long, group = "profile")]
+    iam_compat: bool,
+
+    /// Pre-declare the Firebase security-rules variables and simulate get()/exists()
+    /// against caller-provided documents.
+    #[arg(long, group = "profile")]
+    firestore_compat: bool,
+
+    /// JSON config file (variable resolvers, ...).
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Mask values of context keys matching this pattern (e.g. `*.password`, `token`) in logs,
+    /// audit records and error messages (repeatable; adds to the config file's `redact`).
+    #[arg(long)]
+    redact: Vec<String>,
+
+    /// Keep expressions and context fragments out of client-visible error messages; clients get
+    /// an error ID matching the server log entry instead.
+    #[arg(long)]
+    private_errors: bool,
+
+    /// When an `evaluate` expression fails to compile, ask the client's model (via MCP
+    /// sampling, if the client supports it) for a corrected expression and return it with the
+    /// error.
+    #[arg(long)]
+    suggest_repairs: bool,
+
+    /// Append a JSON Lines record of every evaluation to this file.
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Shape of the audit records.
+    #[arg(long, value_enum, default_value_t = AuditFormat::Native, requires = "audit_log")]
+    audit_format: AuditFormat,
+
+    /// JSON object file whose variables are added to every evaluation; request context
+    /// variables of the same name take precedence.
+    #[arg(long)]
+    base_context: Option<PathBuf>,
+
+    /// Reject contexts whose values nest deeper than this (a variable's value is level 1).
+    #[arg(long, default_value_t = 64)]
+    max_context_depth: usize,
+
+    /// Reject contexts larger than this many bytes (string and key bytes plus 8 per value).
+    #[arg(long)]
+    max_context_bytes: Option<usize>,
+
+    /// Refuse HTTP request bodies and stdio MCP messages larger than this many bytes, before
+    /// reading them whole.
+    #[arg(long, default_value_t = size_limit::DEFAULT_MAX_REQUEST_BYTES)]
+    max_request_bytes: usize,
+
+    /// Reject regular expressions (in `matches()`) compiling to more than this many bytes.
+    #[arg(long, default_value_t = 1 << 20)]
+    max_regex_size: usize,
+
+    /// Abort evaluations running more than this many comprehension iterations (`all`, `exists`,
+    /// `map`, ...) in total, with a "cost budget exceeded" error.
+    #[arg(long, default_value_t = 1_000_000)]
+    max_iterations: u64,
+
+    /// Abort evaluations costing more than this: 1 per expression node visited, plus 1 per list
+    /// or map element and per 8 string bytes built. Requests may set a lower `cost_limit`.
+    #[arg(long)]
+    max_cost: Option<u64>,
+
+    /// Abort evaluations building a string or bytes value longer than this many bytes.
+    #[arg(long, default_value_t = 16 << 20)]
+    max_string_bytes: usize,
+
+    /// Abort evaluations building a list or map with more elements than this.
+    #[arg(long, default_value_t = 1_000_000)]
+    max_collection_size: usize,
+
+    /// Truncate tool results whose JSON exceeds this many bytes, marking them `truncated`.
+    #[arg(long, default_value_t = 1 << 20)]
+    max_result_bytes: usize,
+
+    /// Reject expressions using this macro at compile time, e.g. `all`, `exists`, `exists_one`,
+    /// `map` and `filter` to ban comprehensions (repeatable).
+    #[arg(long = "disable-macro", value_enum)]
+    disabled_macros: Vec<Macro>,
+
+    /// How JSON numbers in contexts become CEL numbers; `int` lets `n + 1` work on integers.
+    #[arg(long, value_enum, default_value_t = JsonNumbers::Uint)]
+    json_numbers: JsonNumbers,
+
+    /// Make selecting a key missing from a map (`m.missing`) give `null` instead of an error,
+    /// unless a request sets `strict_attributes`.
+    #[arg(long)]
+    lenient_attributes: bool,
+
+    /// Disable the tools that change server state (`reload`, `kv_put`, `kv_delete`,
+    /// `register_expression`, `register_composition`, `set_context`) and reloading on SIGHUP.
+    #[arg(long)]
+    read_only: bool,
+
+    /// Exit at startup, and reject reloads, when a policy condition fails to compile. Without
+    /// it such conditions are logged and fail when evaluated.
+    #[arg(long)]
+    fail_on_compile_error: bool,
+
+    /// Return integers beyond ±(2^53 - 1) as decimal strings, which JavaScript clients can't
+    /// hold as numbers without rounding.
+    #[arg(long)]
+    big_ints_as_strings: bool,
+
+    /// How NaN and ±Infinity results are written, unless a request chooses otherwise.
+    #[arg(long, value_enum, default_value_t = NonFinite::Null)]
+    non_finite: NonFinite,
+
+    /// Log evaluations taking at least this many milliseconds, with the expression hash,
+    /// durations and context size.
+    #[arg(long)]
+    slow_eval_threshold_ms: Option<u64>,
+
+    /// Also write slow evaluations to the audit log.
+    #[arg(long, requires_all = ["audit_log", "slow_eval_threshold_ms"])]
+    slow_eval_audit: bool,
+
+    /// Keep at most this many compiled programs; `0` disables the program cache.
+    #[arg(long, default_value_t = program_cache::DEFAULT_MAX_ENTRIES)]
+    cache_max_entries: usize,
+
+    /// Recompile cached programs after this many seconds.
+    #[arg(long)]
+    cache_ttl_secs: Option<u64>,
+
+    /// Keep the results of up to this many distinct requests and answer repeats from them;
+    /// `0` (default) disables the result cache.
+    #[arg(long, default_value_t = 0)]
+    result_cache_max_entries: usize,
+
+    /// Evict the oldest cached results to keep their keys and values within this many bytes.
+    #[arg(long, default_value_t = result_cache::DEFAULT_MAX_BYTES)]
+    result_cache_max_bytes: usize,
+
+    /// Re-evaluate cached results after this many seconds.
+    #[arg(long)]
+    result_cache_ttl_secs: Option<u64>,
+
+    /// Keep the last N `decide` requests of each policy set in memory for the `replay` tool.
+    #[arg(long)]
+    decision_history: Option<usize>,
+
+    /// Evaluate up to this many requests at once, each on its own thread. Defaults to the
+    /// number of CPUs.
+    #[arg(long)]
+    eval_workers: Option<usize>,
+
+    /// Run at most this many `submit_job` jobs at once; further jobs wait in a queue.
+    #[arg(long, default_value_t = job::DEFAULT_MAX_CONCURRENT)]
+    max_concurrent_jobs: usize,
+
+    /// Keep finished jobs and their results for this many seconds.
+    #[arg(long, default_value_t = job::DEFAULT_RETENTION.as_secs())]
+    job_retention_secs: u64,
+
+    /// Spill a job's results to a temporary file once they take about this many bytes.
+    #[arg(long, default_value_t = job::DEFAULT_SPILL_THRESHOLD)]
+    job_spill_bytes: usize,
+
+    /// Serve MCP with the administrative tools (`reload`, `replay`, `cache_stats`) at `/mcp`,
+    /// and usage metrics at `/metrics`, on this separate address (e.g., "127.0.0.1:8081"); they
+    /// are never offered on the regular transport.
+    #[arg(long)]
+    admin_http: Option<SocketAddr>,
+
+    /// Also serve the cel-spec ConformanceService over gRPC on this address
+    /// (e.g., "127.0.0.1:50051"). It has no authentication; only the CIDR filters apply.
+    #[cfg(feature = "grpc")]
+    #[arg(long)]
+    grpc: Option<SocketAddr>,
+
+    /// Load a WebAssembly module whose numeric exports become CEL functions (repeatable).
+    #[cfg(feature = "wasm")]
+    #[arg(long = "wasm-plugin")]
+    wasm_plugins: Vec<PathBuf>,
+
+    /// Fuel granted to each WASM plugin call; a call running out of fuel fails.
+    #[cfg(feature = "wasm")]
+    #[arg(long, default_value_t = rs_cel_mcp::wasm::DEFAULT_FUEL)]
+    wasm_fuel: u64,
+
+    /// JSON file of API keys, managed with `cel-mcp keys`. With it, `--http` and `--admin-http`
+    /// require the secret of a key with the `evaluate` or `admin` scope as a bearer token.
+    #[arg(long)]
+    keys: Option<PathBuf>,
+
+    /// Serve `--http` and `--admin-http` clients only from this address block, e.g.
+    /// "10.0.0.0/8" (repeatable).
+    #[arg(long = "allow-cidr")]
+    allow_cidrs: Vec<Cidr>,
+
+    /// Refuse `--http` and `--admin-http` clients from this address block, even if allowed
+    /// (repeatable).
+    #[arg(long = "deny-cidr")]
+    deny_cidrs: Vec<Cidr>,
+
+    /// PEM certificate chain; with `--tls-key`, `--http` and `--admin-http` serve HTTPS.
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key of `--tls-cert`.
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA certificates; with it, clients must present a certificate the CA issued, whose
+    /// identity the config file's `client_certs` grants scopes and quotas.
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_cert", conflicts_with = "keys")]
+    client_ca: Option<PathBuf>,
+
+    /// JSON file persisting the key-value store read by `kv.get` and `kv.exists`; created on
+    /// the first `kv_put`.
+    #[arg(long)]
+    kv_store: Option<PathBuf>,
+
+    /// MaxMind DB file, such as GeoLite2 Country, looked up by `geoip.country(ip)`.
+    #[cfg(feature = "geoip")]
+    #[arg(long)]
+    geoip_db: Option<PathBuf>,
+
+    /// Enable an optional function library, e.g. `decimal` (repeatable).
+    #[arg(long = "library", value_enum)]
+    libraries: Vec<Library>,
+
+    /// Let `evaluate_dataset` read Parquet and Arrow IPC files under this directory.
+    #[cfg(feature = "arrow")]
+    #[arg(long)]
+    dataset_dir: Option<PathBuf>,
+
+    /// Export evaluation spans to an OTLP/gRPC collector, e.g. http://localhost:4317.
+    #[cfg(feature = "otel")]
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run cel-spec conformance tests (textproto) and report pass/fail per test.
+    Conformance {
+        /// A `.textproto` file, or a directory searched recursively for them.
+        #[arg(long)]
+        suite: PathBuf,
+    },
+    /// Measure compile and evaluation latency (p50/p95/p99) of an expression.
+    Bench {
+        /// The CEL expression to measure.
+        #[arg(long)]
+        expr: String,
+        /// Variables as a JSON object.
+        #[arg(long, default_value = "{}")]
+        context: String,
+        #[arg(long, default_value_t = 1000)]
+        iterations: usize,
+        /// Also measure evaluations that recompile the expression every time.
+        #[arg(long)]
+        compare_cache: bool,
+        /// Report heap allocations per iteration, counted by the global allocator.
+        #[cfg(feature = "profile-alloc")]
+        #[arg(long)]
+        profile_alloc: bool,
+    },
+    /// Manage the API keys in the `--keys` file.
+    Keys {
+        #[command(subcommand)]
+        action: KeysCommand,
+    },
+    /// Evaluate newline-delimited JSON from stdin, writing one result line per input line to
+    /// stdout. Exits with status 1 if any line failed.
+    Pipe {
+        /// Evaluate this expression with each input line as the context; otherwise each line is
+        /// `{"expression": ..., "context": {...}}`.
+        #[arg(long)]
+        expr: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum KeysCommand {
+    /// Create a key and print its secret, which is not shown again.
+    Add {
+        /// A name to recognize the key by.
+        #[arg(long)]
+        name: String,
+        /// What the key may do: `evaluate` for `--http`, `admin` for `--admin-http` too
+        /// (repeatable).
+        #[arg(long = "scope", value_enum, required = true)]
+        scopes: Vec<Scope>,
+        /// The key's quota as JSON, like the config file's `quota`; the server's otherwise.
+        #[arg(long)]
+        quota: Option<String>,
+    },
+    /// List the keys, without their secrets.
+    List,
+    /// Delete a key by ID or name; its secret is refused from then on.
+    Revoke { key: String },
+}
+
+/// The listeners' TLS settings; there are none without the `tls` feature.
+#[cfg(feature = "tls")]
+type Tls = Arc<rs_cel_mcp::tls::ServerConfig>;
+#[cfg(not(feature = "tls"))]
+#[derive(Clone)]
+enum Tls {}
+
+/// Serves `app` on `listener`, over TLS with `tls`, until `shutdown` completes.
+async fn serve(
+    listener: tokio::net::TcpListener,
+    app: axum::Router,
+    tls: Option<Tls>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    match tls {
+        #[cfg(feature = "tls")]
+        Some(config) => {
+            let listener = rs_cel_mcp::tls::TlsListener::new(listener, config)?;
+            let app = app.into_make_service_with_connect_info::<rs_cel_mcp::tls::Peer>();
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await
+        }
+        #[cfg(not(feature = "tls"))]
+        Some(never) => match never {},
+        None => {
+            let app = app.into_make_service_with_connect_info::<SocketAddr>();
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await
+        }
+    }
+}
+
+/// Changes or lists the keys in `path`, printing the outcome.
+fn run_keys(path: &std::path::Path, command: &KeysCommand) -> Result<(), String> {
+    let store = KeyStore::open(path)?;
+    match command {
+        KeysCommand::Add {
+            name,
+            scopes,
+            quota,
+        } => {
+            let quota = quota
+                .as_deref()
+                .map(rmcp::serde_json::from_str::<Quota>)
+                .transpose()
+                .map_err(|e| format!("Invalid --quota JSON: {}", e))?;
+            let (key, secret) = store.add(name, scopes.iter().copied().collect(), quota)?;
+            println!(
+                "Added key {} ({}). Its secret, shown only now:",
+                key.id, key.name
+            );
+            println!("{}", secret);
+        }
+        KeysCommand::List => {
+            for key in store.list() {
+                let scopes: Vec<String> = key.scopes.iter().map(Scope::to_string).collect();
+                let quota = key
+                    .quota
+                    .and_then(|quota| quota.describe())
+                    .unwrap_or_else(|| "server quota".to_string());
+                println!(
+                    "{}  {}  {}  {}  {}",
+                    key.id,
+                    key.name,
+                    scopes.join(","),
+                    key.created_at,
+                    quota
+                );
+            }
+        }
+        KeysCommand::Revoke { key } => match store.revoke(key)? {
+            Some(key) => println!("Revoked key {} ({}).", key.id, key.name),
+            None => return Err(format!("No key has the ID or name `{}`", key)),
+        },
+    }
+    Ok(())
+}
+
+/// Prints one line per test plus a summary; returns whether every test passed or was skipped.
+fn run_conformance(suite: &std::path::Path) -> Result<bool, String> {
+    let results = conformance::run_suite(suite)?;
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for result in &results {
+        match &result.outcome {
+            Outcome::Pass => {
+                passed += 1;
+                println!("PASS {}", result.name);
+            }
+            Outcome::Fail(reason) => {
+                failed += 1;
+                println!("FAIL {}: {}", result.name, reason);
+            }
+            Outcome::Skip(reason) => {
+                skipped += 1;
+                println!("SKIP {}: {}", result.name, reason);
+            }
+        }
+    }
+    println!(
+        "{} passed, {} failed, {} skipped ({} total)",
+        passed,
+        failed,
+        skipped,
+        results.len()
+    );
+    Ok(failed == 0)
+}
+
+#[cfg(feature = "profile-alloc")]
+#[global_allocator]
+static GLOBAL: &stats_alloc::StatsAlloc<std::alloc::System> = &stats_alloc::INSTRUMENTED_SYSTEM;
+
+#[cfg(feature = "profile-alloc")]
+fn alloc_count() -> bench::AllocCount {
+    let stats = GLOBAL.stats();
+    bench::AllocCount {
+        allocations: stats.allocations + stats.reallocations,
+        bytes: stats.bytes_allocated,
+    }
+}
+
+/// Prints one latency line per measured mode.
+fn run_bench(
+    expression: &str,
+    context: &str,
+    profile: Profile,
+    iterations: usize,
+    compare_cache: bool,
+    probe: Option<AllocProbe>,
+) -> Result<(), String> {
+    let context: rmcp::serde_json::Value = rmcp::serde_json::from_str(context)
+        .map_err(|e| format!("Invalid --context JSON: {}", e))?;
+    let measurements = bench::run(
+        expression,
+        &context,
+        profile,
+        iterations,
+        compare_cache,
+        probe,
+    )?;
+    println!("{} iterations", iterations);
+    for m in measurements {
+        let allocations = m
+            .allocations
+            .map(|a| format!("  allocs {:>6}  bytes {:>8}", a.allocations, a.bytes))
+            .unwrap_or_default();
+        println!(
+            "{:<20} p50 {:>10.3?}  p95 {:>10.3?}  p99 {:>10.3?}  max {:>10.3?}{}",
+            m.name, m.latencies.p50, m.latencies.p95, m.latencies.p99, m.latencies.max, allocations
+        );
+    }
+    Ok(())
+}
+
+/// Re-reads the `--config` and `--base-context` files.
+fn settings_loader(config_path: Option<PathBuf>, base_context: Option<PathBuf>) -> SettingsLoader {
+    Arc::new(move || {
+        let config = match &config_path {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        let base_context = match &base_context {
+            Some(path) => config::read_json_object(path)?,
+            None => Default::default(),
+        };
+        let resolvers = config
+            .resolvers
+            .iter()
+            .map(resolver::from_config)
+            .collect::<Result<_, _>>()?;
+        Ok(Settings {
+            base_context,
+            resolvers,
+            policies: config.policies,
+        })
+    })
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::registry()
+    let args = Args::parse();
+
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let redactor = Redactor::new(config.redact.iter().chain(&args.redact));
+
+    #[cfg(feature = "otel")]
+    let tracer_provider = match &args.otlp_endpoint {
+        Some(endpoint) => Some(rs_cel_mcp::telemetry::otlp_provider(endpoint)?),
+        None => None,
+    };
+
+    // In pipe mode stdout carries the results.
+    let log_writer = if matches!(args.command, Some(Command::Pipe { .. })) {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let registry = tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "debug".to_string().into()),
         )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+        .with(
+            tracing_subscriber::fmt::layer().with_writer(RedactingMakeWriter::new(
+                log_writer,
+                Arc::new(redactor.clone()),
+            )),
+        )
+        .with(rs_cel_mcp::mcp_log::McpLogLayer);
+    #[cfg(feature = "otel")]
+    let registry = registry.with(tracer_provider.as_ref().map(rs_cel_mcp::telemetry::layer));
+    registry.init();
 
-    let args = Args::parse();
+    let profile = if args.envoy_compat {
+        Profile::Envoy
+    } else if args.iam_compat {
+        Profile::Iam
+    } else if args.firestore_compat {
+        Profile::Firestore
+    } else {
+        Profile::Default
+    };
 
-    let (tx, rx) = mpsc::channel(32);
+    match &args.command {
+        Some(Command::Conformance { suite }) => {
+            if !run_conformance(suite)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Bench {
+            expr,
+            context,
+            iterations,
+            compare_cache,
+            #[cfg(feature = "profile-alloc")]
+            profile_alloc,
+        }) => {
+            #[cfg(feature = "profile-alloc")]
+            let probe = profile_alloc.then_some(alloc_count as AllocProbe);
+            #[cfg(not(feature = "profile-alloc"))]
+            let probe = None;
+            run_bench(expr, context, profile, *iterations, *compare_cache, probe)?;
+            return Ok(());
+        }
+        Some(Command::Keys { action }) => {
+            let path = args
+                .keys
+                .as_ref()
+                .ok_or("`keys` requires `--keys <file>`")?;
+            run_keys(path, action)?;
+            return Ok(());
+        }
+        Some(Command::Pipe { .. }) | None => {}
+    }
+    let keys = match &args.keys {
+        Some(path) => Some(Arc::new(KeyStore::open(path)?)),
+        None => None,
+    };
+    #[cfg(feature = "tls")]
+    let tls: Option<Tls> = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => Some(rs_cel_mcp::tls::server_config(
+            cert,
+            key,
+            args.client_ca.as_deref(),
+        )?),
+        _ => None,
+    };
+    #[cfg(not(feature = "tls"))]
+    let tls: Option<Tls> = None;
+    #[cfg(feature = "tls")]
+    let client_certs = args
+        .client_ca
+        .is_some()
+        .then(|| Arc::new(config.client_certs.clone()));
+    #[cfg(not(feature = "tls"))]
+    if !config.client_certs.is_empty() {
+        return Err("`client_certs` requires the `tls` feature".into());
+    }
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let addresses = Arc::new(IpFilter {
+        allow: args.allow_cidrs.clone(),
+        deny: args.deny_cidrs.clone(),
+    });
+    let limits = Limits {
+        max_context_depth: Some(args.max_context_depth),
+        max_context_bytes: args.max_context_bytes,
+        max_regex_size: Some(args.max_regex_size),
+        max_result_bytes: Some(args.max_result_bytes),
+        max_iterations: Some(args.max_iterations),
+        max_cost: args.max_cost,
+        max_string_bytes: Some(args.max_string_bytes),
+        max_collection_size: Some(args.max_collection_size),
+        ..Limits::default()
+    };
 
-    tokio::spawn(evaluator_service(rx));
+    let mut builder = CelTool::builder()
+        .with_profile(profile)
+        .with_redactor(redactor.clone())
+        .with_private_errors(args.private_errors)
+        .with_repair_suggestions(args.suggest_repairs)
+        .with_slow_eval_audit(args.slow_eval_audit)
+        .with_disabled_macros(args.disabled_macros.iter().copied())
+        .with_json_numbers(args.json_numbers)
+        .with_strict_attributes(!args.lenient_attributes)
+        .with_program_cache(Arc::new(ProgramCache::new(
+            args.cache_max_entries,
+            args.cache_ttl_secs.map(std::time::Duration::from_secs),
+        )))
+        .with_result_cache(Arc::new(
+            ResultCache::new(
+                args.result_cache_max_entries,
+                args.result_cache_ttl_secs
+                    .map(std::time::Duration::from_secs),
+            )
+            .with_max_bytes(args.result_cache_max_bytes),
+        ))
+        .with_eval_workers(args.eval_workers.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, std::num::NonZero::get)
+        }))
+        .with_job_limits(JobLimits {
+            max_concurrent: args.max_concurrent_jobs,
+            retention: std::time::Duration::from_secs(args.job_retention_secs),
+            spill_threshold: args.job_spill_bytes,
+        })
+        .with_encoding(Encoding {
+            big_ints_as_strings: args.big_ints_as_strings,
+            non_finite: args.non_finite,
+        })
+        .with_limits(limits);
+    if let Some(ms) = args.slow_eval_threshold_ms {
+        builder = builder.with_slow_eval_threshold(std::time::Duration::from_millis(ms));
+    }
+    if let Some(capacity) = args.decision_history {
+        builder = builder.with_decision_history(capacity);
+    }
+    let loader = settings_loader(args.config.clone(), args.base_context.clone());
+    let settings = loader()?;
+    builder = builder
+        .with_base_context(&settings.base_context)
+        .with_settings_loader(loader);
+    for resolver in settings.resolvers {
+        builder = builder.with_variable_resolver(resolver);
+    }
+    for (name, policy) in settings.policies {
+        builder = builder.with_policy_set(name, policy);
+    }
+    builder = builder.with_error_messages(ErrorMessages::load(&config.error_messages)?);
+    builder = builder.with_rewriter(Rewriter::new(config.rewrites.clone())?);
+    builder = builder.with_deny_list(config.deny.clone());
+    builder = builder.with_quota(config.quota);
+    for (name, environment) in &config.environments {
+        builder = builder.with_environment(name, environment.clone());
+    }
+    if let Some(path) = &args.audit_log {
+        let audit = AuditLog::open(path, args.audit_format)?.with_redactor(redactor);
+        builder = builder.with_audit(Arc::new(audit));
+    }
+
+    if let Some(path) = &args.kv_store {
+        if profile == Profile::Firestore {
+            return Err(
+                "`--kv-store` cannot be combined with `--firestore-compat`, whose `get` and `exists` take its place".into(),
+            );
+        }
+        builder = builder.with_kv_store(Arc::new(KvStore::open(path)?));
+    }
+
+    let libraries: std::collections::BTreeSet<Library> = args.libraries.iter().copied().collect();
+    for library in libraries {
+        if let Some(feature) = library.missing_feature() {
+            return Err(
+                format!("`--library {}` requires the `{}` feature", library, feature).into(),
+            );
+        }
+        builder = builder
+            .with_extension_description(library.description())
+            .with_function_provider(library);
+    }
+
+    #[cfg(feature = "arrow")]
+    if let Some(dir) = &args.dataset_dir {
+        builder = builder.with_dataset_dir(dir);
+    }
+
+    #[cfg(feature = "object-store")]
+    if let Some(store) = &config.object_store {
+        builder = builder.with_object_store(Arc::new(rs_cel_mcp::object_store::ObjectStore::new(
+            store.clone(),
+        )));
+    }
+    #[cfg(not(feature = "object-store"))]
+    if config.object_store.is_some() {
+        return Err("`object_store` requires the `object-store` feature".into());
+    }
+
+    #[cfg(feature = "http-function")]
+    if let Some(function) = &config.http_function {
+        if profile == Profile::Firestore {
+            return Err("`http_function` cannot be combined with `--firestore-compat`, whose `get` takes its place".into());
+        }
+        builder = builder
+            .with_extension_description(format!(
+                "http.get(url) fetches JSON from these hosts, at most {} times per request: {}",
+                function.max_calls,
+                function.allow_domains.join(", ")
+            ))
+            .with_function_provider(rs_cel_mcp::http_function::HttpFunction::new(
+                function.clone(),
+            ));
+    }
+    #[cfg(not(feature = "http-function"))]
+    if config.http_function.is_some() {
+        return Err("`http_function` requires the `http-function` feature".into());
+    }
+
+    #[cfg(feature = "geoip")]
+    if let Some(path) = &args.geoip_db {
+        builder = builder
+            .with_extension_description(
+                "geoip.country(ip) returns the ISO 3166-1 alpha-2 country code of an IP address, or null if unknown",
+            )
+            .with_function_provider(rs_cel_mcp::geoip::GeoIp::open(path)?);
+    }
+
+    #[cfg(feature = "wasm")]
+    if !args.wasm_plugins.is_empty() {
+        let mut plugins = rs_cel_mcp::wasm::WasmPlugins::new(args.wasm_fuel)?;
+        for path in &args.wasm_plugins {
+            plugins.load(path)?;
+        }
+        let names: Vec<&str> = plugins.function_names().collect();
+        tracing::info!("Loaded WASM plugin functions: {:?}", names);
+        builder = builder
+            .with_extension_description(format!(
+                "WASM plugin functions (numbers in, number out): {}",
+                names.join(", ")
+            ))
+            .with_function_provider(plugins);
+    }
+
+    builder = builder
+        .with_fail_on_compile_error(args.fail_on_compile_error)
+        .with_read_only(args.read_only);
+    match builder.warm_up() {
+        Ok(compiled) => tracing::info!("Compiled {} policy conditions", compiled),
+        Err(errors) if args.fail_on_compile_error => {
+            return Err(format!("Policy conditions fail to compile: {}", errors.join("; ")).into());
+        }
+        Err(errors) => {
+            for error in errors {
+                tracing::warn!("Policy condition does not compile: {}", error);
+            }
+        }
+    }
+    let tool = builder.build();
+    let admin_tool = tool.clone().with_admin_tools();
+    let tool = tool.with_tool_selection(&config.tools)?;
+
+    #[cfg(unix)]
+    {
+        let tool = tool.clone();
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                tracing::info!(
+                    "SIGHUP received, reloading the base context, resolvers and policy sets."
+                );
+                if let Err(e) = tool.reload() {
+                    tracing::error!("Reload failed, keeping the previous settings: {}", e);
+                }
+            }
+        });
+    }
+
+    if let Some(Command::Pipe { expr }) = &args.command {
+        let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+        let failed =
+            rs_cel_mcp::pipe::run(&tool, expr.as_deref(), stdin, tokio::io::stdout()).await?;
+        if failed > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(addr) = args.admin_http {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tracing::info!(
+            "Serving admin tools on {}://{}/mcp",
+            scheme,
+            listener.local_addr()?
+        );
+        let mut app = axum::Router::new()
+            .merge(quota::router(admin_tool.clone()))
+            .nest_service("/mcp", mcp_http::service(admin_tool));
+        app = size_limit::require(app, args.max_request_bytes);
+        if let Some(store) = &keys {
+            app = rs_cel_mcp::keys::require(app, store.clone(), Scope::Admin);
+        }
+        #[cfg(feature = "tls")]
+        if let Some(grants) = &client_certs {
+            app = rs_cel_mcp::tls::require(app, grants.clone(), Scope::Admin);
+        }
+        if !addresses.is_empty() {
+            app = ip_filter::require(app, addresses.clone());
+        }
+        let tls = tls.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve(listener, app, tls, std::future::pending()).await {
+                tracing::error!("Admin HTTP server failed: {}", e);
+            }
+        });
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = args.grpc {
+        tracing::info!("Starting gRPC ConformanceService on {}", addr);
+        let service = rs_cel_mcp::grpc::CelConformanceService::new(limits);
+        let addresses = addresses.clone();
+        tokio::spawn(async move {
+            if let Err(e) = rs_cel_mcp::grpc::serve(addr, service, addresses).await {
+                tracing::error!("gRPC server failed: {}", e);
+            }
+        });
+    }
 
     if let Some(addr_str) = args.http {
         let addr: SocketAddr = addr_str.parse()?;
-        tracing::info!("Starting HTTP server on http://{}", addr);
-
-        let service = StreamableHttpService::new(
-            move || Ok(CelTool::new(tx.clone())),
-            LocalSessionManager::default().into(),
-            rmcp::transport::streamable_http_server::StreamableHttpServerConfig {
-                stateful_mode: false,
-                ..Default::default()
-            },
-        );
+        tracing::info!("Starting HTTP server on {}://{}", scheme, addr);
 
-        let app = axum::Router::new().nest_service("/mcp", service);
+        let mut app = rs_cel_mcp::rest::router(tool.clone())
+            .merge(rs_cel_mcp::openapi::router(config.admission.is_some()));
+        if let Some(admission) = config.admission {
+            app = app.merge(rs_cel_mcp::admission::router(tool.clone(), admission));
+        }
+        let mut app = size_limit::require(
+            app.nest_service("/mcp", mcp_http::service(tool)),
+            args.max_request_bytes,
+        );
+        if let Some(store) = keys {
+            app = rs_cel_mcp::keys::require(app, store, Scope::Evaluate);
+        }
+        #[cfg(feature = "tls")]
+        if let Some(grants) = client_certs {
+            app = rs_cel_mcp::tls::require(app, grants, Scope::Evaluate);
+        }
+        if !addresses.is_empty() {
+            app = ip_filter::require(app, addresses);
+        }
 
         let listener = tokio::net::TcpListener::bind(addr).await?;
         tracing::info!("Listening on {}", listener.local_addr()?);
-        axum::serve(listener, app)
-            .with_graceful_shutdown(async {
-                if let Err(e) = tokio::signal::ctrl_c().await {
-                    tracing::error!("Failed to listen for ctrl-c signal: {}", e);
-                }
-                tracing::info!("Ctrl-C received, shutting down.");
-            })
-            .await?;
+        serve(listener, app, tls, async {
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                tracing::error!("Failed to listen for ctrl-c signal: {}", e);
+            }
+            tracing::info!("Ctrl-C received, shutting down.");
+        })
+        .await?;
     } else {
         println!("Starting CEL MCP server on stdio...");
-        let service = CelTool::new(tx).serve(stdio()).await?;
+        let service = tool
+            .serve(size_limit::stdio(args.max_request_bytes))
+            .await?;
         eprintln!("Server ready.");
         service.waiting().await?;
     }
 
+    #[cfg(feature = "otel")]
+    if let Some(provider) = tracer_provider {
+        provider.shutdown()?;
+    }
+
     Ok(())
 }
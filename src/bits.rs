@@ -0,0 +1,95 @@
+//! `bits.and`, `bits.or`, `bits.xor`, `bits.shl` and `bits.shr`, bitwise operations CEL
+//! lacks, for checking permission bitmaps and other flag masks.
+//!
+//! `and`, `or` and `xor` take two ints or two uints and return the same type. The shifts
+//! take an int or uint and a shift amount from 0 to 63; `shr` on an int keeps its sign.
+
+use crate::provider::{Method, Namespace};
+use cel::{FunctionContext, Value as CelValue};
+use std::sync::Arc;
+
+/// The namespace the functions are called on.
+pub const VARIABLE: &str = "bits";
+
+type Logical = (&'static str, fn(i64, i64) -> i64, fn(u64, u64) -> u64);
+
+const LOGICAL: [Logical; 3] = [
+    ("and", |a, b| a & b, |a, b| a & b),
+    ("or", |a, b| a | b, |a, b| a | b),
+    ("xor", |a, b| a ^ b, |a, b| a ^ b),
+];
+
+type Shift = (&'static str, fn(i64, u32) -> i64, fn(u64, u32) -> u64);
+
+const SHIFTS: [Shift; 2] = [
+    ("shl", |a, n| a << n, |a, n| a << n),
+    ("shr", |a, n| a >> n, |a, n| a >> n),
+];
+
+pub(crate) fn names() -> Vec<String> {
+    LOGICAL
+        .iter()
+        .map(|(name, _, _)| *name)
+        .chain(SHIFTS.iter().map(|(name, _, _)| *name))
+        .map(|name| format!("{}.{}", VARIABLE, name))
+        .collect()
+}
+
+pub(crate) fn namespace() -> Namespace {
+    let logical = LOGICAL.iter().map(|&(name, int, uint)| {
+        let method: Method = Arc::new(move |ftx: &FunctionContext, args: &[CelValue]| match args {
+            [CelValue::Int(a), CelValue::Int(b)] => Ok(CelValue::Int(int(*a, *b))),
+            [CelValue::UInt(a), CelValue::UInt(b)] => Ok(CelValue::UInt(uint(*a, *b))),
+            _ => Err(ftx.error(format!("bits.{} expects two ints or two uints", name))),
+        });
+        (name.to_string(), method)
+    });
+    let shifts = SHIFTS.iter().map(|&(name, int, uint)| {
+        let method: Method = Arc::new(move |ftx: &FunctionContext, args: &[CelValue]| {
+            let [value, amount] = args else {
+                return Err(ftx.error(format!("bits.{} expects a value and a shift amount", name)));
+            };
+            let amount = match amount {
+                CelValue::Int(n) => u32::try_from(*n).ok(),
+                CelValue::UInt(n) => u32::try_from(*n).ok(),
+                _ => None,
+            }
+            .filter(|n| *n < 64)
+            .ok_or_else(|| ftx.error(format!("bits.{} shifts by 0 to 63 bits", name)))?;
+            match value {
+                CelValue::Int(a) => Ok(CelValue::Int(int(*a, amount))),
+                CelValue::UInt(a) => Ok(CelValue::UInt(uint(*a, amount))),
+                _ => Err(ftx.error(format!("bits.{} expects an int or a uint", name))),
+            }
+        });
+        (name.to_string(), method)
+    });
+    Namespace {
+        name: VARIABLE.to_string(),
+        methods: logical.chain(shifts).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::Evaluator;
+    use crate::library::Library;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_bitwise_functions() {
+        let evaluator = Evaluator::new().with_function_provider(Library::Bits);
+        let evaluate = |expression: &str| {
+            evaluator.evaluate(expression, &json!({"mode": 0o644}), Profile::Default)
+        };
+        assert_eq!(evaluate("bits.and(uint(mode), 2u) != 0u"), Ok(json!(false)));
+        assert_eq!(evaluate("bits.and(uint(mode), 128u)"), Ok(json!(128)));
+        assert_eq!(evaluate("bits.or(1, 4)"), Ok(json!(5)));
+        assert_eq!(evaluate("bits.xor(6, 3)"), Ok(json!(5)));
+        assert_eq!(evaluate("bits.shl(1, 10)"), Ok(json!(1024)));
+        assert_eq!(evaluate("bits.shr(-8, 1)"), Ok(json!(-4)));
+        assert!(evaluate("bits.and(1, 1u)").is_err());
+        assert!(evaluate("bits.shl(1, 64)").is_err());
+    }
+}
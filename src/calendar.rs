@@ -0,0 +1,156 @@
+//! `time.*`, calendar functions for scheduling policies: truncating timestamps to a unit,
+//! converting them to an IANA time zone, and adding business days.
+//!
+//! Timestamps keep their UTC offset, and the functions work in it: `time.inZone` gives a
+//! timestamp the zone's offset, so that truncating it or reading `getHours()` is local.
+
+use crate::provider::{Method, Namespace};
+use cel::{FunctionContext, Value as CelValue};
+use chrono::{DateTime, Datelike, Days, Duration, FixedOffset, NaiveDate, Timelike, Weekday};
+use chrono_tz::Tz;
+use std::sync::Arc;
+
+/// The namespace the functions are called on.
+pub const VARIABLE: &str = "time";
+
+const METHODS: [&str; 3] = ["truncate", "inZone", "addBusinessDays"];
+
+pub(crate) fn names() -> Vec<String> {
+    METHODS
+        .iter()
+        .map(|name| format!("{}.{}", VARIABLE, name))
+        .collect()
+}
+
+pub(crate) fn namespace() -> Namespace {
+    let truncate: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::Timestamp(ts), CelValue::String(unit)] = args else {
+            return Err(ftx.error("time.truncate expects a timestamp and a unit"));
+        };
+        start_of(ts, unit)
+            .map(CelValue::Timestamp)
+            .map_err(|e| ftx.error(e))
+    });
+    let in_zone: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::Timestamp(ts), CelValue::String(zone)] = args else {
+            return Err(ftx.error("time.inZone expects a timestamp and a time zone name"));
+        };
+        let zone: Tz = zone
+            .parse()
+            .map_err(|_| ftx.error(format!("`{}` is not an IANA time zone", zone)))?;
+        Ok(CelValue::Timestamp(ts.with_timezone(&zone).fixed_offset()))
+    });
+    let add_business_days: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::Timestamp(ts), CelValue::Int(days)] = args else {
+            return Err(ftx.error("time.addBusinessDays expects a timestamp and an int"));
+        };
+        add_weekdays(*ts, *days)
+            .map(CelValue::Timestamp)
+            .ok_or_else(|| ftx.error("time.addBusinessDays overflows"))
+    });
+    Namespace {
+        name: VARIABLE.to_string(),
+        methods: vec![
+            ("truncate".to_string(), truncate),
+            ("inZone".to_string(), in_zone),
+            ("addBusinessDays".to_string(), add_business_days),
+        ],
+    }
+}
+
+/// The start of the `unit` containing `ts`, in `ts`'s offset. Weeks start on Monday.
+fn start_of(ts: &DateTime<FixedOffset>, unit: &str) -> Result<DateTime<FixedOffset>, String> {
+    let local = ts.naive_local();
+    let date = local.date();
+    let start = match unit {
+        "second" => date.and_hms_opt(local.hour(), local.minute(), local.second()),
+        "minute" => date.and_hms_opt(local.hour(), local.minute(), 0),
+        "hour" => date.and_hms_opt(local.hour(), 0, 0),
+        "day" => date.and_hms_opt(0, 0, 0),
+        "week" => date
+            .checked_sub_days(Days::new(date.weekday().num_days_from_monday().into()))
+            .and_then(|monday| monday.and_hms_opt(0, 0, 0)),
+        "month" => date
+            .with_day(1)
+            .and_then(|first| first.and_hms_opt(0, 0, 0)),
+        "year" => {
+            NaiveDate::from_ymd_opt(date.year(), 1, 1).and_then(|first| first.and_hms_opt(0, 0, 0))
+        }
+        _ => {
+            return Err(format!(
+                "Unknown unit `{}`; expected second, minute, hour, day, week, month or year",
+                unit
+            ));
+        }
+    };
+    start
+        .and_then(|start| start.and_local_timezone(*ts.offset()).single())
+        .ok_or_else(|| format!("Cannot truncate {} to a {}", ts, unit))
+}
+
+/// `ts` moved by `days` weekdays, skipping Saturdays and Sundays, at the same time of day.
+fn add_weekdays(ts: DateTime<FixedOffset>, days: i64) -> Option<DateTime<FixedOffset>> {
+    if days == 0 {
+        return Some(ts);
+    }
+    let step = days.signum();
+    // From a weekend, count from the weekday before it in the direction of travel, so that
+    // the next weekday is the first business day.
+    let mut ts = ts;
+    while is_weekend(ts.weekday()) {
+        ts = ts.checked_sub_signed(Duration::try_days(step)?)?;
+    }
+    ts = ts.checked_add_signed(Duration::try_weeks(days / 5)?)?;
+    let mut remaining = (days % 5).abs();
+    while remaining > 0 {
+        ts = ts.checked_add_signed(Duration::try_days(step)?)?;
+        if !is_weekend(ts.weekday()) {
+            remaining -= 1;
+        }
+    }
+    Some(ts)
+}
+
+fn is_weekend(day: Weekday) -> bool {
+    matches!(day, Weekday::Sat | Weekday::Sun)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::Evaluator;
+    use crate::library::Library;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_calendar_functions() {
+        let evaluator = Evaluator::new().with_function_provider(Library::Time);
+        let evaluate = |expression: &str| {
+            evaluator.evaluate(
+                expression,
+                // A Friday afternoon in UTC, already Saturday in Tokyo.
+                &json!({"at": "2024-03-15T15:45:30Z"}),
+                Profile::Default,
+            )
+        };
+        let is_true = |expression: &str| assert_eq!(evaluate(expression), Ok(json!(true)));
+        is_true("time.truncate(timestamp(at), 'day') == timestamp('2024-03-15T00:00:00Z')");
+        is_true("time.truncate(timestamp(at), 'hour') == timestamp('2024-03-15T15:00:00Z')");
+        is_true("time.truncate(timestamp(at), 'week') == timestamp('2024-03-11T00:00:00Z')");
+        is_true("time.truncate(timestamp(at), 'month') == timestamp('2024-03-01T00:00:00Z')");
+        is_true("time.inZone(timestamp(at), 'Asia/Tokyo').getHours() == 0");
+        is_true(
+            "time.truncate(time.inZone(timestamp(at), 'Asia/Tokyo'), 'day') \
+             == timestamp('2024-03-16T00:00:00+09:00')",
+        );
+        is_true("time.addBusinessDays(timestamp(at), 1) == timestamp('2024-03-18T15:45:30Z')");
+        is_true("time.addBusinessDays(timestamp(at), 6) == timestamp('2024-03-25T15:45:30Z')");
+        is_true("time.addBusinessDays(timestamp(at), -5) == timestamp('2024-03-08T15:45:30Z')");
+        is_true(
+            "time.addBusinessDays(timestamp('2024-03-16T09:00:00Z'), 1) \
+             == timestamp('2024-03-18T09:00:00Z')",
+        );
+        assert!(evaluate("time.truncate(timestamp(at), 'fortnight')").is_err());
+        assert!(evaluate("time.inZone(timestamp(at), 'Mars/Olympus')").is_err());
+    }
+}
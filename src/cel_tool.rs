@@ -1,99 +1,648 @@
-use cel::{Context, Program, Value as CelValueEnum};
+use crate::ast;
+use crate::audit::{AuditLog, AuditRecord, ShadowDivergence, SlowEvaluation};
+use crate::cel_type::CelType;
+use crate::completion;
+use crate::config::ToolsConfig;
+#[cfg(feature = "arrow")]
+use crate::dataset::{self, DatasetFormat};
+use crate::deny::DenyList;
+use crate::elicitation::{self, MAX_ELICITED_VARIABLES};
+use crate::environment::Environment;
+pub use crate::evaluator::{
+    Encoding, EvalReply, EvalRequest, EvalResponse, Limits, NonFinite, Query, evaluator_service,
+};
+use crate::evaluator::{Evaluator, EvaluatorReloader, Lane, Priority};
+use crate::history::{DecisionHistory, RecordedDecision};
+use crate::ingest::{ContextBudget, JsonNumbers};
+use crate::job::{BatchItem, Job, JobLimits, JobRegistry, JobState, JobStatus};
+use crate::kv_store::KvStore;
+use crate::literal;
+use crate::macros::{self, Macro};
+use crate::mcp_log;
+use crate::merge::MergeStrategy;
+use crate::messages::ErrorMessages;
+#[cfg(feature = "object-store")]
+use crate::object_store::ObjectStore;
+use crate::policy::{Combining, Effect, PolicySet};
+use crate::profile::{self, Profile};
+use crate::program_cache::ProgramCache;
+use crate::provider::{FunctionProvider, VariableResolver};
+use crate::quota::{self, Account, AccountUsage, Quota, Quotas, Usage};
+use crate::redact::{self, Redactor};
+use crate::registry::{Composition, ExpressionRegistry, Registered};
+use crate::reload::{ReloadSummary, Reloadable, SettingsLoader};
+use crate::repair;
+use crate::result_cache::{ResultCache, ResultCacheStats};
+use crate::rewrite::Rewriter;
+use crate::template::{self, Escape};
+use crate::truncate::{Truncation, truncate};
+#[cfg(feature = "arrow")]
+use crate::vectorized;
+use axum::http::request::Parts;
+#[cfg(feature = "arrow")]
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::Utc;
 use rmcp::{
-    ErrorData, ServerHandler,
-    handler::server::{tool::ToolRouter, wrapper::Json, wrapper::Parameters},
+    ErrorData, RoleServer, ServerHandler,
+    handler::server::{
+        tool::{ToolCallContext, ToolRouter},
+        wrapper::Json,
+        wrapper::Parameters,
+    },
+    service::{ElicitationMode, Peer, RequestContext},
     tool, tool_handler, tool_router,
 };
 use rmcp::{
-    model::{Implementation, ServerCapabilities, ServerInfo},
-    schemars::{self, JsonSchema},
+    model::{
+        CallToolRequestParams, CallToolResult, CompleteRequestParams, CompleteResult,
+        CompletionInfo, CreateElicitationRequestParams, ElicitationAction, Implementation,
+        LoggingLevel, ServerCapabilities, ServerInfo, SetLevelRequestParams,
+    },
+    schemars::JsonSchema,
     serde::{Deserialize, Serialize},
     serde_json::{self, Map, Value},
 };
-use std::convert::From;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "arrow")]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use uuid::Uuid;
 
-/// A newtype wrapper to implement `From<CelJsonValue> for Value`
-struct CelJsonValue(CelValueEnum);
-
-/// Converts a `cel::Value` into a `serde_json::Value`.
-impl From<CelJsonValue> for Value {
-    fn from(wrapper: CelJsonValue) -> Self {
-        match wrapper.0 {
-            CelValueEnum::Null => Value::Null,
-            CelValueEnum::Bool(b) => Value::Bool(b),
-            CelValueEnum::Int(i) => serde_json::json!(i),
-            CelValueEnum::UInt(u) => serde_json::json!(u),
-            CelValueEnum::Float(f) => serde_json::json!(f),
-            CelValueEnum::String(s) => Value::String(s.to_string()),
-            CelValueEnum::Bytes(b) => Value::String(String::from_utf8_lossy(&b).to_string()),
-            CelValueEnum::List(list) => {
-                let values: &[CelValueEnum] = &list;
-                Value::Array(
-                    values
-                        .iter()
-                        .map(|v| CelJsonValue(v.clone()).into())
-                        .collect(),
-                )
-            }
-            CelValueEnum::Map(map_obj) => {
-                let mut json_map = Map::new();
-                for (key, val) in map_obj.map.iter() {
-                    let cel_key_value: CelValueEnum = key.into();
-                    let key_str = match cel_key_value {
-                        CelValueEnum::String(s) => s.to_string(),
-                        _ => format!("{:?}", cel_key_value),
-                    };
-                    json_map.insert(key_str, CelJsonValue(val.clone()).into());
-                }
-                Value::Object(json_map)
-            }
-            // For other CEL types (like Type), just return a string representation.
-            cel_value => Value::String(format!("{:?}", cel_value)),
-        }
+/// HTTP header carrying a client-chosen request ID.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// `_meta` key carrying a client-chosen request ID; takes precedence over the header.
+pub const REQUEST_ID_META_KEY: &str = "requestId";
+
+/// Returns the client-supplied request ID, or a fresh one when none (or an unusable one) was sent.
+fn request_id(context: &RequestContext<RoleServer>) -> String {
+    client_request_id(
+        context
+            .meta
+            .get(REQUEST_ID_META_KEY)
+            .and_then(Value::as_str)
+            .or_else(|| {
+                context
+                    .extensions
+                    .get::<Parts>()
+                    .and_then(|parts| parts.headers.get(REQUEST_ID_HEADER))
+                    .and_then(|value| value.to_str().ok())
+            }),
+    )
+}
+
+/// Accepts a non-empty client request ID of at most 128 bytes, or generates one.
+pub(crate) fn client_request_id(id: Option<&str>) -> String {
+    id.filter(|id| !id.is_empty() && id.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Restricts `environment` in a tool's input schema to the configured names and, for each
+/// environment declaring variables, describes the context `field` (or its items, for a list)
+/// when that environment is selected.
+fn describe_environments(
+    schema: &mut Map<String, Value>,
+    field: &str,
+    environments: &BTreeMap<String, Environment>,
+) {
+    if environments.is_empty() {
+        return;
     }
+    if let Some(Value::Object(environment)) = schema
+        .get_mut("properties")
+        .and_then(|properties| properties.get_mut("environment"))
+    {
+        let mut names: Vec<Value> = environments
+            .keys()
+            .map(|name| Value::from(name.as_str()))
+            .collect();
+        names.push(Value::Null);
+        environment.insert("enum".to_string(), Value::Array(names));
+    }
+    let is_list = schema["properties"][field]["type"] == "array";
+    let conditions: Vec<Value> = environments
+        .iter()
+        .filter(|(_, environment)| !environment.variables.is_empty())
+        .map(|(name, environment)| {
+            let context = environment.context_schema();
+            let context = if is_list {
+                serde_json::json!({"items": context})
+            } else {
+                context
+            };
+            serde_json::json!({
+                "if": {"properties": {"environment": {"const": name}}, "required": ["environment"]},
+                "then": {"properties": {field: context}},
+            })
+        })
+        .collect();
+    if !conditions.is_empty() {
+        schema.insert("allOf".to_string(), Value::Array(conditions));
+    }
+}
+
+/// Identifies a request's result by its query fingerprint, a SHA-256 hash of its context and
+/// every setting that changes the result. serde_json writes object keys sorted, so equal
+/// contexts hash alike.
+fn result_cache_key(
+    query: &Query,
+    context: &Value,
+    profile: Profile,
+    merge: MergeStrategy,
+    overrides: &Overrides,
+) -> String {
+    let fingerprint = query
+        .fingerprint()
+        .unwrap_or_else(|| ast::hash(&query.to_string()));
+    format!(
+        "{} {:?} {:?} {:?} {:?} {:?} {:?} {:x}",
+        fingerprint,
+        profile,
+        merge,
+        overrides.non_finite,
+        overrides.cost_limit,
+        overrides.strict_attributes,
+        overrides.environment,
+        Sha256::digest(context.to_string())
+    )
 }
 
-/// Compiles and executes a CEL expression with a given context.
-fn real_evaluate(expression: &str, context: &Value) -> Result<Value, String> {
-    let mut ctx = Context::default();
-    if let Value::Object(map) = context {
-        for (key, value) in map {
-            ctx.add_variable(key, value.clone())
-                .map_err(|e| format!("Context error: {}", e))?;
+/// Tools removed in read-only mode.
+const MUTATING_TOOLS: &[&str] = &[
+    "reload",
+    "kv_put",
+    "kv_delete",
+    "register_expression",
+    "register_composition",
+    "set_context",
+];
+
+/// Callers whose requests wait in the batch queue unless they ask otherwise.
+const BATCH_TOOLS: &[&str] = &["evaluate_batch", "pipe"];
+
+/// The next session's ID.
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(1);
+
+/// The conditions of every policy set, labelled with their set and rule.
+fn policy_conditions(policies: &BTreeMap<String, PolicySet>) -> Vec<(String, String)> {
+    policies
+        .iter()
+        .flat_map(|(name, policy)| {
+            policy
+                .labelled_conditions()
+                .into_iter()
+                .map(move |(label, condition)| (format!("policy `{}` {}", name, label), condition))
+        })
+        .collect()
+}
+
+/// Whether two serialized decisions agree on the effect and the deciding rule.
+fn same_decision(a: &Value, b: &Value) -> bool {
+    a["decision"] == b["decision"] && a["rule"] == b["rule"]
+}
+
+/// The `data` attached to every error response, so clients can quote the request ID.
+fn error_data(request_id: &str) -> Option<Value> {
+    Some(serde_json::json!({ "request_id": request_id }))
+}
+
+/// Per-request settings overriding the server's.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Overrides {
+    /// How NaN and ±Infinity results are written.
+    pub(crate) non_finite: Option<NonFinite>,
+    /// Lowers the server's cost limit.
+    pub(crate) cost_limit: Option<u64>,
+    /// Whether selecting a missing map key is an error.
+    pub(crate) strict_attributes: Option<bool>,
+    /// The configured environment to evaluate in.
+    pub(crate) environment: Option<String>,
+    /// The queue to wait in; by default batch items wait in the batch queue.
+    pub(crate) priority: Option<Priority>,
+    /// Evaluates even if the result is cached, and doesn't cache it.
+    pub(crate) bypass_cache: bool,
+}
+
+/// How many items of a job are in flight at once, and whether results keep the items' order.
+#[derive(Debug, Clone, Copy)]
+struct Parallelism {
+    degree: usize,
+    ordered: bool,
+}
+
+impl Default for Parallelism {
+    fn default() -> Self {
+        Self {
+            degree: 1,
+            ordered: true,
         }
     }
+}
 
-    let prog = Program::compile(expression).map_err(|e| format!("CEL compile error: {}", e))?;
+/// The most items of one job kept in flight at once.
+pub const MAX_PARALLELISM: usize = 32;
 
-    let result = prog
-        .execute(&ctx)
-        .map_err(|e| format!("CEL execution error: {}", e))?;
+/// Items between progress reports of a job.
+const PROGRESS_CHUNK: usize = 1000;
 
-    Ok(CelJsonValue(result).into())
+/// Logs how far `job` is after every chunk of items and at its end.
+fn report_progress(job: &Job, done: usize, total: usize) {
+    if done.is_multiple_of(PROGRESS_CHUNK) || done == total {
+        tracing::info!("Job {} progress: {}/{} items", job.id(), done, total);
+    }
 }
 
-pub type EvalResponse = Result<Value, String>;
+/// What a background job does.
+enum JobWork {
+    /// Evaluates an expression against each context.
+    Batch(EvaluateBatchParams, Parallelism),
+    /// Records results computed already, as by the vectorized path of `evaluate_dataset`.
+    #[cfg(feature = "arrow")]
+    Done {
+        job_id: Option<String>,
+        items: Vec<BatchItem>,
+    },
+}
 
-#[derive(Debug)]
-pub struct EvalRequest {
-    pub expression: String,
-    pub context: Value,
-    pub responder: oneshot::Sender<EvalResponse>,
+/// Assembles a `CelTool` together with its evaluator task.
+#[derive(Default)]
+pub struct CelToolBuilder {
+    profile: Profile,
+    audit: Option<Arc<AuditLog>>,
+    redactor: Redactor,
+    private_errors: bool,
+    repair_suggestions: bool,
+    slow_eval_threshold: Option<Duration>,
+    slow_eval_audit: bool,
+    evaluator: Evaluator,
+    limits: Limits,
+    policies: BTreeMap<String, PolicySet>,
+    decision_history: Option<usize>,
+    job_limits: Option<JobLimits>,
+    settings_loader: Option<SettingsLoader>,
+    extensions: Vec<String>,
+    disabled_macros: BTreeSet<Macro>,
+    fail_on_compile_error: bool,
+    read_only: bool,
+    environments: BTreeMap<String, Environment>,
+    error_messages: ErrorMessages,
+    rewriter: Rewriter,
+    deny_list: DenyList,
+    quota: Quota,
+    result_cache: Option<Arc<ResultCache>>,
+    kv_store: Option<Arc<KvStore>>,
+    #[cfg(feature = "arrow")]
+    dataset_dir: Option<PathBuf>,
+    #[cfg(feature = "object-store")]
+    object_store: Option<Arc<ObjectStore>>,
 }
 
-pub async fn evaluator_service(mut receiver: mpsc::Receiver<EvalRequest>) {
-    while let Some(request) = receiver.recv().await {
-        let response = real_evaluate(&request.expression, &request.context);
-        if request.responder.send(response).is_err() {
-            eprintln!("Failed to send evaluation response");
+impl CelToolBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_audit(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    pub fn with_private_errors(mut self, private_errors: bool) -> Self {
+        self.private_errors = private_errors;
+        self
+    }
+
+    pub fn with_repair_suggestions(mut self, repair_suggestions: bool) -> Self {
+        self.repair_suggestions = repair_suggestions;
+        self
+    }
+
+    pub fn with_slow_eval_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_eval_threshold = Some(threshold);
+        self
+    }
+
+    pub fn with_slow_eval_audit(mut self, slow_eval_audit: bool) -> Self {
+        self.slow_eval_audit = slow_eval_audit;
+        self
+    }
+
+    /// Adds functions available to every expression.
+    pub fn with_function_provider(mut self, provider: impl FunctionProvider + 'static) -> Self {
+        self.evaluator = self.evaluator.with_function_provider(provider);
+        self
+    }
+
+    /// Adds a resolver consulted, in registration order, for variables missing from the context.
+    pub fn with_variable_resolver(mut self, resolver: impl VariableResolver + 'static) -> Self {
+        self.evaluator = self.evaluator.with_variable_resolver(resolver);
+        self
+    }
+
+    /// Evaluates up to `workers` requests at once; see [`Evaluator::with_workers`].
+    pub fn with_eval_workers(mut self, workers: usize) -> Self {
+        self.evaluator = self.evaluator.with_workers(workers);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.evaluator = self.evaluator.with_limits(limits);
+        self.limits = limits;
+        self
+    }
+
+    /// Adds variables to every evaluation; request context variables of the same name win.
+    pub fn with_base_context(mut self, variables: &Map<String, Value>) -> Self {
+        self.evaluator = self.evaluator.with_base_context(variables);
+        self
+    }
+
+    /// Sets how results are written as JSON.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.evaluator = self.evaluator.with_encoding(encoding);
+        self
+    }
+
+    /// Sets how numbers in contexts become CEL numbers.
+    pub fn with_json_numbers(mut self, numbers: JsonNumbers) -> Self {
+        self.evaluator = self.evaluator.with_json_numbers(numbers);
+        self
+    }
+
+    /// Makes selecting a key missing from a map give `null` unless a request asks otherwise.
+    pub fn with_strict_attributes(mut self, strict: bool) -> Self {
+        self.evaluator = self.evaluator.with_strict_attributes(strict);
+        self
+    }
+
+    /// Rejects expressions using any of `macros` (e.g. comprehensions, for untrusted clients).
+    pub fn with_disabled_macros(mut self, macros: impl IntoIterator<Item = Macro>) -> Self {
+        let macros: Vec<Macro> = macros.into_iter().collect();
+        self.evaluator = self.evaluator.with_disabled_macros(macros.iter().copied());
+        self.disabled_macros.extend(macros);
+        self
+    }
+
+    /// Sets how many compiled programs the evaluator keeps; `0` disables caching.
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.evaluator = self.evaluator.with_cache_capacity(capacity);
+        self
+    }
+
+    /// Compiles into `cache`, which other evaluators may share.
+    pub fn with_program_cache(mut self, cache: Arc<ProgramCache>) -> Self {
+        self.evaluator = self.evaluator.with_program_cache(cache);
+        self
+    }
+
+    /// Lets `evaluate_dataset` read files under `dir`.
+    #[cfg(feature = "arrow")]
+    pub fn with_dataset_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dataset_dir = Some(dir.into());
+        self
+    }
+
+    /// Lets requests read allowlisted bucket objects; see [`CelTool::with_object_store`].
+    #[cfg(feature = "object-store")]
+    pub fn with_object_store(mut self, store: Arc<ObjectStore>) -> Self {
+        self.object_store = Some(store);
+        self
+    }
+
+    /// Gives expressions `kv.get(key)` and `kv.exists(key)`, reading `store`, and adds the
+    /// `kv_put`, `kv_delete` and `kv_list` admin tools changing it.
+    pub fn with_kv_store(mut self, store: Arc<KvStore>) -> Self {
+        self.evaluator = self.evaluator.with_function_provider(store.clone());
+        self.extensions.push(
+            "kv.get(key) returns a value of the server's key-value store (null when absent); kv.exists(key) tells whether it is set"
+                .to_string(),
+        );
+        self.kv_store = Some(store);
+        self
+    }
+
+    /// Caches evaluation results; see [`CelTool::with_result_cache`].
+    pub fn with_result_cache(mut self, cache: Arc<ResultCache>) -> Self {
+        self.result_cache = Some(cache);
+        self
+    }
+
+    /// Keeps the last `capacity` `decide` requests of each policy set for the `replay` tool.
+    pub fn with_decision_history(mut self, capacity: usize) -> Self {
+        self.decision_history = Some(capacity);
+        self
+    }
+
+    /// Bounds concurrent `submit_job` jobs, their retention and the memory their results use.
+    pub fn with_job_limits(mut self, limits: JobLimits) -> Self {
+        self.job_limits = Some(limits);
+        self
+    }
+
+    /// Enables the `reload` tool and [`CelTool::reload`], which replace the base context,
+    /// variable resolvers and policy sets with what `loader` returns.
+    pub fn with_settings_loader(mut self, loader: SettingsLoader) -> Self {
+        self.settings_loader = Some(loader);
+        self
+    }
+
+    /// Mentions an extension (e.g. plugin functions) in the server instructions.
+    pub fn with_extension_description(mut self, description: impl Into<String>) -> Self {
+        self.extensions.push(description.into());
+        self
+    }
+
+    /// Registers a policy set for the `decide` tool under `name`.
+    pub fn with_policy_set(mut self, name: impl Into<String>, policy: PolicySet) -> Self {
+        self.policies.insert(name.into(), policy);
+        self
+    }
+
+    /// Makes [`CelTool::reload`] reject settings whose policy conditions fail to compile.
+    pub fn with_fail_on_compile_error(mut self, fail: bool) -> Self {
+        self.fail_on_compile_error = fail;
+        self
+    }
+
+    /// Registers an environment requests can select as `environment: name`.
+    pub fn with_environment(mut self, name: impl Into<String>, environment: Environment) -> Self {
+        self.environments.insert(name.into(), environment);
+        self
+    }
+
+    /// Rewords the error messages of evaluations; see [`CelTool::with_error_messages`].
+    pub fn with_error_messages(mut self, messages: ErrorMessages) -> Self {
+        self.error_messages = messages;
+        self
+    }
+
+    /// Rewrites expressions before compiling them; see [`CelTool::with_rewriter`].
+    pub fn with_rewriter(mut self, rewriter: Rewriter) -> Self {
+        self.rewriter = rewriter;
+        self
+    }
+
+    /// Rejects expressions using banned identifiers or functions; see
+    /// [`CelTool::with_deny_list`].
+    pub fn with_deny_list(mut self, deny_list: DenyList) -> Self {
+        self.deny_list = deny_list;
+        self
+    }
+
+    /// Limits what each account may use; see [`CelTool::with_quota`].
+    pub fn with_quota(mut self, quota: Quota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Disables the tools that change server state; see [`CelTool::with_read_only`].
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Compiles the conditions of the registered policy sets and keeps their programs in the
+    /// cache. Call it after configuring the evaluator. Returns the number compiled, or every
+    /// compile error.
+    pub fn warm_up(&self) -> Result<usize, Vec<String>> {
+        self.evaluator.warm_up(&policy_conditions(&self.policies))
+    }
+
+    /// Spawns the evaluator on the current Tokio runtime and returns a tool connected to it.
+    /// Clones of the returned tool share the same evaluator.
+    pub fn build(self) -> CelTool {
+        let (tx, rx) = mpsc::channel(32);
+        let evaluator = self.evaluator;
+        let reloader = evaluator.reloader();
+        tokio::spawn(evaluator.serve(rx));
+        let tool = CelTool::new(tx)
+            .with_profile(self.profile)
+            .with_limits(self.limits)
+            .with_redactor(self.redactor)
+            .with_private_errors(self.private_errors)
+            .with_repair_suggestions(self.repair_suggestions)
+            .with_slow_eval_audit(self.slow_eval_audit)
+            .with_policies(self.policies)
+            .with_disabled_macros(self.disabled_macros)
+            .with_fail_on_compile_error(self.fail_on_compile_error)
+            .with_environments(self.environments)
+            .with_error_messages(self.error_messages)
+            .with_rewriter(self.rewriter)
+            .with_deny_list(self.deny_list)
+            .with_quota(self.quota)
+            .with_evaluator(reloader.clone());
+        let tool = self
+            .extensions
+            .into_iter()
+            .fold(tool, CelTool::with_extension_description);
+        let tool = match self.slow_eval_threshold {
+            Some(threshold) => tool.with_slow_eval_threshold(threshold),
+            None => tool,
+        };
+        let tool = match self.result_cache {
+            Some(cache) => tool.with_result_cache(cache),
+            None => tool,
+        };
+        let tool = CelTool {
+            kv_store: self.kv_store,
+            ..tool
+        };
+        #[cfg(feature = "arrow")]
+        let tool = match self.dataset_dir {
+            Some(dir) => tool.with_dataset_dir(dir),
+            None => tool,
+        };
+        #[cfg(feature = "object-store")]
+        let tool = match self.object_store {
+            Some(store) => tool.with_object_store(store),
+            None => tool,
+        };
+        let tool = match self.decision_history {
+            Some(capacity) => tool.with_decision_history(capacity),
+            None => tool,
+        };
+        let tool = match self.job_limits {
+            Some(limits) => tool.with_job_limits(limits),
+            None => tool,
+        };
+        let tool = match self.settings_loader {
+            Some(loader) => tool.with_reloader(loader, reloader),
+            None => tool,
+        };
+        let tool = match self.audit {
+            Some(audit) => tool.with_audit(audit),
+            None => tool,
+        };
+        if self.read_only {
+            tool.with_read_only()
+        } else {
+            tool
         }
     }
 }
 
+type ClientLogging = (LoggingLevel, Peer<RoleServer>);
+
 #[derive(Clone)]
 pub struct CelTool {
     pub eval_tx: mpsc::Sender<EvalRequest>,
+    profile: Profile,
+    audit: Option<Arc<AuditLog>>,
+    limits: Limits,
+    redactor: Arc<Redactor>,
+    private_errors: bool,
+    repair_suggestions: bool,
+    slow_eval_threshold: Option<Duration>,
+    slow_eval_audit: bool,
+    policies: Reloadable<BTreeMap<String, PolicySet>>,
+    decision_history: Option<Arc<DecisionHistory>>,
+    jobs: Arc<JobRegistry>,
+    /// Expressions registered with `register_expression`.
+    expressions: Arc<ExpressionRegistry>,
+    settings_loader: Option<SettingsLoader>,
+    evaluator: Option<EvaluatorReloader>,
+    extensions: Vec<String>,
+    disabled_macros: BTreeSet<Macro>,
+    fail_on_compile_error: bool,
+    read_only: bool,
+    environments: Arc<BTreeMap<String, Environment>>,
+    error_messages: Arc<ErrorMessages>,
+    rewriter: Arc<Rewriter>,
+    deny_list: Arc<DenyList>,
+    /// Usage of every account, checked before each evaluation.
+    quotas: Arc<Quotas>,
+    /// The account evaluations are charged to; the session's own when unset.
+    account: Option<Account>,
+    result_cache: Arc<ResultCache>,
+    /// Read by `kv.get` and `kv.exists`, changed by the `kv_*` admin tools.
+    kv_store: Option<Arc<KvStore>>,
+    /// Where `evaluate_dataset` reads `path` from.
+    #[cfg(feature = "arrow")]
+    dataset_dir: Option<PathBuf>,
+    /// Where `context_uri` and `evaluate_dataset`'s `s3://` and `gs://` paths are read from.
+    #[cfg(feature = "object-store")]
+    object_store: Option<Arc<ObjectStore>>,
+    /// The level a client asked for with `logging/setLevel`, and where to send its logs.
+    client_logging: Arc<Mutex<Option<ClientLogging>>>,
+    /// Variables set with `set_context`, under every request context of the session.
+    session_context: Arc<Mutex<Map<String, Value>>>,
+    /// Identifies the session's requests to the evaluator, which takes turns between sessions.
+    session: u64,
     tool_router: ToolRouter<Self>,
 }
 
@@ -103,116 +652,4900 @@ pub struct CelTool {
 struct EvaluateParams {
     expression: String,
     context: Map<String, Value>,
+    /// An `s3://` or `gs://` JSON object to evaluate against, combined with `context`, whose
+    /// keys take precedence. Only objects the server allows can be read.
+    #[cfg(feature = "object-store")]
+    #[serde(default)]
+    context_uri: Option<String>,
+    /// How `context` combines with the session's context (see `set_context`), then with the
+    /// server's base context: `replace` (default), `deep`, or `merge_patch` (RFC 7386; `null`
+    /// deletes a key or unsets a variable).
+    #[serde(default)]
+    merge_strategy: MergeStrategy,
+    /// How NaN and ±Infinity results are written: `null`, `string` (`"NaN"`, `"Infinity"`,
+    /// `"-Infinity"`) or `error`. Defaults to the server's setting.
+    #[serde(default)]
+    non_finite: Option<NonFinite>,
+    /// Aborts the evaluation once it costs more than this (1 per expression node visited and
+    /// per list element or 8 bytes built); the server's limit still applies.
+    #[serde(default)]
+    cost_limit: Option<u64>,
+    /// `false` makes selecting a key missing from a map (`m.missing`) give `null` instead of
+    /// an error. Defaults to the server's setting.
+    #[serde(default)]
+    strict_attributes: Option<bool>,
+    /// A configured environment (its profile, base context and limits) to evaluate in;
+    /// the server's defaults when absent.
+    #[serde(default)]
+    environment: Option<String>,
+    /// `interactive` (default) or `batch`: batch requests wait while interactive ones are
+    /// answered, but get a turn after every few.
+    #[serde(default)]
+    priority: Option<Priority>,
+    /// Evaluates even if the server has cached the result of an identical request.
+    #[serde(default)]
+    bypass_cache: bool,
+    /// For a list result: index of the first element to return. Evaluate again with the
+    /// returned `next_offset` for the next page.
+    #[serde(default)]
+    offset: Option<usize>,
+    /// For a list result: elements to return at most.
+    #[serde(default)]
+    limit: Option<usize>,
+    /// Checks and compiles the expression without evaluating it, returning a `dry_run`
+    /// summary instead of a `result`.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct EvaluateProjectionParams {
+    /// Output names mapped to the CEL expression computing each.
+    outputs: BTreeMap<String, String>,
+    context: Map<String, Value>,
+    /// How `context` combines with the server's base context, as for `evaluate`.
+    #[serde(default)]
+    merge_strategy: MergeStrategy,
+    /// How NaN and ±Infinity results are written, as for `evaluate`.
+    #[serde(default)]
+    non_finite: Option<NonFinite>,
+    /// Cost limit of the evaluation, as for `evaluate`.
+    #[serde(default)]
+    cost_limit: Option<u64>,
+    /// Whether selecting a missing map key is an error, as for `evaluate`.
+    #[serde(default)]
+    strict_attributes: Option<bool>,
+    /// A configured environment to evaluate in, as for `evaluate`.
+    #[serde(default)]
+    environment: Option<String>,
+    /// The queue to wait in, as for `evaluate`.
+    #[serde(default)]
+    priority: Option<Priority>,
+    /// Skips the result cache, as for `evaluate`.
+    #[serde(default)]
+    bypass_cache: bool,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct RenderTemplateParams {
+    /// Text with `${expression}` placeholders; `$${` yields a literal `${`.
+    template: String,
+    context: Map<String, Value>,
+    /// Escaping applied to each substituted value: `none` (default), `html`, `json`, `shell`.
+    #[serde(default)]
+    escape: Escape,
 }
 
 #[derive(Serialize, JsonSchema)]
 #[schemars(crate = "rmcp::schemars")]
 #[serde(crate = "rmcp::serde")]
-struct EvaluateResult {
-    result: String,
+struct RenderTemplateResult {
+    rendered: String,
 }
 
-#[tool_router]
-impl CelTool {
-    pub fn new(eval_tx: mpsc::Sender<EvalRequest>) -> Self {
-        Self {
-            eval_tx,
-            tool_router: Self::tool_router(),
-        }
-    }
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct RegisterExpressionParams {
+    name: String,
+    /// A CEL expression, with a `{parameter}` placeholder wherever an argument goes.
+    expression: String,
+    /// The CEL type of each parameter, such as `int` or `list<string>`, by name.
+    #[serde(default)]
+    parameters: BTreeMap<String, String>,
+    #[serde(default)]
+    description: Option<String>,
+}
 
-    #[tool(description = "Evaluates a Common Expression Language (CEL) expression.")]
-    async fn evaluate(
-        &self,
-        params: Parameters<EvaluateParams>,
-    ) -> Result<Json<EvaluateResult>, ErrorData> {
-        tracing::info!(
-            "CelTool::evaluate called with expression: {:?}",
-            params.0.expression
-        );
-        let (responder, receiver) = oneshot::channel();
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct SetContextParams {
+    context: Map<String, Value>,
+    /// How `context` combines with the session's context: `replace` (default) replaces each
+    /// variable it sets, `deep` merges objects, and `merge_patch` also unsets a variable or
+    /// deletes a key set to `null`.
+    #[serde(default)]
+    merge_strategy: MergeStrategy,
+}
 
-        let request = EvalRequest {
-            expression: params.0.expression,
-            context: Value::Object(params.0.context),
-            responder,
-        };
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct SessionContext {
+    /// The names of the variables now in the session's context.
+    variables: Vec<String>,
+}
 
-        if self.eval_tx.send(request).await.is_err() {
-            tracing::error!("Failed to send evaluation request to service, service is down.");
-            return Err(ErrorData::internal_error("Evaluator service is down", None));
-        }
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct RegisterExpressionResult {
+    name: String,
+    /// Whether an expression registered before under `name` was replaced.
+    replaced: bool,
+}
 
-        match receiver.await {
-            Ok(Ok(value)) => {
-                tracing::info!("Evaluation successful, returning result.");
-                Ok(Json(EvaluateResult {
-                    result: serde_json::to_string(&value).unwrap_or_else(|_| value.to_string()),
-                }))
-            }
-            Ok(Err(e)) => {
-                tracing::error!("Evaluation failed: {}", e);
-                Err(ErrorData::internal_error(e, None))
-            }
-            Err(_) => {
-                tracing::error!("Failed to receive response from evaluator service.");
-                Err(ErrorData::internal_error(
-                    "Failed to receive response from evaluator",
-                    None,
-                ))
-            }
-        }
-    }
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct RegisteredExpression {
+    name: String,
+    expression: String,
+    /// The CEL type of each parameter, by name.
+    parameters: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
 }
 
-#[tool_handler]
-impl ServerHandler for CelTool {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            protocol_version: Default::default(),
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
-            server_info: Implementation::from_build_env(),
-            instructions: Some("This server provides a single tool to evaluate Common Expression Language (CEL) expressions.".into()),
-        }
-    }
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct RegisteredExpressions {
+    expressions: Vec<RegisteredExpression>,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rmcp::serde_json::json;
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct ComposeParams {
+    /// Registered expressions combined with `and`, `or` and `not`, such as
+    /// `{"and": [{"expression": "a"}, {"not": {"expression": "b"}}]}`.
+    composition: Composition,
+}
 
-    #[test]
-    fn test_real_evaluate_addition() {
-        let context = json!({});
-        let response = real_evaluate("1+2", &context);
-        assert_eq!(response, Ok(Value::from(3)));
-    }
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct ComposeResult {
+    /// The composed expression, with the placeholders of the expressions it combines.
+    expression: String,
+    /// The CEL type of each parameter, by name.
+    parameters: BTreeMap<String, String>,
+}
 
-    #[test]
-    fn test_real_evaluate_with_context() {
-        let context = json!({
-            "a": 5,
-            "b": 10
-        });
-        let response = real_evaluate("a * b", &context);
-        assert_eq!(response, Ok(Value::from(50)));
-    }
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct RegisterCompositionParams {
+    name: String,
+    /// Registered expressions combined as for `compose`.
+    composition: Composition,
+    #[serde(default)]
+    description: Option<String>,
+}
 
-    #[test]
-    fn test_real_evaluate_string_concat() {
-        let context = json!({
-            "name": "World"
-        });
-        let response = real_evaluate("'Hello, ' + name", &context);
-        assert_eq!(response, Ok(Value::from("Hello, World")));
-    }
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct EvaluateRegisteredParams {
+    /// Name of an expression registered with `register_expression`.
+    name: String,
+    /// A value of the declared type for each of the expression's parameters.
+    #[serde(default)]
+    arguments: Map<String, Value>,
+    context: Map<String, Value>,
+    /// How `context` combines with the server's base context, as for `evaluate`.
+    #[serde(default)]
+    merge_strategy: MergeStrategy,
+    /// A configured environment to evaluate in, as for `evaluate`.
+    #[serde(default)]
+    environment: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct QuoteValueParams {
+    value: Value,
+    /// The CEL type to quote `value` as, such as `uint`, `bytes`, `timestamp` or
+    /// `map<int, string>`; `dyn` (default) follows the JSON type.
+    #[serde(default, rename = "type")]
+    declared: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct QuoteValueResult {
+    literal: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct DecideParams {
+    /// Name of a policy set configured on the server.
+    policy: String,
+    context: Map<String, Value>,
+    /// How `context` combines with the server's base context, as for `evaluate`.
+    #[serde(default)]
+    merge_strategy: MergeStrategy,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct DecideResult {
+    decision: Effect,
+    /// The first rule whose condition was true; absent when the default effect applied.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule: Option<String>,
+    /// The deciding rule's rendered message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    /// Every matching rule, deciding rule first; only for the `collect_all` strategy.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    matches: Vec<RuleMatch>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct RuleMatch {
+    rule: String,
+    effect: Effect,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct ReplayParams {
+    /// Name of a configured policy set whose recorded requests are replayed.
+    policy: String,
+    /// The new version of the policy set.
+    candidate: PolicySet,
+    /// Replay only the last `last` recorded requests; all of them by default.
+    #[serde(default)]
+    last: Option<usize>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct ReplayResult {
+    replayed: usize,
+    /// How many decisions would change (effect or deciding rule), including candidate errors.
+    changed: usize,
+    changes: Vec<ReplayChange>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct KvPutParams {
+    key: String,
+    /// Any JSON value; `kv.get` returns it as the matching CEL value.
+    value: Value,
+}
 
-    #[test]
-    fn test_real_evaluate_compilation_error() {
-        let context = json!({});
-        let response = real_evaluate("1 +/ 2", &context);
-        assert!(response.is_err());
-        assert!(response.unwrap_err().contains("compile"));
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct KvKeyParams {
+    key: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct KvListParams {
+    /// Lists only keys starting with this.
+    #[serde(default)]
+    prefix: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct KvChange {
+    key: String,
+    /// Whether the key was set before the change.
+    existed: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct KvKeys {
+    keys: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct CacheStats {
+    /// Compiled programs held by the program cache.
+    programs: usize,
+    result_cache: ResultCacheStats,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct ReplayChange {
+    /// Position among the replayed requests, oldest first.
+    index: usize,
+    recorded: Value,
+    /// The candidate's decision, or `{"error": message}`.
+    candidate: Value,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct EvaluateResult {
+    /// The JSON-encoded value; absent for a dry run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<String>,
+    /// Elements of the whole list result, when it was paginated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<usize>,
+    /// Where the next page starts, when the paginated list has more elements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<usize>,
+    /// Present when the result exceeded the server's size limit and was cut.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    truncation: Option<Truncation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dry_run: Option<DryRun>,
+    /// The expression as the server's rewrite rules changed it, if they did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rewritten_expression: Option<String>,
+}
+
+/// What `evaluate` with `dry_run` learns about an expression without running it.
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct DryRun {
+    /// Whether the expression passed the server's checks and compiled.
+    compiled: bool,
+    /// Why it didn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The variables the expression reads, without those its macros bind.
+    variables: Vec<String>,
+    /// The cost of visiting each of its nodes once: what it costs when no macro loops. Each
+    /// macro iteration and each value built adds to it.
+    estimated_cost: u64,
+    /// Whether its compiled program was cached, so evaluating it skips compilation.
+    cached: bool,
+}
+
+impl EvaluateResult {
+    /// Encodes `value`, truncated to `max_bytes` of JSON if given.
+    fn new(value: Value, max_bytes: Option<usize>) -> Self {
+        let (value, truncation) = match max_bytes {
+            Some(max_bytes) => truncate(value, max_bytes),
+            None => (value, None),
+        };
+        Self {
+            result: Some(serde_json::to_string(&value).unwrap_or_else(|_| value.to_string())),
+            total: None,
+            next_offset: None,
+            truncation,
+            dry_run: None,
+            rewritten_expression: None,
+        }
+    }
+
+    /// Returns up to `limit` elements of the list `value` from `offset` on.
+    fn page(
+        value: Value,
+        offset: usize,
+        limit: Option<usize>,
+        max_bytes: Option<usize>,
+    ) -> Result<Self, String> {
+        let Value::Array(items) = value else {
+            return Err("`offset` and `limit` apply to list results only".to_string());
+        };
+        let total = items.len();
+        let offset = offset.min(total);
+        let page: Vec<Value> = items
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+        let end = offset + page.len();
+        Ok(Self {
+            total: Some(total),
+            next_offset: (end < total).then_some(end),
+            ..Self::new(Value::Array(page), max_bytes)
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct EvaluateBatchParams {
+    expression: String,
+    /// One context per item; the expression is evaluated against each in order.
+    contexts: Vec<Map<String, Value>>,
+    /// How each context combines with the server's base context, as for `evaluate`.
+    #[serde(default)]
+    merge_strategy: MergeStrategy,
+    /// How NaN and ±Infinity results are written, as for `evaluate`.
+    #[serde(default)]
+    non_finite: Option<NonFinite>,
+    /// Cost limit of each item's evaluation, as for `evaluate`.
+    #[serde(default)]
+    cost_limit: Option<u64>,
+    /// Whether selecting a missing map key is an error, as for `evaluate`.
+    #[serde(default)]
+    strict_attributes: Option<bool>,
+    /// A configured environment to evaluate in, as for `evaluate`.
+    #[serde(default)]
+    environment: Option<String>,
+    /// The queue to wait in, as for `evaluate`.
+    #[serde(default)]
+    priority: Option<Priority>,
+    /// Skips the result cache, as for `evaluate`.
+    #[serde(default)]
+    bypass_cache: bool,
+    /// ID for `job_status` and `cancel_job`; generated when absent.
+    #[serde(default)]
+    job_id: Option<String>,
+}
+
+#[cfg(feature = "arrow")]
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct EvaluateDatasetParams {
+    /// Evaluated once per row, with each column as a variable.
+    expression: String,
+    /// A Parquet or Arrow IPC file, relative to the server's dataset directory, or an allowed
+    /// `s3://` or `gs://` object when the server reads from buckets.
+    #[serde(default)]
+    path: Option<String>,
+    /// The dataset itself, base64-encoded, instead of `path`.
+    #[serde(default)]
+    data: Option<String>,
+    /// `parquet` or `arrow_ipc`; detected from the content when absent.
+    #[serde(default)]
+    format: Option<DatasetFormat>,
+    /// The columns to expose as variables; all of them when absent.
+    #[serde(default)]
+    columns: Option<Vec<String>>,
+    /// How each row combines with the server's base context, as for `evaluate`.
+    #[serde(default)]
+    merge_strategy: MergeStrategy,
+    /// How NaN and ±Infinity results are written, as for `evaluate`.
+    #[serde(default)]
+    non_finite: Option<NonFinite>,
+    /// Cost limit of each row's evaluation, as for `evaluate`.
+    #[serde(default)]
+    cost_limit: Option<u64>,
+    /// Whether selecting a missing map key is an error, as for `evaluate`.
+    #[serde(default)]
+    strict_attributes: Option<bool>,
+    /// A configured environment to evaluate in, as for `evaluate`.
+    #[serde(default)]
+    environment: Option<String>,
+    /// The queue to wait in, as for `evaluate`.
+    #[serde(default)]
+    priority: Option<Priority>,
+    /// Skips the result cache, as for `evaluate`.
+    #[serde(default)]
+    bypass_cache: bool,
+    /// Rows in flight at once, from 1 to 32; 1 when absent. They share the evaluator's
+    /// workers with every other request.
+    #[serde(default)]
+    parallelism: Option<usize>,
+    /// Records each row's result as it completes, with the row's `index`, instead of in row
+    /// order.
+    #[serde(default)]
+    unordered: bool,
+    /// ID for `job_status`, `cancel_job` and `get_job_result`; generated when absent.
+    #[serde(default)]
+    job_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct EvaluateBatchResult {
+    job_id: String,
+    /// `completed`, `cancelled` when the job was stopped early, or for `get_job_result` of an
+    /// unfinished job `queued`, `running` or `cancelling`.
+    state: JobState,
+    /// One entry per evaluated item, in order; items after a cancellation are absent.
+    results: Vec<BatchItem>,
+    /// Where the next page of `get_job_result` starts, when more results are recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<usize>,
+}
+
+/// Results per `get_job_result` page unless the request sets `limit`.
+const DEFAULT_RESULT_PAGE: usize = 1000;
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct JobResultParams {
+    job_id: String,
+    /// Index of the first result to return.
+    #[serde(default)]
+    offset: usize,
+    /// Results to return at most; defaults to 1000.
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct JobParams {
+    job_id: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct DescribeAstParams {
+    expression: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct DescribeAstResult {
+    /// The macros the expression uses.
+    macros: BTreeSet<Macro>,
+    /// The parsed expression with macros expanded; each expansion carries a `macro` key.
+    ast: Value,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct ExpressionFingerprintParams {
+    expression: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct ExpressionFingerprintResult {
+    /// A 64-bit hash of the syntax tree in hex, unchanged by whitespace, comments and
+    /// redundant parentheses.
+    fingerprint: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct EvaluateEnvoyParams {
+    expression: String,
+    /// An ext_authz `CheckRequest` in proto3 JSON form.
+    check_request: Value,
+    /// Extra variables layered over the attributes derived from `check_request`.
+    #[serde(default)]
+    context: Map<String, Value>,
+}
+
+#[tool_router]
+impl CelTool {
+    pub fn new(eval_tx: mpsc::Sender<EvalRequest>) -> Self {
+        let tool = Self {
+            eval_tx,
+            profile: Profile::Default,
+            audit: None,
+            limits: Limits::default(),
+            redactor: Arc::new(Redactor::default()),
+            private_errors: false,
+            repair_suggestions: false,
+            slow_eval_threshold: None,
+            slow_eval_audit: false,
+            policies: Reloadable::default(),
+            decision_history: None,
+            jobs: Arc::default(),
+            expressions: Arc::default(),
+            settings_loader: None,
+            evaluator: None,
+            extensions: Vec::new(),
+            disabled_macros: BTreeSet::new(),
+            fail_on_compile_error: false,
+            read_only: false,
+            environments: Arc::default(),
+            error_messages: Arc::default(),
+            rewriter: Arc::default(),
+            deny_list: Arc::default(),
+            quotas: Arc::default(),
+            account: None,
+            result_cache: Arc::default(),
+            kv_store: None,
+            #[cfg(feature = "arrow")]
+            dataset_dir: None,
+            #[cfg(feature = "object-store")]
+            object_store: None,
+            client_logging: Arc::default(),
+            session_context: Arc::default(),
+            session: 0,
+            tool_router: Self::tool_router(),
+        };
+        #[cfg(feature = "arrow")]
+        let tool = {
+            let mut tool = tool;
+            tool.tool_router.merge(Self::dataset_tool_router());
+            tool
+        };
+        tool
+    }
+
+    pub fn builder() -> CelToolBuilder {
+        CelToolBuilder::new()
+    }
+
+    /// A clone for serving one MCP session: it shares everything but the session's state, such
+    /// as the level set with `logging/setLevel` and the context set with `set_context`.
+    pub fn session(&self) -> Self {
+        Self {
+            client_logging: Arc::default(),
+            session_context: Arc::default(),
+            session: NEXT_SESSION.fetch_add(1, Ordering::Relaxed),
+            ..self.clone()
+        }
+    }
+
+    /// Records every evaluation to the given audit log.
+    pub fn with_audit(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Masks sensitive context values echoed in error messages and logs.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Arc::new(redactor);
+        self
+    }
+
+    /// Keeps expressions and context fragments out of client-visible error messages, returning
+    /// an error ID that matches the `error_id` of the server log entry instead.
+    pub fn with_private_errors(mut self, private_errors: bool) -> Self {
+        self.private_errors = private_errors;
+        self
+    }
+
+    /// When `evaluate` gets an expression that fails to compile from a client that supports
+    /// sampling, asks the client's model for a corrected one and returns it in the error's data
+    /// as `suggestion`.
+    pub fn with_repair_suggestions(mut self, repair_suggestions: bool) -> Self {
+        self.repair_suggestions = repair_suggestions;
+        self
+    }
+
+    /// Logs every evaluation that takes at least `threshold`, end to end.
+    pub fn with_slow_eval_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_eval_threshold = Some(threshold);
+        self
+    }
+
+    /// Also writes slow evaluations to the audit log, if one is configured.
+    pub fn with_slow_eval_audit(mut self, slow_eval_audit: bool) -> Self {
+        self.slow_eval_audit = slow_eval_audit;
+        self
+    }
+
+    /// Sets the policy sets available to the `decide` tool, by name.
+    pub fn with_policies(mut self, policies: BTreeMap<String, PolicySet>) -> Self {
+        self.policies = Reloadable::new(policies);
+        self
+    }
+
+    /// Lets the server instructions list the base context variables and resolvers of
+    /// `evaluator`, which must be the evaluator behind this tool's channel.
+    pub fn with_evaluator(mut self, evaluator: EvaluatorReloader) -> Self {
+        self.evaluator = Some(evaluator);
+        self
+    }
+
+    /// Enables [`reload`](Self::reload); `evaluator` must belong to the evaluator behind this
+    /// tool's channel.
+    pub fn with_reloader(mut self, loader: SettingsLoader, evaluator: EvaluatorReloader) -> Self {
+        self.settings_loader = Some(loader);
+        self.with_evaluator(evaluator)
+    }
+
+    /// Mentions an extension (e.g. plugin functions) in the server instructions.
+    pub fn with_extension_description(mut self, description: impl Into<String>) -> Self {
+        self.extensions.push(description.into());
+        self
+    }
+
+    /// Mentions `macros` as disabled in the server instructions; the evaluator behind this
+    /// tool's channel must reject them.
+    pub fn with_disabled_macros(mut self, macros: impl IntoIterator<Item = Macro>) -> Self {
+        self.disabled_macros.extend(macros);
+        self
+    }
+
+    /// Sets the environments requests can select by name with `environment`.
+    /// Declared variable types are reflected into the input schemas of the evaluation tools.
+    pub fn with_environments(mut self, environments: BTreeMap<String, Environment>) -> Self {
+        for (tool, field) in [
+            ("evaluate", "context"),
+            ("evaluate_projection", "context"),
+            ("evaluate_batch", "contexts"),
+            ("submit_job", "contexts"),
+        ] {
+            if let Some(route) = self.tool_router.map.get_mut(tool) {
+                let mut schema = (*route.attr.input_schema).clone();
+                describe_environments(&mut schema, field, &environments);
+                route.attr.input_schema = Arc::new(schema);
+            }
+        }
+        self.environments = Arc::new(environments);
+        self
+    }
+
+    /// Rewords the error messages of evaluations by kind, e.g. to translate them.
+    pub fn with_error_messages(mut self, messages: ErrorMessages) -> Self {
+        self.error_messages = Arc::new(messages);
+        self
+    }
+
+    /// Applies `rewriter`'s rules to every expression before it is compiled. `evaluate` reports
+    /// an expression they change as `rewritten_expression`.
+    pub fn with_rewriter(mut self, rewriter: Rewriter) -> Self {
+        self.rewriter = Arc::new(rewriter);
+        self
+    }
+
+    /// Rejects expressions that read one of `deny_list`'s identifiers or call one of its
+    /// functions, before compiling them.
+    pub fn with_deny_list(mut self, deny_list: DenyList) -> Self {
+        self.deny_list = Arc::new(deny_list);
+        self
+    }
+
+    /// Refuses evaluations once their account has used up a limit of `quota`, and tracks what
+    /// every account uses for the `usage` tool and [`quota::router`].
+    pub fn with_quota(mut self, quota: Quota) -> Self {
+        self.quotas = Arc::new(Quotas::new(quota));
+        self
+    }
+
+    /// Charges evaluations to `account`, such as one from [`quota::account`], instead of the
+    /// session.
+    pub fn with_account(mut self, account: Account) -> Self {
+        self.account = Some(account);
+        self
+    }
+
+    /// What every account has used.
+    pub fn quotas(&self) -> Arc<Quotas> {
+        self.quotas.clone()
+    }
+
+    /// Every account's usage and the evaluations that panicked, in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        let mut metrics = self.quotas.metrics();
+        if let Some(evaluator) = &self.evaluator {
+            let name = "cel_mcp_evaluation_panics_total";
+            metrics.push_str(&format!(
+                "# HELP {} Evaluations that panicked and were answered with an error.\n# TYPE {} counter\n{} {}\n",
+                name,
+                name,
+                name,
+                evaluator.panic_count()
+            ));
+        }
+        metrics
+    }
+
+    fn account(&self) -> Account {
+        self.account
+            .clone()
+            .unwrap_or_else(|| Account::new(format!("session-{}", self.session)))
+    }
+
+    /// Lets `evaluate_dataset` read files under `dir`.
+    #[cfg(feature = "arrow")]
+    pub fn with_dataset_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dataset_dir = Some(dir.into());
+        self
+    }
+
+    /// Lets `evaluate` read its context, and `evaluate_dataset` its dataset, from the
+    /// `s3://` and `gs://` objects `store` allows.
+    #[cfg(feature = "object-store")]
+    pub fn with_object_store(mut self, store: Arc<ObjectStore>) -> Self {
+        self.object_store = Some(store);
+        self
+    }
+
+    /// Answers requests repeating an earlier successful one (same query fingerprint, context,
+    /// profile and overrides) from `cache`, unless they set `bypass_cache`. A reload empties
+    /// it.
+    pub fn with_result_cache(mut self, cache: Arc<ResultCache>) -> Self {
+        self.result_cache = cache;
+        self
+    }
+
+    /// Makes [`reload`](Self::reload) reject settings whose policy conditions fail to compile;
+    /// otherwise the errors are logged and the settings installed.
+    pub fn with_fail_on_compile_error(mut self, fail: bool) -> Self {
+        self.fail_on_compile_error = fail;
+        self
+    }
+
+    /// Removes the tools not selected by `tools` from `list_tools` and `call_tool`. Naming a
+    /// tool this server doesn't offer is an error.
+    pub fn with_tool_selection(mut self, tools: &ToolsConfig) -> Result<Self, String> {
+        let names: Vec<String> = self
+            .tool_router
+            .list_all()
+            .into_iter()
+            .map(|tool| tool.name.to_string())
+            .collect();
+        for name in tools.enabled.iter().flatten().chain(&tools.disabled) {
+            if !names.contains(name) {
+                return Err(format!(
+                    "Unknown tool `{}` in tools config (available: {})",
+                    name,
+                    names.join(", ")
+                ));
+            }
+        }
+        for name in &names {
+            let enabled = tools
+                .enabled
+                .as_ref()
+                .is_none_or(|enabled| enabled.contains(name));
+            if !enabled || tools.disabled.contains(name) {
+                self.tool_router.remove_route(name);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Adds the administrative tools (`reload`, `replay`, `cache_stats`, and the `kv_*` tools
+    /// with a key-value store), which change server state or expose other sessions' requests or
+    /// server internals. Serve the result only to operators, never to agent sessions.
+    pub fn with_admin_tools(mut self) -> Self {
+        self.tool_router.merge(Self::admin_tool_router());
+        if self.kv_store.is_some() {
+            self.tool_router.merge(Self::kv_tool_router());
+        }
+        if self.read_only {
+            self.remove_mutating_tools();
+        }
+        self
+    }
+
+    /// Removes the tools that change server state, now and from later
+    /// [`with_admin_tools`](Self::with_admin_tools), and makes [`reload`](Self::reload) fail,
+    /// for deployments that only evaluate what was provisioned at startup.
+    pub fn with_read_only(mut self) -> Self {
+        self.read_only = true;
+        self.remove_mutating_tools();
+        self
+    }
+
+    fn remove_mutating_tools(&mut self) {
+        for name in MUTATING_TOOLS {
+            self.tool_router.remove_route(name);
+        }
+    }
+
+    /// Describes what this deployment offers: tools, pre-declared and base context variables,
+    /// policy sets, extensions and limits. Reflects the settings current at the call.
+    pub fn instructions(&self) -> String {
+        let list = |names: Vec<String>| {
+            names
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut tools: Vec<String> = self
+            .tool_router
+            .list_all()
+            .into_iter()
+            .map(|tool| tool.name.to_string())
+            .collect();
+        tools.sort();
+        let mut lines = vec![
+            "This server evaluates Common Expression Language (CEL) expressions against JSON contexts.".to_string(),
+            format!("Tools: {}.", list(tools)),
+        ];
+        let declared: Vec<String> = self.profile.declared_variables().keys().cloned().collect();
+        if !declared.is_empty() {
+            lines.push(format!(
+                "Pre-declared variables ({:?} profile): {}.",
+                self.profile,
+                list(declared)
+            ));
+        }
+        if let Some(evaluator) = &self.evaluator {
+            let base = evaluator.base_context_names();
+            if !base.is_empty() {
+                lines.push(format!(
+                    "Base context variables, present in every evaluation unless the context sets them: {}.",
+                    list(base)
+                ));
+            }
+            lines.push(format!(
+                "Context numbers: {}. Comparisons work across numeric types; arithmetic needs one type.",
+                evaluator.json_numbers().describe()
+            ));
+            if !evaluator.strict_attributes() {
+                lines.push("Selecting a key missing from a map gives `null` rather than an error, unless a request sets `strict_attributes`.".to_string());
+            }
+            let resolvers = evaluator.resolver_count();
+            if resolvers > 0 {
+                lines.push(format!(
+                    "Variables missing from the context are looked up by {} resolver(s).",
+                    resolvers
+                ));
+            }
+        }
+        let policies: Vec<String> = self.policies.get().keys().cloned().collect();
+        if !policies.is_empty() && self.tool_router.has_route("decide") {
+            lines.push(format!("Policy sets for `decide`: {}.", list(policies)));
+        }
+        if !self.environments.is_empty() {
+            lines.push(format!(
+                "Environments, selected with `environment`: {}.",
+                list(self.environments.keys().cloned().collect())
+            ));
+        }
+        for extension in &self.extensions {
+            lines.push(format!("Extension: {}.", extension));
+        }
+        if self.read_only {
+            lines.push(
+                "Read-only: the policy sets, settings, registered expressions and session context cannot be changed."
+                    .to_string(),
+            );
+        }
+        if let Some(quota) = self.quotas.quota().describe() {
+            lines.push(format!(
+                "Each account may use {}; `usage` reports what it has used.",
+                quota
+            ));
+        }
+        if !self.deny_list.identifiers.is_empty() {
+            lines.push(format!(
+                "Banned identifiers, rejected before compiling: {}.",
+                list(self.deny_list.identifiers.iter().cloned().collect())
+            ));
+        }
+        if !self.deny_list.functions.is_empty() {
+            lines.push(format!(
+                "Banned functions, rejected before compiling: {}.",
+                list(self.deny_list.functions.iter().cloned().collect())
+            ));
+        }
+        if !self.disabled_macros.is_empty() {
+            lines.push(format!(
+                "Disabled macros, rejected at compile time: {}.",
+                list(self.disabled_macros.iter().map(Macro::to_string).collect())
+            ));
+        }
+        let Limits {
+            max_expression_length,
+            timeout,
+            max_context_depth,
+            max_context_bytes,
+            max_regex_size,
+            max_result_bytes,
+            max_iterations,
+            max_cost,
+            max_string_bytes,
+            max_collection_size,
+        } = self.limits;
+        let mut limits = Vec::new();
+        if let Some(bytes) = max_expression_length {
+            limits.push(format!("expressions up to {} bytes", bytes));
+        }
+        if let Some(timeout) = timeout {
+            limits.push(format!(
+                "evaluations time out after {} ms",
+                timeout.as_millis()
+            ));
+        }
+        if let Some(depth) = max_context_depth {
+            limits.push(format!("context nesting up to {} levels", depth));
+        }
+        if let Some(bytes) = max_context_bytes {
+            limits.push(format!("contexts up to {} bytes", bytes));
+        }
+        if let Some(bytes) = max_regex_size {
+            limits.push(format!(
+                "compiled regular expressions up to {} bytes",
+                bytes
+            ));
+        }
+        if let Some(bytes) = max_result_bytes {
+            limits.push(format!("results truncated beyond {} bytes", bytes));
+        }
+        if let Some(iterations) = max_iterations {
+            limits.push(format!(
+                "up to {} comprehension iterations per request",
+                iterations
+            ));
+        }
+        if let Some(cost) = max_cost {
+            limits.push(format!("evaluation cost up to {} per request", cost));
+        }
+        if let Some(bytes) = max_string_bytes {
+            limits.push(format!("strings built up to {} bytes", bytes));
+        }
+        if let Some(size) = max_collection_size {
+            limits.push(format!("lists and maps built up to {} elements", size));
+        }
+        if !limits.is_empty() {
+            lines.push(format!("Limits: {}.", limits.join("; ")));
+        }
+        lines.join("\n")
+    }
+
+    /// Re-reads the [`Settings`](crate::reload::Settings) (base context, resolvers and policy sets) and swaps them in.
+    /// Everything else keeps its startup value. Requests already being evaluated finish with
+    /// the old settings; on error nothing changes.
+    pub fn reload(&self) -> Result<ReloadSummary, String> {
+        if self.read_only {
+            return Err("The server is read-only".to_string());
+        }
+        let (Some(loader), Some(evaluator)) = (&self.settings_loader, &self.evaluator) else {
+            return Err("Reloading is not configured".to_string());
+        };
+        let settings = loader()?;
+        for (name, policy) in &settings.policies {
+            policy
+                .validate()
+                .map_err(|e| format!("Invalid policy `{}`: {}", name, e))?;
+        }
+        match evaluator.warm_up(&policy_conditions(&settings.policies)) {
+            Ok(compiled) => tracing::info!("Compiled {} policy conditions", compiled),
+            Err(errors) if self.fail_on_compile_error => return Err(errors.join("; ")),
+            Err(errors) => {
+                for error in errors {
+                    tracing::warn!("Policy condition does not compile: {}", error);
+                }
+            }
+        }
+        let summary = ReloadSummary {
+            base_context_variables: settings.base_context.len(),
+            resolvers: settings.resolvers.len(),
+            policies: settings.policies.keys().cloned().collect(),
+        };
+        evaluator.set_base_context(&settings.base_context);
+        evaluator.set_variable_resolvers(settings.resolvers);
+        self.policies.set(settings.policies);
+        self.result_cache.clear();
+        tracing::info!(
+            "Base context, resolvers and policy sets reloaded: {:?}",
+            summary
+        );
+        Ok(summary)
+    }
+
+    /// Records the last `capacity` `decide` requests of each policy set, enabling `replay`.
+    pub fn with_decision_history(mut self, capacity: usize) -> Self {
+        self.decision_history = Some(Arc::new(DecisionHistory::new(capacity)));
+        self
+    }
+
+    /// Bounds concurrent `submit_job` jobs, their retention and the memory their results use.
+    pub fn with_job_limits(mut self, limits: JobLimits) -> Self {
+        self.jobs = Arc::new(JobRegistry::new(limits));
+        self
+    }
+
+    /// Evaluates the batch item by item into `job`, stopping early once it is cancelled.
+    async fn run_batch(
+        &self,
+        job: &Job,
+        request_id: &str,
+        params: EvaluateBatchParams,
+        parallelism: Parallelism,
+    ) {
+        let overrides = Overrides {
+            non_finite: params.non_finite,
+            cost_limit: params.cost_limit,
+            strict_attributes: params.strict_attributes,
+            environment: params.environment.clone(),
+            priority: params.priority,
+            bypass_cache: params.bypass_cache,
+        };
+        let total = params.contexts.len();
+        let mut contexts = params.contexts.into_iter().enumerate();
+        let mut running = JoinSet::new();
+        // Results that finished ahead of an earlier item, in ordered mode.
+        let mut ahead = BTreeMap::new();
+        let mut next = 0;
+        loop {
+            while running.len() < parallelism.degree && !job.is_cancelled() {
+                let Some((index, context)) = contexts.next() else {
+                    break;
+                };
+                let (tool, request_id) = (self.clone(), request_id.to_string());
+                let (expression, overrides) = (params.expression.clone(), overrides.clone());
+                let merge = params.merge_strategy;
+                running.spawn(async move {
+                    let result = tool
+                        .query_with(
+                            "evaluate_batch",
+                            &request_id,
+                            Query::Expression(expression),
+                            context,
+                            merge,
+                            overrides,
+                        )
+                        .await;
+                    (index, result)
+                });
+            }
+            let Some(joined) = running.join_next().await else {
+                break;
+            };
+            let (index, result) = match joined {
+                Ok(joined) => joined,
+                Err(e) => {
+                    tracing::error!("Job {} lost an item: {}", job.id(), e);
+                    break;
+                }
+            };
+            let mut item = match result {
+                Ok(value) => BatchItem {
+                    result: Some(
+                        serde_json::to_string(&value).unwrap_or_else(|_| value.to_string()),
+                    ),
+                    error: None,
+                    index: None,
+                },
+                Err(e) => BatchItem {
+                    result: None,
+                    error: Some(e.message.to_string()),
+                    index: None,
+                },
+            };
+            if parallelism.ordered {
+                ahead.insert(index, item);
+                while let Some(item) = ahead.remove(&next) {
+                    job.record(item);
+                    next += 1;
+                    report_progress(job, next, total);
+                }
+            } else {
+                item.index = Some(index);
+                job.record(item);
+                next += 1;
+                report_progress(job, next, total);
+            }
+        }
+    }
+
+    /// Fails unless `path` lies under one of the client's roots, if the client declares roots.
+    #[cfg(feature = "arrow")]
+    async fn check_roots(
+        &self,
+        peer: &Peer<RoleServer>,
+        path: &std::path::Path,
+    ) -> Result<(), String> {
+        if peer
+            .peer_info()
+            .is_none_or(|info| info.capabilities.roots.is_none())
+        {
+            return Ok(());
+        }
+        let roots = peer
+            .list_roots()
+            .await
+            .map_err(|e| format!("Failed to list the client's roots: {}", e))?;
+        dataset::check_roots(path, &roots.roots)
+    }
+
+    /// The content of the bucket object at `uri`.
+    #[cfg(feature = "object-store")]
+    async fn fetch_object(&self, uri: String, request_id: &str) -> Result<Vec<u8>, ErrorData> {
+        let store = self.object_store.clone().ok_or_else(|| {
+            ErrorData::invalid_params(
+                "No object store is configured".to_string(),
+                error_data(request_id),
+            )
+        })?;
+        tokio::task::spawn_blocking(move || store.fetch(&uri))
+            .await
+            .map_err(|e| ErrorData::internal_error(e.to_string(), error_data(request_id)))?
+            .map_err(|e| ErrorData::invalid_params(e, error_data(request_id)))
+    }
+
+    /// Whether `evaluate_dataset` may skip the evaluator for `expression`: nothing but the
+    /// columns may feed it, and no per-evaluation check, limit or record may be skipped. A
+    /// caller held to a quota is checked row by row, so that the rows past it fail.
+    #[cfg(feature = "arrow")]
+    fn vectorizable(&self, expression: &str, params: &EvaluateDatasetParams) -> bool {
+        params.environment.is_none()
+            && params.cost_limit.is_none()
+            && self.limits.max_cost.is_none()
+            && !self
+                .account()
+                .quota
+                .unwrap_or(self.quotas.quota())
+                .is_limited()
+            && self
+                .limits
+                .max_expression_length
+                .is_none_or(|max| expression.len() <= max)
+            && self.audit.is_none()
+            && self.rewriter.is_empty()
+            && self.deny_list.violations(expression).is_empty()
+            && self.profile.violations(expression).is_empty()
+    }
+
+    /// Queues `work` as a background job, returning its status.
+    fn start_job(&self, request_id: String, work: JobWork) -> Result<JobStatus, ErrorData> {
+        let (job_id, total) = match &work {
+            JobWork::Batch(params, _) => (params.job_id.as_deref(), params.contexts.len()),
+            #[cfg(feature = "arrow")]
+            JobWork::Done { job_id, items } => (job_id.as_deref(), items.len()),
+        };
+        let mut job = self
+            .jobs
+            .submit(client_request_id(job_id), total)
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id)))?;
+        let status = job.status();
+        tracing::info!("Queued job {} with {} items", status.job_id, status.total);
+        let tool = self.clone();
+        tokio::spawn(async move {
+            job.acquire_slot().await;
+            match work {
+                JobWork::Batch(params, parallelism) => {
+                    tool.run_batch(&job, &request_id, params, parallelism).await
+                }
+                #[cfg(feature = "arrow")]
+                JobWork::Done { items, .. } => {
+                    for item in items {
+                        if job.is_cancelled() {
+                            break;
+                        }
+                        job.record(item);
+                    }
+                }
+            }
+            tracing::info!("Job {} finished: {:?}", job.id(), job.status());
+        });
+        Ok(status)
+    }
+
+    /// Selects a compatibility profile, exposing its pre-declared variables and tools.
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        match profile {
+            Profile::Default => {}
+            Profile::Envoy => self.tool_router.merge(Self::envoy_tool_router()),
+            Profile::Iam => self.tool_router.merge(Self::iam_tool_router()),
+            Profile::Firestore => self.tool_router.merge(Self::firestore_tool_router()),
+        }
+        self
+    }
+
+    /// In private mode, replaces a message that may echo the expression or context with its
+    /// category (the text before the first `:`) and an ID to look up in the server logs.
+    fn client_message(&self, message: String, error_id: &str) -> String {
+        if !self.private_errors {
+            return message;
+        }
+        let category = message.split(':').next().unwrap_or_default();
+        format!("{} (error id: {})", category, error_id)
+    }
+
+    /// Sends a query to the evaluator service and waits for the JSON-encoded result.
+    async fn run(
+        &self,
+        tool: &str,
+        request_id: &str,
+        query: Query,
+        context: Map<String, Value>,
+        merge: MergeStrategy,
+        overrides: Overrides,
+    ) -> Result<Json<EvaluateResult>, ErrorData> {
+        let value = self
+            .query_with(tool, request_id, query, context, merge, overrides)
+            .await?;
+        Ok(Json(EvaluateResult::new(
+            value,
+            self.limits.max_result_bytes,
+        )))
+    }
+
+    /// Sends a query to the evaluator service and waits for its value.
+    pub(crate) async fn query(
+        &self,
+        tool: &str,
+        request_id: &str,
+        query: Query,
+        context: Map<String, Value>,
+        merge: MergeStrategy,
+    ) -> Result<Value, ErrorData> {
+        self.query_with(
+            tool,
+            request_id,
+            query,
+            context,
+            merge,
+            Overrides::default(),
+        )
+        .await
+    }
+
+    /// Like [`query`](Self::query), overriding the evaluator's handling of NaN and ±Infinity,
+    /// its cost limit and attribute strictness.
+    /// Error messages are worded by the configured templates.
+    pub(crate) async fn query_with(
+        &self,
+        tool: &str,
+        request_id: &str,
+        query: Query,
+        context: Map<String, Value>,
+        merge: MergeStrategy,
+        overrides: Overrides,
+    ) -> Result<Value, ErrorData> {
+        let result = self
+            .send_query(tool, request_id, query, context, merge, overrides)
+            .await;
+        if self.error_messages.is_empty() {
+            return result;
+        }
+        result.map_err(|mut e| {
+            e.message = self.error_messages.render(&e.message, request_id).into();
+            e
+        })
+    }
+
+    /// Checks `query` against the expression length limit, the environment named
+    /// `environment` and the profile in effect, which it returns with the environment.
+    fn check_query(
+        &self,
+        query: &Query,
+        environment: Option<&str>,
+        request_id: &str,
+        decision_id: &str,
+    ) -> Result<(Option<&Environment>, Profile), ErrorData> {
+        if let Some(max) = self.limits.max_expression_length
+            && let Some(expression) = query.expressions().into_iter().find(|e| e.len() > max)
+        {
+            return Err(ErrorData::invalid_params(
+                format!("Expression is {} bytes, limit is {}", expression.len(), max),
+                error_data(request_id),
+            ));
+        }
+        let environment = match environment {
+            Some(name) => Some(self.environments.get(name).ok_or_else(|| {
+                ErrorData::invalid_params(
+                    format!(
+                        "Unknown environment `{}` (available: {})",
+                        name,
+                        self.environments
+                            .keys()
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    error_data(request_id),
+                )
+            })?),
+            None => None,
+        };
+        if let Some(environment) = environment
+            && let Err(e) = query
+                .expressions()
+                .into_iter()
+                .try_for_each(|expression| environment.check(expression))
+        {
+            return Err(ErrorData::invalid_params(e, error_data(request_id)));
+        }
+        let banned: Vec<String> = query
+            .expressions()
+            .into_iter()
+            .flat_map(|expression| self.deny_list.violations(expression))
+            .collect();
+        if !banned.is_empty() {
+            tracing::error!(
+                error_id = %decision_id,
+                "Expression rejected by deny list: {:?}",
+                banned
+            );
+            return Err(ErrorData::invalid_params(
+                self.client_message(
+                    format!("Policy violation: {}", banned.join("; ")),
+                    decision_id,
+                ),
+                error_data(request_id),
+            ));
+        }
+        let profile = environment
+            .and_then(|environment| environment.profile)
+            .unwrap_or(self.profile);
+        let violations: Vec<String> = query
+            .expressions()
+            .into_iter()
+            .flat_map(|expression| profile.violations(expression))
+            .collect();
+        if !violations.is_empty() {
+            tracing::error!(
+                error_id = %decision_id,
+                "Expression rejected by profile: {:?}",
+                violations
+            );
+            return Err(ErrorData::invalid_params(
+                self.client_message(
+                    format!("Profile violation: {}", violations.join("; ")),
+                    decision_id,
+                ),
+                error_data(request_id),
+            ));
+        }
+        Ok((environment, profile))
+    }
+
+    /// Rewrites, checks and compiles `expression` as `evaluate` would, without running it. Also
+    /// returns the rewritten expression, if a rule changed it.
+    fn dry_run(
+        &self,
+        expression: &str,
+        environment: Option<&str>,
+        request_id: &str,
+    ) -> Result<(DryRun, Option<String>), ErrorData> {
+        let rewritten = self
+            .rewriter
+            .rewrite(expression)
+            .map_err(|e| ErrorData::invalid_params(e, error_data(request_id)))?;
+        let expression = rewritten.as_deref().unwrap_or(expression);
+        let functions = self
+            .evaluator
+            .as_ref()
+            .map(EvaluatorReloader::function_names)
+            .unwrap_or_default();
+        let (variables, estimated_cost) = match ast::parse(expression) {
+            Ok(parsed) => (
+                ast::variables(&parsed, |name| functions.iter().any(|f| f == name))
+                    .into_iter()
+                    .collect(),
+                ast::node_count(&parsed),
+            ),
+            Err(_) => (Vec::new(), 0),
+        };
+        let decision_id = Uuid::new_v4().to_string();
+        let query = Query::Expression(expression.to_string());
+        let compiled = match self.check_query(&query, environment, request_id, &decision_id) {
+            Err(e) => Err(e.message.to_string()),
+            Ok(_) => match &self.evaluator {
+                Some(evaluator) => evaluator.compile(expression),
+                None => ast::parse(expression)
+                    .map(|_| false)
+                    .map_err(|e| format!("CEL compile error: {}", e)),
+            }
+            .map_err(|e| {
+                tracing::error!(error_id = %decision_id, "Dry run failed to compile: {}", e);
+                self.client_message(e, &decision_id)
+            }),
+        };
+        let (cached, error) = match compiled {
+            Ok(cached) => (cached, None),
+            Err(e) => (false, Some(self.error_messages.render(&e, request_id))),
+        };
+        let dry_run = DryRun {
+            compiled: error.is_none(),
+            error,
+            variables,
+            estimated_cost,
+            cached,
+        };
+        Ok((dry_run, rewritten))
+    }
+
+    #[tracing::instrument(name = "cel.request", skip_all, fields(tool = tool, request_id = request_id, log_sink = tracing::field::Empty))]
+    async fn send_query(
+        &self,
+        tool: &str,
+        request_id: &str,
+        query: Query,
+        context: Map<String, Value>,
+        merge: MergeStrategy,
+        overrides: Overrides,
+    ) -> Result<Value, ErrorData> {
+        let query = query
+            .try_map(|expression| Ok(self.rewriter.rewrite(&expression)?.unwrap_or(expression)))
+            .map_err(|e| ErrorData::invalid_params(e, error_data(request_id)))?;
+        let decision_id = Uuid::new_v4().to_string();
+        let (environment, profile) = self.check_query(
+            &query,
+            overrides.environment.as_deref(),
+            request_id,
+            &decision_id,
+        )?;
+        let context = self
+            .session_context
+            .lock()
+            .map(|session_context| merge.layer(&session_context, context))
+            .map_err(|_| {
+                ErrorData::internal_error("The session context is poisoned", error_data(request_id))
+            })?;
+
+        let (responder, receiver) = oneshot::channel();
+
+        let context = match environment {
+            Some(environment) => {
+                let context = environment.seed_context(context);
+                if let Err(e) = environment.check_context(&context) {
+                    return Err(ErrorData::invalid_params(e, error_data(request_id)));
+                }
+                context
+            }
+            None => context,
+        };
+        let context = profile.seed_context(context);
+        let mut budget = ContextBudget::new(&self.limits);
+        if let Err(e) = budget.check(&context) {
+            tracing::error!("Context rejected: {}", e);
+            return Err(ErrorData::invalid_params(
+                e.to_string(),
+                Some(serde_json::json!({
+                    "request_id": request_id,
+                    "reason": e.reason(),
+                    "limit": e.limit(),
+                })),
+            ));
+        }
+        let account = self.account();
+        let context_bytes = budget.bytes() as u64;
+        if let Err(e) = self.quotas.check(&account, context_bytes) {
+            tracing::warn!(account = %account.name, "Request refused: {}", e);
+            return Err(ErrorData::invalid_request(
+                e.to_string(),
+                Some(serde_json::json!({
+                    "request_id": request_id,
+                    "reason": "quota_exceeded",
+                    "limit": e.limit,
+                    "resets_at": e.resets_at(),
+                })),
+            ));
+        }
+        let charge = |evaluation: Duration| {
+            self.quotas.charge(
+                &account,
+                Usage {
+                    evaluations: 1,
+                    eval_ms: evaluation.as_secs_f64() * 1000.0,
+                    bytes: context_bytes,
+                },
+            )
+        };
+        let context = Value::Object(context);
+        let secrets = self.redactor.secrets(&context);
+        let _log_subscription = self.forward_logs(&secrets);
+        let audited = self
+            .audit
+            .as_ref()
+            .map(|audit| (audit.clone(), query.to_string(), context.clone()));
+        let fingerprint = (audited.is_some() || self.slow_eval_threshold.is_some())
+            .then(|| query.fingerprint())
+            .flatten();
+        let slow_stats = self.slow_eval_threshold.map(|_| {
+            (
+                fingerprint
+                    .clone()
+                    .unwrap_or_else(|| ast::hash(&query.to_string())),
+                context.to_string().len(),
+            )
+        });
+        let cacheable = self.result_cache.is_enabled()
+            && !overrides.bypass_cache
+            && self.evaluator.as_ref().is_some_and(|evaluator| {
+                query
+                    .expressions()
+                    .iter()
+                    .all(|expression| evaluator.is_deterministic(expression, &context))
+            });
+        let cache_generation = self.result_cache.generation();
+        let cache_key =
+            cacheable.then(|| result_cache_key(&query, &context, profile, merge, &overrides));
+        let cached = cache_key
+            .as_ref()
+            .and_then(|key| self.result_cache.get(key));
+        let timestamp = Utc::now();
+        let started = Instant::now();
+
+        let (response, evaluation) = match cached {
+            // A cached answer costs no evaluation, so it isn't charged, though it is refused
+            // like any other once the account's quota is used up.
+            Some(value) => {
+                tracing::debug!("Answering from the result cache");
+                (Ok(Ok(value)), Duration::ZERO)
+            }
+            None => {
+                let timeout = match environment {
+                    Some(environment) => environment.timeout(self.limits.timeout),
+                    None => self.limits.timeout,
+                };
+                let request = EvalRequest {
+                    query,
+                    context,
+                    profile,
+                    merge,
+                    non_finite: overrides.non_finite,
+                    cost_limit: match environment {
+                        Some(environment) => environment.cost_limit(overrides.cost_limit),
+                        None => overrides.cost_limit,
+                    },
+                    strict_attributes: overrides.strict_attributes,
+                    deadline: timeout.and_then(|limit| started.checked_add(limit)),
+                    responder,
+                    request_id: request_id.to_string(),
+                    span: tracing::Span::current(),
+                    queue_wait: tracing::info_span!("cel.queue_wait"),
+                    lane: Lane {
+                        session: self.session,
+                        priority: overrides
+                            .priority
+                            .unwrap_or(if BATCH_TOOLS.contains(&tool) {
+                                Priority::Batch
+                            } else {
+                                Priority::Interactive
+                            }),
+                    },
+                };
+
+                if self.eval_tx.send(request).await.is_err() {
+                    tracing::error!(
+                        "Failed to send evaluation request to service, service is down."
+                    );
+                    return Err(ErrorData::internal_error(
+                        "Evaluator service is down",
+                        error_data(request_id),
+                    ));
+                }
+
+                let reply = match timeout {
+                    Some(limit) => match tokio::time::timeout(limit, receiver).await {
+                        Ok(reply) => reply,
+                        Err(_) => {
+                            charge(limit);
+                            tracing::error!("Evaluation timed out after {:?}", limit);
+                            return Err(ErrorData::internal_error(
+                                format!("Evaluation timed out after {:?}", limit),
+                                error_data(request_id),
+                            ));
+                        }
+                    },
+                    None => receiver.await,
+                };
+                let (response, evaluation) = match reply {
+                    Ok(reply) => (Ok(reply.response), reply.elapsed),
+                    Err(e) => (Err(e), Duration::ZERO),
+                };
+                if let (Some(key), Ok(Ok(value))) = (cache_key, &response) {
+                    self.result_cache
+                        .insert(key, value.clone(), cache_generation);
+                }
+                charge(evaluation);
+                (response, evaluation)
+            }
+        };
+        let total = started.elapsed();
+
+        if let (Some(threshold), Some((expression_hash, context_bytes))) =
+            (self.slow_eval_threshold, &slow_stats)
+            && total >= threshold
+        {
+            let slow = SlowEvaluation {
+                decision_id: &decision_id,
+                tool,
+                expression_hash,
+                context_bytes: *context_bytes,
+                timestamp,
+                total,
+                evaluation,
+            };
+            tracing::warn!(
+                decision_id = %decision_id,
+                expression_hash = %expression_hash,
+                context_bytes,
+                duration_ms = total.as_secs_f64() * 1000.0,
+                eval_ms = evaluation.as_secs_f64() * 1000.0,
+                "Slow evaluation"
+            );
+            if self.slow_eval_audit
+                && let Some(audit) = &self.audit
+            {
+                audit.record_slow(&slow);
+            }
+        }
+
+        if let (Some((audit, expression, context)), Ok(outcome)) = (audited, &response) {
+            audit.record(&AuditRecord {
+                decision_id: &decision_id,
+                tool,
+                expression: &expression,
+                fingerprint: fingerprint.as_deref(),
+                context: &context,
+                outcome: outcome.as_ref().map_err(String::as_str),
+                timestamp,
+                duration: total,
+            });
+        }
+
+        match response {
+            Ok(Ok(value)) => {
+                tracing::info!("Evaluation successful, returning result.");
+                Ok(value)
+            }
+            Ok(Err(e)) => {
+                let e = redact::mask_secrets(&e, &secrets);
+                tracing::error!(error_id = %decision_id, "Evaluation failed: {}", e);
+                let data = match elicitation::missing_variable(&e) {
+                    Some(name) if !self.private_errors => Some(serde_json::json!({
+                        "request_id": request_id,
+                        "missing_variable": name,
+                    })),
+                    _ => error_data(request_id),
+                };
+                Err(ErrorData::internal_error(
+                    self.client_message(e, &decision_id),
+                    data,
+                ))
+            }
+            Err(_) => {
+                tracing::error!("Failed to receive response from evaluator service.");
+                Err(ErrorData::internal_error(
+                    "Failed to receive response from evaluator",
+                    error_data(request_id),
+                ))
+            }
+        }
+    }
+
+    /// Asks the client's user for the context variable `error` reports missing, with a form
+    /// typed by its declaration in `environment`. `None` if the error is about something else
+    /// or the user declines; an error if the value given doesn't fit the declaration.
+    async fn elicit_variable(
+        &self,
+        peer: &Peer<RoleServer>,
+        error: &ErrorData,
+        environment: Option<&str>,
+    ) -> Result<Option<(String, Value)>, String> {
+        let Some(name) = error
+            .data
+            .as_ref()
+            .and_then(|data| data.get("missing_variable"))
+            .and_then(Value::as_str)
+        else {
+            return Ok(None);
+        };
+        let declared = environment
+            .and_then(|environment| self.environments.get(environment))
+            .and_then(|environment| environment.variables.get(name));
+        let result = peer
+            .create_elicitation(CreateElicitationRequestParams::FormElicitationParams {
+                meta: None,
+                message: elicitation::prompt(name, declared),
+                requested_schema: elicitation::form(name, declared),
+            })
+            .await;
+        match result {
+            Ok(result) if result.action == ElicitationAction::Accept => {
+                elicitation::answer(name, declared, result.content.as_ref())
+                    .map(|value| Some((name.to_string(), value)))
+            }
+            Ok(_) => Ok(None),
+            Err(e) => {
+                tracing::warn!("Eliciting `{}` failed: {}", name, e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// With repair suggestions on, asks the client's model to fix an `expression` that doesn't
+    /// compile, adding its fix to `error`'s data as `suggestion`.
+    async fn suggest_repair(
+        &self,
+        peer: &Peer<RoleServer>,
+        expression: &str,
+        mut error: ErrorData,
+    ) -> ErrorData {
+        let sampling = peer
+            .peer_info()
+            .is_some_and(|info| info.capabilities.sampling.is_some());
+        if !(self.repair_suggestions && sampling) {
+            return error;
+        }
+        let Err(parse_errors) = ast::parse(expression) else {
+            return error;
+        };
+        match peer
+            .create_message(repair::request(expression, &parse_errors.to_string()))
+            .await
+        {
+            Ok(result) => {
+                if let Some(suggestion) = repair::suggestion(expression, &result)
+                    && let Some(Value::Object(data)) = &mut error.data
+                {
+                    data.insert("suggestion".to_string(), Value::from(suggestion));
+                }
+            }
+            Err(e) => tracing::warn!("Sampling a repair suggestion failed: {}", e),
+        }
+        error
+    }
+
+    /// Renders parsed templates, evaluating all of their placeholders as one projection.
+    async fn render(
+        &self,
+        tool: &str,
+        request_id: &str,
+        templates: &[Vec<template::Segment>],
+        context: Map<String, Value>,
+        merge: MergeStrategy,
+        escape: Escape,
+    ) -> Result<Vec<String>, ErrorData> {
+        let placeholders = |segments: &[template::Segment]| -> Vec<String> {
+            segments
+                .iter()
+                .filter_map(|segment| match segment {
+                    template::Segment::Placeholder(expression) => Some(expression.clone()),
+                    template::Segment::Literal(_) => None,
+                })
+                .collect()
+        };
+        let outputs: Vec<(String, String)> = templates
+            .iter()
+            .enumerate()
+            .flat_map(|(t, segments)| {
+                placeholders(segments)
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(i, expression)| (format!("{}.{}", t, i), expression))
+            })
+            .collect();
+        let values = if outputs.is_empty() {
+            Value::Null
+        } else {
+            self.query(tool, request_id, Query::Projection(outputs), context, merge)
+                .await?
+        };
+        Ok(templates
+            .iter()
+            .enumerate()
+            .map(|(t, segments)| {
+                let substituted: Vec<Value> = (0..placeholders(segments).len())
+                    .map(|i| {
+                        values
+                            .get(format!("{}.{}", t, i))
+                            .cloned()
+                            .unwrap_or_default()
+                    })
+                    .collect();
+                template::render(segments, &substituted, escape)
+            })
+            .collect())
+    }
+
+    /// Evaluates a policy set's rules and combines them into a decision.
+    async fn decide_with(
+        &self,
+        tool: &str,
+        request_id: &str,
+        set: &PolicySet,
+        context: Map<String, Value>,
+        merge_strategy: MergeStrategy,
+    ) -> Result<DecideResult, ErrorData> {
+        let query = match set.combining {
+            Combining::FirstMatch => Query::FirstMatch(set.conditions()),
+            _ => Query::AllMatches(set.conditions()),
+        };
+        let matched = self
+            .query(tool, request_id, query, context.clone(), merge_strategy)
+            .await?;
+        let names: Vec<&str> = match &matched {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let (decision, rules) = set.combine(&names);
+        let templates = rules
+            .iter()
+            .map(|rule| template::parse(rule.message.as_deref().unwrap_or_default()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ErrorData::internal_error(e, error_data(request_id)))?;
+        let reasons = self
+            .render(
+                tool,
+                request_id,
+                &templates,
+                context,
+                merge_strategy,
+                Escape::None,
+            )
+            .await?;
+        let mut matches: Vec<RuleMatch> = rules
+            .iter()
+            .zip(reasons)
+            .map(|(rule, reason)| RuleMatch {
+                rule: rule.name.clone(),
+                effect: rule.effect,
+                reason: rule.message.as_ref().map(|_| reason),
+            })
+            .collect();
+        let deciding = matches.first().cloned();
+        if set.combining != Combining::CollectAll {
+            matches.clear();
+        }
+        Ok(DecideResult {
+            decision,
+            rule: deciding.as_ref().map(|m| m.rule.clone()),
+            reason: deciding.and_then(|m| m.reason),
+            matches,
+        })
+    }
+
+    /// Decides with a policy set's shadow version and reports it if it disagrees with the
+    /// active decision (by effect or deciding rule).
+    async fn compare_shadow(
+        &self,
+        request_id: &str,
+        policy: &str,
+        shadow: &PolicySet,
+        context: Map<String, Value>,
+        merge_strategy: MergeStrategy,
+        active: Value,
+    ) {
+        let context_value = self.audit.as_ref().map(|_| Value::Object(context.clone()));
+        let outcome = self
+            .decide_with("decide_shadow", request_id, shadow, context, merge_strategy)
+            .await
+            .map(|result| serde_json::to_value(&result).unwrap_or_default())
+            .map_err(|e| e.message.to_string());
+        if let Ok(result) = &outcome
+            && same_decision(result, &active)
+        {
+            return;
+        }
+        match &outcome {
+            Ok(result) => tracing::warn!(
+                request_id = %request_id,
+                policy = %policy,
+                "Shadow decision diverged: active {}, shadow {}",
+                active,
+                result
+            ),
+            Err(e) => tracing::warn!(
+                request_id = %request_id,
+                policy = %policy,
+                "Shadow decision failed: {}",
+                e
+            ),
+        }
+        if let (Some(audit), Some(context)) = (&self.audit, &context_value) {
+            audit.record_divergence(&ShadowDivergence {
+                request_id,
+                policy,
+                context,
+                active: &active,
+                shadow: outcome.as_ref().map_err(String::as_str),
+                timestamp: Utc::now(),
+            });
+        }
+    }
+
+    /// Decides each recorded request with `candidate` and collects those whose decision changes.
+    async fn replay_with(
+        &self,
+        request_id: &str,
+        candidate: &PolicySet,
+        recorded: Vec<RecordedDecision>,
+    ) -> ReplayResult {
+        let replayed = recorded.len();
+        let mut changes = Vec::new();
+        for (index, recorded) in recorded.into_iter().enumerate() {
+            let outcome = self
+                .decide_with(
+                    "replay",
+                    request_id,
+                    candidate,
+                    recorded.context,
+                    recorded.merge,
+                )
+                .await;
+            let candidate = match outcome {
+                Ok(result) => serde_json::to_value(&result).unwrap_or_default(),
+                Err(e) => serde_json::json!({ "error": e.message }),
+            };
+            if !same_decision(&candidate, &recorded.decision) {
+                changes.push(ReplayChange {
+                    index,
+                    recorded: recorded.decision,
+                    candidate,
+                });
+            }
+        }
+        ReplayResult {
+            replayed,
+            changed: changes.len(),
+            changes,
+        }
+    }
+
+    #[tool(
+        description = "Evaluates a Common Expression Language (CEL) expression. List results can be paginated with offset and limit.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn evaluate(
+        &self,
+        params: Parameters<EvaluateParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<EvaluateResult>, ErrorData> {
+        tracing::info!(
+            "CelTool::evaluate called with expression: {:?}",
+            params.0.expression
+        );
+        let params = params.0;
+        let request_id = request_id(&request);
+        if params.dry_run {
+            let (dry_run, rewritten_expression) = self.dry_run(
+                &params.expression,
+                params.environment.as_deref(),
+                &request_id,
+            )?;
+            return Ok(Json(EvaluateResult {
+                result: None,
+                total: None,
+                next_offset: None,
+                truncation: None,
+                dry_run: Some(dry_run),
+                rewritten_expression,
+            }));
+        }
+        // The query is rewritten again on its way to the evaluator; this is what it becomes.
+        let rewritten_expression = self
+            .rewriter
+            .rewrite(&params.expression)
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id)))?;
+        #[cfg(feature = "object-store")]
+        let context = match params.context_uri {
+            Some(uri) => {
+                let bytes = self.fetch_object(uri.clone(), &request_id).await?;
+                let mut context = match serde_json::from_slice(&bytes) {
+                    Ok(Value::Object(context)) => context,
+                    _ => {
+                        return Err(ErrorData::invalid_params(
+                            format!("`{}` does not hold a JSON object", uri),
+                            error_data(&request_id),
+                        ));
+                    }
+                };
+                context.extend(params.context);
+                context
+            }
+            None => params.context,
+        };
+        #[cfg(not(feature = "object-store"))]
+        let context = params.context;
+        let overrides = Overrides {
+            non_finite: params.non_finite,
+            cost_limit: params.cost_limit,
+            strict_attributes: params.strict_attributes,
+            environment: params.environment,
+            priority: params.priority,
+            bypass_cache: params.bypass_cache,
+        };
+        let elicit = request
+            .peer
+            .supported_elicitation_modes()
+            .contains(&ElicitationMode::Form);
+        let mut context = context;
+        let mut elicited = 0;
+        let value = loop {
+            // A copy to retry with, should the user supply a missing variable.
+            let retry = (elicit && elicited < MAX_ELICITED_VARIABLES).then(|| context.clone());
+            let result = self
+                .query_with(
+                    "evaluate",
+                    &request_id,
+                    Query::Expression(params.expression.clone()),
+                    context,
+                    params.merge_strategy,
+                    overrides.clone(),
+                )
+                .await;
+            let e = match result {
+                Ok(value) => break value,
+                Err(e) => e,
+            };
+            if let Some(mut retry) = retry
+                && let Some((name, value)) = self
+                    .elicit_variable(&request.peer, &e, overrides.environment.as_deref())
+                    .await
+                    .map_err(|message| {
+                        ErrorData::invalid_params(message, error_data(&request_id))
+                    })?
+            {
+                retry.insert(name, value);
+                context = retry;
+                elicited += 1;
+                continue;
+            }
+            return Err(self
+                .suggest_repair(&request.peer, &params.expression, e)
+                .await);
+        };
+        let result = if params.offset.is_none() && params.limit.is_none() {
+            EvaluateResult::new(value, self.limits.max_result_bytes)
+        } else {
+            EvaluateResult::page(
+                value,
+                params.offset.unwrap_or(0),
+                params.limit,
+                self.limits.max_result_bytes,
+            )
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id)))?
+        };
+        Ok(Json(EvaluateResult {
+            rewritten_expression,
+            ..result
+        }))
+    }
+
+    #[tool(
+        description = "Evaluates several named CEL expressions against one context and returns an object mapping each name to its value. Cheaper than one evaluate call per expression.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn evaluate_projection(
+        &self,
+        params: Parameters<EvaluateProjectionParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<EvaluateResult>, ErrorData> {
+        tracing::info!(
+            "CelTool::evaluate_projection called with outputs: {:?}",
+            params.0.outputs.keys().collect::<Vec<_>>()
+        );
+        self.run(
+            "evaluate_projection",
+            &request_id(&request),
+            Query::Projection(params.0.outputs.into_iter().collect()),
+            params.0.context,
+            params.0.merge_strategy,
+            Overrides {
+                non_finite: params.0.non_finite,
+                cost_limit: params.0.cost_limit,
+                strict_attributes: params.0.strict_attributes,
+                environment: params.0.environment,
+                priority: params.0.priority,
+                bypass_cache: params.0.bypass_cache,
+            },
+        )
+        .await
+    }
+
+    #[tool(
+        description = "Evaluates one CEL expression against each of a list of contexts, as a job that job_status can poll and cancel_job can stop between items. Returns each item's result or error.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn evaluate_batch(
+        &self,
+        params: Parameters<EvaluateBatchParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<EvaluateBatchResult>, ErrorData> {
+        let request_id = request_id(&request);
+        let params = params.0;
+        let job_id = client_request_id(params.job_id.as_deref());
+        let job = self
+            .jobs
+            .start(job_id.clone(), params.contexts.len())
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id)))?;
+        tracing::info!(
+            "CelTool::evaluate_batch started job {} with {} items",
+            job_id,
+            params.contexts.len()
+        );
+        self.run_batch(&job, &request_id, params, Parallelism::default())
+            .await;
+        let results = job
+            .take_results()
+            .map_err(|e| ErrorData::internal_error(e, error_data(&request_id)))?;
+        drop(job);
+        let status = self
+            .jobs
+            .status(&job_id)
+            .map_err(|e| ErrorData::internal_error(e, error_data(&request_id)))?;
+        Ok(Json(EvaluateBatchResult {
+            job_id,
+            state: status.state,
+            results,
+            next_offset: None,
+        }))
+    }
+
+    #[tool(
+        description = "Starts evaluate_batch as a background job and returns its ID at once. Poll it with job_status, stop it with cancel_job and fetch its results with get_job_result.",
+        annotations(
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = false
+        )
+    )]
+    async fn submit_job(
+        &self,
+        params: Parameters<EvaluateBatchParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<JobStatus>, ErrorData> {
+        self.start_job(
+            request_id(&request),
+            JobWork::Batch(params.0, Parallelism::default()),
+        )
+        .map(Json)
+    }
+
+    #[tool(
+        description = "Returns the state and the item results so far of a job started with submit_job.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn get_job_result(
+        &self,
+        params: Parameters<JobResultParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<EvaluateBatchResult>, ErrorData> {
+        let request_id = request_id(&request);
+        let JobResultParams {
+            job_id,
+            offset,
+            limit,
+        } = params.0;
+        let job = self
+            .jobs
+            .get(&job_id)
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id)))?;
+        // Read the state first, so a `completed` job's page includes its last results.
+        let state = job.status().state;
+        let (results, recorded) = job
+            .results(offset, limit.unwrap_or(DEFAULT_RESULT_PAGE))
+            .map_err(|e| ErrorData::internal_error(e, error_data(&request_id)))?
+            .ok_or_else(|| {
+                ErrorData::invalid_params(
+                    format!(
+                        "Results of job `{}` were returned by its evaluate_batch call",
+                        job_id
+                    ),
+                    error_data(&request_id),
+                )
+            })?;
+        let end = offset + results.len();
+        Ok(Json(EvaluateBatchResult {
+            job_id,
+            state,
+            results,
+            next_offset: (end < recorded).then_some(end),
+        }))
+    }
+
+    #[tool(
+        description = "Reports the state and progress of an evaluate_batch job.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn job_status(
+        &self,
+        params: Parameters<JobParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<JobStatus>, ErrorData> {
+        self.jobs
+            .status(&params.0.job_id)
+            .map(Json)
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id(&request))))
+    }
+
+    #[tool(
+        description = "Stops an evaluate_batch job before its next item; items already evaluated are still returned to its caller.",
+        annotations(
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = true
+        )
+    )]
+    async fn cancel_job(
+        &self,
+        params: Parameters<JobParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<JobStatus>, ErrorData> {
+        tracing::info!("CelTool::cancel_job called for job {}", params.0.job_id);
+        self.jobs
+            .cancel(&params.0.job_id)
+            .map(Json)
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id(&request))))
+    }
+
+    #[tool(
+        description = "Registers a CEL expression under a name, with {parameter} placeholders for typed arguments that evaluate_registered fills in as quoted literals. Replaces an expression registered under the same name.",
+        annotations(
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = true
+        )
+    )]
+    async fn register_expression(
+        &self,
+        params: Parameters<RegisterExpressionParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<RegisterExpressionResult>, ErrorData> {
+        let RegisterExpressionParams {
+            name,
+            expression,
+            parameters,
+            description,
+        } = params.0;
+        let invalid = |e: String| ErrorData::invalid_params(e, error_data(&request_id(&request)));
+        let parameters = parameters
+            .into_iter()
+            .map(|(name, declared)| CelType::parse(&declared).map(|declared| (name, declared)))
+            .collect::<Result<_, _>>()
+            .map_err(invalid)?;
+        let registered = Registered::new(expression, parameters, description).map_err(invalid)?;
+        let replaced = self
+            .expressions
+            .register(name.clone(), registered)
+            .map_err(|e| ErrorData::internal_error(e, error_data(&request_id(&request))))?;
+        tracing::info!("Registered expression `{}`", name);
+        Ok(Json(RegisterExpressionResult { name, replaced }))
+    }
+
+    #[tool(
+        description = "Sets variables present in every later evaluation of this session, under the request's context, which combines with them by its merge_strategy. Other sessions don't see them; over HTTP, a session is the one its Mcp-Session-Id header names.",
+        annotations(read_only_hint = false, idempotent_hint = true)
+    )]
+    async fn set_context(
+        &self,
+        params: Parameters<SetContextParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<SessionContext>, ErrorData> {
+        let SetContextParams {
+            context,
+            merge_strategy,
+        } = params.0;
+        let mut session_context = self.session_context.lock().map_err(|_| {
+            ErrorData::internal_error(
+                "The session context is poisoned",
+                error_data(&request_id(&request)),
+            )
+        })?;
+        let mut layered = merge_strategy.layer(&session_context, context);
+        layered.retain(|_, value| !value.is_null() || merge_strategy != MergeStrategy::MergePatch);
+        // The whole context counts against the limits, so that repeated calls can't grow it
+        // past them.
+        let mut budget = ContextBudget::new(&self.limits);
+        budget.check(&layered).map_err(|e| {
+            ErrorData::invalid_params(e.to_string(), error_data(&request_id(&request)))
+        })?;
+        *session_context = layered;
+        Ok(Json(SessionContext {
+            variables: session_context.keys().cloned().collect(),
+        }))
+    }
+
+    #[tool(
+        description = "Reports what the caller's account has used of its quota in the current window: evaluations, evaluation time and context bytes.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn usage(&self) -> Json<AccountUsage> {
+        Json(self.quotas.usage(&self.account()))
+    }
+
+    #[tool(
+        description = "Lists the expressions registered with register_expression, with their parameters.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn list_expressions(&self) -> Json<RegisteredExpressions> {
+        let expressions = self
+            .expressions
+            .list()
+            .into_iter()
+            .map(|(name, registered)| RegisteredExpression {
+                name,
+                expression: registered.expression,
+                parameters: registered
+                    .parameters
+                    .iter()
+                    .map(|(name, declared)| (name.clone(), declared.to_string()))
+                    .collect(),
+                description: registered.description,
+            })
+            .collect();
+        Json(RegisteredExpressions { expressions })
+    }
+
+    #[tool(
+        description = "Builds an expression by combining registered expressions with and, or and not, each part parenthesized. The result keeps the parts' parameters; register_composition registers it.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn compose(
+        &self,
+        params: Parameters<ComposeParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<ComposeResult>, ErrorData> {
+        let composed = self
+            .expressions
+            .compose(&params.0.composition, None)
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id(&request))))?;
+        Ok(Json(ComposeResult {
+            expression: composed.expression,
+            parameters: composed
+                .parameters
+                .iter()
+                .map(|(name, declared)| (name.clone(), declared.to_string()))
+                .collect(),
+        }))
+    }
+
+    #[tool(
+        description = "Registers under a name the expression compose builds from a composition, ready for evaluate_registered. Replaces an expression registered under the same name.",
+        annotations(
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = true
+        )
+    )]
+    async fn register_composition(
+        &self,
+        params: Parameters<RegisterCompositionParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<RegisterExpressionResult>, ErrorData> {
+        let RegisterCompositionParams {
+            name,
+            composition,
+            description,
+        } = params.0;
+        let request_id = request_id(&request);
+        let composed = self
+            .expressions
+            .compose(&composition, description)
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id)))?;
+        let replaced = self
+            .expressions
+            .register(name.clone(), composed)
+            .map_err(|e| ErrorData::internal_error(e, error_data(&request_id)))?;
+        tracing::info!("Registered composed expression `{}`", name);
+        Ok(Json(RegisterExpressionResult { name, replaced }))
+    }
+
+    #[tool(
+        description = "Evaluates an expression registered with register_expression, with its placeholders replaced by the given arguments, each checked against its parameter's type and quoted as a CEL literal.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn evaluate_registered(
+        &self,
+        params: Parameters<EvaluateRegisteredParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<EvaluateResult>, ErrorData> {
+        let params = params.0;
+        let request_id = request_id(&request);
+        tracing::info!(
+            "CelTool::evaluate_registered called with expression `{}`",
+            params.name
+        );
+        let expression = self
+            .expressions
+            .get(&params.name)
+            .ok_or_else(|| format!("No expression is registered as `{}`", params.name))
+            .and_then(|registered| registered.instantiate(&params.arguments))
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id)))?;
+        self.run(
+            "evaluate_registered",
+            &request_id,
+            Query::Expression(expression),
+            params.context,
+            params.merge_strategy,
+            Overrides {
+                environment: params.environment,
+                ..Overrides::default()
+            },
+        )
+        .await
+    }
+
+    #[tool(
+        description = "Converts a JSON value into a CEL literal, with strings escaped, for building expressions without quoting bugs. type picks the literal for values JSON can't tell apart: uint, double, bytes, timestamp, duration, or lists and maps of them.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn quote_value(
+        &self,
+        params: Parameters<QuoteValueParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<QuoteValueResult>, ErrorData> {
+        let QuoteValueParams { value, declared } = params.0;
+        let declared = declared.as_deref().map_or(Ok(CelType::Dyn), CelType::parse);
+        declared
+            .and_then(|declared| literal::quote("value", &value, &declared))
+            .map(|literal| Json(QuoteValueResult { literal }))
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id(&request))))
+    }
+
+    #[tool(
+        description = "Renders a text template, replacing each ${cel expression} placeholder with its value (strings unquoted, other values as JSON), with optional html, json or shell escaping.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn render_template(
+        &self,
+        params: Parameters<RenderTemplateParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<RenderTemplateResult>, ErrorData> {
+        let request_id = request_id(&request);
+        let segments = template::parse(&params.0.template)
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id)))?;
+        let rendered = self
+            .render(
+                "render_template",
+                &request_id,
+                &[segments],
+                params.0.context,
+                MergeStrategy::Replace,
+                params.0.escape,
+            )
+            .await?;
+        Ok(Json(RenderTemplateResult {
+            rendered: rendered.concat(),
+        }))
+    }
+
+    #[tool(
+        description = "Decides allow or deny with a named policy set: its rules' CEL conditions are evaluated and combined by the set's strategy (first_match, deny_overrides, allow_overrides, collect_all). Returns the decision, the deciding rule and its rendered reason.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn decide(
+        &self,
+        params: Parameters<DecideParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<DecideResult>, ErrorData> {
+        let request_id = request_id(&request);
+        let DecideParams {
+            policy,
+            context,
+            merge_strategy,
+        } = params.0;
+        tracing::info!("CelTool::decide called with policy: {:?}", policy);
+        let policies = self.policies.get();
+        let Some(set) = policies.get(&policy) else {
+            return Err(ErrorData::invalid_params(
+                format!("Unknown policy `{}`", policy),
+                error_data(&request_id),
+            ));
+        };
+        let shadow = set.shadow.clone().map(|shadow| (shadow, context.clone()));
+        let recorded = self.decision_history.as_ref().map(|_| context.clone());
+        let result = self
+            .decide_with("decide", &request_id, set, context, merge_strategy)
+            .await?;
+        if let (Some(history), Some(context)) = (&self.decision_history, recorded) {
+            history.record(
+                &policy,
+                RecordedDecision {
+                    context,
+                    merge: merge_strategy,
+                    decision: serde_json::to_value(&result).unwrap_or_default(),
+                },
+            );
+        }
+        if let Some((shadow, context)) = shadow {
+            let tool = self.clone();
+            let active = serde_json::to_value(&result).unwrap_or_default();
+            tokio::spawn(async move {
+                tool.compare_shadow(
+                    &request_id,
+                    &policy,
+                    &shadow,
+                    context,
+                    merge_strategy,
+                    active,
+                )
+                .await;
+            });
+        }
+        Ok(Json(result))
+    }
+
+    #[tool(
+        description = "Parses a CEL expression without evaluating it and returns its syntax tree, with macros (has, all, exists, exists_one, map, filter) expanded and labelled, and the list of macros used.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn describe_ast(
+        &self,
+        params: Parameters<DescribeAstParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<DescribeAstResult>, ErrorData> {
+        let parsed = ast::parse(&params.0.expression).map_err(|e| {
+            ErrorData::invalid_params(
+                format!("CEL parse error: {}", e),
+                error_data(&request_id(&request)),
+            )
+        })?;
+        Ok(Json(DescribeAstResult {
+            macros: macros::used(&params.0.expression),
+            ast: ast::describe(&parsed),
+        }))
+    }
+
+    #[tool(
+        description = "Returns the fingerprint of a CEL expression: a hash of its syntax tree, so differently formatted expressions with the same logic share it. Audit records and the program cache use the same fingerprint.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn expression_fingerprint(
+        &self,
+        params: Parameters<ExpressionFingerprintParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<ExpressionFingerprintResult>, ErrorData> {
+        let parsed = ast::parse(&params.0.expression).map_err(|e| {
+            ErrorData::invalid_params(
+                format!("CEL parse error: {}", e),
+                error_data(&request_id(&request)),
+            )
+        })?;
+        Ok(Json(ExpressionFingerprintResult {
+            fingerprint: ast::fingerprint(&parsed),
+        }))
+    }
+}
+
+#[tool_router(router = admin_tool_router)]
+impl CelTool {
+    #[tool(
+        description = "Re-evaluates the recently recorded decide requests of a policy set with a candidate version of the set and reports how many decisions would change.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn replay(
+        &self,
+        params: Parameters<ReplayParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<ReplayResult>, ErrorData> {
+        let request_id = request_id(&request);
+        let ReplayParams {
+            policy,
+            candidate,
+            last,
+        } = params.0;
+        tracing::info!("CelTool::replay called with policy: {:?}", policy);
+        if !self.policies.get().contains_key(&policy) {
+            return Err(ErrorData::invalid_params(
+                format!("Unknown policy `{}`", policy),
+                error_data(&request_id),
+            ));
+        }
+        let Some(history) = &self.decision_history else {
+            return Err(ErrorData::invalid_params(
+                "Decision history is disabled",
+                error_data(&request_id),
+            ));
+        };
+        candidate
+            .validate()
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id)))?;
+        let recorded = history.recent(&policy, last.unwrap_or(usize::MAX));
+        Ok(Json(
+            self.replay_with(&request_id, &candidate, recorded).await,
+        ))
+    }
+
+    #[tool(
+        name = "reload",
+        description = "Re-reads the server's config and base context files, replacing only the variable resolvers, policy sets and base context variables, without dropping sessions. Limits, quotas, client certificates, environments and the other settings keep their startup values.",
+        annotations(
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = true
+        )
+    )]
+    async fn reload_tool(
+        &self,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<ReloadSummary>, ErrorData> {
+        self.reload().map(Json).map_err(|e| {
+            tracing::error!("Reload failed: {}", e);
+            ErrorData::internal_error(e, error_data(&request_id(&request)))
+        })
+    }
+
+    #[tool(
+        description = "Reports how many compiled programs and evaluation results the server has cached, and the result cache's hits and misses.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn cache_stats(&self) -> Json<CacheStats> {
+        Json(CacheStats {
+            programs: self
+                .evaluator
+                .as_ref()
+                .map_or(0, EvaluatorReloader::cached_programs),
+            result_cache: self.result_cache.stats(),
+        })
+    }
+}
+
+#[tool_router(router = kv_tool_router)]
+impl CelTool {
+    #[tool(
+        description = "Sets a key of the server's key-value store, which expressions read with kv.get(key) and kv.exists(key).",
+        annotations(
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = true
+        )
+    )]
+    async fn kv_put(
+        &self,
+        params: Parameters<KvPutParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<KvChange>, ErrorData> {
+        let KvPutParams { key, value } = params.0;
+        let request_id = request_id(&request);
+        let store = self.kv(&request)?;
+        let existed = store.contains(&key);
+        let entry = key.clone();
+        // Writing the file blocks, so it runs off the runtime's threads.
+        tokio::task::spawn_blocking(move || store.put(entry, value))
+            .await
+            .map_err(|e| ErrorData::internal_error(e.to_string(), error_data(&request_id)))?
+            .map_err(|e| ErrorData::internal_error(e, error_data(&request_id)))?;
+        tracing::info!("Key-value store: set `{}`", key);
+        self.result_cache.clear();
+        Ok(Json(KvChange { key, existed }))
+    }
+
+    #[tool(
+        description = "Removes a key from the server's key-value store.",
+        annotations(
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = true
+        )
+    )]
+    async fn kv_delete(
+        &self,
+        params: Parameters<KvKeyParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<KvChange>, ErrorData> {
+        let key = params.0.key;
+        let request_id = request_id(&request);
+        let store = self.kv(&request)?;
+        let entry = key.clone();
+        let existed = tokio::task::spawn_blocking(move || store.delete(&entry))
+            .await
+            .map_err(|e| ErrorData::internal_error(e.to_string(), error_data(&request_id)))?
+            .map_err(|e| ErrorData::internal_error(e, error_data(&request_id)))?;
+        tracing::info!("Key-value store: deleted `{}`", key);
+        self.result_cache.clear();
+        Ok(Json(KvChange { key, existed }))
+    }
+
+    #[tool(
+        description = "Lists the keys of the server's key-value store, optionally by prefix.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn kv_list(
+        &self,
+        params: Parameters<KvListParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<KvKeys>, ErrorData> {
+        Ok(Json(KvKeys {
+            keys: self.kv(&request)?.keys(&params.0.prefix),
+        }))
+    }
+}
+
+impl CelTool {
+    fn kv(&self, request: &RequestContext<RoleServer>) -> Result<Arc<KvStore>, ErrorData> {
+        self.kv_store.clone().ok_or_else(|| {
+            ErrorData::invalid_params(
+                "No key-value store is configured".to_string(),
+                error_data(&request_id(request)),
+            )
+        })
+    }
+}
+
+#[tool_router(router = envoy_tool_router)]
+impl CelTool {
+    #[tool(
+        description = "Evaluates a CEL expression against the Envoy attributes (request.*, source.*, destination.*) derived from an ext_authz CheckRequest.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn evaluate_envoy_check_request(
+        &self,
+        params: Parameters<EvaluateEnvoyParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<EvaluateResult>, ErrorData> {
+        let request_id = request_id(&request);
+        tracing::info!(
+            "CelTool::evaluate_envoy_check_request called with expression: {:?}",
+            params.0.expression
+        );
+        let mut context = profile::envoy::from_check_request(&params.0.check_request)
+            .map_err(|e| ErrorData::invalid_params(e, error_data(&request_id)))?;
+        profile::deep_merge(&mut context, params.0.context);
+        self.run(
+            "evaluate_envoy_check_request",
+            &request_id,
+            Query::Expression(params.0.expression),
+            context,
+            MergeStrategy::Replace,
+            Overrides::default(),
+        )
+        .await
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct ValidateIamParams {
+    expression: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct ValidateIamResult {
+    valid: bool,
+    violations: Vec<String>,
+}
+
+#[tool_router(router = iam_tool_router)]
+impl CelTool {
+    #[tool(
+        description = "Checks whether a CEL expression stays within Google Cloud IAM Conditions restrictions (length, operators, functions, logical operator count).",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn validate_iam_condition(
+        &self,
+        params: Parameters<ValidateIamParams>,
+    ) -> Json<ValidateIamResult> {
+        let violations = profile::iam::validate(&params.0.expression);
+        Json(ValidateIamResult {
+            valid: violations.is_empty(),
+            violations,
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+struct EvaluateFirestoreParams {
+    expression: String,
+    /// Simulated documents keyed by path (e.g. `users/alice`), read by `get()` and `exists()`.
+    #[serde(default)]
+    documents: Map<String, Value>,
+    /// Rule variables such as `request` and `resource`, layered over the profile defaults.
+    #[serde(default)]
+    context: Map<String, Value>,
+}
+
+#[tool_router(router = firestore_tool_router)]
+impl CelTool {
+    #[tool(
+        description = "Evaluates a Firebase security-rules-style CEL expression, with get(path) and exists(path) reading from the provided documents.",
+        annotations(read_only_hint = true, idempotent_hint = true)
+    )]
+    async fn evaluate_firestore_rule(
+        &self,
+        params: Parameters<EvaluateFirestoreParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<EvaluateResult>, ErrorData> {
+        tracing::info!(
+            "CelTool::evaluate_firestore_rule called with expression: {:?}",
+            params.0.expression
+        );
+        let mut context = params.0.context;
+        context.insert(
+            profile::firestore::DOCUMENTS_VARIABLE.to_string(),
+            Value::Object(params.0.documents),
+        );
+        self.run(
+            "evaluate_firestore_rule",
+            &request_id(&request),
+            Query::Expression(params.0.expression),
+            context,
+            MergeStrategy::Replace,
+            Overrides::default(),
+        )
+        .await
+    }
+}
+
+#[cfg(feature = "arrow")]
+#[tool_router(router = dataset_tool_router)]
+impl CelTool {
+    #[tool(
+        description = "Starts a background job evaluating one CEL expression against each row of a Parquet or Arrow IPC dataset, given as a file path or base64 data, with each column as a variable. Poll it with job_status and fetch its results with get_job_result.",
+        annotations(
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = false
+        )
+    )]
+    async fn evaluate_dataset(
+        &self,
+        params: Parameters<EvaluateDatasetParams>,
+        request: RequestContext<RoleServer>,
+    ) -> Result<Json<JobStatus>, ErrorData> {
+        let request_id = request_id(&request);
+        let params = params.0;
+        tracing::info!(
+            "CelTool::evaluate_dataset called with expression: {:?}",
+            params.expression
+        );
+        let invalid = |e: String| ErrorData::invalid_params(e, error_data(&request_id));
+        let (path, data) = match (&params.path, &params.data) {
+            #[cfg(feature = "object-store")]
+            (Some(uri), None) if crate::object_store::is_object_uri(uri) => {
+                (None, self.fetch_object(uri.clone(), &request_id).await?)
+            }
+            (Some(path), None) => {
+                let dir = self
+                    .dataset_dir
+                    .as_ref()
+                    .ok_or_else(|| invalid("No dataset directory is configured".to_string()))?;
+                let path = dataset::resolve(dir, path).map_err(invalid)?;
+                self.check_roots(&request.peer, &path)
+                    .await
+                    .map_err(invalid)?;
+                (Some(path), Vec::new())
+            }
+            (None, Some(data)) => (
+                None,
+                BASE64_STANDARD
+                    .decode(data)
+                    .map_err(|e| invalid(format!("Invalid base64 dataset: {}", e)))?,
+            ),
+            _ => return Err(invalid("Pass either `path` or `data`".to_string())),
+        };
+        let degree = params.parallelism.unwrap_or(1);
+        if !(1..=MAX_PARALLELISM).contains(&degree) {
+            return Err(invalid(format!(
+                "`parallelism` must be between 1 and {}",
+                MAX_PARALLELISM
+            )));
+        }
+        let vectorize = self.vectorizable(&params.expression, &params);
+        let (format, columns) = (params.format, params.columns);
+        let expression = params.expression.clone();
+        let rows = tokio::task::spawn_blocking(move || {
+            let bytes = match path {
+                Some(path) => {
+                    std::fs::read(&path).map_err(|e| format!("Failed to read dataset: {}", e))?
+                }
+                None => data,
+            };
+            let size = bytes.len() as u64;
+            let batches = dataset::read_batches(
+                bytes,
+                format,
+                columns.as_deref(),
+                dataset::DEFAULT_MAX_ROWS,
+            )?;
+            let started = Instant::now();
+            match vectorize
+                .then(|| vectorized::evaluate(&expression, &batches))
+                .flatten()
+            {
+                Some(results) => Ok(Err((results, started.elapsed(), size))),
+                None => dataset::rows(&batches).map(Ok),
+            }
+        })
+        .await
+        .map_err(|e| ErrorData::internal_error(e.to_string(), error_data(&request_id)))?
+        .map_err(invalid)?;
+        let work = match rows {
+            Ok(contexts) => JobWork::Batch(
+                EvaluateBatchParams {
+                    expression: params.expression,
+                    contexts,
+                    merge_strategy: params.merge_strategy,
+                    non_finite: params.non_finite,
+                    cost_limit: params.cost_limit,
+                    strict_attributes: params.strict_attributes,
+                    environment: params.environment,
+                    priority: params.priority,
+                    bypass_cache: params.bypass_cache,
+                    job_id: params.job_id,
+                },
+                Parallelism {
+                    degree,
+                    ordered: !params.unordered,
+                },
+            ),
+            Err((results, evaluation, bytes)) => {
+                tracing::info!("Evaluated {} rows vectorized", results.len());
+                // Only unlimited callers get here, so the rows are charged together afterwards.
+                self.quotas.charge(
+                    &self.account(),
+                    Usage {
+                        evaluations: results.len() as u64,
+                        eval_ms: evaluation.as_secs_f64() * 1000.0,
+                        bytes,
+                    },
+                );
+                JobWork::Done {
+                    job_id: params.job_id,
+                    items: results
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, result)| BatchItem {
+                            result: Some(result.to_string()),
+                            error: None,
+                            index: params.unordered.then_some(index),
+                        })
+                        .collect(),
+                }
+            }
+        };
+        self.start_job(request_id, work).map(Json)
+    }
+}
+
+impl CelTool {
+    /// Completes a partially typed tool argument: `policy` from the policy set names, anything
+    /// else as a CEL expression from the function and variable names.
+    pub fn completions(&self, argument: &str, value: &str) -> CompletionInfo {
+        if argument == "policy" {
+            return completion::complete_name(value, self.policies.get().keys());
+        }
+        let mut functions: BTreeSet<String> = self
+            .profile
+            .function_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let mut variables: BTreeSet<String> =
+            self.profile.declared_variables().keys().cloned().collect();
+        match &self.evaluator {
+            Some(evaluator) => {
+                functions.extend(evaluator.function_names());
+                variables.extend(evaluator.base_context_names());
+            }
+            None => functions.extend(
+                completion::BUILTIN_FUNCTIONS
+                    .iter()
+                    .map(|name| name.to_string()),
+            ),
+        }
+        completion::complete_expression(value, &functions, &variables)
+    }
+
+    /// Streams the current call's log events to the client, if it has set a level, until the
+    /// returned subscription is dropped.
+    fn forward_logs(&self, secrets: &[String]) -> Option<mcp_log::Subscription> {
+        let (level, peer) = self.client_logging.lock().ok()?.clone()?;
+        let (subscription, mut messages) = mcp_log::subscribe(level);
+        tracing::Span::current().record(mcp_log::SINK_FIELD, subscription.key());
+        let redactor = self.redactor.clone();
+        let secrets = secrets.to_vec();
+        tokio::spawn(async move {
+            while let Some(mut message) = messages.recv().await {
+                if let Value::Object(fields) = &mut message.data {
+                    for field in fields.values_mut() {
+                        if let Value::String(text) = field {
+                            *text = redact::mask_secrets(&redactor.redact_log_line(text), &secrets);
+                        }
+                    }
+                }
+                if peer.notify_logging_message(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Some(subscription)
+    }
+}
+
+#[tool_handler(router = self.tool_router)]
+impl ServerHandler for CelTool {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo::new(
+            ServerCapabilities::builder()
+                .enable_tools()
+                .enable_logging()
+                .enable_completions()
+                .build(),
+        )
+        .with_server_info(Implementation::from_build_env())
+        .with_instructions(self.instructions())
+    }
+
+    /// Charges calls over HTTP to the authenticated caller; see [`quota::account`].
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let charged;
+        let tool = match context.extensions.get::<Parts>() {
+            Some(parts) => {
+                charged = self.clone().with_account(quota::account(&parts.extensions));
+                &charged
+            }
+            None => self,
+        };
+        let call = ToolCallContext::new(tool, request, context);
+        tool.tool_router.call(call).await
+    }
+
+    async fn complete(
+        &self,
+        request: CompleteRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CompleteResult, ErrorData> {
+        Ok(CompleteResult::new(self.completions(
+            &request.argument.name,
+            &request.argument.value,
+        )))
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), ErrorData> {
+        let mut client_logging = self
+            .client_logging
+            .lock()
+            .map_err(|_| ErrorData::internal_error("Logging state is poisoned", None))?;
+        *client_logging = Some((request.level, context.peer));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_private_errors_hide_expression() {
+        let tool = CelTool::builder().with_private_errors(true).build();
+        let err = tool
+            .run(
+                "evaluate",
+                "req-1",
+                Query::Expression("undeclared_secret + 1".to_string()),
+                Map::new(),
+                MergeStrategy::Replace,
+                Overrides::default(),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert!(err.message.starts_with("CEL execution error (error id: "));
+        assert!(!err.message.contains("undeclared_secret"));
+        assert_eq!(err.data, Some(serde_json::json!({ "request_id": "req-1" })));
+    }
+
+    #[tokio::test]
+    async fn test_slow_evaluation_is_audited() {
+        let path = std::env::temp_dir().join(format!("cel-slow-{}.jsonl", std::process::id()));
+        let audit = AuditLog::open(&path, crate::audit::AuditFormat::Native).unwrap();
+        let tool = CelTool::builder()
+            .with_audit(Arc::new(audit))
+            .with_slow_eval_threshold(Duration::ZERO)
+            .with_slow_eval_audit(true)
+            .build();
+        tool.run(
+            "evaluate",
+            "req-2",
+            Query::Expression("1 + 1".to_string()),
+            Map::new(),
+            MergeStrategy::Replace,
+            Overrides::default(),
+        )
+        .await
+        .unwrap();
+        let lines = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let slow: Value = serde_json::from_str(lines.lines().next().unwrap()).unwrap();
+        assert_eq!(slow["type"], "slow_evaluation");
+        assert_eq!(
+            slow["expression_hash"],
+            Query::Expression("1+1".to_string()).fingerprint().unwrap()
+        );
+        let record: Value = serde_json::from_str(lines.lines().nth(1).unwrap()).unwrap();
+        assert_eq!(record["fingerprint"], slow["expression_hash"]);
+        assert_eq!(lines.lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_shadow_divergence_is_audited() {
+        let path = std::env::temp_dir().join(format!("cel-shadow-{}.jsonl", std::process::id()));
+        let audit = AuditLog::open(&path, crate::audit::AuditFormat::Native).unwrap();
+        let tool = CelTool::builder().with_audit(Arc::new(audit)).build();
+        let shadow: PolicySet = serde_json::from_value(serde_json::json!({
+            "rules": [{"name": "admins", "condition": "role == 'admin'", "effect": "allow"}]
+        }))
+        .unwrap();
+        let context = serde_json::json!({"role": "admin"});
+        let context = context.as_object().cloned().unwrap_or_default();
+        let active = serde_json::json!({"decision": "allow", "rule": "admins"});
+        tool.compare_shadow(
+            "req-4",
+            "p",
+            &shadow,
+            context.clone(),
+            MergeStrategy::Replace,
+            active,
+        )
+        .await;
+        let diverging = serde_json::json!({"decision": "deny"});
+        tool.compare_shadow(
+            "req-4",
+            "p",
+            &shadow,
+            context,
+            MergeStrategy::Replace,
+            diverging,
+        )
+        .await;
+        let lines = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Two shadow evaluations, one divergence.
+        let records: Vec<Value> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["tool"], "decide_shadow");
+        assert_eq!(records[2]["type"], "shadow_divergence");
+        assert_eq!(records[2]["shadow"]["rule"], "admins");
+    }
+
+    #[tokio::test]
+    async fn test_replay_counts_changed_decisions() {
+        let tool = CelTool::builder().build();
+        let candidate: PolicySet = serde_json::from_value(serde_json::json!({
+            "rules": [{"name": "admins", "condition": "role == 'admin'", "effect": "allow"}]
+        }))
+        .unwrap();
+        let recorded = ["admin", "guest"]
+            .into_iter()
+            .map(|role| RecordedDecision {
+                context: serde_json::json!({ "role": role })
+                    .as_object()
+                    .cloned()
+                    .unwrap_or_default(),
+                merge: MergeStrategy::Replace,
+                decision: serde_json::json!({"decision": "deny"}),
+            })
+            .collect();
+        let result = tool.replay_with("req-5", &candidate, recorded).await;
+        assert_eq!((result.replayed, result.changed), (2, 1));
+        assert_eq!(result.changes[0].index, 0);
+        assert_eq!(result.changes[0].candidate["rule"], "admins");
+    }
+
+    #[tokio::test]
+    async fn test_reload_replaces_settings() {
+        let generation = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = generation.clone();
+        let loader: SettingsLoader = Arc::new(move || {
+            let n = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let mut base_context = Map::new();
+            base_context.insert("generation".to_string(), Value::from(n.to_string()));
+            Ok(crate::reload::Settings {
+                base_context,
+                resolvers: Vec::new(),
+                policies: BTreeMap::from([(format!("p{}", n), PolicySet::default())]),
+            })
+        });
+        let tool = CelTool::builder()
+            .with_settings_loader(loader.clone())
+            .build();
+        let generation_value = || async {
+            tool.query(
+                "evaluate",
+                "req-8",
+                Query::Expression("generation".to_string()),
+                Map::new(),
+                MergeStrategy::Replace,
+            )
+            .await
+            .ok()
+        };
+        assert_eq!(generation_value().await, None);
+
+        let summary = tool.reload().unwrap();
+        assert_eq!(summary.policies, vec!["p0".to_string()]);
+        assert_eq!(generation_value().await, Some(Value::from("0")));
+        tool.reload().unwrap();
+        assert_eq!(generation_value().await, Some(Value::from("1")));
+        assert!(tool.policies.get().contains_key("p1"));
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_rejects_broken_policies() {
+        let policy = |condition: &str| -> PolicySet {
+            serde_json::from_value(serde_json::json!({
+                "rules": [{"name": "r", "condition": condition, "effect": "allow"}]
+            }))
+            .unwrap()
+        };
+        let builder = CelTool::builder().with_policy_set("ok", policy("true"));
+        assert_eq!(builder.warm_up(), Ok(1));
+        let builder = builder.with_policy_set("broken", policy("a ||"));
+        let errors = builder.warm_up().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("policy `broken` rule `r`: CEL compile error"));
+
+        let loader: SettingsLoader = Arc::new(move || {
+            Ok(crate::reload::Settings {
+                base_context: Map::new(),
+                resolvers: Vec::new(),
+                policies: BTreeMap::from([("broken".to_string(), policy("a ||"))]),
+            })
+        });
+        let tool = CelTool::builder()
+            .with_settings_loader(loader)
+            .with_fail_on_compile_error(true)
+            .build();
+        assert!(tool.reload().is_err());
+        assert!(tool.policies.get().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_admin_tools_are_opt_in() {
+        let tool = CelTool::builder()
+            .with_kv_store(Arc::new(KvStore::in_memory()))
+            .build();
+        let names = |tool: &CelTool| -> Vec<String> {
+            tool.tool_router
+                .list_all()
+                .into_iter()
+                .map(|t| t.name.to_string())
+                .collect()
+        };
+        let regular = names(&tool);
+        assert!(regular.contains(&"evaluate".to_string()));
+        assert!(!regular.contains(&"reload".to_string()));
+        assert!(!regular.contains(&"replay".to_string()));
+        let admin = names(&tool.clone().with_admin_tools());
+        assert!(admin.contains(&"reload".to_string()));
+        assert!(admin.contains(&"replay".to_string()));
+
+        let read_only = tool.with_read_only();
+        assert!(read_only.instructions().contains("Read-only"));
+        let admin = names(&read_only.clone().with_admin_tools());
+        for name in MUTATING_TOOLS {
+            assert!(!admin.contains(&name.to_string()), "{}", name);
+        }
+        assert!(admin.contains(&"replay".to_string()));
+        assert!(admin.contains(&"kv_list".to_string()));
+        assert_eq!(read_only.reload().unwrap_err(), "The server is read-only");
+    }
+
+    #[tokio::test]
+    async fn test_tool_annotations() {
+        let tool = CelTool::builder()
+            .with_kv_store(Arc::new(KvStore::in_memory()))
+            .build()
+            .with_admin_tools();
+        let tools = tool.tool_router.list_all();
+        assert!(tools.iter().all(|t| t.annotations.is_some()), "{:?}", tools);
+        let hints = |name: &str| {
+            let annotations = tools
+                .iter()
+                .find(|t| t.name == name)
+                .and_then(|t| t.annotations.clone())
+                .unwrap();
+            (
+                annotations.read_only_hint,
+                annotations.destructive_hint,
+                annotations.idempotent_hint,
+            )
+        };
+        assert_eq!(hints("evaluate"), (Some(true), None, Some(true)));
+        assert_eq!(hints("kv_list"), (Some(true), None, Some(true)));
+        assert_eq!(hints("kv_delete"), (Some(false), Some(true), Some(true)));
+        assert_eq!(hints("reload"), (Some(false), Some(true), Some(true)));
+        assert_eq!(hints("submit_job"), (Some(false), Some(false), Some(false)));
+    }
+
+    #[tokio::test]
+    async fn test_tool_selection() {
+        let tools = ToolsConfig {
+            enabled: Some(vec!["evaluate".to_string(), "decide".to_string()]),
+            disabled: vec!["decide".to_string()],
+        };
+        let tool = CelTool::builder()
+            .build()
+            .with_tool_selection(&tools)
+            .unwrap();
+        let names: Vec<String> = tool
+            .tool_router
+            .list_all()
+            .into_iter()
+            .map(|t| t.name.to_string())
+            .collect();
+        assert_eq!(names, vec!["evaluate".to_string()]);
+
+        let unknown = ToolsConfig {
+            enabled: None,
+            disabled: vec!["nope".to_string()],
+        };
+        let err = CelTool::builder()
+            .build()
+            .with_tool_selection(&unknown)
+            .err()
+            .unwrap();
+        assert!(err.starts_with("Unknown tool `nope`"));
+    }
+
+    #[tokio::test]
+    async fn test_instructions_describe_deployment() {
+        let mut base_context = Map::new();
+        base_context.insert("tenant".to_string(), Value::from("acme"));
+        let tool = CelTool::builder()
+            .with_base_context(&base_context)
+            .with_policy_set("documents", PolicySet::default())
+            .with_extension_description("WASM plugin functions `add`")
+            .with_limits(Limits {
+                max_context_depth: Some(8),
+                ..Limits::default()
+            })
+            .build();
+        let instructions = tool.instructions();
+        assert!(
+            instructions
+                .contains("Tools: `cancel_job`, `compose`, `decide`, `describe_ast`, `evaluate`,")
+        );
+        assert!(instructions.contains("Base context variables, present in every evaluation unless the context sets them: `tenant`."));
+        assert!(instructions.contains("Policy sets for `decide`: `documents`."));
+        assert!(instructions.contains("Extension: WASM plugin functions `add`."));
+        assert!(instructions.contains("Limits: context nesting up to 8 levels."));
+        assert!(!instructions.contains("resolver"));
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_batch_job() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let tool = CelTool::builder().build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let batch = session
+            .call(
+                "evaluate_batch",
+                json!({
+                    "expression": "10u / n",
+                    "contexts": [{"n": 2}, {"n": 0}, {"n": 5}],
+                    "job_id": "nightly",
+                }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(batch["job_id"], "nightly");
+        assert_eq!(batch["state"], "completed");
+        assert_eq!(batch["results"][0], json!({"result": "5"}));
+        assert!(batch["results"][1]["error"].is_string());
+        assert_eq!(batch["results"][2], json!({"result": "2"}));
+
+        let status = session
+            .call("job_status", json!({"job_id": "nightly"}))
+            .await
+            .unwrap();
+        assert_eq!(
+            status,
+            json!({"job_id": "nightly", "state": "completed", "total": 3, "completed": 3, "failed": 1})
+        );
+        let err = session
+            .call("cancel_job", json!({"job_id": "weekly"}))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.message, "Unknown job `weekly`");
+
+        let submitted = session
+            .call(
+                "submit_job",
+                json!({"expression": "n > 1u", "contexts": [{"n": 1}, {"n": 2}], "job_id": "bg"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(submitted["job_id"], "bg");
+        let result = loop {
+            let result = session
+                .call("get_job_result", json!({"job_id": "bg"}))
+                .await
+                .unwrap();
+            if result["state"] == "completed" {
+                break result;
+            }
+            tokio::task::yield_now().await;
+        };
+        assert_eq!(
+            result["results"],
+            json!([{"result": "false"}, {"result": "true"}])
+        );
+        let page = session
+            .call(
+                "get_job_result",
+                json!({"job_id": "bg", "offset": 0, "limit": 1}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(page["results"], json!([{"result": "false"}]));
+        assert_eq!(page["next_offset"], 1);
+
+        let page = session
+            .call(
+                "evaluate",
+                json!({"expression": "[1, 2, 3, 4, 5]", "context": {}, "offset": 1, "limit": 2}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            page,
+            json!({"result": "[2,3]", "total": 5, "next_offset": 3})
+        );
+        let err = session
+            .call(
+                "evaluate",
+                json!({"expression": "1", "context": {}, "limit": 2}),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert!(err.message.contains("list results only"));
+        let err = session
+            .call("get_job_result", json!({"job_id": "nightly"}))
+            .await
+            .err()
+            .unwrap();
+        assert!(err.message.contains("returned by its evaluate_batch call"));
+        session.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_evaluations_stop() {
+        let tool = CelTool::builder()
+            .with_limits(Limits {
+                timeout: Some(Duration::from_millis(100)),
+                ..Limits::default()
+            })
+            .build();
+        let mut context = Map::new();
+        context.insert("l".to_string(), (0..1000).collect::<Vec<i64>>().into());
+        let evaluate = |expression: &str| {
+            tool.query(
+                "evaluate",
+                "req-t",
+                Query::Expression(expression.to_string()),
+                context.clone(),
+                MergeStrategy::Replace,
+            )
+        };
+        // A billion iterations: the evaluator only gets to the next request if it gives up.
+        let err = evaluate("l.all(a, l.all(b, l.all(c, a + b + c >= 0)))")
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("timed out"));
+        let next = tokio::time::timeout(Duration::from_secs(30), evaluate("size(l)"))
+            .await
+            .unwrap();
+        assert_eq!(next.unwrap(), serde_json::json!(1000));
+    }
+
+    #[tokio::test]
+    async fn test_results_are_truncated() {
+        let tool = CelTool::builder()
+            .with_limits(Limits {
+                max_result_bytes: Some(16),
+                ..Limits::default()
+            })
+            .build();
+        let evaluate = |expression: &str| {
+            tool.run(
+                "evaluate",
+                "req-t",
+                Query::Expression(expression.to_string()),
+                Map::new(),
+                MergeStrategy::Replace,
+                Overrides::default(),
+            )
+        };
+        let result = evaluate("[1, 2, 3]").await.unwrap();
+        assert_eq!(
+            serde_json::to_value(result.0).unwrap(),
+            serde_json::json!({"result": "[1,2,3]"})
+        );
+        let result = evaluate("[100, 200, 300, 400, 500, 600]").await.unwrap();
+        assert_eq!(
+            serde_json::to_value(result.0).unwrap(),
+            serde_json::json!({
+                "result": "[100,200,300]",
+                "truncated": true,
+                "original_bytes": 25,
+                "omitted_elements": 3,
+                "shortened_strings": 0,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_describe_ast_and_disabled_macros() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let tool = CelTool::builder()
+            .with_disabled_macros([Macro::Map])
+            .build();
+        assert!(
+            tool.instructions()
+                .contains("Disabled macros, rejected at compile time: `map`.")
+        );
+        let session = TestClient::connect(&tool).await.unwrap();
+        let described = session
+            .call("describe_ast", json!({"expression": "l.exists(x, x > 1)"}))
+            .await
+            .unwrap();
+        assert_eq!(described["macros"], json!(["exists"]));
+        let comprehension = &described["ast"];
+        assert_eq!(comprehension["macro"], "exists");
+        assert_eq!(comprehension["comprehension"]["iter_var"], "x");
+        assert_eq!(
+            comprehension["comprehension"]["iter_range"],
+            json!({"ident": "l"})
+        );
+
+        let err = session
+            .call(
+                "evaluate",
+                json!({"expression": "[1].map(x, x * 2)", "context": {}}),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("macro `map` is disabled"));
+    }
+
+    #[tokio::test]
+    async fn test_environment_per_request() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let environment: Environment = serde_json::from_value(json!({
+            "base_context": {"cluster": "prod"},
+            "disabled_macros": ["map"],
+            "variables": {"count": "int"}
+        }))
+        .unwrap();
+        let tool = CelTool::builder()
+            .with_environment("k8s", environment)
+            .build();
+        assert!(
+            tool.instructions()
+                .contains("Environments, selected with `environment`: `k8s`.")
+        );
+        let schema = tool
+            .tool_router
+            .get("evaluate")
+            .unwrap()
+            .input_schema
+            .clone();
+        assert_eq!(
+            schema["properties"]["environment"]["enum"],
+            json!(["k8s", null])
+        );
+        assert_eq!(
+            schema["allOf"][0]["then"]["properties"]["context"]["properties"]["count"],
+            json!({"type": "integer"})
+        );
+        let schema = tool
+            .tool_router
+            .get("evaluate_batch")
+            .unwrap()
+            .input_schema
+            .clone();
+        assert_eq!(
+            schema["allOf"][0]["then"]["properties"]["contexts"]["items"]["properties"]["count"],
+            json!({"type": "integer"})
+        );
+        let session = TestClient::connect(&tool).await.unwrap();
+        let evaluate = async |params: Value| session.call("evaluate", params).await;
+        let result =
+            evaluate(json!({"expression": "cluster", "context": {}, "environment": "k8s"}))
+                .await
+                .unwrap();
+        assert_eq!(result["result"], "\"prod\"");
+        assert!(
+            evaluate(json!({"expression": "cluster", "context": {}}))
+                .await
+                .is_err()
+        );
+        let err =
+            evaluate(json!({"expression": "[1].map(x, x)", "context": {}, "environment": "k8s"}))
+                .await
+                .unwrap_err();
+        assert!(err.message.contains("macro `map` is disabled"));
+        let err = evaluate(json!({"expression": "1", "context": {}, "environment": "gke"}))
+            .await
+            .unwrap_err();
+        assert_eq!(err.message, "Unknown environment `gke` (available: k8s)");
+        let err = evaluate(
+            json!({"expression": "count", "context": {"count": true}, "environment": "k8s"}),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.message,
+            "`count` is declared `int`, but the context has a bool"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_variables_are_elicited() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let environment: Environment =
+            serde_json::from_value(json!({"variables": {"limit": "int", "roles": "list<string>"}}))
+                .unwrap();
+        let tool = CelTool::builder()
+            .with_environment("quota", environment)
+            .build();
+        let params = json!({
+            "expression": "count < limit && 'admin' in roles",
+            "context": {"count": 3},
+            "environment": "quota",
+        });
+
+        let session = TestClient::connect(&tool).await.unwrap();
+        let err = session.call("evaluate", params.clone()).await.unwrap_err();
+        assert_eq!(err.data.unwrap()["missing_variable"], "limit");
+
+        let answers = json!({"limit": 5, "roles": "[\"admin\"]"});
+        let session = TestClient::connect_eliciting(&tool, answers.as_object().cloned().unwrap())
+            .await
+            .unwrap();
+        let result = session.call("evaluate", params.clone()).await.unwrap();
+        assert_eq!(result["result"], "true");
+
+        let answers = json!({"limit": "five"});
+        let session = TestClient::connect_eliciting(&tool, answers.as_object().cloned().unwrap())
+            .await
+            .unwrap();
+        let err = session.call("evaluate", params.clone()).await.unwrap_err();
+        assert_eq!(
+            err.message,
+            "`limit` is declared `int`, but the context has a string"
+        );
+
+        let session = TestClient::connect_eliciting(&tool, Map::new())
+            .await
+            .unwrap();
+        let err = session.call("evaluate", params).await.unwrap_err();
+        assert!(err.message.contains("Undeclared reference to 'limit'"));
+    }
+
+    #[tokio::test]
+    async fn test_repair_suggestions() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let broken = json!({"expression": "size(items) >", "context": {"items": [1]}});
+        let tool = CelTool::builder().with_repair_suggestions(true).build();
+        let session = TestClient::connect_sampling(&tool, "```cel\nsize(items) > 0\n```")
+            .await
+            .unwrap();
+        let err = session.call("evaluate", broken.clone()).await.unwrap_err();
+        assert!(err.message.starts_with("CEL "));
+        assert_eq!(err.data.unwrap()["suggestion"], "size(items) > 0");
+
+        let session = TestClient::connect_sampling(&tool, "size(items) >")
+            .await
+            .unwrap();
+        let err = session.call("evaluate", broken.clone()).await.unwrap_err();
+        assert!(err.data.unwrap().get("suggestion").is_none());
+
+        let tool = CelTool::builder().build();
+        let session = TestClient::connect_sampling(&tool, "size(items) > 0")
+            .await
+            .unwrap();
+        let err = session.call("evaluate", broken).await.unwrap_err();
+        assert!(err.data.unwrap().get("suggestion").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let tool = CelTool::builder().build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let dry_run =
+            |expression: &str| json!({"expression": expression, "context": {}, "dry_run": true});
+        let result = session
+            .call("evaluate", dry_run("items.all(i, i > limit)"))
+            .await
+            .unwrap();
+        assert!(result.get("result").is_none());
+        assert_eq!(result["dry_run"]["compiled"], true);
+        assert_eq!(result["dry_run"]["variables"], json!(["items", "limit"]));
+        assert_eq!(result["dry_run"]["cached"], false);
+        assert!(result["dry_run"]["estimated_cost"].as_u64().unwrap() > 0);
+
+        session
+            .call(
+                "evaluate",
+                json!({"expression": "items.all(i, i > limit)", "context": {"items": [2], "limit": 1}}),
+            )
+            .await
+            .unwrap();
+        let result = session
+            .call("evaluate", dry_run("items.all(i, i > limit)"))
+            .await
+            .unwrap();
+        assert_eq!(result["dry_run"]["cached"], true);
+
+        let result = session
+            .call("evaluate", dry_run("size(items) >"))
+            .await
+            .unwrap();
+        assert_eq!(result["dry_run"]["compiled"], false);
+        assert!(
+            result["dry_run"]["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("CEL ")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registered_expressions() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let tool = CelTool::builder().build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let registered = session
+            .call(
+                "register_expression",
+                json!({
+                    "name": "too_many",
+                    "expression": "size(items) > {threshold} || {owner} in admins",
+                    "parameters": {"threshold": "int", "owner": "string"},
+                }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(registered, json!({"name": "too_many", "replaced": false}));
+        let listed = session.call("list_expressions", json!({})).await.unwrap();
+        assert_eq!(
+            listed["expressions"][0]["parameters"],
+            json!({"owner": "string", "threshold": "int"})
+        );
+
+        let evaluate = |owner: &str| {
+            json!({
+                "name": "too_many",
+                "arguments": {"threshold": 2, "owner": owner},
+                "context": {"items": [1, 2], "admins": ["root"]},
+            })
+        };
+        let result = session
+            .call("evaluate_registered", evaluate("root"))
+            .await
+            .unwrap();
+        assert_eq!(result["result"], "true");
+        let result = session
+            .call(
+                "evaluate_registered",
+                evaluate("x' in admins || true || 'y"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["result"], "false");
+
+        let err = session
+            .call(
+                "evaluate_registered",
+                json!({"name": "too_many", "arguments": {"threshold": "2", "owner": "root"}, "context": {}}),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("`threshold` is declared `int`"));
+        let err = session
+            .call(
+                "register_expression",
+                json!({"name": "bad", "expression": "x > {min}", "parameters": {}}),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("`{min}`"));
+    }
+
+    #[tokio::test]
+    async fn test_session_context_is_limited() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let tool = CelTool::builder()
+            .with_limits(Limits {
+                max_context_bytes: Some(64),
+                ..Limits::default()
+            })
+            .build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let set = |name: &str| json!({"context": {name: "x".repeat(40)}});
+        session.call("set_context", set("a")).await.unwrap();
+        let err = session.call("set_context", set("b")).await.unwrap_err();
+        assert!(err.message.contains("64"), "{}", err.message);
+        let set = session
+            .call("set_context", json!({"context": {}}))
+            .await
+            .unwrap();
+        assert_eq!(set, json!({"variables": ["a"]}));
+    }
+
+    #[tokio::test]
+    async fn test_session_context() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let mut base_context = Map::new();
+        base_context.insert("limits".to_string(), json!({"cpu": 1, "memory": 2}));
+        let tool = CelTool::builder().with_base_context(&base_context).build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let set = session
+            .call(
+                "set_context",
+                json!({"context": {"user": {"name": "alice", "role": "dev"}, "tier": "gold"}}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(set, json!({"variables": ["tier", "user"]}));
+        let evaluate = |expression: &str, context: Value, merge_strategy: &str| {
+            json!({
+                "expression": expression,
+                "context": context,
+                "merge_strategy": merge_strategy,
+            })
+        };
+        let result = |value: Value| {
+            serde_json::from_str::<Value>(value["result"].as_str().unwrap()).unwrap()
+        };
+
+        // The request context over the session's.
+        let replaced = session
+            .call(
+                "evaluate",
+                evaluate(
+                    "has(user.name) ? 'kept' : tier + ' ' + user.role",
+                    json!({"user": {"role": "ops"}}),
+                    "replace",
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result(replaced), "gold ops");
+        let deep = session
+            .call(
+                "evaluate",
+                evaluate(
+                    "user.name + ' ' + user.role",
+                    json!({"user": {"role": "ops"}}),
+                    "deep",
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result(deep), "alice ops");
+        let unset = session
+            .call(
+                "evaluate",
+                evaluate(
+                    "has(user.role) || tier == 'gold'",
+                    json!({"user": {"role": null}, "tier": null}),
+                    "merge_patch",
+                ),
+            )
+            .await
+            .unwrap_err();
+        assert!(unset.message.contains("tier"));
+
+        // The session's context over the base context.
+        session
+            .call(
+                "set_context",
+                json!({"context": {"limits": {"cpu": 4}}, "merge_strategy": "deep"}),
+            )
+            .await
+            .unwrap();
+        let replaced = session
+            .call(
+                "evaluate",
+                evaluate("has(limits.memory)", json!({}), "replace"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result(replaced), false);
+        let deep = session
+            .call(
+                "evaluate",
+                evaluate("limits.cpu + limits.memory", json!({}), "deep"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result(deep), 6);
+
+        let set = session
+            .call(
+                "set_context",
+                json!({"context": {"user": {"role": null}, "tier": null}, "merge_strategy": "merge_patch"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(set, json!({"variables": ["limits", "user"]}));
+        let patched = session
+            .call("evaluate", evaluate("user", json!({}), "replace"))
+            .await
+            .unwrap();
+        assert_eq!(result(patched), json!({"name": "alice"}));
+
+        let other = TestClient::connect(&tool).await.unwrap();
+        let err = other
+            .call("evaluate", evaluate("user.name", json!({}), "replace"))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("user"));
+    }
+
+    #[tokio::test]
+    async fn test_compose() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let tool = CelTool::builder().build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        for (name, expression, parameters) in [
+            ("big", "size(items) > {max}", json!({"max": "int"})),
+            ("frozen", "frozen", json!({})),
+        ] {
+            let registered =
+                json!({"name": name, "expression": expression, "parameters": parameters});
+            session
+                .call("register_expression", registered)
+                .await
+                .unwrap();
+        }
+        let composition = json!({"or": [{"expression": "big"}, {"not": {"expression": "frozen"}}]});
+        let composed = session
+            .call("compose", json!({"composition": composition}))
+            .await
+            .unwrap();
+        assert_eq!(
+            composed,
+            json!({
+                "expression": "((size(items) > {max}) || !(frozen))",
+                "parameters": {"max": "int"},
+            })
+        );
+        let registered = session
+            .call(
+                "register_composition",
+                json!({"name": "editable", "composition": composition}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(registered, json!({"name": "editable", "replaced": false}));
+        let result = session
+            .call(
+                "evaluate_registered",
+                json!({
+                    "name": "editable",
+                    "arguments": {"max": 1},
+                    "context": {"items": [1], "frozen": true},
+                }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["result"], "false");
+
+        let read_only = TestClient::connect(&tool.clone().with_read_only())
+            .await
+            .unwrap();
+        let composition = json!({"not": {"expression": "frozen"}});
+        assert!(
+            read_only
+                .call("compose", json!({"composition": composition}))
+                .await
+                .is_ok()
+        );
+        assert!(
+            read_only
+                .call(
+                    "register_composition",
+                    json!({"name": "thawed", "composition": composition})
+                )
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quote_value() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let tool = CelTool::builder().build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let quote = |value: Value, declared: Option<&'static str>| {
+            let session = &session;
+            async move {
+                session
+                    .call("quote_value", json!({"value": value, "type": declared}))
+                    .await
+            }
+        };
+        assert_eq!(
+            quote(json!({"name": "a\"b", "ids": [1, 2]}), None)
+                .await
+                .unwrap()["literal"],
+            "{\"ids\": [1, 2], \"name\": \"a\\\"b\"}"
+        );
+        assert_eq!(
+            quote(json!(["2024-01-01T00:00:00Z"]), Some("list<timestamp>"))
+                .await
+                .unwrap()["literal"],
+            "[timestamp(\"2024-01-01T00:00:00Z\")]"
+        );
+        assert!(quote(json!(-1), Some("uint")).await.is_err());
+        assert!(quote(json!(1), Some("nope")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_rules() {
+        use crate::rewrite::RewriteRule;
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let rewriter = Rewriter::new(vec![
+            RewriteRule::RenameFunction {
+                from: "count".to_string(),
+                to: "size".to_string(),
+            },
+            RewriteRule::WrapVariable {
+                name: "created".to_string(),
+                function: "timestamp".to_string(),
+            },
+        ])
+        .unwrap();
+        let tool = CelTool::builder().with_rewriter(rewriter).build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let result = session
+            .call(
+                "evaluate",
+                json!({
+                    "expression": "count(items) > 1 && created < timestamp('2030-01-01T00:00:00Z')",
+                    "context": {"items": [1, 2], "created": "2024-05-01T00:00:00Z"},
+                }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["result"], "true");
+        assert_eq!(
+            result["rewritten_expression"],
+            "size(items) > 1 && timestamp(created) < timestamp(\"2030-01-01T00:00:00Z\")"
+        );
+
+        let result = session
+            .call(
+                "evaluate_projection",
+                json!({"outputs": {"n": "count(items)"}, "context": {"items": [1]}}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["result"], "{\"n\":1}");
+
+        let result = session
+            .call(
+                "evaluate",
+                json!({"expression": "size(items)", "context": {"items": []}}),
+            )
+            .await
+            .unwrap();
+        assert!(result.get("rewritten_expression").is_none());
+
+        // Dry runs check the rewritten expression, and return it.
+        let result = session
+            .call(
+                "evaluate",
+                json!({"expression": "count(items) > n", "context": {}, "dry_run": true}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["dry_run"]["compiled"], true);
+        assert_eq!(result["dry_run"]["variables"], json!(["items", "n"]));
+        assert_eq!(result["rewritten_expression"], "size(items) > n");
+        let rename = Rewriter::new(vec![RewriteRule::RenameFunction {
+            from: "count".to_string(),
+            to: "size".to_string(),
+        }])
+        .unwrap();
+        let banned = CelTool::builder()
+            .with_rewriter(rename)
+            .with_deny_list(DenyList {
+                identifiers: Default::default(),
+                functions: ["size".to_string()].into(),
+            })
+            .build();
+        let session = TestClient::connect(&banned).await.unwrap();
+        let result = session
+            .call(
+                "evaluate",
+                json!({"expression": "count(items)", "context": {}, "dry_run": true}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["dry_run"]["compiled"], false);
+        assert!(
+            result["dry_run"]["error"]
+                .as_str()
+                .unwrap()
+                .contains("`size` is a banned function")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quota() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let tool = CelTool::builder()
+            .with_quota(Quota {
+                max_evaluations: Some(2),
+                ..Quota::default()
+            })
+            .build();
+        assert!(tool.instructions().contains(
+            "Each account may use 2 evaluations per UTC day; `usage` reports what it has used."
+        ));
+        let session = TestClient::connect(&tool).await.unwrap();
+        let params = json!({"expression": "size(name)", "context": {"name": "cel"}});
+        for _ in 0..2 {
+            session.call("evaluate", params.clone()).await.unwrap();
+        }
+        let err = session.call("evaluate", params.clone()).await.unwrap_err();
+        assert!(
+            err.message
+                .starts_with("Quota exceeded: `max_evaluations` is 2 per UTC day")
+        );
+        assert_eq!(err.data.unwrap()["reason"], "quota_exceeded");
+
+        let usage = session.call("usage", json!({})).await.unwrap();
+        assert!(usage["account"].as_str().unwrap().starts_with("session-"));
+        assert_eq!(usage["used"]["evaluations"], 2);
+        assert_eq!(usage["used"]["bytes"], 2 * (4 + 8 + 3));
+        assert_eq!(usage["quota"]["max_evaluations"], 2);
+
+        let other = TestClient::connect(&tool).await.unwrap();
+        other.call("evaluate", params).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_deny_list() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+        use std::collections::BTreeSet;
+
+        let tool = CelTool::builder()
+            .with_deny_list(DenyList {
+                identifiers: BTreeSet::from(["secret".to_string()]),
+                functions: BTreeSet::from(["matches".to_string()]),
+            })
+            .build();
+        assert!(
+            tool.instructions()
+                .contains("Banned identifiers, rejected before compiling: `secret`.")
+        );
+        let session = TestClient::connect(&tool).await.unwrap();
+        let err = session
+            .call(
+                "evaluate",
+                json!({"expression": "secret.key == '' || name.matches('^a')", "context": {}}),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.message.starts_with(
+            "Policy violation: `secret` is a banned identifier; `matches` is a banned function"
+        ));
+
+        let result = session
+            .call(
+                "evaluate",
+                json!({"expression": "secret", "context": {}, "dry_run": true}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["dry_run"]["compiled"], false);
+
+        let result = session
+            .call(
+                "evaluate",
+                json!({"expression": "items.all(secret, secret > 0)", "context": {"items": [1]}}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["result"], "true");
+    }
+
+    #[tokio::test]
+    async fn test_error_messages_are_reworded() {
+        use crate::messages::ErrorKind;
+
+        let tool = CelTool::builder()
+            .with_error_messages(ErrorMessages::new(BTreeMap::from([(
+                ErrorKind::CompileError,
+                "式が不正です ({request_id})".to_string(),
+            )])))
+            .build();
+        let query = |expression: &str| {
+            tool.query(
+                "evaluate",
+                "req-9",
+                Query::Expression(expression.to_string()),
+                Map::new(),
+                MergeStrategy::Replace,
+            )
+        };
+        assert_eq!(
+            query("1 +").await.unwrap_err().message,
+            "式が不正です (req-9)"
+        );
+        assert!(
+            query("1 / 0")
+                .await
+                .unwrap_err()
+                .message
+                .starts_with("CEL execution error")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_result_cache_answers_repeats() {
+        let cache = Arc::new(ResultCache::new(16, None));
+        let tool = CelTool::builder().with_result_cache(cache.clone()).build();
+        let query = |expression: &str, name: &str, bypass_cache| {
+            tool.query_with(
+                "evaluate",
+                "req-9",
+                Query::Expression(expression.to_string()),
+                serde_json::json!({"name": name})
+                    .as_object()
+                    .cloned()
+                    .unwrap(),
+                MergeStrategy::Replace,
+                Overrides {
+                    bypass_cache,
+                    ..Overrides::default()
+                },
+            )
+        };
+        assert_eq!(
+            query("'hi ' + name", "a", false).await.ok(),
+            Some(serde_json::json!("hi a"))
+        );
+        assert_eq!(
+            query("'hi '+name", "a", false).await.ok(),
+            Some(serde_json::json!("hi a"))
+        );
+        assert_eq!(
+            query("'hi ' + name", "b", false).await.ok(),
+            Some(serde_json::json!("hi b"))
+        );
+        assert_eq!(
+            query("'hi ' + name", "b", true).await.ok(),
+            Some(serde_json::json!("hi b"))
+        );
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (2, 1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_kv_admin_tools_feed_expressions() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let tool = CelTool::builder()
+            .with_kv_store(Arc::new(KvStore::in_memory()))
+            .with_result_cache(Arc::new(ResultCache::new(16, None)))
+            .build();
+        let admin = TestClient::connect(&tool.clone().with_admin_tools())
+            .await
+            .unwrap();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let blocked = json!({"expression": "kv.exists('blocked') && user in kv.get('blocked')", "context": {"user": "mallory"}});
+        assert_eq!(
+            session.call("evaluate", blocked.clone()).await.unwrap()["result"],
+            "false"
+        );
+        let put = admin
+            .call("kv_put", json!({"key": "blocked", "value": ["mallory"]}))
+            .await
+            .unwrap();
+        assert_eq!(put, json!({"key": "blocked", "existed": false}));
+        assert_eq!(
+            session.call("evaluate", blocked).await.unwrap()["result"],
+            "true"
+        );
+        assert_eq!(
+            admin.call("kv_list", json!({})).await.unwrap(),
+            json!({"keys": ["blocked"]})
+        );
+        assert!(
+            session
+                .call("kv_put", json!({"key": "x", "value": 1}))
+                .await
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "object-store")]
+    #[tokio::test]
+    async fn test_context_from_bucket_object() {
+        use crate::config::ObjectStoreConfig;
+        use crate::object_store::ObjectStore;
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let app = axum::Router::new().route(
+            "/tenants/eu/acme.json",
+            axum::routing::get(|| async { r#"{"tier": "gold", "region": "eu"}"# }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let store = ObjectStore::new(ObjectStoreConfig {
+            allow: vec!["s3://tenants/eu/".to_string()],
+            s3_endpoint: Some(endpoint),
+            ..ObjectStoreConfig::default()
+        });
+        let tool = CelTool::builder()
+            .with_object_store(Arc::new(store))
+            .build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let result = session
+            .call(
+                "evaluate",
+                json!({
+                    "expression": "tier == 'gold' && region == 'us'",
+                    "context": {"region": "us"},
+                    "context_uri": "s3://tenants/eu/acme.json"
+                }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["result"], "true");
+        assert!(
+            session
+                .call(
+                    "evaluate",
+                    json!({"expression": "true", "context": {}, "context_uri": "s3://tenants/us/acme.json"}),
+                )
+                .await
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "arrow")]
+    #[tokio::test]
+    async fn test_evaluate_dataset_quota() {
+        use crate::test_util::TestClient;
+        use arrow_array::{ArrayRef, Int64Array, RecordBatch};
+        use serde_json::json;
+
+        let batch = RecordBatch::try_from_iter([(
+            "n",
+            Arc::new(Int64Array::from(vec![1, 5, 7])) as ArrayRef,
+        )])
+        .unwrap();
+        let mut parquet = Vec::new();
+        let mut writer =
+            parquet::arrow::ArrowWriter::try_new(&mut parquet, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        let data = BASE64_STANDARD.encode(&parquet);
+        let results = |tool: CelTool| {
+            let params = json!({"expression": "n > 2", "data": data});
+            async move {
+                let session = TestClient::connect(&tool).await.unwrap();
+                let submitted = session.call("evaluate_dataset", params).await.unwrap();
+                let job_id = submitted["job_id"].clone();
+                let result = loop {
+                    let result = session
+                        .call("get_job_result", json!({"job_id": job_id}))
+                        .await
+                        .unwrap();
+                    if result["state"] == "completed" {
+                        break result;
+                    }
+                    tokio::task::yield_now().await;
+                };
+                let usage = session.call("usage", json!({})).await.unwrap();
+                (
+                    result["results"].clone(),
+                    usage["used"]["evaluations"].clone(),
+                )
+            }
+        };
+
+        // Unlimited, the rows are evaluated vectorized and charged together.
+        let (unlimited, evaluations) = results(CelTool::builder().build()).await;
+        assert_eq!(
+            unlimited,
+            json!([{"result": "false"}, {"result": "true"}, {"result": "true"}])
+        );
+        assert_eq!(evaluations, 3);
+
+        // With a quota, each row is checked, and those past it fail.
+        let limited = CelTool::builder()
+            .with_quota(Quota {
+                max_evaluations: Some(2),
+                ..Quota::default()
+            })
+            .build();
+        let (limited, evaluations) = results(limited).await;
+        assert_eq!(limited[0], json!({"result": "false"}));
+        assert_eq!(limited[1], json!({"result": "true"}));
+        assert!(
+            limited[2]["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Quota exceeded")
+        );
+        assert_eq!(evaluations, 2);
+    }
+
+    #[cfg(feature = "arrow")]
+    #[tokio::test]
+    async fn test_evaluate_dataset() {
+        use crate::test_util::TestClient;
+        use arrow_array::{ArrayRef, Int64Array, RecordBatch};
+        use serde_json::json;
+
+        let batch =
+            RecordBatch::try_from_iter([("n", Arc::new(Int64Array::from(vec![1, 5])) as ArrayRef)])
+                .unwrap();
+        let mut parquet = Vec::new();
+        let mut writer =
+            parquet::arrow::ArrowWriter::try_new(&mut parquet, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        let dir = std::env::temp_dir().join(format!("cel-datasets-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("rows.parquet"), &parquet).unwrap();
+
+        let tool = CelTool::builder().with_dataset_dir(&dir).build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let submitted = session
+            .call(
+                "evaluate_dataset",
+                json!({"expression": "n > 2", "data": BASE64_STANDARD.encode(&parquet), "job_id": "rows"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(submitted["total"], 2);
+        // `n > 2` runs vectorized, the function call row by row, with the same results.
+        session
+            .call(
+                "evaluate_dataset",
+                json!({"expression": "string(n) != '1'", "data": BASE64_STANDARD.encode(&parquet), "job_id": "rows_by_row"}),
+            )
+            .await
+            .unwrap();
+        session
+            .call(
+                "evaluate_dataset",
+                json!({"expression": "string(n) != '1'", "data": BASE64_STANDARD.encode(&parquet), "parallelism": 2, "job_id": "rows_parallel"}),
+            )
+            .await
+            .unwrap();
+        session
+            .call(
+                "evaluate_dataset",
+                json!({"expression": "string(n) != '1'", "data": BASE64_STANDARD.encode(&parquet), "parallelism": 2, "unordered": true, "job_id": "rows_unordered"}),
+            )
+            .await
+            .unwrap();
+        for job_id in ["rows", "rows_by_row", "rows_parallel", "rows_unordered"] {
+            let result = loop {
+                let result = session
+                    .call("get_job_result", json!({"job_id": job_id}))
+                    .await
+                    .unwrap();
+                if result["state"] == "completed" {
+                    break result;
+                }
+                tokio::task::yield_now().await;
+            };
+            let mut results = result["results"].as_array().unwrap().clone();
+            if job_id == "rows_unordered" {
+                results.sort_by_key(|item| item["index"].as_u64());
+                for item in &mut results {
+                    item.as_object_mut().unwrap().remove("index");
+                }
+            }
+            assert_eq!(
+                Value::Array(results),
+                json!([{"result": "false"}, {"result": "true"}])
+            );
+        }
+        assert!(
+            session
+                .call(
+                    "evaluate_dataset",
+                    json!({"expression": "n", "data": BASE64_STANDARD.encode(&parquet), "parallelism": 0}),
+                )
+                .await
+                .is_err()
+        );
+
+        let submitted = session
+            .call(
+                "evaluate_dataset",
+                json!({"expression": "n", "path": "rows.parquet"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(submitted["total"], 2);
+        let outside = dir.with_extension("parquet");
+        std::fs::write(&outside, &parquet).unwrap();
+        let escape = format!("../{}", outside.file_name().unwrap().to_string_lossy());
+        let err = session
+            .call(
+                "evaluate_dataset",
+                json!({"expression": "n", "path": escape}),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert!(err.message.ends_with("is outside the dataset directory"));
+
+        let root =
+            |dir: &std::path::Path| format!("file://{}", dir.canonicalize().unwrap().display());
+        let session = TestClient::connect_with_roots(&tool, vec![root(&dir)])
+            .await
+            .unwrap();
+        let submitted = session
+            .call(
+                "evaluate_dataset",
+                json!({"expression": "n", "path": "rows.parquet"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(submitted["total"], 2);
+        let elsewhere = std::env::temp_dir().join(format!("cel-root-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&elsewhere).unwrap();
+        let session = TestClient::connect_with_roots(&tool, vec![root(&elsewhere)])
+            .await
+            .unwrap();
+        let err = session
+            .call(
+                "evaluate_dataset",
+                json!({"expression": "n", "path": "rows.parquet"}),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.message.ends_with("is outside the client's roots"));
+        std::fs::remove_dir_all(&elsewhere).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_file(&outside).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evaluation_panic_is_contained() {
+        let tool = CelTool::builder()
+            .with_function_provider(|ctx: &mut cel::Context| {
+                ctx.add_function("boom", || -> i64 { panic!("boom") });
+            })
+            .build();
+        let query = |expression: &str| {
+            tool.query(
+                "evaluate",
+                "req-10",
+                Query::Expression(expression.to_string()),
+                Map::new(),
+                MergeStrategy::Replace,
+            )
+        };
+        let err = query("boom()").await.unwrap_err();
+        assert_eq!(err.message, "Internal evaluation panic: boom");
+        assert_eq!(query("1 + 1").await.unwrap(), Value::from(2));
+        assert_eq!(tool.evaluator.as_ref().unwrap().panic_count(), 1);
+        assert!(
+            tool.metrics()
+                .contains("\ncel_mcp_evaluation_panics_total 1\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expression_fingerprint() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let session = TestClient::connect(&CelTool::builder().build())
+            .await
+            .unwrap();
+        let fingerprint = async |expression: &str| {
+            session
+                .call("expression_fingerprint", json!({"expression": expression}))
+                .await
+                .map(|result| result["fingerprint"].clone())
+        };
+        let plain = fingerprint("a.b > 1 && c").await.unwrap();
+        assert_eq!(
+            plain,
+            fingerprint("(a.b>1)\n  && (c) // why").await.unwrap()
+        );
+        assert_ne!(plain, fingerprint("a.b > 1u && c").await.unwrap());
+        assert!(fingerprint("a >").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cost_limit() {
+        use crate::test_util::TestClient;
+        use serde_json::json;
+
+        let tool = CelTool::builder()
+            .with_limits(Limits {
+                max_cost: Some(200),
+                ..Limits::default()
+            })
+            .build();
+        let session = TestClient::connect(&tool).await.unwrap();
+        let evaluate = |expression: &str, cost_limit: Option<u64>| {
+            session.call(
+                "evaluate",
+                json!({"expression": expression, "context": {}, "cost_limit": cost_limit}),
+            )
+        };
+        let loop_over = "[1, 2, 3, 4, 5, 6, 7, 8, 9, 10].map(x, x * 2).size()";
+        assert!(evaluate(loop_over, None).await.is_ok());
+        let err = evaluate(loop_over, Some(20)).await.unwrap_err();
+        assert!(err.message.contains("cost budget exceeded"));
+        let err = evaluate(&format!("{0} + {0}", loop_over), Some(10_000))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("evaluation cost is over 200"));
+    }
+
+    #[tokio::test]
+    async fn test_completions() {
+        let mut base_context = Map::new();
+        base_context.insert("tenant".to_string(), Value::from("acme"));
+        let tool = CelTool::builder()
+            .with_profile(Profile::Firestore)
+            .with_base_context(&base_context)
+            .with_function_provider(FnNames)
+            .with_policy_set("documents", PolicySet::default())
+            .with_policy_set("tenants", PolicySet::default())
+            .build();
+        assert_eq!(tool.completions("expression", "te").values, vec!["tenant"]);
+        assert_eq!(
+            tool.completions("expression", "ge").values,
+            vec![
+                "get",
+                "getDate",
+                "getDayOfMonth",
+                "getDayOfWeek",
+                "getDayOfYear",
+                "getFullYear",
+                "getHours",
+                "getMilliseconds",
+                "getMinutes",
+                "getMonth",
+                "getSeconds"
+            ]
+        );
+        assert_eq!(
+            tool.completions("expression", "tenant.sh").values,
+            vec!["tenant.shout"]
+        );
+        assert_eq!(tool.completions("policy", "d").values, vec!["documents"]);
+    }
+
+    struct FnNames;
+
+    impl FunctionProvider for FnNames {
+        fn register(&self, ctx: &mut cel::Context) {
+            ctx.add_function("shout", |s: Arc<String>| s.to_uppercase());
+        }
+
+        fn names(&self) -> Vec<String> {
+            vec!["shout".to_string()]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_finite_override_per_request() {
+        let tool = CelTool::builder().build();
+        let nan = |non_finite| {
+            tool.query_with(
+                "evaluate",
+                "req-9",
+                Query::Expression("[0.0 / 0.0, 1.0 / 0.0]".to_string()),
+                Map::new(),
+                MergeStrategy::Replace,
+                Overrides {
+                    non_finite,
+                    ..Overrides::default()
+                },
+            )
+        };
+        assert_eq!(nan(None).await.ok(), Some(serde_json::json!([null, null])));
+        assert_eq!(
+            nan(Some(NonFinite::String)).await.ok(),
+            Some(serde_json::json!(["NaN", "Infinity"]))
+        );
+        assert!(nan(Some(NonFinite::Error)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_too_deep_context_is_rejected() {
+        let tool = CelTool::builder()
+            .with_limits(Limits {
+                max_context_depth: Some(2),
+                ..Limits::default()
+            })
+            .build();
+        let context = serde_json::json!({"a": {"b": {"c": 1}}});
+        let err = tool
+            .run(
+                "evaluate",
+                "req-3",
+                Query::Expression("true".to_string()),
+                context.as_object().cloned().unwrap_or_default(),
+                MergeStrategy::Replace,
+                Overrides::default(),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.data,
+            Some(serde_json::json!({
+                "request_id": "req-3",
+                "reason": "context_too_deep",
+                "limit": 2,
+            }))
+        );
     }
 }
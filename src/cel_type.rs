@@ -0,0 +1,210 @@
+//! CEL type names such as `int` or `map<string, dyn>`, as declared for context variables.
+//!
+//! A declaration is checked against the JSON a request sends, so a mistyped variable fails
+//! with a message naming it rather than a confusing error deep in an expression, and it can be
+//! rendered as a JSON Schema describing what to send.
+
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{Value, json};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rmcp::serde", try_from = "String")]
+pub enum CelType {
+    Dyn,
+    Null,
+    Bool,
+    Int,
+    Uint,
+    Double,
+    String,
+    Bytes,
+    Timestamp,
+    Duration,
+    List(Box<CelType>),
+    Map(Box<CelType>, Box<CelType>),
+}
+
+impl CelType {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (name, params) = match text.split_once('<') {
+            Some((name, rest)) => {
+                let params = rest
+                    .strip_suffix('>')
+                    .ok_or_else(|| format!("Unclosed `<` in type `{}`", text))?;
+                (name.trim(), Some(split_params(params)))
+            }
+            None => (text, None),
+        };
+        let parsed = match (name, params.as_deref()) {
+            ("dyn", None) => CelType::Dyn,
+            ("null_type", None) => CelType::Null,
+            ("bool", None) => CelType::Bool,
+            ("int", None) => CelType::Int,
+            ("uint", None) => CelType::Uint,
+            ("double", None) => CelType::Double,
+            ("string", None) => CelType::String,
+            ("bytes", None) => CelType::Bytes,
+            ("timestamp" | "google.protobuf.Timestamp", None) => CelType::Timestamp,
+            ("duration" | "google.protobuf.Duration", None) => CelType::Duration,
+            ("list", None) => CelType::List(Box::new(CelType::Dyn)),
+            ("map", None) => CelType::Map(Box::new(CelType::Dyn), Box::new(CelType::Dyn)),
+            ("list", Some([element])) => CelType::List(Box::new(CelType::parse(element)?)),
+            ("map", Some([key, value])) => {
+                let key = CelType::parse(key)?;
+                if !matches!(
+                    key,
+                    CelType::String | CelType::Int | CelType::Uint | CelType::Bool | CelType::Dyn
+                ) {
+                    return Err(format!("`{}` cannot be a map key type", key));
+                }
+                CelType::Map(Box::new(key), Box::new(CelType::parse(value)?))
+            }
+            _ => return Err(format!("Unknown type `{}`", text)),
+        };
+        Ok(parsed)
+    }
+
+    /// Fails, naming `path` and what was found, if `value` cannot be sent as this type.
+    /// Timestamps, durations and bytes travel as strings, and map keys as object keys.
+    pub fn check(&self, path: &str, value: &Value) -> Result<(), String> {
+        let matches = match (self, value) {
+            (CelType::Dyn, _) | (CelType::Null, Value::Null) | (CelType::Bool, Value::Bool(_)) => {
+                true
+            }
+            (CelType::Int, Value::Number(n)) => n.is_i64(),
+            (CelType::Uint, Value::Number(n)) => n.is_u64(),
+            (CelType::Double, Value::Number(_)) => true,
+            (
+                CelType::String | CelType::Bytes | CelType::Timestamp | CelType::Duration,
+                Value::String(_),
+            ) => true,
+            (CelType::List(element), Value::Array(items)) => {
+                for (i, item) in items.iter().enumerate() {
+                    element.check(&format!("{}[{}]", path, i), item)?;
+                }
+                true
+            }
+            (CelType::Map(_, value_type), Value::Object(entries)) => {
+                for (key, item) in entries {
+                    value_type.check(&format!("{}.{}", path, key), item)?;
+                }
+                true
+            }
+            _ => false,
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(format!(
+                "`{}` is declared `{}`, but the context has {}",
+                path,
+                self,
+                describe(value)
+            ))
+        }
+    }
+
+    /// A JSON Schema for values of this type as sent in a context.
+    pub fn json_schema(&self) -> Value {
+        match self {
+            CelType::Dyn => json!({}),
+            CelType::Null => json!({"type": "null"}),
+            CelType::Bool => json!({"type": "boolean"}),
+            CelType::Int => json!({"type": "integer"}),
+            CelType::Uint => json!({"type": "integer", "minimum": 0}),
+            CelType::Double => json!({"type": "number"}),
+            CelType::String | CelType::Bytes => json!({"type": "string"}),
+            CelType::Timestamp => json!({"type": "string", "format": "date-time"}),
+            CelType::Duration => json!({"type": "string", "pattern": "^-?[0-9.]+(h|m|s|ms|us|ns)"}),
+            CelType::List(element) => json!({"type": "array", "items": element.json_schema()}),
+            CelType::Map(_, value) => {
+                json!({"type": "object", "additionalProperties": value.json_schema()})
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for CelType {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        CelType::parse(&text)
+    }
+}
+
+impl fmt::Display for CelType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CelType::Dyn => f.write_str("dyn"),
+            CelType::Null => f.write_str("null_type"),
+            CelType::Bool => f.write_str("bool"),
+            CelType::Int => f.write_str("int"),
+            CelType::Uint => f.write_str("uint"),
+            CelType::Double => f.write_str("double"),
+            CelType::String => f.write_str("string"),
+            CelType::Bytes => f.write_str("bytes"),
+            CelType::Timestamp => f.write_str("timestamp"),
+            CelType::Duration => f.write_str("duration"),
+            CelType::List(element) => write!(f, "list<{}>", element),
+            CelType::Map(key, value) => write!(f, "map<{}, {}>", key, value),
+        }
+    }
+}
+
+/// Splits type parameters at top-level commas.
+fn split_params(params: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in params.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&params[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&params[start..]);
+    parts
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(_) => "a bool".to_string(),
+        Value::Number(n) if n.is_f64() => format!("the number {}", n),
+        Value::Number(n) => format!("the integer {}", n),
+        Value::String(_) => "a string".to_string(),
+        Value::Array(_) => "a list".to_string(),
+        Value::Object(_) => "a map".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_check() {
+        let request = CelType::parse("map<string, list<int>>").unwrap();
+        assert_eq!(request.to_string(), "map<string, list<int>>");
+        assert!(request.check("request", &json!({"ids": [1, 2]})).is_ok());
+        assert_eq!(
+            request.check("request", &json!({"ids": [1, 2.5]})),
+            Err(
+                "`request.ids[1]` is declared `int`, but the context has the number 2.5"
+                    .to_string()
+            )
+        );
+        assert!(CelType::parse("map<list<int>, int>").is_err());
+        assert!(CelType::parse("int<string>").is_err());
+        assert_eq!(
+            CelType::parse("list<uint>").unwrap().json_schema(),
+            json!({"type": "array", "items": {"type": "integer", "minimum": 0}})
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! `crc32(data)` and `adler32(data)`, lightweight integrity checks of bytes or strings (their
+//! UTF-8 bytes), returned as uints. `checksum.crc32(data)` and `checksum.adler32(data)` are
+//! aliases.
+//!
+//! Neither is a cryptographic hash: both catch accidental corruption, not tampering.
+
+use crate::provider::{Method, Namespace};
+use cel::{FunctionContext, Value as CelValue};
+use std::sync::Arc;
+
+/// The namespace the aliases are called on.
+pub const VARIABLE: &str = "checksum";
+
+type Checksum = (&'static str, fn(&[u8]) -> u32);
+
+const CHECKSUMS: [Checksum; 2] = [("crc32", crc32fast::hash), ("adler32", adler32)];
+
+pub(crate) fn names() -> Vec<String> {
+    CHECKSUMS
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .chain(
+            CHECKSUMS
+                .iter()
+                .map(|(name, _)| format!("{}.{}", VARIABLE, name)),
+        )
+        .collect()
+}
+
+pub(crate) fn functions() -> Vec<(String, Method)> {
+    CHECKSUMS
+        .iter()
+        .map(|&(name, checksum)| {
+            let method: Method = Arc::new(move |ftx: &FunctionContext, args: &[CelValue]| {
+                let data = match args {
+                    [CelValue::Bytes(bytes)] => bytes.as_slice(),
+                    [CelValue::String(s)] => s.as_bytes(),
+                    _ => {
+                        return Err(
+                            ftx.error(format!("{} expects one bytes or string value", name))
+                        );
+                    }
+                };
+                Ok(CelValue::UInt(checksum(data).into()))
+            });
+            (name.to_string(), method)
+        })
+        .collect()
+}
+
+pub(crate) fn namespace() -> Namespace {
+    Namespace {
+        name: VARIABLE.to_string(),
+        methods: functions(),
+    }
+}
+
+/// Adler-32, as in RFC 1950.
+fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before `b` could overflow.
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += u32::from(*byte);
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::library::Library;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&[0xff; 100_000]), 0x149a_302c);
+    }
+
+    #[test]
+    fn test_checksum_functions() {
+        let evaluator = Evaluator::new().with_function_provider(Library::Checksum);
+        let evaluate =
+            |expression: &str| evaluator.evaluate(expression, &json!({}), Profile::Default);
+        assert_eq!(evaluate("crc32('123456789')"), Ok(json!(0xcbf4_3926u32)));
+        assert_eq!(
+            evaluate("crc32(b'123456789') == crc32('123456789')"),
+            Ok(json!(true))
+        );
+        assert_eq!(evaluate("adler32('Wikipedia')"), Ok(json!(0x11e6_0398u32)));
+        assert!(evaluate("crc32(1)").is_err());
+        assert!(evaluate("'123456789'.crc32()").is_err());
+
+        // The namespace form is an alias.
+        assert_eq!(
+            evaluate("checksum.crc32('123456789') == crc32('123456789')"),
+            Ok(json!(true))
+        );
+        assert_eq!(
+            evaluate("checksum.adler32('Wikipedia')"),
+            Ok(json!(0x11e6_0398u32))
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Suggestions for MCP `completion/complete` while a client's user types arguments: CEL
+//! function and variable names inside expressions, and policy set names.
+
+use rmcp::model::CompletionInfo;
+use std::collections::BTreeSet;
+
+/// Functions and macros every evaluation context has.
+pub const BUILTIN_FUNCTIONS: &[&str] = &[
+    "all",
+    "bytes",
+    "contains",
+    "double",
+    "duration",
+    "endsWith",
+    "exists",
+    "exists_one",
+    "filter",
+    "getDate",
+    "getDayOfMonth",
+    "getDayOfWeek",
+    "getDayOfYear",
+    "getFullYear",
+    "getHours",
+    "getMilliseconds",
+    "getMinutes",
+    "getMonth",
+    "getSeconds",
+    "has",
+    "int",
+    "map",
+    "matches",
+    "max",
+    "min",
+    "size",
+    "startsWith",
+    "string",
+    "timestamp",
+    "uint",
+];
+
+/// Completes the identifier `expression` ends with. After a `.` only functions are offered,
+/// as the identifier is then a member or method name. Returns whole argument values.
+pub fn complete_expression(
+    expression: &str,
+    functions: &BTreeSet<String>,
+    variables: &BTreeSet<String>,
+) -> CompletionInfo {
+    let start = expression
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map_or(expression.len(), |(i, _)| i);
+    let (head, word) = expression.split_at(start);
+    let candidates: Box<dyn Iterator<Item = &String>> = if head.ends_with('.') {
+        Box::new(functions.iter())
+    } else {
+        Box::new(functions.union(variables))
+    };
+    let matches: Vec<String> = candidates
+        .filter(|name| name.starts_with(word) && name.as_str() != word)
+        .map(|name| format!("{}{}", head, name))
+        .collect();
+    page(matches)
+}
+
+/// Completes `value` as the start of one of `names`.
+pub fn complete_name<'a>(
+    value: &str,
+    names: impl IntoIterator<Item = &'a String>,
+) -> CompletionInfo {
+    page(
+        names
+            .into_iter()
+            .filter(|name| name.starts_with(value))
+            .cloned()
+            .collect(),
+    )
+}
+
+fn page(mut values: Vec<String>) -> CompletionInfo {
+    let total = values.len();
+    values.truncate(CompletionInfo::MAX_VALUES);
+    CompletionInfo {
+        values,
+        total: u32::try_from(total).ok(),
+        has_more: Some(total > CompletionInfo::MAX_VALUES),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_expression() {
+        let functions: BTreeSet<String> = BUILTIN_FUNCTIONS.iter().map(|f| f.to_string()).collect();
+        let variables: BTreeSet<String> = ["request".to_string(), "resource".to_string()].into();
+
+        let completion = complete_expression("size(re", &functions, &variables);
+        assert_eq!(completion.values, vec!["size(request", "size(resource"]);
+        assert_eq!(completion.total, Some(2));
+
+        let completion = complete_expression("request.name.sta", &functions, &variables);
+        assert_eq!(completion.values, vec!["request.name.startsWith"]);
+        assert!(
+            complete_expression("x.re", &functions, &variables)
+                .values
+                .is_empty()
+        );
+
+        let completion = complete_expression("", &functions, &variables);
+        assert_eq!(completion.values.len(), BUILTIN_FUNCTIONS.len() + 2);
+        assert_eq!(completion.has_more, Some(false));
+    }
+}
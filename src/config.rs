@@ -0,0 +1,238 @@
+//! The `--config` file: a JSON document with server settings that don't fit on the command line.
+
+use crate::admission::AdmissionConfig;
+use crate::deny::DenyList;
+use crate::environment::Environment;
+use crate::keys::Scope;
+use crate::messages::ErrorMessagesConfig;
+use crate::policy::PolicySet;
+use crate::quota::Quota;
+use crate::rewrite::RewriteRule;
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct Config {
+    /// Sources consulted, in order, for variables missing from a request context.
+    #[serde(default)]
+    pub resolvers: Vec<ResolverConfig>,
+    /// Key patterns whose values are masked in logs, audit records and error messages.
+    #[serde(default)]
+    pub redact: Vec<String>,
+    /// Policy sets for the `decide` tool, by name.
+    #[serde(default)]
+    pub policies: BTreeMap<String, PolicySet>,
+    /// Validations served at `/admission` as a Kubernetes admission webhook (with `--http`).
+    #[serde(default)]
+    pub admission: Option<AdmissionConfig>,
+    /// Which MCP tools regular sessions are offered.
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    /// Environments requests may select with `environment`, by name.
+    #[serde(default)]
+    pub environments: BTreeMap<String, Environment>,
+    /// Wording of the error messages clients see.
+    #[serde(default)]
+    pub error_messages: ErrorMessagesConfig,
+    /// Rules rewriting every expression before it is compiled, applied in order.
+    #[serde(default)]
+    pub rewrites: Vec<RewriteRule>,
+    /// Identifiers and functions expressions may not use.
+    #[serde(default)]
+    pub deny: DenyList,
+    /// What each account may use per window.
+    #[serde(default)]
+    pub quota: Quota,
+    /// Client certificate identities accepted with `--client-ca`, and what they may do.
+    #[serde(default)]
+    pub client_certs: BTreeMap<String, ClientCertConfig>,
+    /// Bucket objects requests may read as contexts and datasets.
+    #[serde(default)]
+    pub object_store: Option<ObjectStoreConfig>,
+    /// Enables the `http.get(url)` function.
+    #[serde(default)]
+    pub http_function: Option<HttpFunctionConfig>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct ToolsConfig {
+    /// Offer only these tools; all tools when absent.
+    #[serde(default)]
+    pub enabled: Option<Vec<String>>,
+    /// Hide these tools.
+    #[serde(default)]
+    pub disabled: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct ClientCertConfig {
+    pub scopes: BTreeSet<Scope>,
+    /// Replaces the server's quota for the certificate's requests.
+    #[serde(default)]
+    pub quota: Option<Quota>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(
+    crate = "rmcp::serde",
+    tag = "type",
+    rename_all = "snake_case",
+    deny_unknown_fields
+)]
+pub enum ResolverConfig {
+    /// Reads variable `name` from the environment variable `<prefix><name>`.
+    Env { prefix: String },
+    /// Reads variables from a JSON object file, loaded once at startup.
+    File { path: PathBuf },
+    /// Fetches variable `name` with `GET <url>/<name>`; only names in `allow` are looked up.
+    Http {
+        url: String,
+        allow: Vec<String>,
+        #[serde(default = "default_http_timeout_ms")]
+        timeout_ms: u64,
+    },
+}
+
+fn default_http_timeout_ms() -> u64 {
+    1000
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct ObjectStoreConfig {
+    /// URI prefixes, such as `s3://bucket/reports/`, of the objects that may be read.
+    pub allow: Vec<String>,
+    #[serde(default = "default_s3_region")]
+    pub s3_region: String,
+    /// An S3-compatible endpoint, such as `http://localhost:9000`, addressed path-style.
+    #[serde(default)]
+    pub s3_endpoint: Option<String>,
+    /// Replaces `https://storage.googleapis.com`.
+    #[serde(default)]
+    pub gcs_endpoint: Option<String>,
+    #[serde(default = "default_object_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Objects larger than this are rejected.
+    #[serde(default = "default_object_max_bytes")]
+    pub max_bytes: u64,
+}
+
+impl Default for ObjectStoreConfig {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            s3_region: default_s3_region(),
+            s3_endpoint: None,
+            gcs_endpoint: None,
+            timeout_ms: default_object_timeout_ms(),
+            max_bytes: default_object_max_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct HttpFunctionConfig {
+    /// Hosts `http.get` may fetch from, such as `api.example.com`, or `*.example.com` for
+    /// its subdomains.
+    pub allow_domains: Vec<String>,
+    /// Responses larger than this are rejected.
+    #[serde(default = "default_http_function_max_bytes")]
+    pub max_bytes: u64,
+    #[serde(default = "default_http_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Calls each evaluation request may make.
+    #[serde(default = "default_http_function_max_calls")]
+    pub max_calls: u32,
+}
+
+impl Default for HttpFunctionConfig {
+    fn default() -> Self {
+        Self {
+            allow_domains: Vec::new(),
+            max_bytes: default_http_function_max_bytes(),
+            timeout_ms: default_http_timeout_ms(),
+            max_calls: default_http_function_max_calls(),
+        }
+    }
+}
+
+fn default_http_function_max_bytes() -> u64 {
+    1 << 20
+}
+
+fn default_http_function_max_calls() -> u32 {
+    8
+}
+
+fn default_s3_region() -> String {
+    std::env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string())
+}
+
+fn default_object_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_object_max_bytes() -> u64 {
+    256 << 20
+}
+
+/// Reads a file containing a single JSON object.
+pub fn read_json_object(path: &Path) -> Result<Map<String, Value>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    match rmcp::serde_json::from_str(&text) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(_) => Err(format!("{} must contain a JSON object", path.display())),
+        Err(e) => Err(format!("Invalid JSON in {}: {}", path.display(), e)),
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        let config: Self = rmcp::serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        for (name, policy) in &config.policies {
+            policy
+                .validate()
+                .map_err(|e| format!("Invalid policy `{}` in {}: {}", name, path.display(), e))?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resolvers() {
+        let config: Config = rmcp::serde_json::from_str(
+            r#"{"resolvers": [
+                {"type": "env", "prefix": "CEL_VAR_"},
+                {"type": "http", "url": "http://localhost:9000/vars", "allow": ["tenant"]}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.resolvers,
+            vec![
+                ResolverConfig::Env {
+                    prefix: "CEL_VAR_".to_string()
+                },
+                ResolverConfig::Http {
+                    url: "http://localhost:9000/vars".to_string(),
+                    allow: vec!["tenant".to_string()],
+                    timeout_ms: 1000,
+                },
+            ]
+        );
+    }
+}
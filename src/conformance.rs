@@ -0,0 +1,380 @@
+//! Runs cel-spec conformance tests (`SimpleTestFile` textproto files) against this crate's
+//! interpreter.
+//!
+//! There is no type checker, so tests that only make sense with one (`check_only`, unknowns)
+//! are reported as skipped rather than failed.
+
+pub mod textproto;
+
+use cel::objects::{Key, Map as CelMap};
+use cel::{Context, Program, Value as CelValue};
+use chrono::{DateTime, TimeDelta};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use textproto::{FieldValue, Message};
+
+/// How one conformance test fared.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Skip(String),
+}
+
+/// The outcome of one test, named `file/section/test`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+/// Runs a single `.textproto` file, or every `.textproto` file under a directory.
+pub fn run_suite(path: &Path) -> Result<Vec<TestResult>, String> {
+    let mut files = Vec::new();
+    collect_files(path, &mut files)?;
+    files.sort();
+    let mut results = Vec::new();
+    for file in files {
+        let input = fs::read_to_string(&file)
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        let parsed = textproto::parse(&input).map_err(|e| format!("{}: {}", file.display(), e))?;
+        results.extend(run_file(&parsed));
+    }
+    Ok(results)
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let entries =
+        fs::read_dir(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "textproto") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Runs every test in a parsed `SimpleTestFile`.
+pub fn run_file(file: &Message) -> Vec<TestResult> {
+    let file_name = file.string("name").unwrap_or_default();
+    let mut results = Vec::new();
+    for section in file.all("section") {
+        let FieldValue::Message(section) = section else {
+            continue;
+        };
+        let section_name = section.string("name").unwrap_or_default();
+        for test in section.all("test") {
+            let FieldValue::Message(test) = test else {
+                continue;
+            };
+            results.push(TestResult {
+                name: format!(
+                    "{}/{}/{}",
+                    file_name,
+                    section_name,
+                    test.string("name").unwrap_or_default()
+                ),
+                outcome: run_test(test),
+            });
+        }
+    }
+    results
+}
+
+/// Runs one `SimpleTest`.
+pub fn run_test(test: &Message) -> Outcome {
+    if test.flag("disable_macros") {
+        return Outcome::Skip("disable_macros is not supported".to_string());
+    }
+    if test.flag("check_only") {
+        return Outcome::Skip("no type checker".to_string());
+    }
+    if test.string("container").is_some_and(|c| !c.is_empty()) {
+        return Outcome::Skip("containers are not supported".to_string());
+    }
+    if test.get("unknown").is_some() || test.get("any_unknowns").is_some() {
+        return Outcome::Skip("unknown values are not supported".to_string());
+    }
+
+    let expected = if test.get("eval_error").is_some() || test.get("any_eval_errors").is_some() {
+        None
+    } else {
+        let value = test.message("value").or_else(|| {
+            test.message("typed_result")
+                .and_then(|t| t.message("result"))
+        });
+        match value.map(value_from_message).transpose() {
+            Ok(value) => Some(value.unwrap_or(CelValue::Bool(true))),
+            Err(e) => return Outcome::Skip(e),
+        }
+    };
+
+    let mut ctx = Context::default();
+    for binding in test.all("bindings") {
+        let FieldValue::Message(binding) = binding else {
+            continue;
+        };
+        let name = binding.string("key").unwrap_or_default();
+        let Some(value) = binding.message("value").and_then(|v| v.message("value")) else {
+            return Outcome::Skip(format!("binding `{}` is not a value", name));
+        };
+        match value_from_message(value) {
+            Ok(value) => ctx.add_variable_from_value(name, value),
+            Err(e) => return Outcome::Skip(e),
+        }
+    }
+
+    let expression = test.string("expr").unwrap_or_default();
+    let program = match Program::compile(&expression) {
+        Ok(program) => program,
+        Err(e) => return Outcome::Fail(format!("CEL compile error: {}", e)),
+    };
+    match (program.execute(&ctx), expected) {
+        (Ok(actual), Some(expected)) if same_value(&actual, &expected) => Outcome::Pass,
+        (Ok(actual), Some(expected)) => {
+            Outcome::Fail(format!("expected {:?}, got {:?}", expected, actual))
+        }
+        (Ok(actual), None) => Outcome::Fail(format!("expected an error, got {:?}", actual)),
+        (Err(_), None) => Outcome::Pass,
+        (Err(e), Some(expected)) => Outcome::Fail(format!(
+            "expected {:?}, got CEL execution error: {}",
+            expected, e
+        )),
+    }
+}
+
+/// Strict equality: no cross-type numeric equality, and NaN matches NaN.
+fn same_value(a: &CelValue, b: &CelValue) -> bool {
+    match (a, b) {
+        (CelValue::Float(x), CelValue::Float(y)) => x == y || (x.is_nan() && y.is_nan()),
+        (CelValue::List(x), CelValue::List(y)) => {
+            x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| same_value(x, y))
+        }
+        (CelValue::Map(x), CelValue::Map(y)) => {
+            x.map.len() == y.map.len()
+                && x.map
+                    .iter()
+                    .all(|(k, v)| y.map.get(k).is_some_and(|w| same_value(v, w)))
+        }
+        (CelValue::Int(_), CelValue::Int(_))
+        | (CelValue::UInt(_), CelValue::UInt(_))
+        | (CelValue::String(_), CelValue::String(_))
+        | (CelValue::Bytes(_), CelValue::Bytes(_))
+        | (CelValue::Bool(_), CelValue::Bool(_))
+        | (CelValue::Null, CelValue::Null)
+        | (CelValue::Duration(_), CelValue::Duration(_))
+        | (CelValue::Timestamp(_), CelValue::Timestamp(_)) => a == b,
+        _ => false,
+    }
+}
+
+fn scalar<'a>(message: &'a Message, field: &str) -> Result<&'a str, String> {
+    match message.get(field) {
+        Some(FieldValue::Scalar(s)) => Ok(s),
+        other => Err(format!("{}: expected a scalar, got {:?}", field, other)),
+    }
+}
+
+fn bytes<'a>(message: &'a Message, field: &str) -> Result<&'a [u8], String> {
+    match message.get(field) {
+        Some(FieldValue::Str(s)) => Ok(s),
+        other => Err(format!("{}: expected a string, got {:?}", field, other)),
+    }
+}
+
+fn parse_int<T: TryFrom<i128>>(text: &str) -> Result<T, String> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let magnitude = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i128::from_str_radix(hex, 16),
+        None => digits.parse::<i128>(),
+    }
+    .map_err(|e| format!("invalid integer {}: {}", text, e))?;
+    let value = if negative { -magnitude } else { magnitude };
+    T::try_from(value).map_err(|_| format!("integer out of range: {}", text))
+}
+
+fn parse_double(text: &str) -> Result<f64, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "inf" | "infinity" => Ok(f64::INFINITY),
+        "-inf" | "-infinity" => Ok(f64::NEG_INFINITY),
+        "nan" => Ok(f64::NAN),
+        _ => text
+            .trim_end_matches(['f', 'F'])
+            .parse()
+            .map_err(|e| format!("invalid double {}: {}", text, e)),
+    }
+}
+
+/// Converts a textproto `google.api.expr.v1alpha1.Value` into a CEL value.
+fn value_from_message(value: &Message) -> Result<CelValue, String> {
+    let Some((kind, field)) = value.fields.first() else {
+        return Ok(CelValue::Null);
+    };
+    Ok(match (kind.as_str(), field) {
+        ("null_value", _) => CelValue::Null,
+        ("bool_value", _) => CelValue::Bool(scalar(value, kind)? == "true"),
+        ("int64_value", _) => CelValue::Int(parse_int(scalar(value, kind)?)?),
+        ("uint64_value", _) => CelValue::UInt(parse_int(scalar(value, kind)?)?),
+        ("double_value", _) => CelValue::Float(parse_double(scalar(value, kind)?)?),
+        ("string_value", _) => CelValue::String(Arc::new(
+            String::from_utf8(bytes(value, kind)?.to_vec()).map_err(|e| e.to_string())?,
+        )),
+        ("bytes_value", _) => CelValue::Bytes(Arc::new(bytes(value, kind)?.to_vec())),
+        ("enum_value", FieldValue::Message(e)) => CelValue::Int(match e.get("value") {
+            Some(_) => parse_int(scalar(e, "value")?)?,
+            None => 0,
+        }),
+        ("list_value", FieldValue::Message(list)) => CelValue::List(Arc::new(
+            list.all("values")
+                .map(|v| match v {
+                    FieldValue::Message(v) => value_from_message(v),
+                    other => Err(format!("invalid list element: {:?}", other)),
+                })
+                .collect::<Result<_, _>>()?,
+        )),
+        ("map_value", FieldValue::Message(map)) => {
+            let mut entries = HashMap::new();
+            for entry in map.all("entries") {
+                let FieldValue::Message(entry) = entry else {
+                    return Err(format!("invalid map entry: {:?}", entry));
+                };
+                let key = match entry.message("key").map(value_from_message).transpose()? {
+                    Some(CelValue::String(s)) => Key::String(s),
+                    Some(CelValue::Int(i)) => Key::Int(i),
+                    Some(CelValue::UInt(u)) => Key::Uint(u),
+                    Some(CelValue::Bool(b)) => Key::Bool(b),
+                    other => return Err(format!("unsupported map key: {:?}", other)),
+                };
+                let value = match entry.message("value") {
+                    Some(value) => value_from_message(value)?,
+                    None => CelValue::Null,
+                };
+                entries.insert(key, value);
+            }
+            CelValue::Map(CelMap {
+                map: Arc::new(entries),
+            })
+        }
+        ("object_value", FieldValue::Message(any)) => object_from_any(any)?,
+        (other, _) => return Err(format!("unsupported value kind: {}", other)),
+    })
+}
+
+/// Converts the well-known types CEL maps onto its own values; other messages are unsupported.
+fn object_from_any(any: &Message) -> Result<CelValue, String> {
+    let Some((type_url, FieldValue::Message(body))) = any.fields.first() else {
+        return Err("object_value without a type URL".to_string());
+    };
+    let type_name = type_url
+        .trim_matches(['[', ']'])
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let field_or_zero = |field: &str| -> Result<i64, String> {
+        match body.get(field) {
+            Some(_) => parse_int(scalar(body, field)?),
+            None => Ok(0),
+        }
+    };
+    Ok(match type_name {
+        "google.protobuf.Duration" => {
+            let seconds = TimeDelta::try_seconds(field_or_zero("seconds")?)
+                .ok_or_else(|| "Duration out of range".to_string())?;
+            CelValue::Duration(seconds + TimeDelta::nanoseconds(field_or_zero("nanos")?))
+        }
+        "google.protobuf.Timestamp" => {
+            let nanos = u32::try_from(field_or_zero("nanos")?).map_err(|e| e.to_string())?;
+            let utc = DateTime::from_timestamp(field_or_zero("seconds")?, nanos)
+                .ok_or_else(|| "Timestamp out of range".to_string())?;
+            CelValue::Timestamp(utc.fixed_offset())
+        }
+        "google.protobuf.Int32Value" | "google.protobuf.Int64Value" => {
+            CelValue::Int(field_or_zero("value")?)
+        }
+        "google.protobuf.UInt32Value" | "google.protobuf.UInt64Value" => {
+            CelValue::UInt(match body.get("value") {
+                Some(_) => parse_int(scalar(body, "value")?)?,
+                None => 0,
+            })
+        }
+        "google.protobuf.DoubleValue" | "google.protobuf.FloatValue" => {
+            CelValue::Float(match body.get("value") {
+                Some(_) => parse_double(scalar(body, "value")?)?,
+                None => 0.0,
+            })
+        }
+        "google.protobuf.BoolValue" => CelValue::Bool(body.flag("value")),
+        "google.protobuf.StringValue" => {
+            CelValue::String(Arc::new(body.string("value").unwrap_or_default()))
+        }
+        "google.protobuf.BytesValue" => CelValue::Bytes(Arc::new(match body.get("value") {
+            Some(_) => bytes(body, "value")?.to_vec(),
+            None => Vec::new(),
+        })),
+        other => return Err(format!("unsupported message type: {}", other)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+        name: "basic"
+        section {
+          name: "arith"
+          test { name: "add" expr: "x + 1u" bindings { key: "x" value { value { uint64_value: 1 } } } value { uint64_value: 2 } }
+          test { name: "div_zero" expr: "1 / 0" eval_error { errors { message: "divide by zero" } } }
+          test { name: "implicit_true" expr: "[1, 2].size() == 2" }
+          test { name: "wrong" expr: "1" value { uint64_value: 1 } }
+          test { name: "macros" expr: "1" disable_macros: true }
+        }
+    "#;
+
+    #[test]
+    fn test_run_file_reports_each_test() {
+        let results = run_file(&textproto::parse(FILE).unwrap());
+        let outcomes: Vec<_> = results
+            .iter()
+            .map(|r| (r.name.as_str(), &r.outcome))
+            .collect();
+        assert_eq!(outcomes[0], ("basic/arith/add", &Outcome::Pass));
+        assert_eq!(outcomes[1].1, &Outcome::Pass);
+        assert_eq!(outcomes[2].1, &Outcome::Pass);
+        assert!(matches!(outcomes[3].1, Outcome::Fail(_)));
+        assert!(matches!(outcomes[4].1, Outcome::Skip(_)));
+    }
+
+    #[test]
+    fn test_value_from_message_well_known_types() {
+        let message = textproto::parse(
+            "object_value { [type.googleapis.com/google.protobuf.Duration] { seconds: 90 } }",
+        )
+        .unwrap();
+        assert_eq!(
+            value_from_message(&message).unwrap(),
+            CelValue::Duration(TimeDelta::seconds(90))
+        );
+        let message = textproto::parse("double_value: -inf").unwrap();
+        assert_eq!(
+            value_from_message(&message).unwrap(),
+            CelValue::Float(f64::NEG_INFINITY)
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! A schema-less protobuf text format reader, sufficient for the cel-spec conformance files.
+//!
+//! Messages are kept as ordered `(field, value)` lists; scalars keep their source text so the
+//! caller decides how to interpret them from the field name.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    /// A quoted string, unescaped to raw bytes (it may hold `bytes` field data).
+    Str(Vec<u8>),
+    /// A number, enum name or identifier such as `true` / `NULL_VALUE` / `inf`.
+    Scalar(String),
+    Message(Message),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Message {
+    pub fields: Vec<(String, FieldValue)>,
+}
+
+impl Message {
+    /// The first value of `name`, if present.
+    pub fn get(&self, name: &str) -> Option<&FieldValue> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    /// All values of a repeated field `name`, in order.
+    pub fn all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a FieldValue> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, v)| v)
+    }
+
+    pub fn message(&self, name: &str) -> Option<&Message> {
+        match self.get(name) {
+            Some(FieldValue::Message(m)) => Some(m),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, name: &str) -> Option<String> {
+        match self.get(name) {
+            Some(FieldValue::Str(s)) => Some(String::from_utf8_lossy(s).into_owned()),
+            Some(FieldValue::Scalar(s)) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        matches!(self.get(name), Some(FieldValue::Scalar(s)) if s == "true" || s == "True" || s == "t" || s == "1")
+    }
+}
+
+/// Parses a text-format document into its top-level message.
+pub fn parse(input: &str) -> Result<Message, String> {
+    let mut parser = TextParser {
+        chars: input.chars().peekable(),
+        line: 1,
+    };
+    let message = parser.message(None)?;
+    Ok(message)
+}
+
+struct TextParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl TextParser<'_> {
+    fn error(&self, message: impl std::fmt::Display) -> String {
+        format!("textproto error at line {}: {}", self.line, message)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn skip_trivia(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c == '#' {
+                while let Some(c) = self.bump() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            } else if c.is_whitespace() || c == ',' || c == ';' {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Parses fields until `close` (or end of input for the top-level message).
+    fn message(&mut self, close: Option<char>) -> Result<Message, String> {
+        let mut message = Message::default();
+        loop {
+            self.skip_trivia();
+            match self.chars.peek().copied() {
+                None if close.is_none() => return Ok(message),
+                None => return Err(self.error("unexpected end of input")),
+                Some(c) if Some(c) == close => {
+                    self.bump();
+                    return Ok(message);
+                }
+                Some(_) => {
+                    let name = self.field_name()?;
+                    self.skip_trivia();
+                    let has_colon = self.chars.peek() == Some(&':');
+                    if has_colon {
+                        self.bump();
+                        self.skip_trivia();
+                    }
+                    if self.chars.peek() == Some(&'[') {
+                        self.bump();
+                        loop {
+                            self.skip_trivia();
+                            if self.chars.peek() == Some(&']') {
+                                self.bump();
+                                break;
+                            }
+                            let value = self.value()?;
+                            message.fields.push((name.clone(), value));
+                        }
+                    } else {
+                        let value = self.value()?;
+                        message.fields.push((name, value));
+                    }
+                }
+            }
+        }
+    }
+
+    fn field_name(&mut self) -> Result<String, String> {
+        if self.chars.peek() == Some(&'[') {
+            // Extension or Any type URL: `[type.googleapis.com/google.protobuf.Int32Value]`.
+            self.bump();
+            let mut name = String::from("[");
+            loop {
+                match self.bump() {
+                    Some(']') => break,
+                    Some(c) if !c.is_whitespace() => name.push(c),
+                    Some(_) => {}
+                    None => return Err(self.error("unterminated type name")),
+                }
+            }
+            name.push(']');
+            return Ok(name);
+        }
+        let name = self.word();
+        if name.is_empty() {
+            let found = self.chars.peek().copied();
+            return Err(self.error(format!("expected field name, found {:?}", found)));
+        }
+        Ok(name)
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '+') {
+                word.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        word
+    }
+
+    fn value(&mut self) -> Result<FieldValue, String> {
+        match self.chars.peek().copied() {
+            Some('{') => {
+                self.bump();
+                Ok(FieldValue::Message(self.message(Some('}'))?))
+            }
+            Some('<') => {
+                self.bump();
+                Ok(FieldValue::Message(self.message(Some('>'))?))
+            }
+            Some('"') | Some('\'') => {
+                let mut bytes = Vec::new();
+                // Adjacent string literals are concatenated.
+                while let Some(&quote @ ('"' | '\'')) = self.chars.peek() {
+                    self.bump();
+                    self.string_body(quote, &mut bytes)?;
+                    self.skip_trivia();
+                }
+                Ok(FieldValue::Str(bytes))
+            }
+            Some(_) => {
+                let word = self.word();
+                if word.is_empty() {
+                    let found = self.chars.peek().copied();
+                    return Err(self.error(format!("expected value, found {:?}", found)));
+                }
+                Ok(FieldValue::Scalar(word))
+            }
+            None => Err(self.error("expected value, found end of input")),
+        }
+    }
+
+    fn string_body(&mut self, quote: char, out: &mut Vec<u8>) -> Result<(), String> {
+        loop {
+            let c = self
+                .bump()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            if c == quote {
+                return Ok(());
+            }
+            if c != '\\' {
+                let mut buf = [0; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                continue;
+            }
+            let escaped = self
+                .bump()
+                .ok_or_else(|| self.error("unterminated escape"))?;
+            match escaped {
+                'n' => out.push(b'\n'),
+                'r' => out.push(b'\r'),
+                't' => out.push(b'\t'),
+                'a' => out.push(0x07),
+                'b' => out.push(0x08),
+                'f' => out.push(0x0c),
+                'v' => out.push(0x0b),
+                '?' => out.push(b'?'),
+                '\\' | '\'' | '"' => out.push(escaped as u8),
+                'x' | 'X' => {
+                    let digits = self.digits(16, 2);
+                    let byte = u8::from_str_radix(&digits, 16)
+                        .map_err(|_| self.error("invalid hex escape"))?;
+                    out.push(byte);
+                }
+                'u' | 'U' => {
+                    let len = if escaped == 'u' { 4 } else { 8 };
+                    let digits = self.digits(16, len);
+                    let ch = u32::from_str_radix(&digits, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| self.error("invalid unicode escape"))?;
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                }
+                '0'..='7' => {
+                    let mut digits = escaped.to_string();
+                    digits.push_str(&self.digits(8, 2));
+                    let byte = u8::from_str_radix(&digits, 8)
+                        .map_err(|_| self.error("invalid octal escape"))?;
+                    out.push(byte);
+                }
+                other => return Err(self.error(format!("unknown escape \\{}", other))),
+            }
+        }
+    }
+
+    fn digits(&mut self, radix: u32, max: usize) -> String {
+        let mut digits = String::new();
+        while digits.len() < max {
+            match self.chars.peek() {
+                Some(&c) if c.is_digit(radix) => {
+                    digits.push(c);
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+        digits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nested_messages_and_strings() {
+        let message = parse(
+            r#"
+            name: "basic"  # comment
+            section {
+              test { name: 'a' expr: "'\x41' + \"b\"" value: { int64_value: -1 } }
+              test < name: "b" bytes_value: "\303\277" >
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(message.string("name").as_deref(), Some("basic"));
+        let section = message.message("section").unwrap();
+        let tests: Vec<_> = section.all("test").collect();
+        assert_eq!(tests.len(), 2);
+        let FieldValue::Message(first) = tests[0] else {
+            panic!("expected message");
+        };
+        assert_eq!(first.string("expr").as_deref(), Some("'A' + \"b\""));
+        assert_eq!(
+            first.message("value").and_then(|v| v.string("int64_value")),
+            Some("-1".to_string())
+        );
+        let FieldValue::Message(second) = tests[1] else {
+            panic!("expected message");
+        };
+        assert_eq!(
+            second.get("bytes_value"),
+            Some(&FieldValue::Str(vec![0xc3, 0xbf]))
+        );
+    }
+
+    #[test]
+    fn test_parse_any_type_url_field() {
+        let message =
+            parse("object_value { [type.googleapis.com/google.protobuf.Int32Value] { value: 1 } }")
+                .unwrap();
+        let any = message.message("object_value").unwrap();
+        assert!(
+            any.message("[type.googleapis.com/google.protobuf.Int32Value]")
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Conversion between JSON and CEL values.
+//!
+//! JSON to CEL (request contexts, base context):
+//!
+//! | JSON | CEL |
+//! |---|---|
+//! | `null`, `true`/`false`, string | `null`, `bool`, `string` |
+//! | integer ≥ 0 | `uint` |
+//! | integer < 0 | `int` |
+//! | other number | `double` |
+//! | array, object | `list`, `map` with string keys |
+//!
+//! CEL to JSON (results):
+//!
+//! | CEL | JSON |
+//! |---|---|
+//! | `null`, `bool`, `string` | `null`, `true`/`false`, string |
+//! | `int`, `uint` | number; a decimal string beyond ±2^53 - 1 with [`Encoding::big_ints_as_strings`] |
+//! | `double` | number; NaN and ±Infinity per [`Encoding::non_finite`] |
+//! | `bytes` | base64 string |
+//! | `list` | array |
+//! | `map` | object with sorted keys; `int`, `uint` and `bool` keys as their text |
+//! | `google.protobuf.Timestamp` | RFC 3339 string in UTC, e.g. `"2024-01-02T03:04:05.500Z"` |
+//! | `google.protobuf.Duration` | seconds string, e.g. `"1.500s"` |
+//!
+//! Every JSON value survives JSON → CEL → JSON unchanged. The reverse cannot hold in general,
+//! because JSON has no bytes, timestamps, durations or non-string keys. But converting a
+//! result back and forth again yields the same JSON.
+
+use crate::evaluator::Limits;
+use crate::ingest::ContextBudget;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use cel::Value as CelValue;
+use cel::objects::Key;
+use chrono::{SecondsFormat, Utc};
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{self, Map, Value};
+use std::sync::Arc;
+
+/// Converts a JSON value to CEL without limits.
+pub fn from_json(value: &Value) -> CelValue {
+    // Without limits, conversion cannot fail.
+    ContextBudget::new(&Limits::default())
+        .convert("", value)
+        .unwrap_or(CelValue::Null)
+}
+
+/// The largest integer a JSON number parsed as an IEEE double (as in JavaScript) holds exactly.
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Choices in how results are written as JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Encoding {
+    /// Integers beyond ±[`MAX_SAFE_INTEGER`] become decimal strings instead of numbers.
+    pub big_ints_as_strings: bool,
+    pub non_finite: NonFinite,
+}
+
+/// How NaN and ±Infinity, which JSON numbers cannot represent, are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema, clap::ValueEnum)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum NonFinite {
+    /// `null`.
+    #[default]
+    Null,
+    /// `"NaN"`, `"Infinity"` or `"-Infinity"`.
+    String,
+    /// Fail the evaluation.
+    Error,
+}
+
+/// Converts a CEL value to JSON with the default [`Encoding`].
+pub fn to_json(value: CelValue) -> Value {
+    // The default encoding writes non-finite floats as `null`, so it cannot fail.
+    to_json_with(value, Encoding::default()).unwrap_or_default()
+}
+
+/// Converts a CEL value to JSON, taking ownership so that list and map elements are moved
+/// rather than cloned (a shared list or map is copied once, shallowly). Nested values are walked
+/// with an explicit stack, so arbitrarily deep results cannot overflow the call stack.
+pub fn to_json_with(value: CelValue, encoding: Encoding) -> Result<Value, String> {
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut current = value;
+    loop {
+        let mut converted = match current {
+            CelValue::List(list) => {
+                let items = Arc::try_unwrap(list).unwrap_or_else(|shared| (*shared).clone());
+                stack.push(Frame::List {
+                    out: Vec::with_capacity(items.len()),
+                    items: items.into_iter(),
+                });
+                None
+            }
+            CelValue::Map(map) => {
+                let entries = Arc::try_unwrap(map.map).unwrap_or_else(|shared| (*shared).clone());
+                let mut entries: Vec<(String, Key, CelValue)> = entries
+                    .into_iter()
+                    .map(|(key, value)| (json_key(&key), key, value))
+                    .collect();
+                // CEL maps have no order of their own; sorting keeps the output independent of
+                // hashing and of whether serde_json's map preserves insertion order.
+                entries.sort_unstable_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+                stack.push(Frame::Map {
+                    entries: entries.into_iter(),
+                    out: Map::new(),
+                    key: String::new(),
+                });
+                None
+            }
+            scalar => Some(scalar_to_json(scalar, encoding)?),
+        };
+        loop {
+            let Some(frame) = stack.last_mut() else {
+                return Ok(converted.unwrap_or(Value::Null));
+            };
+            if let Some(value) = converted.take() {
+                frame.push(value);
+            }
+            if let Some(child) = frame.next_child() {
+                current = child;
+                break;
+            }
+            converted = stack.pop().map(Frame::finish);
+        }
+    }
+}
+
+/// A list or map whose elements are being converted by [`to_json`].
+enum Frame {
+    List {
+        items: std::vec::IntoIter<CelValue>,
+        out: Vec<Value>,
+    },
+    Map {
+        entries: std::vec::IntoIter<(String, Key, CelValue)>,
+        out: Map<String, Value>,
+        /// Key of the entry currently being converted.
+        key: String,
+    },
+}
+
+impl Frame {
+    fn next_child(&mut self) -> Option<CelValue> {
+        match self {
+            Frame::List { items, .. } => items.next(),
+            Frame::Map { entries, key, .. } => {
+                let (next_key, _, value) = entries.next()?;
+                *key = next_key;
+                Some(value)
+            }
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        match self {
+            Frame::List { out, .. } => out.push(value),
+            Frame::Map { out, key, .. } => {
+                out.insert(std::mem::take(key), value);
+            }
+        }
+    }
+
+    fn finish(self) -> Value {
+        match self {
+            Frame::List { out, .. } => Value::Array(out),
+            Frame::Map { out, .. } => Value::Object(out),
+        }
+    }
+}
+
+/// Seconds with 0, 3, 6 or 9 fractional digits, e.g. `"1.500s"`.
+fn duration_text(duration: chrono::Duration) -> String {
+    let sign = if duration < chrono::Duration::zero() {
+        "-"
+    } else {
+        ""
+    };
+    let duration = duration.abs();
+    let seconds = duration.num_seconds();
+    let nanos = duration.subsec_nanos();
+    match nanos {
+        0 => format!("{}{}s", sign, seconds),
+        n if n % 1_000_000 == 0 => format!("{}{}.{:03}s", sign, seconds, n / 1_000_000),
+        n if n % 1_000 == 0 => format!("{}{}.{:06}s", sign, seconds, n / 1_000),
+        n => format!("{}{}.{:09}s", sign, seconds, n),
+    }
+}
+
+/// A map key as a JSON object key: strings as-is, numbers in decimal, bools as `true`/`false`.
+fn json_key(key: &Key) -> String {
+    match key {
+        Key::String(s) => s.to_string(),
+        Key::Int(i) => i.to_string(),
+        Key::Uint(u) => u.to_string(),
+        Key::Bool(b) => b.to_string(),
+    }
+}
+
+fn scalar_to_json(value: CelValue, encoding: Encoding) -> Result<Value, String> {
+    Ok(match value {
+        CelValue::Null => Value::Null,
+        CelValue::Bool(b) => Value::Bool(b),
+        CelValue::Int(i) if encoding.big_ints_as_strings && i.unsigned_abs() > MAX_SAFE_INTEGER => {
+            Value::String(i.to_string())
+        }
+        CelValue::UInt(u) if encoding.big_ints_as_strings && u > MAX_SAFE_INTEGER => {
+            Value::String(u.to_string())
+        }
+        CelValue::Int(i) => serde_json::json!(i),
+        CelValue::UInt(u) => serde_json::json!(u),
+        CelValue::Float(f) if !f.is_finite() => match encoding.non_finite {
+            NonFinite::Null => Value::Null,
+            NonFinite::String => Value::String(
+                match f {
+                    f if f.is_nan() => "NaN",
+                    f if f > 0.0 => "Infinity",
+                    _ => "-Infinity",
+                }
+                .to_string(),
+            ),
+            NonFinite::Error => {
+                return Err(format!(
+                    "Result encoding error: {} has no JSON representation",
+                    f
+                ));
+            }
+        },
+        CelValue::Float(f) => serde_json::json!(f),
+        CelValue::String(s) => {
+            Value::String(Arc::try_unwrap(s).unwrap_or_else(|shared| (*shared).clone()))
+        }
+        CelValue::Bytes(b) => Value::String(BASE64_STANDARD.encode(&*b)),
+        CelValue::Timestamp(t) => Value::String(
+            t.with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        ),
+        CelValue::Duration(d) => Value::String(duration_text(d)),
+        // Functions have no data to convert.
+        cel_value => Value::String(format!("{:?}", cel_value)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel::objects::Map as CelMap;
+    use proptest::prelude::*;
+    use rmcp::serde_json::json;
+    use std::collections::HashMap;
+
+    fn json_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<u64>().prop_map(Value::from),
+            any::<f64>()
+                .prop_filter("finite", |f| f.is_finite())
+                .prop_map(Value::from),
+            ".{0,8}".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(4, 32, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Value::Array),
+                prop::collection::btree_map(".{0,4}", inner, 0..4)
+                    .prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        })
+    }
+
+    fn cel_value() -> impl Strategy<Value = CelValue> {
+        let leaf = prop_oneof![
+            Just(CelValue::Null),
+            any::<bool>().prop_map(CelValue::Bool),
+            any::<i64>().prop_map(CelValue::Int),
+            any::<u64>().prop_map(CelValue::UInt),
+            any::<f64>().prop_map(CelValue::Float),
+            ".{0,8}".prop_map(|s| CelValue::String(Arc::new(s))),
+            prop::collection::vec(any::<u8>(), 0..8).prop_map(|b| CelValue::Bytes(Arc::new(b))),
+            any::<i64>().prop_map(|n| CelValue::Duration(chrono::Duration::nanoseconds(n))),
+        ];
+        let key = prop_oneof![
+            any::<i64>().prop_map(Key::Int),
+            any::<u64>().prop_map(Key::Uint),
+            any::<bool>().prop_map(Key::Bool),
+            ".{0,4}".prop_map(|s| Key::String(Arc::new(s))),
+        ];
+        leaf.prop_recursive(4, 32, 4, move |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4)
+                    .prop_map(|items| CelValue::List(Arc::new(items))),
+                prop::collection::hash_map(key.clone(), inner, 0..4).prop_map(
+                    |map: HashMap<Key, CelValue>| CelValue::Map(CelMap { map: Arc::new(map) })
+                ),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_json_round_trips_through_cel(value in json_value()) {
+            prop_assert_eq!(to_json(from_json(&value)), value);
+        }
+
+        #[test]
+        fn test_results_are_stable_across_round_trips(value in cel_value(), big in any::<bool>()) {
+            let encoding = Encoding {
+                big_ints_as_strings: big,
+                non_finite: NonFinite::String,
+            };
+            let once = to_json_with(value, encoding).unwrap();
+            prop_assert_eq!(to_json_with(from_json(&once), encoding).unwrap(), once);
+        }
+    }
+
+    #[test]
+    fn test_bytes_and_time_values() {
+        let timestamp =
+            chrono::DateTime::parse_from_rfc3339("2024-01-02T12:04:05.5+09:00").unwrap();
+        let values = CelValue::List(Arc::new(vec![
+            CelValue::Bytes(Arc::new(vec![0xff, 0x00])),
+            CelValue::Timestamp(timestamp),
+            CelValue::Duration(chrono::Duration::milliseconds(-1500)),
+            CelValue::Duration(chrono::Duration::seconds(3)),
+        ]));
+        assert_eq!(
+            to_json(values),
+            json!(["/wA=", "2024-01-02T03:04:05.500Z", "-1.500s", "3s"])
+        );
+    }
+}
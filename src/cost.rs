@@ -0,0 +1,518 @@
+//! Runtime cost accounting, so one request cannot run unbounded loops or build huge values.
+//!
+//! The cel interpreter has no hook inside comprehensions, so [`execute`] walks the part of the
+//! expression leading to a comprehension itself, runs each comprehension's loop with a
+//! [`Meter`], and hands every other sub-expression to cel. Results of the walked children are
+//! bound to `@`-prefixed variables, which CEL source cannot name, before cel resolves their
+//! parent.
+//!
+//! A sub-expression handed to cel costs its node count, whether or not cel short-circuits
+//! part of it, plus the size of the value it builds (see [`allocation`]). The values that
+//! calls, list and map literals and comprehension steps build are also checked against the
+//! size limits; values from the context are not.
+//!
+//! A request's deadline is checked at every comprehension iteration, so that an evaluation
+//! its caller stopped waiting for doesn't keep the evaluator busy.
+//!
+//! With lenient attributes, field selections are walked too, so that selecting a key missing
+//! from a map (or any field of `null`) gives `null` instead of an error.
+
+use crate::evaluator::Limits;
+use cel::common::ast::{EntryExpr, Expr, operators};
+use cel::objects::{Key, Value};
+use cel::parser::Expression;
+use cel::{Context, ExecutionError, Program};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Counts what a request's evaluations spend against its limits.
+#[derive(Debug, Default)]
+pub(crate) struct Meter {
+    max_iterations: Option<u64>,
+    max_cost: Option<u64>,
+    max_string_bytes: Option<usize>,
+    max_collection_size: Option<usize>,
+    lenient_attributes: bool,
+    deadline: Option<Instant>,
+    iterations: u64,
+    cost: u64,
+}
+
+impl Meter {
+    /// A meter enforcing `limits`, with the cost limit lowered to `cost_limit` if given.
+    pub(crate) fn new(limits: &Limits, cost_limit: Option<u64>) -> Self {
+        Self {
+            max_iterations: limits.max_iterations,
+            max_cost: match (limits.max_cost, cost_limit) {
+                (Some(max), Some(limit)) => Some(max.min(limit)),
+                (max, limit) => max.or(limit),
+            },
+            max_string_bytes: limits.max_string_bytes,
+            max_collection_size: limits.max_collection_size,
+            lenient_attributes: false,
+            deadline: None,
+            iterations: 0,
+            cost: 0,
+        }
+    }
+
+    /// Makes selecting a missing map key give `null` rather than an error.
+    pub(crate) fn with_lenient_attributes(mut self, lenient: bool) -> Self {
+        self.lenient_attributes = lenient;
+        self
+    }
+
+    /// Aborts the evaluation at the first comprehension iteration after `deadline`.
+    pub(crate) fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    fn is_unlimited(&self) -> bool {
+        !self.lenient_attributes
+            && self.deadline.is_none()
+            && self.max_iterations.is_none()
+            && self.max_cost.is_none()
+            && self.max_string_bytes.is_none()
+            && self.max_collection_size.is_none()
+    }
+
+    /// Charges for `value`, which was just built, and checks its size.
+    fn built(&mut self, value: &Value) -> Result<(), String> {
+        self.charge(allocation(value))?;
+        let (size, max, unit) = match value {
+            Value::String(s) => (s.len(), self.max_string_bytes, "byte string"),
+            Value::Bytes(b) => (b.len(), self.max_string_bytes, "byte bytes value"),
+            Value::List(items) => (items.len(), self.max_collection_size, "element list"),
+            Value::Map(map) => (map.map.len(), self.max_collection_size, "entry map"),
+            _ => return Ok(()),
+        };
+        match max {
+            Some(max) if size > max => Err(format!(
+                "size limit exceeded: built a {}-{}, limit is {}",
+                size, unit, max
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn charge(&mut self, cost: u64) -> Result<(), String> {
+        self.cost = self.cost.saturating_add(cost);
+        match self.max_cost {
+            Some(max) if self.cost > max => Err(format!(
+                "cost budget exceeded: evaluation cost is over {}",
+                max
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn iterate(&mut self) -> Result<(), String> {
+        self.iterations += 1;
+        if let Some(max) = self.max_iterations
+            && self.iterations > max
+        {
+            return Err(format!(
+                "cost budget exceeded: more than {} comprehension iterations",
+                max
+            ));
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(format!(
+                "evaluation timed out after {} comprehension iterations",
+                self.iterations - 1
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Executes `program` in `ctx`, charging `meter`. Errors are cel's execution error messages.
+pub(crate) fn execute(
+    program: &Program,
+    ctx: &Context,
+    meter: &mut Meter,
+) -> Result<Value, String> {
+    resolve(program.expression(), ctx, meter)
+}
+
+/// Like [`execute`], for an expression that wasn't compiled from text, such as one received as
+/// a syntax tree.
+pub(crate) fn resolve(
+    expr: &Expression,
+    ctx: &Context,
+    meter: &mut Meter,
+) -> Result<Value, String> {
+    if meter.is_unlimited() {
+        return Value::resolve(expr, ctx).map_err(|e| e.to_string());
+    }
+    let mut walker = Walker {
+        walked: HashSet::new(),
+        sizes: HashMap::new(),
+        meter,
+    };
+    walker.index(expr);
+    walker.resolve(expr, ctx)
+}
+
+fn children(expr: &Expression) -> Vec<&Expression> {
+    match &expr.expr {
+        Expr::Call(call) => call
+            .target
+            .as_deref()
+            .into_iter()
+            .chain(&call.args)
+            .collect(),
+        Expr::Comprehension(comp) => vec![
+            &comp.iter_range,
+            &comp.accu_init,
+            &comp.loop_cond,
+            &comp.loop_step,
+            &comp.result,
+        ],
+        Expr::List(list) => list.elements.iter().collect(),
+        Expr::Map(map) => map
+            .entries
+            .iter()
+            .flat_map(|entry| match &entry.expr {
+                EntryExpr::MapEntry(e) => vec![&e.key, &e.value],
+                EntryExpr::StructField(field) => vec![&field.value],
+            })
+            .collect(),
+        Expr::Struct(st) => st
+            .entries
+            .iter()
+            .filter_map(|entry| match &entry.expr {
+                EntryExpr::StructField(field) => Some(&field.value),
+                EntryExpr::MapEntry(_) => None,
+            })
+            .collect(),
+        Expr::Select(select) => vec![select.operand.as_ref()],
+        Expr::Ident(_) | Expr::Literal(_) | Expr::Unspecified => Vec::new(),
+    }
+}
+
+/// Cost of the value a call, list or map built: its elements, or its bytes in units of 8.
+fn allocation(value: &Value) -> u64 {
+    let units = match value {
+        Value::List(items) => items.len(),
+        Value::Map(map) => map.map.len(),
+        Value::String(s) => s.len().div_ceil(8),
+        Value::Bytes(b) => b.len().div_ceil(8),
+        _ => 0,
+    };
+    u64::try_from(units).unwrap_or(u64::MAX)
+}
+
+fn builds(expr: &Expression) -> bool {
+    matches!(
+        expr.expr,
+        Expr::Call(_) | Expr::List(_) | Expr::Map(_) | Expr::Struct(_)
+    )
+}
+
+fn to_bool(value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(ExecutionError::NoSuchOverload.to_string()),
+    }
+}
+
+struct Walker<'m> {
+    /// Comprehensions and the nodes containing one.
+    walked: HashSet<u64>,
+    /// Node count of each sub-expression.
+    sizes: HashMap<u64, u64>,
+    meter: &'m mut Meter,
+}
+
+impl Walker<'_> {
+    /// Records the size of `expr` and its sub-expressions, and whether they are walked.
+    fn index(&mut self, expr: &Expression) -> u64 {
+        let mut size = 1;
+        let mut walked = match &expr.expr {
+            Expr::Comprehension(_) => true,
+            Expr::Select(select) => self.meter.lenient_attributes && !select.test,
+            _ => false,
+        };
+        for child in children(expr) {
+            size += self.index(child);
+            walked |= self.walked.contains(&child.id);
+        }
+        if walked {
+            self.walked.insert(expr.id);
+        }
+        self.sizes.insert(expr.id, size);
+        size
+    }
+
+    fn resolve(&mut self, expr: &Expression, ctx: &Context) -> Result<Value, String> {
+        if !self.walked.contains(&expr.id) {
+            self.meter
+                .charge(self.sizes.get(&expr.id).copied().unwrap_or(1))?;
+            let value = ctx.resolve(expr).map_err(|e| e.to_string())?;
+            if builds(expr) {
+                self.meter.built(&value)?;
+            }
+            return Ok(value);
+        }
+        self.meter.charge(1)?;
+        match &expr.expr {
+            Expr::Comprehension(comp) => {
+                let accu_init = self.resolve(&comp.accu_init, ctx)?;
+                let range = self.resolve(&comp.iter_range, ctx)?;
+                let mut scope = ctx.new_inner_scope();
+                scope.add_variable_from_value(&comp.accu_var, accu_init);
+                let items: Vec<Value> = match range {
+                    Value::List(items) => items.iter().cloned().collect(),
+                    Value::Map(map) => map.map.keys().cloned().map(Value::from).collect(),
+                    other => {
+                        return Err(ExecutionError::unsupported_target_type(other).to_string());
+                    }
+                };
+                for item in items {
+                    if !to_bool(&self.resolve(&comp.loop_cond, &scope)?)? {
+                        break;
+                    }
+                    self.meter.iterate()?;
+                    scope.add_variable_from_value(&comp.iter_var, item);
+                    let accu = self.resolve(&comp.loop_step, &scope)?;
+                    scope.add_variable_from_value(&comp.accu_var, accu);
+                }
+                self.resolve(&comp.result, &scope)
+            }
+            Expr::Select(select) if self.meter.lenient_attributes && !select.test => {
+                let operand = self.resolve(&select.operand, ctx)?;
+                match &operand {
+                    Value::Null => return Ok(Value::Null),
+                    Value::Map(map) => {
+                        let key = Key::String(Arc::new(select.field.clone()));
+                        return Ok(map.map.get(&key).cloned().unwrap_or(Value::Null));
+                    }
+                    _ => {}
+                }
+                let mut scope = ctx.new_inner_scope();
+                scope.add_variable_from_value("@operand", operand);
+                let mut bound = expr.clone();
+                if let Expr::Select(select) = &mut bound.expr {
+                    select.operand.expr = Expr::Ident("@operand".to_string());
+                }
+                scope.resolve(&bound).map_err(|e| e.to_string())
+            }
+            Expr::Call(call)
+                if call.args.len() == 3 && call.func_name == operators::CONDITIONAL =>
+            {
+                if to_bool(&self.resolve(&call.args[0], ctx)?)? {
+                    self.resolve(&call.args[1], ctx)
+                } else {
+                    self.resolve(&call.args[2], ctx)
+                }
+            }
+            Expr::Call(call) if call.args.len() == 2 && call.func_name == operators::LOGICAL_OR => {
+                let left = self.resolve(&call.args[0], ctx)?;
+                if to_bool(&left)? {
+                    Ok(left)
+                } else {
+                    self.resolve(&call.args[1], ctx)
+                }
+            }
+            Expr::Call(call)
+                if call.args.len() == 2 && call.func_name == operators::LOGICAL_AND =>
+            {
+                if !to_bool(&self.resolve(&call.args[0], ctx)?)? {
+                    Ok(Value::Bool(false))
+                } else {
+                    Ok(Value::Bool(to_bool(&self.resolve(&call.args[1], ctx)?)?))
+                }
+            }
+            _ => {
+                let mut scope = ctx.new_inner_scope();
+                let mut bound = expr.clone();
+                self.bind_children(&mut bound, ctx, &mut scope)?;
+                let value = scope.resolve(&bound).map_err(|e| e.to_string())?;
+                if builds(expr) {
+                    self.meter.built(&value)?;
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    /// Resolves the walked children of `expr` and replaces each with a variable bound in
+    /// `scope` to its value.
+    fn bind_children(
+        &mut self,
+        expr: &mut Expression,
+        ctx: &Context,
+        scope: &mut Context,
+    ) -> Result<(), String> {
+        let children: Vec<&mut Expression> = match &mut expr.expr {
+            Expr::Call(call) => call
+                .target
+                .as_deref_mut()
+                .into_iter()
+                .chain(&mut call.args)
+                .collect(),
+            Expr::List(list) => list.elements.iter_mut().collect(),
+            Expr::Map(map) => map
+                .entries
+                .iter_mut()
+                .flat_map(|entry| match &mut entry.expr {
+                    EntryExpr::MapEntry(e) => vec![&mut e.key, &mut e.value],
+                    EntryExpr::StructField(field) => vec![&mut field.value],
+                })
+                .collect(),
+            Expr::Struct(st) => st
+                .entries
+                .iter_mut()
+                .filter_map(|entry| match &mut entry.expr {
+                    EntryExpr::StructField(field) => Some(&mut field.value),
+                    EntryExpr::MapEntry(_) => None,
+                })
+                .collect(),
+            Expr::Select(select) => vec![select.operand.as_mut()],
+            _ => Vec::new(),
+        };
+        for (i, child) in children.into_iter().enumerate() {
+            if self.walked.contains(&child.id) {
+                let name = format!("@walked{}", i);
+                scope.add_variable_from_value(&name, self.resolve(child, ctx)?);
+                child.expr = Expr::Ident(name);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn run(expression: &str, max_iterations: Option<u64>) -> Result<Value, String> {
+        let limits = Limits {
+            max_iterations,
+            ..Limits::default()
+        };
+        run_metered(expression, &mut Meter::new(&limits, None))
+    }
+
+    fn run_metered(expression: &str, meter: &mut Meter) -> Result<Value, String> {
+        let program = Program::compile(expression).unwrap();
+        let mut ctx = Context::default();
+        ctx.add_variable_from_value("l", vec![1, 2, 3, 4]);
+        execute(&program, &ctx, meter)
+    }
+
+    #[test]
+    fn test_iterations_are_limited() {
+        let expression = "l.map(x, l.filter(y, y < x)).exists(p, size(p) > 2) && [1][0] == 1";
+        assert_eq!(run(expression, None), Ok(Value::Bool(true)));
+        // 4 outer + 4 × 4 inner + 4 for `exists` finding the last element.
+        assert_eq!(run(expression, Some(24)), Ok(Value::Bool(true)));
+        let err = run(expression, Some(23)).unwrap_err();
+        assert_eq!(
+            err,
+            "cost budget exceeded: more than 23 comprehension iterations"
+        );
+        assert_eq!(
+            run("false && l.all(x, x > 0)", Some(0)),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            run("{'n': l.filter(x, x > 2)}.n", Some(4)),
+            run("{'n': l.filter(x, x > 2)}.n", None)
+        );
+        // Field values of a message literal are walked like those of a map literal.
+        assert_eq!(
+            run("Msg{items: l.map(x, l.map(y, y))}", Some(5)),
+            Err("cost budget exceeded: more than 5 comprehension iterations".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cost_counts_nodes_and_allocations() {
+        // 3 nodes (`_+_` and two literals) plus 2 units for the 9 bytes built.
+        let mut meter = Meter::new(&Limits::default(), Some(100));
+        assert!(run_metered("'abcd' + 'efghi'", &mut meter).is_ok());
+        assert_eq!(meter.cost, 5);
+
+        let mut meter = Meter::new(&Limits::default(), Some(1000));
+        assert!(run_metered("l.map(x, x * 2)", &mut meter).is_ok());
+        let cost = meter.cost;
+        let err = run_metered(
+            "l.map(x, x * 2)",
+            &mut Meter::new(&Limits::default(), Some(cost - 1)),
+        );
+        assert_eq!(
+            err,
+            Err(format!(
+                "cost budget exceeded: evaluation cost is over {}",
+                cost - 1
+            ))
+        );
+        let mut meter = Meter::new(&Limits::default(), Some(1000));
+        assert!(run_metered("l.map(x, [x, x, x, x])", &mut meter).is_ok());
+        assert!(meter.cost > cost);
+    }
+
+    #[test]
+    fn test_deadline_aborts_loops() {
+        let expression = "l.map(x, l.map(y, x * y)).size() == 4";
+        let run = |deadline| run_metered(expression, &mut Meter::default().with_deadline(deadline));
+        assert_eq!(
+            run(Some(Instant::now() + Duration::from_secs(60))),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            run(Some(Instant::now())),
+            Err("evaluation timed out after 0 comprehension iterations".to_string())
+        );
+        assert_eq!(
+            run_metered(
+                "[1, 2].size() == 2",
+                &mut Meter::default().with_deadline(Some(Instant::now()))
+            ),
+            Ok(Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn test_built_values_are_size_limited() {
+        let limits = Limits {
+            max_string_bytes: Some(8),
+            max_collection_size: Some(3),
+            ..Limits::default()
+        };
+        let run = |expression: &str| run_metered(expression, &mut Meter::new(&limits, None));
+        assert!(run("'abcd' + 'efgh'").is_ok());
+        assert_eq!(
+            run("'abcd' + 'efghi'"),
+            Err("size limit exceeded: built a 9-byte string, limit is 8".to_string())
+        );
+        // Each step appends to the accumulator; `l` itself comes from the context.
+        assert_eq!(
+            run("l.map(x, x)"),
+            Err("size limit exceeded: built a 4-element list, limit is 3".to_string())
+        );
+        assert!(run("l.filter(x, x > 1)").is_ok());
+    }
+
+    #[test]
+    fn test_lenient_attributes() {
+        let program = Program::compile("m.missing == null && m.a.b == null && m.n == 1").unwrap();
+        let mut ctx = Context::default();
+        let mut m = HashMap::new();
+        m.insert("n".to_string(), 1);
+        ctx.add_variable_from_value("m", m);
+        let strict = execute(&program, &ctx, &mut Meter::default());
+        assert!(strict.unwrap_err().contains("No such key: missing"));
+        let lenient = execute(
+            &program,
+            &ctx,
+            &mut Meter::default().with_lenient_attributes(true),
+        );
+        assert_eq!(lenient, Ok(Value::Bool(true)));
+    }
+}
@@ -0,0 +1,194 @@
+//! Columnar datasets, Parquet or Arrow IPC, read into one context per row for
+//! `evaluate_dataset`. Each column becomes a variable; nulls are kept as `null`.
+
+use arrow_array::RecordBatch;
+use arrow_ipc::reader::{FileReader, StreamReader};
+use arrow_json::writer::{JsonArray, WriterBuilder};
+use bytes::Bytes;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use rmcp::model::Root;
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{self, Map, Value};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// How many rows a dataset may have by default.
+pub const DEFAULT_MAX_ROWS: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum DatasetFormat {
+    Parquet,
+    /// The Arrow IPC file or stream format.
+    ArrowIpc,
+}
+
+impl DatasetFormat {
+    /// The format of `bytes`, by its magic number.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(b"PAR1") {
+            DatasetFormat::Parquet
+        } else {
+            DatasetFormat::ArrowIpc
+        }
+    }
+}
+
+/// Resolves `path` inside `dir`, refusing paths that lead out of it.
+pub fn resolve(dir: &Path, path: &str) -> Result<PathBuf, String> {
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| format!("Invalid dataset directory {}: {}", dir.display(), e))?;
+    let resolved = dir
+        .join(path)
+        .canonicalize()
+        .map_err(|e| format!("Cannot open dataset `{}`: {}", path, e))?;
+    if !resolved.starts_with(&dir) {
+        return Err(format!(
+            "Dataset `{}` is outside the dataset directory",
+            path
+        ));
+    }
+    Ok(resolved)
+}
+
+/// Fails unless `path` lies under one of the `file:` URIs a client exposes as its roots.
+pub fn check_roots(path: &Path, roots: &[Root]) -> Result<(), String> {
+    let inside = roots.iter().any(|root| {
+        Url::parse(&root.uri)
+            .ok()
+            .and_then(|uri| uri.to_file_path().ok())
+            .and_then(|root| root.canonicalize().ok())
+            .is_some_and(|root| path.starts_with(root))
+    });
+    if inside {
+        Ok(())
+    } else {
+        Err(format!(
+            "Dataset {} is outside the client's roots",
+            path.display()
+        ))
+    }
+}
+
+/// The rows of a dataset as contexts, keeping only `columns` when given.
+pub fn read_rows(
+    bytes: Vec<u8>,
+    format: Option<DatasetFormat>,
+    columns: Option<&[String]>,
+    max_rows: usize,
+) -> Result<Vec<Map<String, Value>>, String> {
+    rows(&read_batches(bytes, format, columns, max_rows)?)
+}
+
+/// The record batches of a dataset, keeping only `columns` when given.
+pub fn read_batches(
+    bytes: Vec<u8>,
+    format: Option<DatasetFormat>,
+    columns: Option<&[String]>,
+    max_rows: usize,
+) -> Result<Vec<RecordBatch>, String> {
+    let format = format.unwrap_or_else(|| DatasetFormat::detect(&bytes));
+    let batches: Vec<RecordBatch> = match format {
+        DatasetFormat::Parquet => ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes))
+            .and_then(|builder| builder.build())
+            .map_err(|e| format!("Invalid Parquet dataset: {}", e))?
+            .collect::<Result<_, _>>(),
+        DatasetFormat::ArrowIpc if bytes.starts_with(b"ARROW1") => {
+            FileReader::try_new(Cursor::new(bytes), None)
+                .map_err(|e| format!("Invalid Arrow IPC dataset: {}", e))?
+                .collect::<Result<_, _>>()
+        }
+        DatasetFormat::ArrowIpc => StreamReader::try_new(Cursor::new(bytes), None)
+            .map_err(|e| format!("Invalid Arrow IPC dataset: {}", e))?
+            .collect::<Result<_, _>>(),
+    }
+    .map_err(|e| format!("Failed to read dataset: {}", e))?;
+
+    let total: usize = batches.iter().map(RecordBatch::num_rows).sum();
+    if total > max_rows {
+        return Err(format!("Dataset has {} rows, limit is {}", total, max_rows));
+    }
+    match columns {
+        Some(columns) => batches
+            .iter()
+            .map(|batch| {
+                let indices = columns
+                    .iter()
+                    .map(|name| {
+                        batch
+                            .schema()
+                            .index_of(name)
+                            .map_err(|_| format!("Unknown column `{}`", name))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                batch.project(&indices).map_err(|e| e.to_string())
+            })
+            .collect(),
+        None => Ok(batches),
+    }
+}
+
+/// One context per row of `batches`.
+pub fn rows(batches: &[RecordBatch]) -> Result<Vec<Map<String, Value>>, String> {
+    let mut writer = WriterBuilder::new()
+        .with_explicit_nulls(true)
+        .build::<_, JsonArray>(Vec::new());
+    writer
+        .write_batches(&batches.iter().collect::<Vec<_>>())
+        .and_then(|()| writer.finish())
+        .map_err(|e| format!("Failed to convert dataset rows: {}", e))?;
+    let json = writer.into_inner();
+    if json.is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_slice(&json).map_err(|e| format!("Failed to convert dataset rows: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{ArrayRef, Int64Array, StringArray};
+    use arrow_ipc::writer::StreamWriter;
+    use parquet::arrow::ArrowWriter;
+    use rmcp::serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_read_parquet_and_arrow_rows() {
+        let batch = RecordBatch::try_from_iter([
+            ("id", Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef),
+            (
+                "name",
+                Arc::new(StringArray::from(vec![Some("a"), None])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+
+        let mut parquet = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut parquet, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        let rows = read_rows(parquet, None, None, 10).unwrap();
+        assert_eq!(
+            Value::Array(rows.into_iter().map(Value::Object).collect()),
+            json!([{"id": 1, "name": "a"}, {"id": 2, "name": null}])
+        );
+
+        let mut stream = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut stream, &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        let columns = ["name".to_string()];
+        let rows = read_rows(stream.clone(), None, Some(&columns), 10).unwrap();
+        assert_eq!(Value::Object(rows[0].clone()), json!({"name": "a"}));
+        assert_eq!(
+            read_rows(stream, None, None, 1),
+            Err("Dataset has 2 rows, limit is 1".to_string())
+        );
+    }
+}
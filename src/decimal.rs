@@ -0,0 +1,129 @@
+//! `decimal.*`, fixed-point decimal arithmetic for financial policies, which binary floating
+//! point gets wrong (`0.1 + 0.2 != 0.3`).
+//!
+//! Decimals travel through expressions as strings, such as `'19.99'`, and every function
+//! returns its result as one. Operands may also be integers, or doubles, which are read from
+//! their shortest decimal form so that `0.1` means exactly `0.1`.
+
+use crate::provider::{Method, Namespace};
+use cel::{ExecutionError, FunctionContext, Value as CelValue};
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::sync::Arc;
+
+/// The namespace the functions are called on.
+pub const VARIABLE: &str = "decimal";
+
+type Arithmetic = (&'static str, fn(Decimal, Decimal) -> Option<Decimal>);
+
+const ARITHMETIC: [Arithmetic; 4] = [
+    ("add", Decimal::checked_add),
+    ("sub", Decimal::checked_sub),
+    ("mul", Decimal::checked_mul),
+    ("div", Decimal::checked_div),
+];
+
+pub(crate) fn names() -> Vec<String> {
+    ARITHMETIC
+        .iter()
+        .map(|(name, _)| *name)
+        .chain(["cmp", "round", "parse"])
+        .map(|name| format!("{}.{}", VARIABLE, name))
+        .collect()
+}
+
+pub(crate) fn namespace() -> Namespace {
+    let mut methods: Vec<(String, Method)> = ARITHMETIC
+        .iter()
+        .map(|&(name, op)| {
+            let method: Method = Arc::new(move |ftx: &FunctionContext, args: &[CelValue]| {
+                let [a, b] = args else {
+                    return Err(ftx.error(format!("decimal.{} expects two decimals", name)));
+                };
+                op(operand(ftx, a)?, operand(ftx, b)?)
+                    .map(|result| CelValue::from(result.to_string()))
+                    .ok_or_else(|| {
+                        ftx.error(format!("decimal.{} overflows or divides by zero", name))
+                    })
+            });
+            (name.to_string(), method)
+        })
+        .collect();
+    let cmp: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [a, b] = args else {
+            return Err(ftx.error("decimal.cmp expects two decimals"));
+        };
+        Ok(CelValue::Int(operand(ftx, a)?.cmp(&operand(ftx, b)?) as i64))
+    });
+    let round: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [a, CelValue::Int(places)] = args else {
+            return Err(ftx.error("decimal.round expects a decimal and a number of places"));
+        };
+        let places = u32::try_from(*places)
+            .map_err(|_| ftx.error("decimal.round expects a non-negative number of places"))?;
+        Ok(CelValue::from(
+            operand(ftx, a)?
+                .round_dp_with_strategy(places, RoundingStrategy::MidpointAwayFromZero)
+                .to_string(),
+        ))
+    });
+    let parse: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [a] = args else {
+            return Err(ftx.error("decimal.parse expects one decimal"));
+        };
+        Ok(CelValue::from(operand(ftx, a)?.to_string()))
+    });
+    methods.extend([
+        ("cmp".to_string(), cmp),
+        ("round".to_string(), round),
+        ("parse".to_string(), parse),
+    ]);
+    Namespace {
+        name: VARIABLE.to_string(),
+        methods,
+    }
+}
+
+fn operand(ftx: &FunctionContext, value: &CelValue) -> Result<Decimal, ExecutionError> {
+    let text = match value {
+        CelValue::Int(i) => return Ok(Decimal::from(*i)),
+        CelValue::UInt(u) => return Ok(Decimal::from(*u)),
+        CelValue::Float(f) => f.to_string(),
+        CelValue::String(s) => s.trim().to_string(),
+        other => {
+            return Err(ftx.error(format!(
+                "expected a decimal string or number, got {}",
+                other.type_of()
+            )));
+        }
+    };
+    Decimal::from_str_exact(&text)
+        .map_err(|e| ftx.error(format!("`{}` is not a decimal: {}", text, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::Evaluator;
+    use crate::library::Library;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_decimal_functions() {
+        let evaluator = Evaluator::new().with_function_provider(Library::Decimal);
+        let evaluate = |expression: &str| {
+            evaluator.evaluate(expression, &json!({"price": "19.99"}), Profile::Default)
+        };
+        assert_eq!(evaluate("decimal.add(0.1, 0.2)"), Ok(json!("0.3")));
+        assert_eq!(evaluate("decimal.mul(price, 3)"), Ok(json!("59.97")));
+        assert_eq!(evaluate("decimal.sub('10', '0.01')"), Ok(json!("9.99")));
+        assert_eq!(
+            evaluate("decimal.round(decimal.div(price, 3), 2)"),
+            Ok(json!("6.66"))
+        );
+        assert_eq!(evaluate("decimal.round('2.345', 2)"), Ok(json!("2.35")));
+        assert_eq!(evaluate("decimal.cmp(price, '20.00') < 0"), Ok(json!(true)));
+        assert_eq!(evaluate("decimal.parse(' 1.50 ')"), Ok(json!("1.50")));
+        assert!(evaluate("decimal.div(1, 0)").is_err());
+        assert!(evaluate("decimal.parse('1.2.3')").is_err());
+    }
+}
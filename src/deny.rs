@@ -0,0 +1,90 @@
+//! Identifiers and functions the `--config` file's `deny` section bans from expressions, such
+//! as a `secret` variable a resolver could supply or an `env` namespace. Expressions using
+//! them are rejected before they are compiled.
+
+use crate::ast;
+use cel::common::ast::Expr;
+use rmcp::serde::Deserialize;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct DenyList {
+    /// Variables expressions may not read. A namespace listed here bans its functions too.
+    #[serde(default)]
+    pub identifiers: BTreeSet<String>,
+    /// Functions expressions may not call, as `name` or, for a namespace's, `namespace.name`.
+    #[serde(default)]
+    pub functions: BTreeSet<String>,
+}
+
+impl DenyList {
+    pub fn is_empty(&self) -> bool {
+        self.identifiers.is_empty() && self.functions.is_empty()
+    }
+
+    /// Describes each banned identifier and function `expression` uses; none if it doesn't
+    /// parse.
+    pub fn violations(&self, expression: &str) -> Vec<String> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let Ok(parsed) = ast::parse(expression) else {
+            return Vec::new();
+        };
+        let mut functions = BTreeSet::new();
+        ast::walk(&parsed, &mut |node| {
+            if let Expr::Call(call) = &node.expr {
+                if self.functions.contains(&call.func_name) {
+                    functions.insert(call.func_name.clone());
+                }
+                if let Some(Expr::Ident(namespace)) = call.target.as_ref().map(|t| &t.expr) {
+                    let qualified = format!("{}.{}", namespace, call.func_name);
+                    if self.functions.contains(&qualified) {
+                        functions.insert(qualified);
+                    }
+                }
+            }
+        });
+        ast::variables(&parsed, |_| false)
+            .into_iter()
+            .filter(|name| self.identifiers.contains(name))
+            .map(|name| format!("`{}` is a banned identifier", name))
+            .chain(
+                functions
+                    .into_iter()
+                    .map(|name| format!("`{}` is a banned function", name)),
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_violations() {
+        let deny = DenyList {
+            identifiers: BTreeSet::from(["secret".to_string(), "env".to_string()]),
+            functions: BTreeSet::from(["http.get".to_string(), "matches".to_string()]),
+        };
+        assert_eq!(
+            deny.violations("secret == 'x' || env.get('HOME') != '' || http.get(u).status == 200"),
+            vec![
+                "`env` is a banned identifier",
+                "`secret` is a banned identifier",
+                "`http.get` is a banned function",
+            ]
+        );
+        assert_eq!(
+            deny.violations("name.matches('^a') && matches(name, 'b')"),
+            vec!["`matches` is a banned function"]
+        );
+        assert!(
+            deny.violations("items.all(secret, secret > 0) && request.secret == ''")
+                .is_empty()
+        );
+        assert!(DenyList::default().violations("secret").is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+//! Asking the user, through MCP elicitation, for context variables an expression references
+//! but the request doesn't set, so `evaluate` can retry instead of failing.
+//!
+//! The form has one field for the missing variable, typed by its declaration in the request's
+//! environment. Lists, maps and undeclared variables are entered as JSON text; an undeclared
+//! variable whose text isn't JSON is taken as a string.
+
+use crate::cel_type::CelType;
+use rmcp::model::{
+    BooleanSchema, ElicitationSchema, IntegerSchema, NumberSchema, PrimitiveSchema, StringSchema,
+};
+use rmcp::serde_json::{self, Value};
+use std::collections::BTreeMap;
+
+/// Variables asked for in one `evaluate` call before its error is returned.
+pub(crate) const MAX_ELICITED_VARIABLES: usize = 4;
+
+/// The variable an evaluator error reports as unknown, if any.
+pub(crate) fn missing_variable(message: &str) -> Option<&str> {
+    let (_, rest) = message.split_once("Undeclared reference to '")?;
+    rest.split_once('\'').map(|(name, _)| name)
+}
+
+/// The message shown with the form.
+pub(crate) fn prompt(name: &str, declared: Option<&CelType>) -> String {
+    match declared {
+        Some(declared) => format!(
+            "The expression needs the context variable `{}` ({}).",
+            name, declared
+        ),
+        None => format!("The expression needs the context variable `{}`.", name),
+    }
+}
+
+/// A form with one required field for `name`.
+pub(crate) fn form(name: &str, declared: Option<&CelType>) -> ElicitationSchema {
+    let field = match declared {
+        Some(CelType::Bool) => PrimitiveSchema::Boolean(BooleanSchema::new()),
+        Some(CelType::Int) => PrimitiveSchema::Integer(IntegerSchema::new()),
+        Some(CelType::Uint) => PrimitiveSchema::Integer(IntegerSchema::new().minimum(0)),
+        Some(CelType::Double) => PrimitiveSchema::Number(NumberSchema::new()),
+        Some(CelType::Timestamp) => PrimitiveSchema::String(StringSchema::date_time()),
+        Some(CelType::String | CelType::Bytes) => PrimitiveSchema::String(StringSchema::new()),
+        Some(CelType::Duration) => PrimitiveSchema::String(
+            StringSchema::new().description("A duration such as 1h30m or 2.5s"),
+        ),
+        Some(CelType::Null | CelType::List(_) | CelType::Map(..)) => {
+            PrimitiveSchema::String(StringSchema::new().description("A JSON value"))
+        }
+        Some(CelType::Dyn) | None => PrimitiveSchema::String(
+            StringSchema::new().description("A JSON value, or text taken as a string"),
+        ),
+    };
+    ElicitationSchema::new(BTreeMap::from([(name.to_string(), field)]))
+        .with_required(vec![name.to_string()])
+}
+
+/// The value of `name` in an accepted form's `content`, checked against its declaration.
+pub(crate) fn answer(
+    name: &str,
+    declared: Option<&CelType>,
+    content: Option<&Value>,
+) -> Result<Value, String> {
+    let field = content
+        .and_then(|content| content.get(name))
+        .ok_or_else(|| format!("No value was given for `{}`", name))?;
+    let value = match (declared, field) {
+        (Some(CelType::Null | CelType::List(_) | CelType::Map(..)), Value::String(text)) => {
+            serde_json::from_str(text)
+                .map_err(|e| format!("`{}` is not valid JSON: {}", name, e))?
+        }
+        (Some(CelType::Dyn) | None, Value::String(text)) => {
+            serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.clone()))
+        }
+        _ => field.clone(),
+    };
+    if let Some(declared) = declared {
+        declared.check(name, &value)?;
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_missing_variable() {
+        assert_eq!(
+            missing_variable("CEL execution error: Undeclared reference to 'user'"),
+            Some("user")
+        );
+        assert_eq!(
+            missing_variable("CEL execution error: No such key: a"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_form_and_answer() {
+        let count = CelType::parse("uint").unwrap();
+        assert_eq!(
+            serde_json::to_value(form("count", Some(&count))).unwrap(),
+            json!({
+                "type": "object",
+                "properties": {"count": {"type": "integer", "minimum": 0}},
+                "required": ["count"],
+            })
+        );
+        let content = json!({"count": 3, "roles": "[\"admin\"]", "name": "jane"});
+        assert_eq!(answer("count", Some(&count), Some(&content)), Ok(json!(3)));
+        let roles = CelType::parse("list<string>").unwrap();
+        assert_eq!(
+            answer("roles", Some(&roles), Some(&content)),
+            Ok(json!(["admin"]))
+        );
+        assert_eq!(answer("name", None, Some(&content)), Ok(json!("jane")));
+        assert_eq!(answer("roles", None, Some(&content)), Ok(json!(["admin"])));
+        assert!(answer("name", Some(&count), Some(&content)).is_err());
+        assert!(answer("missing", None, Some(&content)).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Named environments from the `--config` file, selected per request with `environment`.
+//!
+//! Each environment layers its own profile, base context, disabled macros and limits over the
+//! server's, so one server can serve several policy domains. Limits only ever tighten. Declared
+//! variable types are checked against each request's context.
+
+use crate::cel_type::CelType;
+use crate::macros::{self, Macro};
+use crate::profile::Profile;
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct Environment {
+    /// Pre-declared variables, functions and expression checks; the server's when absent.
+    #[serde(default)]
+    pub profile: Option<Profile>,
+    /// Variables present in every evaluation unless the context sets them; they take
+    /// precedence over the server's base context.
+    #[serde(default)]
+    pub base_context: Map<String, Value>,
+    /// Macros rejected in this environment, on top of the server's.
+    #[serde(default)]
+    pub disabled_macros: BTreeSet<Macro>,
+    #[serde(default)]
+    pub limits: EnvironmentLimits,
+    /// Expected types of context variables, such as `int` or `map<string, dyn>`.
+    #[serde(default)]
+    pub variables: BTreeMap<String, CelType>,
+}
+
+/// Limits applied on top of the server's; the lower one wins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct EnvironmentLimits {
+    #[serde(default)]
+    pub max_expression_length: Option<usize>,
+    #[serde(default)]
+    pub max_cost: Option<u64>,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+impl Environment {
+    /// Adds the base context variables the context doesn't set.
+    pub fn seed_context(&self, mut context: Map<String, Value>) -> Map<String, Value> {
+        for (name, value) in &self.base_context {
+            if !context.contains_key(name) {
+                context.insert(name.clone(), value.clone());
+            }
+        }
+        context
+    }
+
+    /// Fails if a context variable does not match its declared type.
+    pub fn check_context(&self, context: &Map<String, Value>) -> Result<(), String> {
+        for (name, declared) in &self.variables {
+            if let Some(value) = context.get(name) {
+                declared.check(name, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A JSON Schema for contexts, with a property per declared variable.
+    pub fn context_schema(&self) -> Value {
+        let properties: Map<String, Value> = self
+            .variables
+            .iter()
+            .map(|(name, declared)| (name.clone(), declared.json_schema()))
+            .collect();
+        json!({"type": "object", "properties": properties})
+    }
+
+    /// Fails if `expression` is too long or uses a macro disabled here.
+    pub fn check(&self, expression: &str) -> Result<(), String> {
+        if let Some(max) = self.limits.max_expression_length
+            && expression.len() > max
+        {
+            return Err(format!(
+                "Expression is {} bytes, limit is {}",
+                expression.len(),
+                max
+            ));
+        }
+        macros::check(expression, &self.disabled_macros)
+            .map_err(|e| format!("CEL compile error: {}", e))
+    }
+
+    /// The lower of `limit` and this environment's.
+    pub fn cost_limit(&self, limit: Option<u64>) -> Option<u64> {
+        min(limit, self.limits.max_cost)
+    }
+
+    /// The lower of `timeout` and this environment's.
+    pub fn timeout(&self, timeout: Option<Duration>) -> Option<Duration> {
+        min(timeout, self.limits.timeout_ms.map(Duration::from_millis))
+    }
+}
+
+fn min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::{self, json};
+
+    #[test]
+    fn test_environment_layers_over_request() {
+        let environment: Environment = serde_json::from_value(json!({
+            "profile": "envoy",
+            "base_context": {"cluster": "prod", "tenant": "a"},
+            "disabled_macros": ["map"],
+            "limits": {"max_cost": 100, "timeout_ms": 50},
+            "variables": {"count": "int"}
+        }))
+        .unwrap();
+        assert_eq!(environment.profile, Some(Profile::Envoy));
+
+        let context = json!({"tenant": "b"}).as_object().cloned().unwrap();
+        assert_eq!(
+            Value::Object(environment.seed_context(context)),
+            json!({"cluster": "prod", "tenant": "b"})
+        );
+        assert_eq!(environment.cost_limit(Some(1000)), Some(100));
+        assert_eq!(environment.cost_limit(Some(10)), Some(10));
+        assert_eq!(environment.timeout(None), Some(Duration::from_millis(50)));
+        assert!(environment.check("[1].map(x, x)").is_err());
+        assert!(environment.check("[1].all(x, x > 0)").is_ok());
+        let context = json!({"count": "3"}).as_object().cloned().unwrap();
+        assert_eq!(
+            environment.check_context(&context),
+            Err("`count` is declared `int`, but the context has a string".to_string())
+        );
+        assert_eq!(
+            environment.context_schema()["properties"]["count"],
+            json!({"type": "integer"})
+        );
+    }
+}
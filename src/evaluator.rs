@@ -0,0 +1,1489 @@
+//! The CEL evaluation core shared by every transport.
+//!
+//! `Evaluator` compiles (with a bounded program cache), applies registered extensions and
+//! limits, and converts results to JSON. It has no MCP dependency, so other Rust programs can
+//! reuse exactly the semantics the MCP tools expose.
+
+use crate::ast;
+use crate::completion::BUILTIN_FUNCTIONS;
+pub use crate::conversion::{Encoding, MAX_SAFE_INTEGER, NonFinite, to_json, to_json_with};
+use crate::cost::{self, Meter};
+use crate::fair_queue::FairQueue;
+use crate::ingest::{ContextBudget, JsonNumbers};
+use crate::macros::{self, Macro};
+use crate::merge::MergeStrategy;
+use crate::pattern::RegexCache;
+use crate::profile::Profile;
+use crate::program_cache::{self, ProgramCache};
+use crate::provider::{Extensions, FunctionProvider, VariableResolver};
+use crate::reload::Reloadable;
+use cel::common::ast::Expr;
+use cel::{Context, Program, Value as CelValueEnum};
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{Map, Value};
+use std::any::Any;
+use std::collections::BTreeSet;
+use std::convert::From;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+
+/// Compiled programs kept by default.
+pub const DEFAULT_CACHE_CAPACITY: usize = program_cache::DEFAULT_MAX_ENTRIES;
+
+/// Limits applied to each evaluation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Expressions longer than this many bytes are rejected.
+    pub max_expression_length: Option<usize>,
+    /// Transports stop waiting for a result after this long and report a timeout, and set
+    /// the request's [`EvalRequest::deadline`] to match.
+    pub timeout: Option<Duration>,
+    /// Context values nested deeper than this are rejected (a variable's value is level 1).
+    pub max_context_depth: Option<usize>,
+    /// Contexts larger than this many (approximate) bytes are rejected.
+    pub max_context_bytes: Option<usize>,
+    /// Regular expressions compiling to more than this many bytes are rejected.
+    pub max_regex_size: Option<usize>,
+    /// Tool results whose JSON exceeds this many bytes are truncated.
+    pub max_result_bytes: Option<usize>,
+    /// Evaluations running more comprehension iterations than this, in total, are aborted.
+    pub max_iterations: Option<u64>,
+    /// Evaluations costing more than this (see [`EvalRequest::cost_limit`]) are aborted.
+    pub max_cost: Option<u64>,
+    /// Evaluations building a string or bytes value longer than this many bytes are aborted.
+    pub max_string_bytes: Option<usize>,
+    /// Evaluations building a list or map with more elements than this are aborted.
+    pub max_collection_size: Option<usize>,
+}
+
+/// Compiles expressions into a program cache under an evaluator's settings.
+struct Compiler<'a> {
+    max_expression_length: Option<usize>,
+    cache: &'a ProgramCache,
+    regexes: &'a RegexCache,
+    disabled_macros: &'a BTreeSet<Macro>,
+}
+
+impl Compiler<'_> {
+    fn compile(&self, expression: &str) -> Result<Arc<Program>, String> {
+        if let Some(max) = self.max_expression_length
+            && expression.len() > max
+        {
+            return Err(format!(
+                "Expression is {} bytes, limit is {}",
+                expression.len(),
+                max
+            ));
+        }
+        if !self.cache.is_enabled() {
+            return self.compile_uncached(expression);
+        }
+        if let Some(program) = self.cache.get_by_text(expression) {
+            return Ok(program);
+        }
+        let parsed = ast::parse(expression).map_err(|e| format!("CEL compile error: {}", e))?;
+        let fingerprint = ast::fingerprint(&parsed);
+        if let Some(program) = self.cache.get_aliased(&fingerprint, expression) {
+            return Ok(program);
+        }
+        let program = self.compile_uncached(expression)?;
+        self.cache.insert(&fingerprint, expression, program.clone());
+        Ok(program)
+    }
+
+    /// Whether compiling `expression` would reuse a cached program.
+    fn is_cached(&self, expression: &str) -> bool {
+        self.cache.get_by_text(expression).is_some()
+            || ast::parse(expression).is_ok_and(|parsed| {
+                self.cache
+                    .get_aliased(&ast::fingerprint(&parsed), expression)
+                    .is_some()
+            })
+    }
+
+    fn compile_uncached(&self, expression: &str) -> Result<Arc<Program>, String> {
+        let program = Arc::new(
+            Program::compile(expression).map_err(|e| format!("CEL compile error: {}", e))?,
+        );
+        macros::check(expression, self.disabled_macros)
+            .map_err(|e| format!("CEL compile error: {}", e))?;
+        self.regexes
+            .check_literals(expression)
+            .map_err(|e| format!("CEL compile error: {}", e))?;
+        Ok(program)
+    }
+
+    fn warm_up(&self, expressions: &[(String, String)]) -> Result<usize, Vec<String>> {
+        let mut pinned = Vec::new();
+        let mut errors = Vec::new();
+        for (label, expression) in expressions {
+            match self.compile(expression) {
+                Ok(program) => {
+                    if let Ok(parsed) = ast::parse(expression) {
+                        pinned.push((ast::fingerprint(&parsed), expression.clone(), program));
+                    }
+                }
+                Err(e) => errors.push(format!("{}: {}", label, e)),
+            }
+        }
+        self.cache.pin(pinned);
+        if errors.is_empty() {
+            Ok(expressions.len())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The text a panic was raised with, if it was a string.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
+
+/// A base context variable, kept as JSON for merging and as CEL for direct binding.
+struct BaseVariable {
+    name: String,
+    json: Value,
+    value: CelValueEnum,
+}
+
+fn base_variables(variables: &Map<String, Value>, numbers: JsonNumbers) -> Vec<BaseVariable> {
+    // Without limits, conversion cannot fail.
+    let mut budget = ContextBudget::new(&Limits::default()).with_numbers(numbers);
+    variables
+        .iter()
+        .filter_map(|(name, json)| {
+            let value = budget.convert(name, json).ok()?;
+            Some(BaseVariable {
+                name: name.clone(),
+                json: json.clone(),
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Inspects and replaces an [`Evaluator`]'s base context and variable resolvers while it
+/// serves requests.
+#[derive(Clone)]
+pub struct EvaluatorReloader {
+    base_context: Reloadable<Vec<BaseVariable>>,
+    resolvers: Reloadable<Vec<Arc<dyn VariableResolver>>>,
+    functions: Arc<[String]>,
+    volatile_functions: Arc<[String]>,
+    json_numbers: JsonNumbers,
+    strict_attributes: bool,
+    max_expression_length: Option<usize>,
+    cache: Arc<ProgramCache>,
+    regexes: Arc<RegexCache>,
+    disabled_macros: BTreeSet<Macro>,
+    panics: Arc<AtomicU64>,
+}
+
+impl EvaluatorReloader {
+    /// Names of the built-in and provided functions, as of when the reloader was taken.
+    pub fn function_names(&self) -> Vec<String> {
+        self.functions.to_vec()
+    }
+
+    /// Names of the current base context variables.
+    pub fn base_context_names(&self) -> Vec<String> {
+        self.base_context
+            .get()
+            .iter()
+            .map(|variable| variable.name.clone())
+            .collect()
+    }
+
+    pub fn resolver_count(&self) -> usize {
+        self.resolvers.get().len()
+    }
+
+    /// Whether `expression`'s result depends only on `context` and the base context: it
+    /// parses, calls no [volatile](FunctionProvider::is_volatile) function and reads no
+    /// variable a resolver would supply.
+    pub fn is_deterministic(&self, expression: &str, context: &Value) -> bool {
+        let Ok(parsed) = ast::parse(expression) else {
+            return false;
+        };
+        let mut volatile = false;
+        ast::walk(&parsed, &mut |node| {
+            if let Expr::Call(call) = &node.expr {
+                let qualified = match call.target.as_ref().map(|t| &t.expr) {
+                    Some(Expr::Ident(namespace)) => format!("{}.{}", namespace, call.func_name),
+                    _ => call.func_name.clone(),
+                };
+                volatile |= self
+                    .volatile_functions
+                    .iter()
+                    .any(|name| *name == call.func_name || *name == qualified);
+            }
+        });
+        if volatile {
+            return false;
+        }
+        if self.resolvers.get().is_empty() {
+            return true;
+        }
+        let base_context = self.base_context.get();
+        ast::variables(&parsed, |name| self.functions.iter().any(|f| f == name))
+            .iter()
+            .all(|name| {
+                context.get(name).is_some()
+                    || base_context.iter().any(|variable| variable.name == *name)
+            })
+    }
+
+    pub fn set_base_context(&self, variables: &Map<String, Value>) {
+        self.base_context
+            .set(base_variables(variables, self.json_numbers));
+    }
+
+    /// How the evaluator converts context numbers.
+    pub fn json_numbers(&self) -> JsonNumbers {
+        self.json_numbers
+    }
+
+    /// Whether selecting a missing map key is an error unless a request says otherwise.
+    pub fn strict_attributes(&self) -> bool {
+        self.strict_attributes
+    }
+
+    /// How many evaluations have panicked since the evaluator started.
+    pub fn panic_count(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    /// How many compiled programs the evaluator's cache holds.
+    pub fn cached_programs(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Like [`Evaluator::warm_up`], replacing the programs pinned before.
+    pub fn warm_up(&self, expressions: &[(String, String)]) -> Result<usize, Vec<String>> {
+        self.compiler().warm_up(expressions)
+    }
+
+    /// Compiles `expression` as an evaluation would, without running it, and returns whether
+    /// the program cache already held its program. Either way, it holds it afterwards.
+    pub fn compile(&self, expression: &str) -> Result<bool, String> {
+        let compiler = self.compiler();
+        let cached = compiler.is_cached(expression);
+        compiler.compile(expression).map(|_| cached)
+    }
+
+    fn compiler(&self) -> Compiler<'_> {
+        Compiler {
+            max_expression_length: self.max_expression_length,
+            cache: &self.cache,
+            regexes: &self.regexes,
+            disabled_macros: &self.disabled_macros,
+        }
+    }
+
+    pub fn set_variable_resolvers(&self, resolvers: Vec<Box<dyn VariableResolver>>) {
+        self.resolvers
+            .set(resolvers.into_iter().map(Arc::from).collect());
+    }
+}
+
+/// Compiles and runs CEL expressions against JSON contexts.
+pub struct Evaluator {
+    extensions: Extensions,
+    /// Variables present in every evaluation, converted once.
+    base_context: Reloadable<Vec<BaseVariable>>,
+    limits: Limits,
+    cache: Arc<ProgramCache>,
+    regexes: Arc<RegexCache>,
+    encoding: Encoding,
+    json_numbers: JsonNumbers,
+    strict_attributes: bool,
+    disabled_macros: BTreeSet<Macro>,
+    /// Evaluations that panicked and were answered with an error.
+    panics: Arc<AtomicU64>,
+    /// Requests evaluated at once by [`Evaluator::serve`].
+    workers: usize,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self {
+            extensions: Extensions::default(),
+            base_context: Reloadable::default(),
+            limits: Limits::default(),
+            cache: Arc::default(),
+            regexes: Arc::new(RegexCache::new(None)),
+            encoding: Encoding::default(),
+            json_numbers: JsonNumbers::default(),
+            strict_attributes: true,
+            disabled_macros: BTreeSet::new(),
+            panics: Arc::default(),
+            workers: 1,
+        }
+    }
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds functions available to every expression.
+    pub fn with_function_provider(mut self, provider: impl FunctionProvider + 'static) -> Self {
+        self.extensions.functions.push(Arc::new(provider));
+        self
+    }
+
+    /// Adds a resolver consulted, in registration order, for variables missing from the context.
+    pub fn with_variable_resolver(self, resolver: impl VariableResolver + 'static) -> Self {
+        let mut resolvers = (*self.extensions.resolvers.get()).clone();
+        resolvers.push(Arc::new(resolver));
+        self.extensions.resolvers.set(resolvers);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self.regexes = Arc::new(RegexCache::new(limits.max_regex_size));
+        self
+    }
+
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets how numbers in contexts (base context included) become CEL numbers.
+    pub fn with_json_numbers(mut self, numbers: JsonNumbers) -> Self {
+        self.json_numbers = numbers;
+        let json: Map<String, Value> = self
+            .base_context
+            .get()
+            .iter()
+            .map(|base| (base.name.clone(), base.json.clone()))
+            .collect();
+        self.base_context.set(base_variables(&json, numbers));
+        self
+    }
+
+    /// Whether selecting a key missing from a map (`m.missing`) is an error, the default, or
+    /// gives `null`. Requests may choose otherwise.
+    pub fn with_strict_attributes(mut self, strict: bool) -> Self {
+        self.strict_attributes = strict;
+        self
+    }
+
+    /// Rejects expressions using any of `macros` when they are compiled.
+    pub fn with_disabled_macros(mut self, macros: impl IntoIterator<Item = Macro>) -> Self {
+        self.disabled_macros.extend(macros);
+        self
+    }
+
+    /// Adds variables to every evaluation; how a request context variable of the same name
+    /// combines with them is chosen per call with a [`MergeStrategy`]. They are converted once
+    /// here and not counted against the context limits.
+    pub fn with_base_context(self, variables: &Map<String, Value>) -> Self {
+        self.base_context
+            .set(base_variables(variables, self.json_numbers));
+        self
+    }
+
+    /// A handle replacing this evaluator's base context and resolvers, also once it serves.
+    pub fn reloader(&self) -> EvaluatorReloader {
+        EvaluatorReloader {
+            base_context: self.base_context.clone(),
+            resolvers: self.extensions.resolvers.clone(),
+            functions: BUILTIN_FUNCTIONS
+                .iter()
+                .map(|name| name.to_string())
+                .chain(self.extensions.function_names())
+                .collect(),
+            volatile_functions: self.extensions.volatile_function_names().into(),
+            json_numbers: self.json_numbers,
+            strict_attributes: self.strict_attributes,
+            max_expression_length: self.limits.max_expression_length,
+            cache: self.cache.clone(),
+            regexes: self.regexes.clone(),
+            disabled_macros: self.disabled_macros.clone(),
+            panics: self.panics.clone(),
+        }
+    }
+
+    /// Evaluates up to `workers` requests at once when serving, each on a blocking thread; the
+    /// queues still choose which request runs next. At least 1; 1 by default.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Sets how many compiled programs are kept; `0` disables caching.
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache = Arc::new(ProgramCache::new(capacity, None));
+        self
+    }
+
+    /// Uses `cache` for compiled programs, so evaluators sharing it compile each expression once.
+    pub fn with_program_cache(mut self, cache: Arc<ProgramCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Compiles `expression`, reusing a cached program when possible.
+    pub fn compile(&self, expression: &str) -> Result<Arc<Program>, String> {
+        self.compiler().compile(expression)
+    }
+
+    /// Compiles the `(label, expression)` pairs and pins them in the program cache, so their
+    /// first evaluation does not pay for it. Returns the number compiled, or each failure
+    /// prefixed with its label.
+    pub fn warm_up(&self, expressions: &[(String, String)]) -> Result<usize, Vec<String>> {
+        self.compiler().warm_up(expressions)
+    }
+
+    fn compiler(&self) -> Compiler<'_> {
+        Compiler {
+            max_expression_length: self.limits.max_expression_length,
+            cache: &self.cache,
+            regexes: &self.regexes,
+            disabled_macros: &self.disabled_macros,
+        }
+    }
+
+    /// Compiles and executes `expression` with the variables in `context` under `profile`.
+    pub fn evaluate(
+        &self,
+        expression: &str,
+        context: &Value,
+        profile: Profile,
+    ) -> Result<Value, String> {
+        self.evaluate_merged(expression, context, profile, MergeStrategy::Replace)
+    }
+
+    /// Like [`evaluate`](Self::evaluate), combining `context` with the base context by `merge`.
+    pub fn evaluate_merged(
+        &self,
+        expression: &str,
+        context: &Value,
+        profile: Profile,
+        merge: MergeStrategy,
+    ) -> Result<Value, String> {
+        let result = self.execute_merged(
+            expression,
+            context,
+            profile,
+            merge,
+            &mut self.meter(None, None),
+        )?;
+        tracing::info_span!("cel.convert").in_scope(|| to_json_with(result, self.encoding))
+    }
+
+    /// Like [`evaluate`](Self::evaluate), but returns the CEL result without converting it.
+    pub fn execute(
+        &self,
+        expression: &str,
+        context: &Value,
+        profile: Profile,
+    ) -> Result<CelValueEnum, String> {
+        self.execute_merged(
+            expression,
+            context,
+            profile,
+            MergeStrategy::Replace,
+            &mut self.meter(None, None),
+        )
+    }
+
+    fn execute_merged(
+        &self,
+        expression: &str,
+        context: &Value,
+        profile: Profile,
+        merge: MergeStrategy,
+        meter: &mut Meter,
+    ) -> Result<CelValueEnum, String> {
+        let prog = tracing::info_span!("cel.compile").in_scope(|| self.compile(expression))?;
+        let ctx = self.bind(&[&prog], context, profile, merge)?;
+        tracing::info_span!("cel.execute")
+            .in_scope(|| cost::execute(&prog, &ctx, meter))
+            .map_err(|e| format!("CEL execution error: {}", e))
+    }
+
+    /// Evaluates several named expressions against one context, compiling each once and binding
+    /// the context once. Returns an object mapping each name to its value.
+    pub fn project(
+        &self,
+        outputs: &[(String, String)],
+        context: &Value,
+        profile: Profile,
+        merge: MergeStrategy,
+    ) -> Result<Value, String> {
+        self.project_encoded(
+            outputs,
+            context,
+            profile,
+            merge,
+            self.encoding,
+            &mut self.meter(None, None),
+        )
+    }
+
+    fn project_encoded(
+        &self,
+        outputs: &[(String, String)],
+        context: &Value,
+        profile: Profile,
+        merge: MergeStrategy,
+        encoding: Encoding,
+        meter: &mut Meter,
+    ) -> Result<Value, String> {
+        let programs = tracing::info_span!("cel.compile").in_scope(|| {
+            outputs
+                .iter()
+                .map(|(name, expression)| {
+                    self.compile(expression)
+                        .map_err(|e| format!("{} (output `{}`)", e, name))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        let refs: Vec<&Program> = programs.iter().map(|p| p.as_ref()).collect();
+        let ctx = self.bind(&refs, context, profile, merge)?;
+
+        let mut values = Map::new();
+        for ((name, _), prog) in outputs.iter().zip(&programs) {
+            let result = tracing::info_span!("cel.execute")
+                .in_scope(|| cost::execute(prog, &ctx, meter))
+                .map_err(|e| format!("CEL execution error: {} (output `{}`)", e, name))?;
+            let value = tracing::info_span!("cel.convert")
+                .in_scope(|| to_json_with(result, encoding))
+                .map_err(|e| format!("{} (output `{}`)", e, name))?;
+            values.insert(name.clone(), value);
+        }
+        Ok(Value::Object(values))
+    }
+
+    /// Evaluates `rules` (named bool conditions) in order against one context and returns the
+    /// name of the first that is true, or `null` when none is.
+    pub fn first_match(
+        &self,
+        rules: &[(String, String)],
+        context: &Value,
+        profile: Profile,
+        merge: MergeStrategy,
+    ) -> Result<Value, String> {
+        let matched = self.match_rules(
+            rules,
+            context,
+            profile,
+            merge,
+            true,
+            &mut self.meter(None, None),
+        )?;
+        Ok(matched
+            .first()
+            .map_or(Value::Null, |name| Value::from(*name)))
+    }
+
+    /// Evaluates every rule and returns the names of those that are true, in order.
+    pub fn all_matches(
+        &self,
+        rules: &[(String, String)],
+        context: &Value,
+        profile: Profile,
+        merge: MergeStrategy,
+    ) -> Result<Value, String> {
+        let matched = self.match_rules(
+            rules,
+            context,
+            profile,
+            merge,
+            false,
+            &mut self.meter(None, None),
+        )?;
+        Ok(Value::from(matched))
+    }
+
+    fn match_rules<'r>(
+        &self,
+        rules: &'r [(String, String)],
+        context: &Value,
+        profile: Profile,
+        merge: MergeStrategy,
+        first_only: bool,
+        meter: &mut Meter,
+    ) -> Result<Vec<&'r str>, String> {
+        let programs = tracing::info_span!("cel.compile").in_scope(|| {
+            rules
+                .iter()
+                .map(|(name, expression)| {
+                    self.compile(expression)
+                        .map_err(|e| format!("{} (rule `{}`)", e, name))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        let refs: Vec<&Program> = programs.iter().map(|p| p.as_ref()).collect();
+        let ctx = self.bind(&refs, context, profile, merge)?;
+
+        let mut matched = Vec::new();
+        for ((name, _), prog) in rules.iter().zip(&programs) {
+            let result = tracing::info_span!("cel.execute")
+                .in_scope(|| cost::execute(prog, &ctx, meter))
+                .map_err(|e| format!("CEL execution error: {} (rule `{}`)", e, name))?;
+            match result {
+                CelValueEnum::Bool(true) => {
+                    matched.push(name.as_str());
+                    if first_only {
+                        break;
+                    }
+                }
+                CelValueEnum::Bool(false) => {}
+                other => {
+                    return Err(format!(
+                        "CEL execution error: condition returned {}, expected bool (rule `{}`)",
+                        other.type_of(),
+                        name
+                    ));
+                }
+            }
+        }
+        Ok(matched)
+    }
+
+    /// A meter for the evaluations of one request; `cost_limit` can only lower the server's.
+    fn meter(&self, cost_limit: Option<u64>, strict_attributes: Option<bool>) -> Meter {
+        Meter::new(&self.limits, cost_limit)
+            .with_lenient_attributes(!strict_attributes.unwrap_or(self.strict_attributes))
+    }
+
+    /// Builds an evaluation context: functions, base and request variables, and resolved
+    /// variables referenced by any of `programs`.
+    fn bind(
+        &self,
+        programs: &[&Program],
+        context: &Value,
+        profile: Profile,
+        merge: MergeStrategy,
+    ) -> Result<Context<'static>, String> {
+        let mut ctx = Context::default();
+        self.regexes.register(&mut ctx);
+        self.extensions.register_functions(&mut ctx);
+        profile.register_functions(&mut ctx);
+        let empty = Map::new();
+        let map = match context {
+            Value::Object(map) => map,
+            _ => &empty,
+        };
+        let base_context = self.base_context.get();
+        for base in base_context.iter() {
+            if !map.contains_key(&base.name) {
+                ctx.add_variable_from_value(&base.name, base.value.clone());
+            }
+        }
+        let mut budget = ContextBudget::new(&self.limits).with_numbers(self.json_numbers);
+        for (key, value) in map {
+            if merge == MergeStrategy::MergePatch && value.is_null() {
+                // Unsets the variable; it also stays hidden from resolvers.
+                continue;
+            }
+            let merged = base_context
+                .iter()
+                .find(|base| &base.name == key)
+                .map(|base| merge.apply(&base.json, value));
+            let value = budget
+                .convert(key, merged.as_ref().unwrap_or(value))
+                .map_err(|e| e.to_string())?;
+            ctx.add_variable_from_value(key, profile.type_attributes(key, value));
+        }
+
+        let is_bound = |name: &str| {
+            map.contains_key(name) || base_context.iter().any(|base| base.name == name)
+        };
+        for prog in programs {
+            self.extensions
+                .resolve_variables(prog, &is_bound, &mut ctx, &mut budget)?;
+        }
+        Ok(ctx)
+    }
+
+    /// Evaluates one request's query.
+    fn answer(
+        &self,
+        query: &Query,
+        context: &Value,
+        profile: Profile,
+        merge: MergeStrategy,
+        encoding: Encoding,
+        meter: &mut Meter,
+    ) -> Result<Value, String> {
+        match query {
+            Query::Expression(expression) => self
+                .execute_merged(expression, context, profile, merge, meter)
+                .and_then(|result| {
+                    tracing::info_span!("cel.convert").in_scope(|| to_json_with(result, encoding))
+                }),
+            Query::Projection(outputs) => {
+                self.project_encoded(outputs, context, profile, merge, encoding, meter)
+            }
+            Query::FirstMatch(rules) => self
+                .match_rules(rules, context, profile, merge, true, meter)
+                .map(|matched| {
+                    matched
+                        .first()
+                        .map_or(Value::Null, |name| Value::from(*name))
+                }),
+            Query::AllMatches(rules) => self
+                .match_rules(rules, context, profile, merge, false, meter)
+                .map(Value::from),
+        }
+    }
+
+    /// Answers requests from `receiver`, up to [`workers`](Evaluator::with_workers) at once and
+    /// taking turns between their [`Lane`]s, until every sender is dropped and every request
+    /// is answered.
+    pub async fn serve(self, mut receiver: mpsc::Receiver<EvalRequest>) {
+        let workers = self.workers;
+        let evaluator = Arc::new(self);
+        let mut queues = Queues::default();
+        let mut running = JoinSet::new();
+        let mut open = true;
+        loop {
+            while let Ok(request) = receiver.try_recv() {
+                queues.push(request);
+            }
+            while running.len() < workers
+                && let Some(request) = queues.pop()
+            {
+                // Evaluating on a blocking thread keeps a long evaluation from stalling the
+                // runtime.
+                let evaluator = evaluator.clone();
+                running.spawn_blocking(move || evaluator.respond(request));
+            }
+            if running.is_empty() && !open {
+                break;
+            }
+            tokio::select! {
+                request = receiver.recv(), if open => match request {
+                    Some(request) => queues.push(request),
+                    None => open = false,
+                },
+                Some(finished) = running.join_next() => {
+                    if let Err(e) = finished {
+                        tracing::error!("Evaluation task failed: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Answers `request`, with an error if its evaluation panics.
+    fn respond(&self, request: EvalRequest) {
+        drop(request.queue_wait);
+        let started = Instant::now();
+        let encoding = Encoding {
+            non_finite: request.non_finite.unwrap_or(self.encoding.non_finite),
+            ..self.encoding
+        };
+        let meter = &mut self
+            .meter(request.cost_limit, request.strict_attributes)
+            .with_deadline(request.deadline);
+        let response = request.span.in_scope(|| {
+            if request.deadline.is_some_and(|deadline| started >= deadline) {
+                return Err("Evaluation timed out while queued".to_string());
+            }
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                self.answer(
+                    &request.query,
+                    &request.context,
+                    request.profile,
+                    request.merge,
+                    encoding,
+                    meter,
+                )
+            }))
+            .unwrap_or_else(|payload| {
+                self.panics.fetch_add(1, Ordering::Relaxed);
+                let message = panic_message(payload.as_ref());
+                tracing::error!(
+                    request_id = %request.request_id,
+                    "Evaluation panicked: {}",
+                    message
+                );
+                Err(format!("Internal evaluation panic: {}", message))
+            })
+        });
+        let reply = EvalReply {
+            response,
+            elapsed: started.elapsed(),
+        };
+        if request.responder.send(reply).is_err() {
+            tracing::warn!(
+                request_id = %request.request_id,
+                "Failed to send evaluation response"
+            );
+        }
+    }
+}
+
+pub type EvalResponse = Result<Value, String>;
+
+/// The evaluator's answer to an [`EvalRequest`].
+#[derive(Debug)]
+pub struct EvalReply {
+    pub response: EvalResponse,
+    /// Time spent evaluating, excluding the wait in the queue.
+    pub elapsed: Duration,
+}
+
+/// What an [`EvalRequest`] asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// One expression; the response is its value.
+    Expression(String),
+    /// Named expressions sharing one context; the response maps each name to its value.
+    Projection(Vec<(String, String)>),
+    /// Named bool conditions tried in order; the response is the first true one's name, or
+    /// `null`.
+    FirstMatch(Vec<(String, String)>),
+    /// Named bool conditions, all evaluated; the response lists the true ones' names in order.
+    AllMatches(Vec<(String, String)>),
+}
+
+impl Query {
+    pub fn expressions(&self) -> Vec<&str> {
+        match self {
+            Query::Expression(expression) => vec![expression],
+            Query::Projection(outputs)
+            | Query::FirstMatch(outputs)
+            | Query::AllMatches(outputs) => outputs.iter().map(|(_, e)| e.as_str()).collect(),
+        }
+    }
+
+    /// The query with each expression replaced by what `f` returns for it.
+    pub fn try_map(
+        self,
+        mut f: impl FnMut(String) -> Result<String, String>,
+    ) -> Result<Self, String> {
+        fn named(
+            outputs: Vec<(String, String)>,
+            f: &mut impl FnMut(String) -> Result<String, String>,
+        ) -> Result<Vec<(String, String)>, String> {
+            outputs
+                .into_iter()
+                .map(|(name, expression)| Ok((name, f(expression)?)))
+                .collect()
+        }
+        Ok(match self {
+            Query::Expression(expression) => Query::Expression(f(expression)?),
+            Query::Projection(outputs) => Query::Projection(named(outputs, &mut f)?),
+            Query::FirstMatch(outputs) => Query::FirstMatch(named(outputs, &mut f)?),
+            Query::AllMatches(outputs) => Query::AllMatches(named(outputs, &mut f)?),
+        })
+    }
+
+    /// The expression's fingerprint; for named expressions, a hash of the query kind and each
+    /// name with its expression's fingerprint. `None` if an expression does not parse.
+    pub fn fingerprint(&self) -> Option<String> {
+        let fingerprint =
+            |expression: &str| ast::parse(expression).ok().map(|e| ast::fingerprint(&e));
+        let (kind, outputs) = match self {
+            Query::Expression(expression) => return fingerprint(expression),
+            Query::Projection(outputs) => ("projection", outputs),
+            Query::FirstMatch(outputs) => ("first_match", outputs),
+            Query::AllMatches(outputs) => ("all_matches", outputs),
+        };
+        let parts = outputs
+            .iter()
+            .map(|(name, expression)| {
+                Some(Value::from(vec![name.clone(), fingerprint(expression)?]))
+            })
+            .collect::<Option<Vec<Value>>>()?;
+        Some(ast::hash(&format!("{}{}", kind, Value::Array(parts))))
+    }
+}
+
+/// An expression as itself; a projection or rule list as a JSON object of its expressions.
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Query::Expression(expression) => f.write_str(expression),
+            Query::Projection(outputs)
+            | Query::FirstMatch(outputs)
+            | Query::AllMatches(outputs) => {
+                let object: Map<String, Value> = outputs
+                    .iter()
+                    .map(|(name, expression)| (name.clone(), Value::from(expression.as_str())))
+                    .collect();
+                write!(f, "{}", Value::Object(object))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EvalRequest {
+    pub query: Query,
+    pub context: Value,
+    pub profile: Profile,
+    /// How `context` combines with the evaluator's base context.
+    pub merge: MergeStrategy,
+    /// Overrides the evaluator's [`Encoding::non_finite`] for this request.
+    pub non_finite: Option<NonFinite>,
+    /// Lowers the evaluator's [`Limits::max_cost`] for this request. Each visited expression
+    /// node costs 1; each list or map element, and each 8 bytes of a string or bytes value,
+    /// built by a call, literal or comprehension step costs 1 more.
+    pub cost_limit: Option<u64>,
+    /// Overrides whether selecting a missing map key is an error for this request.
+    pub strict_attributes: Option<bool>,
+    /// When the caller stops waiting: a request still queued then isn't evaluated, and an
+    /// evaluation still running is aborted at its next comprehension iteration.
+    pub deadline: Option<Instant>,
+    pub responder: oneshot::Sender<EvalReply>,
+    /// Correlates the evaluator's log events with the MCP call that caused them.
+    pub request_id: String,
+    /// Parent of the compile, execute and convert spans.
+    pub span: tracing::Span,
+    /// Open while the request waits in the queue; closed when the evaluator picks it up.
+    pub queue_wait: tracing::Span,
+    /// Where the request waits its turn.
+    pub lane: Lane,
+}
+
+/// How many interactive requests are answered in a row while batch requests wait.
+pub const INTERACTIVE_SHARE: u32 = 4;
+
+/// Which of the evaluator's queues a request waits in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum Priority {
+    /// Latency-sensitive calls, answered ahead of batch requests.
+    #[default]
+    Interactive,
+    /// Background work, such as the items of batch jobs, answered in the interactive
+    /// requests' gaps and after every [`INTERACTIVE_SHARE`] of them.
+    Batch,
+}
+
+/// A session's requests of one priority, queued apart from other lanes' and taking turns
+/// with them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Lane {
+    pub session: u64,
+    pub priority: Priority,
+}
+
+/// Waiting requests by priority, each queue taking turns between sessions.
+#[derive(Default)]
+struct Queues {
+    interactive: FairQueue<u64, EvalRequest>,
+    batch: FairQueue<u64, EvalRequest>,
+    /// Interactive requests answered since the last batch request.
+    streak: u32,
+}
+
+impl Queues {
+    fn push(&mut self, request: EvalRequest) {
+        let queue = match request.lane.priority {
+            Priority::Interactive => &mut self.interactive,
+            Priority::Batch => &mut self.batch,
+        };
+        queue.push(request.lane.session, request);
+    }
+
+    fn pop(&mut self) -> Option<EvalRequest> {
+        if (self.streak < INTERACTIVE_SHARE || self.batch.is_empty())
+            && let Some(request) = self.interactive.pop()
+        {
+            self.streak += 1;
+            return Some(request);
+        }
+        self.streak = 0;
+        self.batch.pop()
+    }
+}
+
+/// Runs a default `Evaluator` over the request channel.
+pub async fn evaluator_service(receiver: mpsc::Receiver<EvalRequest>) {
+    Evaluator::new().serve(receiver).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_evaluate_addition() {
+        let context = json!({});
+        let response = Evaluator::new().evaluate("1+2", &context, Profile::Default);
+        assert_eq!(response, Ok(Value::from(3)));
+    }
+
+    #[test]
+    fn test_evaluate_with_context() {
+        let context = json!({
+            "a": 5,
+            "b": 10
+        });
+        let response = Evaluator::new().evaluate("a * b", &context, Profile::Default);
+        assert_eq!(response, Ok(Value::from(50)));
+    }
+
+    #[test]
+    fn test_evaluate_string_concat() {
+        let context = json!({
+            "name": "World"
+        });
+        let response = Evaluator::new().evaluate("'Hello, ' + name", &context, Profile::Default);
+        assert_eq!(response, Ok(Value::from("Hello, World")));
+    }
+
+    #[test]
+    fn test_evaluate_compilation_error() {
+        let context = json!({});
+        let response = Evaluator::new().evaluate("1 +/ 2", &context, Profile::Default);
+        assert!(response.is_err());
+        assert!(response.unwrap_err().contains("compile"));
+    }
+
+    #[test]
+    fn test_evaluate_firestore_get() {
+        let context = Value::Object(
+            Profile::Firestore.seed_context(
+                json!({
+                    "request": { "auth": { "uid": "alice" } },
+                    "__firestore_documents__": {
+                        "users/alice": { "role": "admin" }
+                    }
+                })
+                .as_object()
+                .cloned()
+                .unwrap_or_default(),
+            ),
+        );
+        let response = Evaluator::new().evaluate(
+            "exists('/databases/(default)/documents/users/' + request.auth.uid) && get('users/alice').data.role == 'admin'",
+            &context,
+            Profile::Firestore,
+        );
+        assert_eq!(response, Ok(Value::from(true)));
+    }
+
+    struct Constants;
+
+    impl VariableResolver for Constants {
+        fn resolve(&self, name: &str) -> Result<Option<Value>, String> {
+            Ok((name == "greeting").then(|| json!("hello")))
+        }
+    }
+
+    /// `tick()` counts its calls.
+    struct Ticker(Arc<AtomicU64>);
+
+    impl FunctionProvider for Ticker {
+        fn register(&self, ctx: &mut Context) {
+            let ticks = self.0.clone();
+            ctx.add_function("tick", move || ticks.fetch_add(1, Ordering::SeqCst));
+        }
+
+        fn names(&self) -> Vec<String> {
+            vec!["tick".to_string()]
+        }
+
+        fn is_volatile(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_is_deterministic() {
+        let plain = Evaluator::new().with_function_provider(Ticker(Arc::default()));
+        let reloader = plain.reloader();
+        let context = json!({"name": "a"});
+        assert!(reloader.is_deterministic("name + 'b'", &context));
+        assert!(reloader.is_deterministic("greeting", &context));
+        assert!(!reloader.is_deterministic("tick() > 0u", &context));
+        assert!(!reloader.is_deterministic("name +", &context));
+
+        let resolved = Evaluator::new()
+            .with_variable_resolver(Constants)
+            .with_base_context(json!({"region": "eu"}).as_object().unwrap());
+        let reloader = resolved.reloader();
+        assert!(reloader.is_deterministic("name + region", &context));
+        assert!(reloader.is_deterministic("[1].all(x, x > 0)", &context));
+        assert!(!reloader.is_deterministic("greeting + name", &context));
+    }
+
+    #[test]
+    fn test_evaluate_with_extensions() {
+        let evaluator = Evaluator::new()
+            .with_function_provider(|ctx: &mut Context| {
+                ctx.add_function("shout", |s: Arc<String>| s.to_uppercase());
+            })
+            .with_variable_resolver(Constants);
+        let response = evaluator.evaluate(
+            "shout(greeting) + name",
+            &json!({"name": ", world"}),
+            Profile::Default,
+        );
+        assert_eq!(response, Ok(Value::from("HELLO, world")));
+    }
+
+    #[test]
+    fn test_project_shares_context() {
+        let outputs = vec![
+            ("admin".to_string(), "'admin' in user.roles".to_string()),
+            ("name".to_string(), "user.name + '!'".to_string()),
+        ];
+        let context = json!({"user": {"name": "alice", "roles": ["admin"]}});
+        let response =
+            Evaluator::new().project(&outputs, &context, Profile::Default, MergeStrategy::Replace);
+        assert_eq!(response, Ok(json!({"admin": true, "name": "alice!"})));
+
+        let broken = vec![("bad".to_string(), "1 +".to_string())];
+        let err = Evaluator::new()
+            .project(&broken, &context, Profile::Default, MergeStrategy::Replace)
+            .unwrap_err();
+        assert!(err.starts_with("CEL compile error") && err.ends_with("(output `bad`)"));
+    }
+
+    #[test]
+    fn test_base_context_is_overridden_by_request() {
+        let base = json!({"env": "prod", "flags": {"beta": true}});
+        let evaluator = Evaluator::new().with_base_context(base.as_object().unwrap());
+        let response = evaluator.evaluate(
+            "flags.beta ? env : 'off'",
+            &json!({"env": "dev"}),
+            Profile::Default,
+        );
+        assert_eq!(response, Ok(Value::from("dev")));
+    }
+
+    #[test]
+    fn test_merge_strategies_with_base_context() {
+        let base = json!({"flags": {"beta": true, "dark": false}, "env": "prod"});
+        let evaluator = Evaluator::new().with_base_context(base.as_object().unwrap());
+        let deep = evaluator.evaluate_merged(
+            "flags",
+            &json!({"flags": {"dark": true}}),
+            Profile::Default,
+            MergeStrategy::Deep,
+        );
+        assert_eq!(deep, Ok(json!({"beta": true, "dark": true})));
+
+        let patched = evaluator.evaluate_merged(
+            "flags",
+            &json!({"flags": {"beta": null}}),
+            Profile::Default,
+            MergeStrategy::MergePatch,
+        );
+        assert_eq!(patched, Ok(json!({"dark": false})));
+
+        let unset = evaluator.evaluate_merged(
+            "env",
+            &json!({"env": null}),
+            Profile::Default,
+            MergeStrategy::MergePatch,
+        );
+        assert!(unset.is_err());
+    }
+
+    #[test]
+    fn test_json_numbers() {
+        let context = json!({"n": 5, "base": 2});
+        let evaluator = Evaluator::new().with_base_context(context.as_object().unwrap());
+        assert!(
+            evaluator
+                .evaluate("n + 1", &context, Profile::Default)
+                .is_err()
+        );
+        assert_eq!(
+            evaluator.evaluate("n == 5 && 2u > -1 && n < 5.5", &context, Profile::Default),
+            Ok(Value::Bool(true))
+        );
+        let evaluator = evaluator.with_json_numbers(JsonNumbers::Int);
+        assert_eq!(
+            evaluator.evaluate("n + 1", &context, Profile::Default),
+            Ok(json!(6))
+        );
+        assert_eq!(
+            evaluator.evaluate("base - 3", &json!({}), Profile::Default),
+            Ok(json!(-1))
+        );
+    }
+
+    #[test]
+    fn test_lenient_attributes_by_default() {
+        let context = json!({"user": {"name": "ada"}});
+        let evaluator = Evaluator::new().with_strict_attributes(false);
+        assert_eq!(
+            evaluator.evaluate(
+                "user.role == null ? 'guest' : user.role",
+                &context,
+                Profile::Default
+            ),
+            Ok(json!("guest"))
+        );
+        assert!(
+            Evaluator::new()
+                .evaluate("user.role", &context, Profile::Default)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_compile_cache_is_bounded() {
+        let evaluator = Evaluator::new().with_cache_capacity(1);
+        let first = evaluator.compile("1 + 1").unwrap();
+        assert!(Arc::ptr_eq(&first, &evaluator.compile("1 + 1").unwrap()));
+        assert!(Arc::ptr_eq(&first, &evaluator.compile("(1)+ 1").unwrap()));
+        evaluator.compile("2 + 2").unwrap();
+        assert!(!Arc::ptr_eq(&first, &evaluator.compile("1 + 1").unwrap()));
+    }
+
+    #[test]
+    fn test_warm_up_pins_programs() {
+        let evaluator = Evaluator::new().with_cache_capacity(1);
+        let expressions = vec![("r".to_string(), "a > 1".to_string())];
+        assert_eq!(evaluator.warm_up(&expressions), Ok(1));
+        let pinned = evaluator.compile("a > 1").unwrap();
+        evaluator.compile("2 + 2").unwrap();
+        evaluator.compile("3 + 3").unwrap();
+        assert!(Arc::ptr_eq(&pinned, &evaluator.compile("a>1").unwrap()));
+    }
+
+    #[test]
+    fn test_to_json_nested_collections() {
+        let response = Evaluator::new().evaluate(
+            "{'a': [1, 'x', {'b': null}], 2: b'hi'}",
+            &json!({}),
+            Profile::Default,
+        );
+        assert_eq!(
+            response,
+            Ok(json!({"a": [1, "x", {"b": null}], "2": "aGk="}))
+        );
+    }
+
+    #[test]
+    fn test_big_ints_as_strings() {
+        let evaluator = Evaluator::new().with_encoding(Encoding {
+            big_ints_as_strings: true,
+            ..Encoding::default()
+        });
+        let response = evaluator.evaluate(
+            "[9007199254740991, 9007199254740992, -9007199254740993, 18446744073709551615u]",
+            &json!({}),
+            Profile::Default,
+        );
+        let expected = json!([
+            9007199254740991u64,
+            "9007199254740992",
+            "-9007199254740993",
+            "18446744073709551615"
+        ]);
+        assert_eq!(response, Ok(expected));
+        let response = evaluator.evaluate(
+            "int(id) + 1",
+            &json!({"id": "9007199254740992"}),
+            Profile::Default,
+        );
+        assert_eq!(response, Ok(json!("9007199254740993")));
+    }
+
+    #[test]
+    fn test_non_finite_floats() {
+        let encode = |non_finite| {
+            to_json_with(
+                CelValueEnum::List(Arc::new(vec![
+                    CelValueEnum::Float(f64::NAN),
+                    CelValueEnum::Float(f64::NEG_INFINITY),
+                    CelValueEnum::Float(1.5),
+                ])),
+                Encoding {
+                    non_finite,
+                    ..Encoding::default()
+                },
+            )
+        };
+        assert_eq!(encode(NonFinite::Null), Ok(json!([null, null, 1.5])));
+        assert_eq!(
+            encode(NonFinite::String),
+            Ok(json!(["NaN", "-Infinity", 1.5]))
+        );
+        assert_eq!(
+            encode(NonFinite::Error),
+            Err("Result encoding error: NaN has no JSON representation".to_string())
+        );
+    }
+
+    #[test]
+    fn test_to_json_sorts_map_keys() {
+        let response = Evaluator::new().evaluate(
+            "{'b': 1, 'a': 2, 10: 3, true: 4, 2u: 5}",
+            &json!({}),
+            Profile::Default,
+        );
+        let Ok(Value::Object(map)) = response else {
+            panic!("expected an object, got {:?}", response);
+        };
+        let keys: Vec<&str> = map.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["10", "2", "a", "b", "true"]);
+    }
+
+    #[test]
+    fn test_to_json_deeply_nested_list() {
+        const DEPTH: usize = 100_000;
+        let mut value = CelValueEnum::Int(1);
+        for _ in 0..DEPTH {
+            value = CelValueEnum::List(Arc::new(vec![value]));
+        }
+        // Unwrap level by level: dropping the result whole would recurse.
+        let mut json = to_json(value);
+        let mut depth = 0;
+        while let Value::Array(mut items) = json {
+            json = items.pop().unwrap_or_default();
+            depth += 1;
+        }
+        assert_eq!((depth, json), (DEPTH, json!(1)));
+    }
+
+    #[test]
+    fn test_interactive_requests_go_first() {
+        let request = |session, priority, id: &str| EvalRequest {
+            query: Query::Expression("1".to_string()),
+            context: json!({}),
+            profile: Profile::Default,
+            merge: MergeStrategy::Replace,
+            non_finite: None,
+            cost_limit: None,
+            strict_attributes: None,
+            deadline: None,
+            responder: oneshot::channel().0,
+            request_id: id.to_string(),
+            span: tracing::Span::none(),
+            queue_wait: tracing::Span::none(),
+            lane: Lane { session, priority },
+        };
+        let mut queues = Queues::default();
+        for i in 0..3 {
+            queues.push(request(1, Priority::Batch, &format!("b{}", i)));
+        }
+        for i in 0..6 {
+            queues.push(request(
+                2 + i % 2,
+                Priority::Interactive,
+                &format!("i{}", i),
+            ));
+        }
+        let order: Vec<String> = std::iter::from_fn(|| queues.pop())
+            .map(|request| request.request_id)
+            .collect();
+        assert_eq!(
+            order,
+            ["i0", "i1", "i2", "i3", "b0", "i4", "i5", "b1", "b2"]
+        );
+    }
+
+    #[test]
+    fn test_first_match_stops_at_first_true_rule() {
+        let rules = |conditions: &[&str]| -> Vec<(String, String)> {
+            conditions
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (format!("r{}", i), c.to_string()))
+                .collect()
+        };
+        let context = json!({"role": "admin"});
+        let first_match = |conditions: &[&str]| {
+            Evaluator::new().first_match(
+                &rules(conditions),
+                &context,
+                Profile::Default,
+                MergeStrategy::Replace,
+            )
+        };
+        // The failing third rule is never executed.
+        assert_eq!(
+            first_match(&["role == 'guest'", "role == 'admin'", "1 / 0 == 0"]),
+            Ok(json!("r1"))
+        );
+        assert_eq!(first_match(&["false"]), Ok(Value::Null));
+        assert_eq!(
+            Evaluator::new().all_matches(
+                &rules(&["true", "false", "role != ''"]),
+                &context,
+                Profile::Default,
+                MergeStrategy::Replace,
+            ),
+            Ok(json!(["r0", "r2"]))
+        );
+        let err = first_match(&["role"]).unwrap_err();
+        assert!(err.contains("expected bool (rule `r0`)"), "{}", err);
+    }
+
+    /// `meet()` returns once two evaluations call it at the same time, or false after a while.
+    struct Meeting(Arc<AtomicU64>);
+
+    impl FunctionProvider for Meeting {
+        fn register(&self, ctx: &mut Context) {
+            let arrived = self.0.clone();
+            ctx.add_function("meet", move || {
+                arrived.fetch_add(1, Ordering::SeqCst);
+                let started = Instant::now();
+                while arrived.load(Ordering::SeqCst) < 2 {
+                    if started.elapsed() > Duration::from_secs(5) {
+                        return false;
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                true
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_workers_evaluate_at_once() {
+        let (sender, receiver) = mpsc::channel(8);
+        let evaluator = Evaluator::new()
+            .with_function_provider(Meeting(Arc::default()))
+            .with_workers(2);
+        tokio::spawn(evaluator.serve(receiver));
+        let mut replies = Vec::new();
+        for session in 0..2 {
+            let (responder, reply) = oneshot::channel();
+            let request = EvalRequest {
+                query: Query::Expression("meet()".to_string()),
+                context: json!({}),
+                profile: Profile::Default,
+                merge: MergeStrategy::Replace,
+                non_finite: None,
+                cost_limit: None,
+                strict_attributes: None,
+                deadline: None,
+                responder,
+                request_id: session.to_string(),
+                span: tracing::Span::none(),
+                queue_wait: tracing::Span::none(),
+                lane: Lane {
+                    session,
+                    priority: Priority::Interactive,
+                },
+            };
+            sender.send(request).await.unwrap();
+            replies.push(reply);
+        }
+        for reply in replies {
+            assert_eq!(reply.await.unwrap().response, Ok(json!(true)));
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Round robin over per-key queues, so the evaluator shares its time between sessions.
+//!
+//! Each key with waiting items takes a turn of one item before the next key's turn, so a
+//! client queueing many batch items cannot starve another's interactive calls.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+pub struct FairQueue<K, T> {
+    queues: HashMap<K, VecDeque<T>>,
+    /// Keys with waiting items, the one taking its turn first.
+    turns: VecDeque<K>,
+}
+
+impl<K, T> Default for FairQueue<K, T> {
+    fn default() -> Self {
+        Self {
+            queues: HashMap::new(),
+            turns: VecDeque::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, T> FairQueue<K, T> {
+    /// Queues `item` under `key`.
+    pub fn push(&mut self, key: K, item: T) {
+        self.queues
+            .entry(key.clone())
+            .or_insert_with(|| {
+                self.turns.push_back(key);
+                VecDeque::new()
+            })
+            .push_back(item);
+    }
+
+    /// The next item in turn.
+    pub fn pop(&mut self) -> Option<T> {
+        let key = self.turns.pop_front()?;
+        let queue = self.queues.get_mut(&key)?;
+        let item = queue.pop_front();
+        if queue.is_empty() {
+            self.queues.remove(&key);
+        } else {
+            self.turns.push_back(key);
+        }
+        item
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_turns() {
+        let mut queue = FairQueue::default();
+        for i in 0..4 {
+            queue.push("a", format!("a{}", i));
+        }
+        queue.push("b", "b0".to_string());
+        queue.push("b", "b1".to_string());
+        queue.push("c", "c0".to_string());
+        assert_eq!(queue.len(), 7);
+
+        let order: Vec<String> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(order, ["a0", "b0", "c0", "a1", "b1", "a2", "a3"]);
+        assert!(queue.is_empty());
+    }
+}
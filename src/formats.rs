@@ -0,0 +1,265 @@
+//! Decoders for legacy payload formats that contexts carry as strings: `xml.decode(s)`,
+//! `qs.parse(s)` and `csv.decode(s, options)`.
+//!
+//! An XML document decodes to a map from its root element's name to the element's value.
+//! An element holding only text is that string; otherwise it is a map of its attributes,
+//! keyed `@name`, its child elements by name (a list when a name repeats), and its text,
+//! keyed `#text`. DTDs are rejected, so entities can't expand.
+
+use crate::conversion::from_json;
+use crate::provider::{Method, Namespace};
+use cel::objects::{Key, Map as CelMap};
+use cel::{FunctionContext, Value as CelValue};
+use rmcp::serde_json::{Map, Value};
+use roxmltree::{Document, Node};
+use std::sync::Arc;
+
+/// The namespace `xml.decode` is called on.
+pub const XML_VARIABLE: &str = "xml";
+
+/// The namespace `qs.parse` is called on.
+pub const QS_VARIABLE: &str = "qs";
+
+/// The namespace `csv.decode` is called on.
+pub const CSV_VARIABLE: &str = "csv";
+
+pub(crate) fn xml_namespace() -> Namespace {
+    let decode: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::String(text)] = args else {
+            return Err(ftx.error("xml.decode expects one string"));
+        };
+        decode_xml(text)
+            .map(|value| from_json(&value))
+            .map_err(|e| ftx.error(e))
+    });
+    Namespace {
+        name: XML_VARIABLE.to_string(),
+        methods: vec![("decode".to_string(), decode)],
+    }
+}
+
+pub(crate) fn qs_namespace() -> Namespace {
+    let parse: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::String(text)] = args else {
+            return Err(ftx.error("qs.parse expects one string"));
+        };
+        Ok(from_json(&parse_query(text)))
+    });
+    Namespace {
+        name: QS_VARIABLE.to_string(),
+        methods: vec![("parse".to_string(), parse)],
+    }
+}
+
+pub(crate) fn csv_namespace() -> Namespace {
+    let decode: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let (text, options) = match args {
+            [CelValue::String(text)] => (text, CsvOptions::default()),
+            [CelValue::String(text), CelValue::Map(options)] => (
+                text,
+                CsvOptions::from_map(options).map_err(|e| ftx.error(e))?,
+            ),
+            _ => {
+                return Err(ftx.error("csv.decode expects a string and optionally an options map"));
+            }
+        };
+        decode_csv(text, &options)
+            .map(|value| from_json(&value))
+            .map_err(|e| ftx.error(e))
+    });
+    Namespace {
+        name: CSV_VARIABLE.to_string(),
+        methods: vec![("decode".to_string(), decode)],
+    }
+}
+
+struct CsvOptions {
+    /// Whether the first row names the columns, making rows maps instead of lists.
+    header: bool,
+    delimiter: u8,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            header: true,
+            delimiter: b',',
+        }
+    }
+}
+
+impl CsvOptions {
+    fn from_map(options: &CelMap) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        for (key, value) in options.map.iter() {
+            match (key, value) {
+                (Key::String(key), CelValue::Bool(header)) if key.as_str() == "header" => {
+                    parsed.header = *header;
+                }
+                (Key::String(key), CelValue::String(delimiter)) if key.as_str() == "delimiter" => {
+                    let &[delimiter] = delimiter.as_bytes() else {
+                        return Err("csv.decode's `delimiter` must be one ASCII character".into());
+                    };
+                    parsed.delimiter = delimiter;
+                }
+                _ => {
+                    return Err(
+                        "csv.decode's options are `header` (a bool) and `delimiter` (a string)"
+                            .into(),
+                    );
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// A list of rows: maps from column names to fields with a header, lists of fields without.
+fn decode_csv(text: &str, options: &CsvOptions) -> Result<Value, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(options.header)
+        .delimiter(options.delimiter)
+        .from_reader(text.as_bytes());
+    let invalid = |e: csv::Error| format!("Invalid CSV: {}", e);
+    let header = if options.header {
+        Some(reader.headers().map_err(invalid)?.clone())
+    } else {
+        None
+    };
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(invalid)?;
+            Ok(match &header {
+                Some(header) => Value::Object(
+                    header
+                        .iter()
+                        .zip(record.iter())
+                        .map(|(name, field)| (name.to_string(), Value::from(field)))
+                        .collect(),
+                ),
+                None => Value::Array(record.iter().map(Value::from).collect()),
+            })
+        })
+        .collect::<Result<Vec<Value>, String>>()
+        .map(Value::Array)
+}
+
+fn decode_xml(text: &str) -> Result<Value, String> {
+    let document = Document::parse(text).map_err(|e| format!("Invalid XML: {}", e))?;
+    let root = document.root_element();
+    let mut decoded = Map::new();
+    decoded.insert(root.tag_name().name().to_string(), element(root));
+    Ok(Value::Object(decoded))
+}
+
+fn element(node: Node) -> Value {
+    let mut fields = Map::new();
+    for attribute in node.attributes() {
+        fields.insert(
+            format!("@{}", attribute.name()),
+            Value::from(attribute.value()),
+        );
+    }
+    let mut text = String::new();
+    for child in node.children() {
+        if child.is_element() {
+            push(&mut fields, child.tag_name().name(), element(child));
+        } else if child.is_text() {
+            text.push_str(child.text().unwrap_or_default());
+        }
+    }
+    let text = text.trim();
+    if fields.is_empty() {
+        return Value::from(text);
+    }
+    if !text.is_empty() {
+        fields.insert("#text".to_string(), Value::from(text));
+    }
+    Value::Object(fields)
+}
+
+/// Adds `value` under `key`, turning the entry into a list when `key` repeats.
+fn push(fields: &mut Map<String, Value>, key: &str, value: Value) {
+    match fields.get_mut(key) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+        None => {
+            fields.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// A map from each key to its value, or a list of its values when it repeats.
+fn parse_query(text: &str) -> Value {
+    let mut fields = Map::new();
+    let text = text.strip_prefix('?').unwrap_or(text);
+    for (key, value) in form_urlencoded::parse(text.as_bytes()) {
+        push(&mut fields, &key, Value::from(value.into_owned()));
+    }
+    Value::Object(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::Evaluator;
+    use crate::library::Library;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_decode_xml_and_query_strings() {
+        let evaluator = Evaluator::new()
+            .with_function_provider(Library::Xml)
+            .with_function_provider(Library::Qs);
+        let evaluate = |expression: &str| {
+            evaluator.evaluate(
+                expression,
+                &json!({
+                    "body": "<order id=\"7\"><item>a</item><item>b</item><note>rush</note></order>",
+                    "query": "?user=jane%20doe&tag=a&tag=b",
+                }),
+                Profile::Default,
+            )
+        };
+        assert_eq!(
+            evaluate("xml.decode(body)"),
+            Ok(json!({"order": {"@id": "7", "item": ["a", "b"], "note": "rush"}}))
+        );
+        assert_eq!(
+            evaluate("qs.parse(query)"),
+            Ok(json!({"user": "jane doe", "tag": ["a", "b"]}))
+        );
+        assert!(evaluate("xml.decode('<a>')").is_err());
+        assert!(evaluate("xml.decode('<!DOCTYPE a [<!ENTITY e \"x\">]><a>&e;</a>')").is_err());
+    }
+
+    #[test]
+    fn test_decode_csv() {
+        let evaluator = Evaluator::new().with_function_provider(Library::Csv);
+        let evaluate = |expression: &str| {
+            evaluator.evaluate(
+                expression,
+                &json!({"limits": "user,quota\njane,10\n\"doe, john\",20\n"}),
+                Profile::Default,
+            )
+        };
+        assert_eq!(
+            evaluate("csv.decode(limits)"),
+            Ok(json!([
+                {"user": "jane", "quota": "10"},
+                {"user": "doe, john", "quota": "20"},
+            ]))
+        );
+        assert_eq!(
+            evaluate("csv.decode(limits, {'header': false})[1]"),
+            Ok(json!(["jane", "10"]))
+        );
+        assert_eq!(
+            evaluate("csv.decode('a;b\\n1;2', {'delimiter': ';'}).all(r, r.a == '1')"),
+            Ok(json!(true))
+        );
+        assert!(evaluate("csv.decode('a,b\\n1')").is_err());
+        assert!(evaluate("csv.decode(limits, {'headers': true})").is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! `geoip.country(ip)`, looking up the country of an IP address in a local MaxMind DB
+//! (MMDB) file, such as GeoLite2 Country, for geo-fencing policies.
+
+use crate::provider::{FunctionProvider, Method, Namespace};
+use cel::{Context, FunctionContext, Value as CelValue};
+use maxminddb::{Reader, geoip2};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The namespace `country` is called on.
+pub const VARIABLE: &str = "geoip";
+
+/// A GeoIP database, loaded into memory.
+#[derive(Clone)]
+pub struct GeoIp {
+    reader: Arc<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    /// Reads a database file.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let reader = Reader::open_readfile(path)
+            .map_err(|e| format!("Invalid GeoIP database {}: {}", path.display(), e))?;
+        Ok(Self {
+            reader: Arc::new(reader),
+        })
+    }
+
+    /// Reads a database from its bytes.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, String> {
+        let reader =
+            Reader::from_source(bytes).map_err(|e| format!("Invalid GeoIP database: {}", e))?;
+        Ok(Self {
+            reader: Arc::new(reader),
+        })
+    }
+
+    /// The ISO 3166-1 alpha-2 code of the country `ip` is located in, if the database knows.
+    pub fn country(&self, ip: &str) -> Result<Option<String>, String> {
+        let address: IpAddr = ip
+            .parse()
+            .map_err(|_| format!("`{}` is not an IP address", ip))?;
+        let found = self
+            .reader
+            .lookup::<geoip2::Country>(address)
+            .map_err(|e| format!("GeoIP lookup of {} failed: {}", ip, e))?;
+        Ok(found
+            .and_then(|found| found.country)
+            .and_then(|country| country.iso_code)
+            .map(str::to_string))
+    }
+}
+
+impl FunctionProvider for GeoIp {
+    fn register(&self, _ctx: &mut Context) {}
+
+    fn names(&self) -> Vec<String> {
+        vec!["geoip.country".to_string()]
+    }
+
+    fn namespace(&self) -> Option<Namespace> {
+        let geoip = self.clone();
+        let country: Method = Arc::new(move |ftx: &FunctionContext, args: &[CelValue]| {
+            let [CelValue::String(ip)] = args else {
+                return Err(ftx.error("geoip.country expects one string IP address"));
+            };
+            geoip
+                .country(ip)
+                .map(|code| code.map_or(CelValue::Null, CelValue::from))
+                .map_err(|e| ftx.error(e))
+        });
+        Some(Namespace {
+            name: VARIABLE.to_string(),
+            methods: vec![("country".to_string(), country)],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    /// A control byte for `kind` (1 to 7) and a short `size`, then `payload`.
+    fn field(kind: u8, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![(kind << 5) | payload.len() as u8];
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        field(2, s.as_bytes())
+    }
+
+    fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut out = vec![(7 << 5) | entries.len() as u8];
+        for (key, value) in entries {
+            out.extend(string(key));
+            out.extend_from_slice(value);
+        }
+        out
+    }
+
+    /// An IPv4 database with one node: `0.0.0.0/1` is in the US, the other half unknown.
+    fn database() -> Vec<u8> {
+        let node_count = 1u32;
+        let data_record = node_count + 16;
+        let mut db = Vec::new();
+        db.extend_from_slice(&data_record.to_be_bytes()[1..]);
+        db.extend_from_slice(&node_count.to_be_bytes()[1..]);
+        db.extend_from_slice(&[0; 16]);
+        db.extend(map(&[("country", map(&[("iso_code", string("US"))]))]));
+        db.extend_from_slice(b"\xab\xcd\xefMaxMind.com");
+        db.extend(map(&[
+            ("binary_format_major_version", field(5, &[2])),
+            ("binary_format_minor_version", field(5, &[])),
+            ("build_epoch", vec![1, 2, 1]),
+            ("database_type", string("Test-Country")),
+            ("description", map(&[])),
+            ("ip_version", field(5, &[4])),
+            ("languages", vec![0, 4]),
+            ("node_count", field(6, &[1])),
+            ("record_size", field(5, &[24])),
+        ]));
+        db
+    }
+
+    #[test]
+    fn test_country_lookup() {
+        let geoip = GeoIp::from_bytes(database()).unwrap();
+        assert_eq!(geoip.country("8.8.8.8"), Ok(Some("US".to_string())));
+        assert_eq!(geoip.country("200.1.1.1"), Ok(None));
+        assert!(geoip.country("not-an-ip").is_err());
+
+        let evaluator = Evaluator::new().with_function_provider(geoip);
+        let evaluate =
+            |expression: &str| evaluator.evaluate(expression, &json!({}), Profile::Default);
+        assert_eq!(
+            evaluate("geoip.country('8.8.8.8') in ['US', 'CA']"),
+            Ok(json!(true))
+        );
+        assert_eq!(evaluate("geoip.country('200.1.1.1')"), Ok(json!(null)));
+    }
+}
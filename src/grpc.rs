@@ -0,0 +1,324 @@
+//! gRPC transport implementing the cel-spec `ConformanceService` (Parse/Check/Eval).
+//!
+//! Enabled with the `grpc` feature. The service shares the parser and interpreter used by the
+//! MCP tools, so the official conformance runner can exercise the CEL semantics exposed over
+//! MCP. This crate has no type checker: `Check` returns the parsed expression unchanged.
+//!
+//! It is a conformance port, not an evaluation API. `Eval` takes a syntax tree and typed
+//! bindings rather than text and JSON, so it bypasses the evaluator's queue: it runs on a
+//! blocking thread under the server's timeout, iteration, cost, size and regex limits, and a
+//! panic fails only its request. It offers plain CEL, without function libraries, plugins,
+//! resolvers or a base context. It has no authentication; only `--allow-cidr` and
+//! `--deny-cidr` restrict who may call it.
+
+pub mod convert;
+
+use crate::ast;
+use crate::cost::{self, Meter};
+use crate::evaluator::{Limits, panic_message};
+use crate::ip_filter::IpFilter;
+use crate::pattern::RegexCache;
+use cel::Context;
+use googleapis_tonic_google_api_expr_conformance_v1alpha1::google::api::expr::conformance::v1alpha1::{
+    CheckRequest, CheckResponse, EvalRequest, EvalResponse, ParseRequest, ParseResponse,
+    eval_request,
+};
+use googleapis_tonic_google_api_expr_conformance_v1alpha1::google::api::expr::v1alpha1::{
+    CheckedExpr, ErrorSet, ExprValue, ParsedExpr, SourceInfo, expr_value,
+};
+use googleapis_tonic_google_api_expr_conformance_v1alpha1::google::rpc::Status;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::Instant;
+use tonic::{Request, Response};
+
+mod generated {
+    include!(concat!(
+        env!("OUT_DIR"),
+        "/google.api.expr.conformance.v1alpha1.ConformanceService.rs"
+    ));
+}
+
+pub use generated::conformance_service_server::{ConformanceService, ConformanceServiceServer};
+
+/// `google.rpc.Code.INVALID_ARGUMENT`
+const INVALID_ARGUMENT: i32 = 3;
+
+fn issue(message: impl Into<String>) -> Status {
+    Status {
+        code: INVALID_ARGUMENT,
+        message: message.into(),
+        details: Vec::new(),
+    }
+}
+
+/// The `ConformanceService` backed by this crate's CEL implementation.
+#[derive(Clone)]
+pub struct CelConformanceService {
+    limits: Limits,
+    regexes: Arc<RegexCache>,
+}
+
+impl Default for CelConformanceService {
+    fn default() -> Self {
+        Self::new(Limits::default())
+    }
+}
+
+impl CelConformanceService {
+    /// Evaluates under `limits`, as the evaluator would.
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            regexes: Arc::new(RegexCache::new(limits.max_regex_size)),
+        }
+    }
+
+    pub fn parse(&self, request: &ParseRequest) -> ParseResponse {
+        if request.disable_macros {
+            return ParseResponse {
+                parsed_expr: None,
+                issues: vec![issue("disable_macros is not supported by this parser")],
+            };
+        }
+        match ast::parse(&request.cel_source) {
+            Ok(expr) => ParseResponse {
+                parsed_expr: Some(ParsedExpr {
+                    expr: Some(convert::expr_to_proto(&expr)),
+                    source_info: Some(SourceInfo {
+                        syntax_version: request.syntax_version.clone(),
+                        location: request.source_location.clone(),
+                        ..Default::default()
+                    }),
+                }),
+                issues: Vec::new(),
+            },
+            Err(e) => ParseResponse {
+                parsed_expr: None,
+                issues: vec![issue(format!("CEL compile error: {}", e))],
+            },
+        }
+    }
+
+    pub fn check(&self, request: &CheckRequest) -> CheckResponse {
+        match &request.parsed_expr {
+            Some(parsed) => CheckResponse {
+                checked_expr: Some(CheckedExpr {
+                    expr: parsed.expr.clone(),
+                    source_info: parsed.source_info.clone(),
+                    ..Default::default()
+                }),
+                issues: Vec::new(),
+            },
+            None => CheckResponse {
+                checked_expr: None,
+                issues: vec![issue("missing parsed_expr")],
+            },
+        }
+    }
+
+    /// Evaluates `request`, blocking until it is done or the timeout passes.
+    pub fn eval(&self, request: &EvalRequest) -> EvalResponse {
+        let started = Instant::now();
+        let mut meter = Meter::new(&self.limits, None).with_deadline(
+            self.limits
+                .timeout
+                .and_then(|limit| started.checked_add(limit)),
+        );
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+            eval(request, &self.regexes, &mut meter)
+        }))
+        .unwrap_or_else(|payload| {
+            let message = panic_message(payload.as_ref());
+            tracing::error!("gRPC evaluation panicked: {}", message);
+            Err(format!("Internal evaluation panic: {}", message))
+        });
+        match outcome {
+            Ok(result) => EvalResponse {
+                result: Some(result),
+                issues: Vec::new(),
+            },
+            Err(e) => EvalResponse {
+                result: None,
+                issues: vec![issue(e)],
+            },
+        }
+    }
+}
+
+/// Evaluates the request; runtime errors become an error `ExprValue`, malformed requests an `Err`.
+fn eval(
+    request: &EvalRequest,
+    regexes: &Arc<RegexCache>,
+    meter: &mut Meter,
+) -> Result<ExprValue, String> {
+    let expr = match &request.expr_kind {
+        Some(eval_request::ExprKind::ParsedExpr(parsed)) => parsed.expr.as_ref(),
+        Some(eval_request::ExprKind::CheckedExpr(checked)) => checked.expr.as_ref(),
+        None => None,
+    }
+    .ok_or_else(|| "missing expression".to_string())?;
+    let expr = convert::expr_from_proto(expr)?;
+
+    let mut ctx = Context::default();
+    regexes.register(&mut ctx);
+    for (name, value) in &request.bindings {
+        match &value.kind {
+            Some(expr_value::Kind::Value(value)) => {
+                ctx.add_variable_from_value(name.clone(), convert::value_from_proto(value)?);
+            }
+            _ => return Err(format!("binding `{}` is not a value", name)),
+        }
+    }
+
+    let kind = match cost::resolve(&expr, &ctx, meter) {
+        Ok(value) => match convert::value_to_proto(&value) {
+            Ok(value) => expr_value::Kind::Value(value),
+            Err(e) => expr_value::Kind::Error(ErrorSet {
+                errors: vec![issue(e)],
+            }),
+        },
+        Err(e) => expr_value::Kind::Error(ErrorSet {
+            errors: vec![issue(format!("CEL execution error: {}", e))],
+        }),
+    };
+    Ok(ExprValue { kind: Some(kind) })
+}
+
+#[tonic::async_trait]
+impl ConformanceService for CelConformanceService {
+    async fn parse(
+        &self,
+        request: Request<ParseRequest>,
+    ) -> Result<Response<ParseResponse>, tonic::Status> {
+        Ok(Response::new(CelConformanceService::parse(
+            self,
+            request.get_ref(),
+        )))
+    }
+
+    async fn check(
+        &self,
+        request: Request<CheckRequest>,
+    ) -> Result<Response<CheckResponse>, tonic::Status> {
+        Ok(Response::new(CelConformanceService::check(
+            self,
+            request.get_ref(),
+        )))
+    }
+
+    async fn eval(
+        &self,
+        request: Request<EvalRequest>,
+    ) -> Result<Response<EvalResponse>, tonic::Status> {
+        // Evaluating on a blocking thread keeps a long evaluation from stalling the runtime.
+        let service = self.clone();
+        let request = request.into_inner();
+        tokio::task::spawn_blocking(move || CelConformanceService::eval(&service, &request))
+            .await
+            .map(Response::new)
+            .map_err(|e| tonic::Status::internal(e.to_string()))
+    }
+}
+
+/// Serves `service` on `addr` until the process exits, refusing clients `filter` doesn't
+/// permit with `PERMISSION_DENIED`.
+pub async fn serve(
+    addr: SocketAddr,
+    service: CelConformanceService,
+    filter: Arc<IpFilter>,
+) -> Result<(), tonic::transport::Error> {
+    let check = move |request: Request<()>| match request.remote_addr() {
+        Some(peer) if filter.permits(peer.ip()) => Ok(request),
+        peer => {
+            let client = peer.map_or_else(|| "unknown".to_string(), |peer| peer.ip().to_string());
+            tracing::warn!("Refused a gRPC request from {}", client);
+            Err(tonic::Status::permission_denied(format!(
+                "Requests from {} are not allowed",
+                client
+            )))
+        }
+    };
+    tonic::transport::Server::builder()
+        .add_service(ConformanceServiceServer::with_interceptor(service, check))
+        .serve(addr)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googleapis_tonic_google_api_expr_conformance_v1alpha1::google::api::expr::v1alpha1::{
+        Value, value,
+    };
+
+    #[test]
+    fn test_parse_then_eval_with_bindings() {
+        let service = CelConformanceService::default();
+        let parsed = service.parse(&ParseRequest {
+            cel_source: "x + 1".to_string(),
+            ..Default::default()
+        });
+        assert!(parsed.issues.is_empty());
+
+        let mut request = EvalRequest {
+            expr_kind: parsed.parsed_expr.map(eval_request::ExprKind::ParsedExpr),
+            ..Default::default()
+        };
+        request.bindings.insert(
+            "x".to_string(),
+            ExprValue {
+                kind: Some(expr_value::Kind::Value(Value {
+                    kind: Some(value::Kind::Int64Value(41)),
+                })),
+            },
+        );
+        let response = service.eval(&request);
+        assert_eq!(
+            response.result,
+            Some(ExprValue {
+                kind: Some(expr_value::Kind::Value(Value {
+                    kind: Some(value::Kind::Int64Value(42)),
+                })),
+            })
+        );
+    }
+
+    #[test]
+    fn test_eval_runtime_error_is_error_set() {
+        let service = CelConformanceService::default();
+        let parsed = service.parse(&ParseRequest {
+            cel_source: "1 / 0".to_string(),
+            ..Default::default()
+        });
+        let response = service.eval(&EvalRequest {
+            expr_kind: parsed.parsed_expr.map(eval_request::ExprKind::ParsedExpr),
+            ..Default::default()
+        });
+        assert!(matches!(
+            response.result.and_then(|r| r.kind),
+            Some(expr_value::Kind::Error(_))
+        ));
+    }
+
+    #[test]
+    fn test_eval_applies_limits() {
+        let service = CelConformanceService::new(Limits {
+            max_iterations: Some(2),
+            ..Limits::default()
+        });
+        let parsed = service.parse(&ParseRequest {
+            cel_source: "[1, 2, 3].all(x, x > 0)".to_string(),
+            ..Default::default()
+        });
+        let response = service.eval(&EvalRequest {
+            expr_kind: parsed.parsed_expr.map(eval_request::ExprKind::ParsedExpr),
+            ..Default::default()
+        });
+        let Some(expr_value::Kind::Error(errors)) = response.result.and_then(|r| r.kind) else {
+            panic!("expected an error");
+        };
+        assert!(errors.errors[0].message.contains("cost budget exceeded"));
+    }
+}
@@ -0,0 +1,315 @@
+//! Conversions between the cel-spec protobuf representation and the `cel` crate's AST and values.
+
+use cel::Value as CelValueEnum;
+use cel::common::ast::{
+    CallExpr, ComprehensionExpr, EntryExpr, Expr, IdedEntryExpr, IdedExpr, ListExpr, MapEntryExpr,
+    MapExpr, SelectExpr, StructExpr, StructFieldExpr,
+};
+use cel::common::value::CelVal;
+use cel::objects::{Key, Map as CelMap};
+use chrono::{DateTime, TimeDelta};
+use googleapis_tonic_google_api_expr_conformance_v1alpha1::google::api::expr::v1alpha1 as pb;
+use pb::expr::create_struct::{Entry, entry::KeyKind};
+use pb::expr::{Call, Comprehension, CreateList, CreateStruct, ExprKind, Ident, Select};
+use prost::Message;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const TIMESTAMP_TYPE_URL: &str = "type.googleapis.com/google.protobuf.Timestamp";
+const DURATION_TYPE_URL: &str = "type.googleapis.com/google.protobuf.Duration";
+
+/// Converts a parsed `cel` AST into its protobuf form.
+pub fn expr_to_proto(expr: &IdedExpr) -> pb::Expr {
+    let id = i64::try_from(expr.id).unwrap_or_default();
+    let expr_kind = match &expr.expr {
+        Expr::Unspecified => None,
+        Expr::Literal(val) => Some(ExprKind::ConstExpr(literal_to_constant(val))),
+        Expr::Ident(name) => Some(ExprKind::IdentExpr(Ident { name: name.clone() })),
+        Expr::Select(select) => Some(ExprKind::SelectExpr(Box::new(Select {
+            operand: Some(Box::new(expr_to_proto(&select.operand))),
+            field: select.field.clone(),
+            test_only: select.test,
+        }))),
+        Expr::Call(call) => Some(ExprKind::CallExpr(Box::new(Call {
+            target: call.target.as_deref().map(|t| Box::new(expr_to_proto(t))),
+            function: call.func_name.clone(),
+            args: call.args.iter().map(expr_to_proto).collect(),
+        }))),
+        Expr::List(list) => Some(ExprKind::ListExpr(CreateList {
+            elements: list.elements.iter().map(expr_to_proto).collect(),
+            optional_indices: Vec::new(),
+        })),
+        Expr::Map(map) => Some(ExprKind::StructExpr(CreateStruct {
+            message_name: String::new(),
+            entries: map.entries.iter().map(entry_to_proto).collect(),
+        })),
+        Expr::Struct(st) => Some(ExprKind::StructExpr(CreateStruct {
+            message_name: st.type_name.clone(),
+            entries: st.entries.iter().map(entry_to_proto).collect(),
+        })),
+        Expr::Comprehension(comp) => Some(ExprKind::ComprehensionExpr(Box::new(Comprehension {
+            iter_var: comp.iter_var.clone(),
+            iter_var2: comp.iter_var2.clone().unwrap_or_default(),
+            iter_range: Some(Box::new(expr_to_proto(&comp.iter_range))),
+            accu_var: comp.accu_var.clone(),
+            accu_init: Some(Box::new(expr_to_proto(&comp.accu_init))),
+            loop_condition: Some(Box::new(expr_to_proto(&comp.loop_cond))),
+            loop_step: Some(Box::new(expr_to_proto(&comp.loop_step))),
+            result: Some(Box::new(expr_to_proto(&comp.result))),
+        }))),
+    };
+    pb::Expr { id, expr_kind }
+}
+
+fn entry_to_proto(entry: &IdedEntryExpr) -> Entry {
+    let id = i64::try_from(entry.id).unwrap_or_default();
+    match &entry.expr {
+        EntryExpr::MapEntry(e) => Entry {
+            id,
+            value: Some(expr_to_proto(&e.value)),
+            optional_entry: e.optional,
+            key_kind: Some(KeyKind::MapKey(expr_to_proto(&e.key))),
+        },
+        EntryExpr::StructField(f) => Entry {
+            id,
+            value: Some(expr_to_proto(&f.value)),
+            optional_entry: f.optional,
+            key_kind: Some(KeyKind::FieldKey(f.field.clone())),
+        },
+    }
+}
+
+fn literal_to_constant(val: &CelVal) -> pb::Constant {
+    use pb::constant::ConstantKind;
+    let kind = match val {
+        CelVal::Boolean(b) => ConstantKind::BoolValue(*b),
+        CelVal::Int(i) => ConstantKind::Int64Value(*i),
+        CelVal::UInt(u) => ConstantKind::Uint64Value(*u),
+        CelVal::Double(d) => ConstantKind::DoubleValue(*d),
+        CelVal::String(s) => ConstantKind::StringValue(s.clone()),
+        CelVal::Bytes(b) => ConstantKind::BytesValue(b.clone()),
+        _ => ConstantKind::NullValue(0),
+    };
+    pb::Constant {
+        constant_kind: Some(kind),
+    }
+}
+
+/// Converts a protobuf expression into the `cel` AST so it can be evaluated.
+pub fn expr_from_proto(expr: &pb::Expr) -> Result<IdedExpr, String> {
+    let id = u64::try_from(expr.id).unwrap_or_default();
+    let kind = match &expr.expr_kind {
+        None => Expr::Unspecified,
+        Some(ExprKind::ConstExpr(constant)) => Expr::Literal(constant_to_literal(constant)?),
+        Some(ExprKind::IdentExpr(ident)) => Expr::Ident(ident.name.clone()),
+        Some(ExprKind::SelectExpr(select)) => Expr::Select(SelectExpr {
+            operand: Box::new(required(&select.operand, "select operand")?),
+            field: select.field.clone(),
+            test: select.test_only,
+        }),
+        Some(ExprKind::CallExpr(call)) => Expr::Call(CallExpr {
+            func_name: call.function.clone(),
+            target: match &call.target {
+                Some(target) => Some(Box::new(expr_from_proto(target)?)),
+                None => None,
+            },
+            args: call
+                .args
+                .iter()
+                .map(expr_from_proto)
+                .collect::<Result<_, _>>()?,
+        }),
+        Some(ExprKind::ListExpr(list)) => Expr::List(ListExpr {
+            elements: list
+                .elements
+                .iter()
+                .map(expr_from_proto)
+                .collect::<Result<_, _>>()?,
+        }),
+        Some(ExprKind::StructExpr(st)) => {
+            let entries = st
+                .entries
+                .iter()
+                .map(entry_from_proto)
+                .collect::<Result<_, _>>()?;
+            if st.message_name.is_empty() {
+                Expr::Map(MapExpr { entries })
+            } else {
+                Expr::Struct(StructExpr {
+                    type_name: st.message_name.clone(),
+                    entries,
+                })
+            }
+        }
+        Some(ExprKind::ComprehensionExpr(comp)) => {
+            Expr::Comprehension(Box::new(ComprehensionExpr {
+                iter_range: required(&comp.iter_range, "comprehension iter_range")?,
+                iter_var: comp.iter_var.clone(),
+                iter_var2: Some(comp.iter_var2.clone()).filter(|v| !v.is_empty()),
+                accu_var: comp.accu_var.clone(),
+                accu_init: required(&comp.accu_init, "comprehension accu_init")?,
+                loop_cond: required(&comp.loop_condition, "comprehension loop_condition")?,
+                loop_step: required(&comp.loop_step, "comprehension loop_step")?,
+                result: required(&comp.result, "comprehension result")?,
+            }))
+        }
+    };
+    Ok(IdedExpr { id, expr: kind })
+}
+
+fn required(expr: &Option<Box<pb::Expr>>, what: &str) -> Result<IdedExpr, String> {
+    match expr {
+        Some(expr) => expr_from_proto(expr),
+        None => Err(format!("missing {}", what)),
+    }
+}
+
+fn entry_from_proto(entry: &Entry) -> Result<IdedEntryExpr, String> {
+    let id = u64::try_from(entry.id).unwrap_or_default();
+    let value = match &entry.value {
+        Some(value) => expr_from_proto(value)?,
+        None => return Err("missing struct entry value".to_string()),
+    };
+    let expr = match &entry.key_kind {
+        Some(KeyKind::MapKey(key)) => EntryExpr::MapEntry(MapEntryExpr {
+            key: expr_from_proto(key)?,
+            value,
+            optional: entry.optional_entry,
+        }),
+        Some(KeyKind::FieldKey(field)) => EntryExpr::StructField(StructFieldExpr {
+            field: field.clone(),
+            value,
+            optional: entry.optional_entry,
+        }),
+        None => return Err("missing struct entry key".to_string()),
+    };
+    Ok(IdedEntryExpr { id, expr })
+}
+
+fn constant_to_literal(constant: &pb::Constant) -> Result<CelVal, String> {
+    use pb::constant::ConstantKind;
+    Ok(match &constant.constant_kind {
+        None | Some(ConstantKind::NullValue(_)) => CelVal::Null,
+        Some(ConstantKind::BoolValue(b)) => CelVal::Boolean(*b),
+        Some(ConstantKind::Int64Value(i)) => CelVal::Int(*i),
+        Some(ConstantKind::Uint64Value(u)) => CelVal::UInt(*u),
+        Some(ConstantKind::DoubleValue(d)) => CelVal::Double(*d),
+        Some(ConstantKind::StringValue(s)) => CelVal::String(s.clone()),
+        Some(ConstantKind::BytesValue(b)) => CelVal::Bytes(b.clone()),
+        #[allow(deprecated)]
+        Some(ConstantKind::DurationValue(_) | ConstantKind::TimestampValue(_)) => {
+            return Err("deprecated duration/timestamp constants are not supported".to_string());
+        }
+    })
+}
+
+/// Converts a CEL runtime value into a protobuf `Value`.
+pub fn value_to_proto(value: &CelValueEnum) -> Result<pb::Value, String> {
+    use pb::value::Kind;
+    let kind = match value {
+        CelValueEnum::Null => Kind::NullValue(0),
+        CelValueEnum::Bool(b) => Kind::BoolValue(*b),
+        CelValueEnum::Int(i) => Kind::Int64Value(*i),
+        CelValueEnum::UInt(u) => Kind::Uint64Value(*u),
+        CelValueEnum::Float(f) => Kind::DoubleValue(*f),
+        CelValueEnum::String(s) => Kind::StringValue(s.to_string()),
+        CelValueEnum::Bytes(b) => Kind::BytesValue(b.to_vec()),
+        CelValueEnum::List(list) => Kind::ListValue(pb::ListValue {
+            values: list.iter().map(value_to_proto).collect::<Result<_, _>>()?,
+        }),
+        CelValueEnum::Map(map) => Kind::MapValue(pb::MapValue {
+            entries: map
+                .map
+                .iter()
+                .map(|(k, v)| {
+                    Ok(pb::map_value::Entry {
+                        key: Some(value_to_proto(&k.into())?),
+                        value: Some(value_to_proto(v)?),
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        }),
+        CelValueEnum::Duration(d) => Kind::ObjectValue(prost_types::Any {
+            type_url: DURATION_TYPE_URL.to_string(),
+            value: prost_types::Duration {
+                seconds: d.num_seconds(),
+                nanos: d.subsec_nanos(),
+            }
+            .encode_to_vec(),
+        }),
+        CelValueEnum::Timestamp(t) => Kind::ObjectValue(prost_types::Any {
+            type_url: TIMESTAMP_TYPE_URL.to_string(),
+            value: prost_types::Timestamp {
+                seconds: t.timestamp(),
+                nanos: i32::try_from(t.timestamp_subsec_nanos()).unwrap_or_default(),
+            }
+            .encode_to_vec(),
+        }),
+        CelValueEnum::Function(name, _) => {
+            return Err(format!("function value `{}` has no protobuf form", name));
+        }
+    };
+    Ok(pb::Value { kind: Some(kind) })
+}
+
+/// Converts a protobuf `Value` into a CEL runtime value.
+pub fn value_from_proto(value: &pb::Value) -> Result<CelValueEnum, String> {
+    use pb::value::Kind;
+    Ok(match &value.kind {
+        None | Some(Kind::NullValue(_)) => CelValueEnum::Null,
+        Some(Kind::BoolValue(b)) => CelValueEnum::Bool(*b),
+        Some(Kind::Int64Value(i)) => CelValueEnum::Int(*i),
+        Some(Kind::Uint64Value(u)) => CelValueEnum::UInt(*u),
+        Some(Kind::DoubleValue(d)) => CelValueEnum::Float(*d),
+        Some(Kind::StringValue(s)) => CelValueEnum::String(Arc::new(s.clone())),
+        Some(Kind::BytesValue(b)) => CelValueEnum::Bytes(Arc::new(b.clone())),
+        Some(Kind::EnumValue(e)) => CelValueEnum::Int(i64::from(e.value)),
+        Some(Kind::ListValue(list)) => CelValueEnum::List(Arc::new(
+            list.values
+                .iter()
+                .map(value_from_proto)
+                .collect::<Result<_, _>>()?,
+        )),
+        Some(Kind::MapValue(map)) => {
+            let mut entries = HashMap::with_capacity(map.entries.len());
+            for entry in &map.entries {
+                let key = match entry.key.as_ref().map(value_from_proto).transpose()? {
+                    Some(CelValueEnum::String(s)) => Key::String(s),
+                    Some(CelValueEnum::Int(i)) => Key::Int(i),
+                    Some(CelValueEnum::UInt(u)) => Key::Uint(u),
+                    Some(CelValueEnum::Bool(b)) => Key::Bool(b),
+                    other => return Err(format!("unsupported map key: {:?}", other)),
+                };
+                let value = match &entry.value {
+                    Some(value) => value_from_proto(value)?,
+                    None => CelValueEnum::Null,
+                };
+                entries.insert(key, value);
+            }
+            CelValueEnum::Map(CelMap {
+                map: Arc::new(entries),
+            })
+        }
+        Some(Kind::ObjectValue(any)) => match any.type_url.as_str() {
+            TIMESTAMP_TYPE_URL => {
+                let ts = prost_types::Timestamp::decode(any.value.as_slice())
+                    .map_err(|e| format!("invalid Timestamp: {}", e))?;
+                let nanos = u32::try_from(ts.nanos).map_err(|e| e.to_string())?;
+                let utc = DateTime::from_timestamp(ts.seconds, nanos)
+                    .ok_or_else(|| "Timestamp out of range".to_string())?;
+                CelValueEnum::Timestamp(utc.fixed_offset())
+            }
+            DURATION_TYPE_URL => {
+                let d = prost_types::Duration::decode(any.value.as_slice())
+                    .map_err(|e| format!("invalid Duration: {}", e))?;
+                let seconds = TimeDelta::try_seconds(d.seconds)
+                    .ok_or_else(|| "Duration out of range".to_string())?;
+                CelValueEnum::Duration(seconds + TimeDelta::nanoseconds(i64::from(d.nanos)))
+            }
+            other => return Err(format!("unsupported message type: {}", other)),
+        },
+        Some(Kind::TypeValue(name)) => {
+            return Err(format!("type values are not supported: {}", name));
+        }
+    })
+}
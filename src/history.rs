@@ -0,0 +1,95 @@
+//! Recent `decide` requests, kept in memory per policy set so the `replay` tool can test a
+//! candidate policy against real traffic.
+
+use crate::merge::MergeStrategy;
+use rmcp::serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// One recorded decision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedDecision {
+    /// The request context as sent, before seeding and merging.
+    pub context: Map<String, Value>,
+    pub merge: MergeStrategy,
+    /// The decision returned to the client.
+    pub decision: Value,
+}
+
+/// Keeps the last `capacity` decisions of each policy set.
+pub struct DecisionHistory {
+    capacity: usize,
+    decisions: Mutex<HashMap<String, VecDeque<RecordedDecision>>>,
+}
+
+impl DecisionHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            decisions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, policy: &str, decision: RecordedDecision) {
+        if self.capacity == 0 {
+            return;
+        }
+        let Ok(mut decisions) = self.decisions.lock() else {
+            tracing::error!("Decision history lock poisoned, dropping record.");
+            return;
+        };
+        let recent = decisions.entry(policy.to_string()).or_default();
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(decision);
+    }
+
+    /// The last `n` decisions of `policy`, oldest first.
+    pub fn recent(&self, policy: &str, n: usize) -> Vec<RecordedDecision> {
+        let Ok(decisions) = self.decisions.lock() else {
+            return Vec::new();
+        };
+        decisions
+            .get(policy)
+            .map(|recent| {
+                recent
+                    .iter()
+                    .skip(recent.len().saturating_sub(n))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_keeps_most_recent() {
+        let history = DecisionHistory::new(2);
+        for i in 0..3 {
+            history.record(
+                "p",
+                RecordedDecision {
+                    context: Map::new(),
+                    merge: MergeStrategy::Replace,
+                    decision: json!(i),
+                },
+            );
+        }
+        let decisions = |n| -> Vec<Value> {
+            history
+                .recent("p", n)
+                .into_iter()
+                .map(|recorded| recorded.decision)
+                .collect()
+        };
+        assert_eq!(decisions(5), vec![json!(1), json!(2)]);
+        assert_eq!(decisions(1), vec![json!(2)]);
+        assert!(history.recent("other", 5).is_empty());
+    }
+}
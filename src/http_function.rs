@@ -0,0 +1,169 @@
+//! An opt-in `http.get(url)` CEL function for enrichment lookups in trusted deployments.
+//!
+//! Only `http` and `https` URLs on allowlisted hosts are fetched, without following redirects.
+//! Each response must be JSON, within a size cap, and arrive before a timeout; each evaluation
+//! request may make a limited number of calls. Calls block the evaluator while they run.
+
+use crate::config::HttpFunctionConfig;
+use crate::conversion::from_json;
+use crate::provider::{FunctionProvider, Method, Namespace};
+use cel::{Context, FunctionContext, Value as CelValue};
+use rmcp::serde_json::{self, Value};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use ureq::http::Uri;
+
+/// The namespace `get` is called on.
+pub const VARIABLE: &str = "http";
+
+/// Registers `http.get(url)`.
+#[derive(Clone)]
+pub struct HttpFunction {
+    config: Arc<HttpFunctionConfig>,
+    agent: ureq::Agent,
+}
+
+impl HttpFunction {
+    pub fn new(config: HttpFunctionConfig) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_millis(config.timeout_ms)))
+            .max_redirects(0)
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Self {
+            config: Arc::new(config),
+            agent,
+        }
+    }
+
+    /// Whether `uri`'s host is allowed: listed as is, or under a `*.` entry.
+    fn allows(&self, uri: &Uri) -> bool {
+        let Some(host) = uri.host().map(str::to_ascii_lowercase) else {
+            return false;
+        };
+        self.config.allow_domains.iter().any(|domain| {
+            let domain = domain.to_ascii_lowercase();
+            match domain.strip_prefix("*.") {
+                Some(parent) => host
+                    .strip_suffix(parent)
+                    .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+                None => host == domain,
+            }
+        })
+    }
+
+    fn get(&self, url: &str) -> Result<Value, String> {
+        let uri: Uri = url
+            .parse()
+            .map_err(|e| format!("Invalid URL `{}`: {}", url, e))?;
+        if !matches!(uri.scheme_str(), Some("http" | "https"))
+            || uri.authority().is_some_and(|a| a.as_str().contains('@'))
+        {
+            return Err(format!("`{}` is not an http or https URL", url));
+        }
+        if !self.allows(&uri) {
+            return Err(format!("`{}` is not on an allowed domain", url));
+        }
+        let mut response = self
+            .agent
+            .get(url)
+            .header("accept", "application/json")
+            .call()
+            .map_err(|e| format!("Failed to fetch `{}`: {}", url, e))?;
+        let status = response.status().as_u16();
+        if !(200..=299).contains(&status) {
+            return Err(format!("Failed to fetch `{}`: HTTP status {}", url, status));
+        }
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(self.config.max_bytes)
+            .read_to_vec()
+            .map_err(|e| format!("Failed to fetch `{}`: {}", url, e))?;
+        serde_json::from_slice(&body).map_err(|e| format!("Invalid JSON from `{}`: {}", url, e))
+    }
+}
+
+impl FunctionProvider for HttpFunction {
+    fn register(&self, _ctx: &mut Context) {}
+
+    fn names(&self) -> Vec<String> {
+        vec!["http.get".to_string()]
+    }
+
+    fn is_volatile(&self) -> bool {
+        true
+    }
+
+    fn namespace(&self) -> Option<Namespace> {
+        // A fresh budget for each evaluation context, i.e. each request.
+        let calls = AtomicU32::new(0);
+        let this = self.clone();
+        let get: Method = Arc::new(move |ftx: &FunctionContext, args: &[CelValue]| {
+            let [CelValue::String(url)] = args else {
+                return Err(ftx.error("http.get expects one string URL"));
+            };
+            if calls.fetch_add(1, Ordering::Relaxed) >= this.config.max_calls {
+                return Err(ftx.error(format!(
+                    "http.get called more than {} times",
+                    this.config.max_calls
+                )));
+            }
+            this.get(url)
+                .map(|value| from_json(&value))
+                .map_err(|e| ftx.error(e))
+        });
+        Some(Namespace {
+            name: VARIABLE.to_string(),
+            methods: vec![("get".to_string(), get)],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[tokio::test]
+    async fn test_http_get_allowlist_and_budget() {
+        let app = axum::Router::new().route(
+            "/users/alice",
+            axum::routing::get(|| async { r#"{"tier": "gold"}"# }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://localhost:{}", listener.local_addr().unwrap().port());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let function = HttpFunction::new(HttpFunctionConfig {
+            allow_domains: vec!["localhost".to_string()],
+            max_calls: 1,
+            ..HttpFunctionConfig::default()
+        });
+        assert!(function.allows(&"https://localhost/x".parse().unwrap()));
+        assert!(!function.allows(&"https://localhost.evil.com/x".parse().unwrap()));
+        let evaluator = Evaluator::new().with_function_provider(function);
+        let context = json!({ "base": base });
+        let evaluate =
+            move |expression: &str| evaluator.evaluate(expression, &context, Profile::Default);
+
+        let result = tokio::task::spawn_blocking(move || {
+            (
+                evaluate("http.get(base + '/users/alice').tier"),
+                evaluate("http.get(base + '/users/alice') == http.get(base + '/users/alice')"),
+                evaluate("http.get('http://example.com/')"),
+                evaluate("{'a': 1}.get(base)"),
+            )
+        })
+        .await
+        .unwrap();
+        assert_eq!(result.0, Ok(json!("gold")));
+        assert!(result.1.unwrap_err().contains("more than 1 times"));
+        assert!(result.2.unwrap_err().contains("not on an allowed domain"));
+        assert!(result.3.unwrap_err().contains("only available on http"));
+    }
+}
@@ -0,0 +1,331 @@
+//! Conversion of JSON context variables to CEL values.
+//!
+//! Values are walked with an explicit stack, so nesting depth is bounded by
+//! [`Limits::max_context_depth`] rather than by the call stack, and the approximate size of the
+//! whole context is accounted against [`Limits::max_context_bytes`]. A variable's own value is
+//! at depth 1; each enclosing list or map adds one level. Sizes count string and key bytes plus
+//! 8 bytes per value.
+
+use crate::evaluator::Limits;
+use cel::Value as CelValue;
+use cel::objects::Key;
+use rmcp::serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// How JSON numbers in a context become CEL numbers. Comparisons work across `int`, `uint`
+/// and `double` in every mode, but arithmetic needs operands of one type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum JsonNumbers {
+    /// Non-negative integers become `uint`, negative ones `int`, everything else `double`.
+    #[default]
+    Uint,
+    /// Integers become `int` (`uint` beyond its range), everything else `double`.
+    Int,
+    /// Every number becomes `double`, as in CEL's mapping of `google.protobuf.Value`.
+    Double,
+}
+
+impl JsonNumbers {
+    /// What the mode does, for the server instructions.
+    pub fn describe(self) -> &'static str {
+        match self {
+            JsonNumbers::Uint => {
+                "non-negative integers are `uint`, negative ones `int`, others `double`"
+            }
+            JsonNumbers::Int => "integers are `int`, others `double`",
+            JsonNumbers::Double => "all numbers are `double`",
+        }
+    }
+}
+
+/// Bytes accounted for every value, in addition to its string content.
+const VALUE_OVERHEAD: usize = 8;
+
+/// Why a context was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextError {
+    /// Values are nested more than `limit` levels deep.
+    TooDeep { limit: usize },
+    /// The context is larger than `limit` bytes.
+    TooLarge { limit: usize },
+}
+
+impl ContextError {
+    /// A stable, machine-readable code for the error.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ContextError::TooDeep { .. } => "context_too_deep",
+            ContextError::TooLarge { .. } => "context_too_large",
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        match self {
+            ContextError::TooDeep { limit } | ContextError::TooLarge { limit } => *limit,
+        }
+    }
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContextError::TooDeep { limit } => {
+                write!(
+                    f,
+                    "Context too deep: values nest more than {} levels",
+                    limit
+                )
+            }
+            ContextError::TooLarge { limit } => {
+                write!(f, "Context too large: more than {} bytes", limit)
+            }
+        }
+    }
+}
+
+/// Tracks the size of one evaluation's context across all of its variables.
+pub struct ContextBudget {
+    max_depth: Option<usize>,
+    max_bytes: Option<usize>,
+    bytes: usize,
+    numbers: JsonNumbers,
+}
+
+impl ContextBudget {
+    pub fn new(limits: &Limits) -> Self {
+        Self {
+            max_depth: limits.max_context_depth,
+            max_bytes: limits.max_context_bytes,
+            bytes: 0,
+            numbers: JsonNumbers::default(),
+        }
+    }
+
+    /// Sets how numbers are converted.
+    pub fn with_numbers(mut self, numbers: JsonNumbers) -> Self {
+        self.numbers = numbers;
+        self
+    }
+
+    fn add_bytes(&mut self, bytes: usize) -> Result<(), ContextError> {
+        self.bytes = self.bytes.saturating_add(bytes);
+        match self.max_bytes {
+            Some(limit) if self.bytes > limit => Err(ContextError::TooLarge { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Accounts for one value at `depth`.
+    fn enter(&mut self, value: &Value, depth: usize) -> Result<(), ContextError> {
+        if let Some(limit) = self.max_depth
+            && depth > limit
+        {
+            return Err(ContextError::TooDeep { limit });
+        }
+        let content = match value {
+            Value::String(s) => s.len(),
+            _ => 0,
+        };
+        self.add_bytes(VALUE_OVERHEAD + content)
+    }
+
+    /// Bytes charged so far.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Checks a whole context against the limits without converting it.
+    pub fn check(&mut self, variables: &Map<String, Value>) -> Result<(), ContextError> {
+        let mut stack = Vec::new();
+        for (name, value) in variables {
+            self.add_bytes(name.len())?;
+            stack.push((value, 1));
+        }
+        while let Some((value, depth)) = stack.pop() {
+            self.enter(value, depth)?;
+            match value {
+                Value::Array(items) => stack.extend(items.iter().map(|item| (item, depth + 1))),
+                Value::Object(map) => {
+                    for (key, item) in map {
+                        self.add_bytes(key.len())?;
+                        stack.push((item, depth + 1));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts the value of variable `name`, charging it against the budget.
+    pub fn convert(&mut self, name: &str, value: &Value) -> Result<CelValue, ContextError> {
+        self.add_bytes(name.len())?;
+        let mut stack: Vec<Frame<'_>> = Vec::new();
+        let mut current = value;
+        loop {
+            self.enter(current, stack.len() + 1)?;
+            let mut converted = match current {
+                Value::Array(items) => {
+                    stack.push(Frame::List {
+                        items: items.iter(),
+                        out: Vec::with_capacity(items.len()),
+                    });
+                    None
+                }
+                Value::Object(map) => {
+                    stack.push(Frame::Map {
+                        entries: map.iter(),
+                        out: HashMap::with_capacity(map.len()),
+                        key: None,
+                    });
+                    None
+                }
+                scalar => Some(scalar_to_cel(scalar, self.numbers)),
+            };
+            loop {
+                let Some(frame) = stack.last_mut() else {
+                    return Ok(converted.unwrap_or(CelValue::Null));
+                };
+                if let Some(value) = converted.take() {
+                    frame.push(value);
+                }
+                if let Some((key_bytes, child)) = frame.next_child() {
+                    self.add_bytes(key_bytes)?;
+                    current = child;
+                    break;
+                }
+                converted = stack.pop().map(Frame::finish);
+            }
+        }
+    }
+}
+
+/// A list or map whose elements are being converted.
+enum Frame<'a> {
+    List {
+        items: std::slice::Iter<'a, Value>,
+        out: Vec<CelValue>,
+    },
+    Map {
+        entries: rmcp::serde_json::map::Iter<'a>,
+        out: HashMap<Key, CelValue>,
+        /// Key of the entry currently being converted.
+        key: Option<&'a String>,
+    },
+}
+
+impl<'a> Frame<'a> {
+    /// Returns the next element and the bytes of its key (0 for list items).
+    fn next_child(&mut self) -> Option<(usize, &'a Value)> {
+        match self {
+            Frame::List { items, .. } => items.next().map(|item| (0, item)),
+            Frame::Map { entries, key, .. } => {
+                let (next_key, value) = entries.next()?;
+                *key = Some(next_key);
+                Some((next_key.len(), value))
+            }
+        }
+    }
+
+    fn push(&mut self, value: CelValue) {
+        match self {
+            Frame::List { out, .. } => out.push(value),
+            Frame::Map { out, key, .. } => {
+                if let Some(key) = key.take() {
+                    out.insert(Key::String(Arc::new(key.clone())), value);
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> CelValue {
+        match self {
+            Frame::List { out, .. } => CelValue::List(Arc::new(out)),
+            Frame::Map { out, .. } => CelValue::Map(cel::objects::Map { map: Arc::new(out) }),
+        }
+    }
+}
+
+/// By default numbers keep the types the `cel` serializer gives them; see [`JsonNumbers`].
+fn scalar_to_cel(value: &Value, numbers: JsonNumbers) -> CelValue {
+    match value {
+        Value::Bool(b) => CelValue::Bool(*b),
+        Value::Number(n) => match (numbers, n.as_i64(), n.as_u64()) {
+            (JsonNumbers::Uint, _, Some(u)) => CelValue::UInt(u),
+            (JsonNumbers::Uint | JsonNumbers::Int, Some(i), _) => CelValue::Int(i),
+            (JsonNumbers::Int, None, Some(u)) => CelValue::UInt(u),
+            _ => CelValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => CelValue::String(Arc::new(s.clone())),
+        _ => CelValue::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    fn limits(max_context_depth: Option<usize>, max_context_bytes: Option<usize>) -> Limits {
+        Limits {
+            max_context_depth,
+            max_context_bytes,
+            ..Limits::default()
+        }
+    }
+
+    #[test]
+    fn test_convert_matches_cel_serializer() {
+        let value = json!({"a": [1, -2, 1.5, "x", null, true], "b": {"c": {}}});
+        let converted = ContextBudget::new(&Limits::default())
+            .convert("v", &value)
+            .unwrap();
+        assert_eq!(converted, cel::to_value(&value).unwrap());
+    }
+
+    #[test]
+    fn test_json_numbers_modes() {
+        let convert = |numbers, value: Value| {
+            let converted = ContextBudget::new(&Limits::default())
+                .with_numbers(numbers)
+                .convert("v", &value)
+                .unwrap();
+            // `Value`'s `==` compares numbers across types, so compare the variants.
+            format!("{:?}", converted)
+        };
+        let big = json!(u64::MAX);
+        assert_eq!(convert(JsonNumbers::Uint, json!(5)), "UInt(5)");
+        assert_eq!(convert(JsonNumbers::Uint, json!(-5)), "Int(-5)");
+        assert_eq!(convert(JsonNumbers::Int, json!(5)), "Int(5)");
+        assert_eq!(
+            convert(JsonNumbers::Int, big),
+            format!("UInt({})", u64::MAX)
+        );
+        assert_eq!(convert(JsonNumbers::Int, json!(0.5)), "Float(0.5)");
+        assert_eq!(convert(JsonNumbers::Double, json!(5)), "Float(5.0)");
+    }
+
+    #[test]
+    fn test_depth_and_size_limits() {
+        let context = json!({"a": {"b": [1]}});
+        let variables = context.as_object().unwrap();
+        let deep = ContextBudget::new(&limits(Some(2), None)).check(variables);
+        assert_eq!(deep, Err(ContextError::TooDeep { limit: 2 }));
+        let converted = ContextBudget::new(&limits(Some(2), None)).convert("a", &context["a"]);
+        assert_eq!(converted, Err(ContextError::TooDeep { limit: 2 }));
+        assert_eq!(
+            ContextBudget::new(&limits(Some(3), None)).check(variables),
+            Ok(())
+        );
+
+        // 3 values * 8 bytes + "a" + "b".
+        let large = ContextBudget::new(&limits(None, Some(25))).check(variables);
+        assert_eq!(large, Err(ContextError::TooLarge { limit: 25 }));
+        assert_eq!(
+            ContextBudget::new(&limits(None, Some(26))).check(variables),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! `--allow-cidr` and `--deny-cidr`: which client addresses the HTTP listeners serve, checked
+//! before a request is processed.
+
+use axum::Router;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// An address block such as `10.0.0.0/8` or `fd00::/8`; a bare address is a block of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                masked(u32::from(network).into(), 32, self.prefix)
+                    == masked(u32::from(ip).into(), 32, self.prefix)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                masked(network.into(), 128, self.prefix) == masked(ip.into(), 128, self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The first `prefix` of the `width` bits of `bits`.
+fn masked(bits: u128, width: u8, prefix: u8) -> u128 {
+    match width - prefix {
+        0 => bits,
+        host if host >= 128 => 0,
+        host => bits >> host << host,
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let network = address
+            .parse::<IpAddr>()
+            .map_err(|_| format!("`{}` is not an IP address or CIDR block", s))?
+            .to_canonical();
+        let width = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= width)
+                .ok_or_else(|| format!("`{}` has an invalid prefix length", s))?,
+            None => width,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Serves an address in no denied block and, if any blocks are allowed, in an allowed one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IpFilter {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+}
+
+impl IpFilter {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        !self.deny.iter().any(|cidr| cidr.contains(ip))
+            && (self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip)))
+    }
+}
+
+/// The client address of `request`, from the connection info the app is served with.
+fn remote_ip(request: &Request) -> Option<IpAddr> {
+    if let Some(ConnectInfo(addr)) = request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        return Some(addr.ip());
+    }
+    #[cfg(feature = "tls")]
+    if let Some(ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<crate::tls::Peer>>() {
+        return Some(peer.addr.ip());
+    }
+    None
+}
+
+/// Makes `router`, served with `SocketAddr` (or, over TLS, `Peer`) connection info, refuse
+/// requests from addresses `filter` doesn't permit with status 403. A request whose address
+/// is unknown is refused too.
+pub fn require(router: Router, filter: Arc<IpFilter>) -> Router {
+    router.layer(middleware::from_fn_with_state(filter, check))
+}
+
+async fn check(State(filter): State<Arc<IpFilter>>, request: Request, next: Next) -> Response {
+    match remote_ip(&request) {
+        Some(ip) if filter.permits(ip) => next.run(request).await,
+        ip => {
+            let client = ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+            tracing::warn!("Refused a request from {}", client);
+            (
+                StatusCode::FORBIDDEN,
+                format!("Requests from {} are not allowed\n", client),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr() {
+        let lan: Cidr = "192.168.0.0/16".parse().unwrap();
+        assert!(lan.contains(ip("192.168.10.1")));
+        assert!(lan.contains(ip("::ffff:192.168.10.1")));
+        assert!(!lan.contains(ip("192.169.0.1")));
+        assert!(!lan.contains(ip("fd00::1")));
+
+        let host: Cidr = "10.0.0.5".parse().unwrap();
+        assert_eq!(host.to_string(), "10.0.0.5/32");
+        assert!(host.contains(ip("10.0.0.5")));
+        assert!(!host.contains(ip("10.0.0.6")));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("203.0.113.9")));
+        let ula: Cidr = "fd00::/8".parse().unwrap();
+        assert!(ula.contains(ip("fd12:3456::1")));
+        assert!(!ula.contains(ip("fe80::1")));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert!("fd00::/129".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = IpFilter {
+            allow: vec!["10.0.0.0/8".parse().unwrap(), "::1".parse().unwrap()],
+            deny: vec!["10.0.9.0/24".parse().unwrap()],
+        };
+        assert!(filter.permits(ip("10.1.2.3")));
+        assert!(filter.permits(ip("::1")));
+        assert!(!filter.permits(ip("10.0.9.7")));
+        assert!(!filter.permits(ip("192.168.1.1")));
+
+        let deny_only = IpFilter {
+            allow: Vec::new(),
+            deny: vec!["203.0.113.0/24".parse().unwrap()],
+        };
+        assert!(deny_only.permits(ip("192.168.1.1")));
+        assert!(!deny_only.permits(ip("203.0.113.50")));
+        assert!(IpFilter::default().permits(ip("192.168.1.1")));
+    }
+}
@@ -0,0 +1,129 @@
+//! `iso.isCountry(code)`, `iso.isCurrency(code)` and `locale.isValid(tag)`, checking codes
+//! for data-quality policies against tables built into the server.
+//!
+//! Country and currency codes must be uppercase, as the standards write them. Locales are
+//! BCP 47 language tags, checked against the IANA Language Subtag Registry.
+
+use crate::provider::{Method, Namespace};
+use cel::{FunctionContext, Value as CelValue};
+use language_tags::LanguageTag;
+use std::sync::Arc;
+
+/// The namespace `iso.isCountry` and `iso.isCurrency` are called on.
+pub const ISO_VARIABLE: &str = "iso";
+
+/// The namespace `locale.isValid` is called on.
+pub const LOCALE_VARIABLE: &str = "locale";
+
+/// ISO 3166-1 alpha-2 codes, sorted.
+const COUNTRIES: [&str; 249] = [
+    "AD", "AE", "AF", "AG", "AI", "AL", "AM", "AO", "AQ", "AR", "AS", "AT", "AU", "AW", "AX", "AZ",
+    "BA", "BB", "BD", "BE", "BF", "BG", "BH", "BI", "BJ", "BL", "BM", "BN", "BO", "BQ", "BR", "BS",
+    "BT", "BV", "BW", "BY", "BZ", "CA", "CC", "CD", "CF", "CG", "CH", "CI", "CK", "CL", "CM", "CN",
+    "CO", "CR", "CU", "CV", "CW", "CX", "CY", "CZ", "DE", "DJ", "DK", "DM", "DO", "DZ", "EC", "EE",
+    "EG", "EH", "ER", "ES", "ET", "FI", "FJ", "FK", "FM", "FO", "FR", "GA", "GB", "GD", "GE", "GF",
+    "GG", "GH", "GI", "GL", "GM", "GN", "GP", "GQ", "GR", "GS", "GT", "GU", "GW", "GY", "HK", "HM",
+    "HN", "HR", "HT", "HU", "ID", "IE", "IL", "IM", "IN", "IO", "IQ", "IR", "IS", "IT", "JE", "JM",
+    "JO", "JP", "KE", "KG", "KH", "KI", "KM", "KN", "KP", "KR", "KW", "KY", "KZ", "LA", "LB", "LC",
+    "LI", "LK", "LR", "LS", "LT", "LU", "LV", "LY", "MA", "MC", "MD", "ME", "MF", "MG", "MH", "MK",
+    "ML", "MM", "MN", "MO", "MP", "MQ", "MR", "MS", "MT", "MU", "MV", "MW", "MX", "MY", "MZ", "NA",
+    "NC", "NE", "NF", "NG", "NI", "NL", "NO", "NP", "NR", "NU", "NZ", "OM", "PA", "PE", "PF", "PG",
+    "PH", "PK", "PL", "PM", "PN", "PR", "PS", "PT", "PW", "PY", "QA", "RE", "RO", "RS", "RU", "RW",
+    "SA", "SB", "SC", "SD", "SE", "SG", "SH", "SI", "SJ", "SK", "SL", "SM", "SN", "SO", "SR", "SS",
+    "ST", "SV", "SX", "SY", "SZ", "TC", "TD", "TF", "TG", "TH", "TJ", "TK", "TL", "TM", "TN", "TO",
+    "TR", "TT", "TV", "TW", "TZ", "UA", "UG", "UM", "US", "UY", "UZ", "VA", "VC", "VE", "VG", "VI",
+    "VN", "VU", "WF", "WS", "YE", "YT", "ZA", "ZM", "ZW",
+];
+
+/// ISO 4217 codes in current use, including funds and precious metals, sorted.
+const CURRENCIES: [&str; 177] = [
+    "AED", "AFN", "ALL", "AMD", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT", "BHD",
+    "BIF", "BMD", "BND", "BOB", "BOV", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD", "CDF",
+    "CHE", "CHF", "CHW", "CLF", "CLP", "CNY", "COP", "COU", "CRC", "CUP", "CVE", "CZK", "DJF",
+    "DKK", "DOP", "DZD", "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP",
+    "GMD", "GNF", "GTQ", "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR",
+    "ISK", "JMD", "JOD", "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT",
+    "LAK", "LBP", "LKR", "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP",
+    "MRU", "MUR", "MVR", "MWK", "MXN", "MXV", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR",
+    "NZD", "OMR", "PAB", "PEN", "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB",
+    "RWF", "SAR", "SBD", "SCR", "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD", "SSP", "STN",
+    "SVC", "SYP", "SZL", "THB", "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH",
+    "UGX", "USD", "USN", "UYI", "UYU", "UYW", "UZS", "VED", "VES", "VND", "VUV", "WST", "XAF",
+    "XAG", "XAU", "XBA", "XBB", "XBC", "XBD", "XCD", "XCG", "XDR", "XOF", "XPD", "XPF", "XPT",
+    "XSU", "XTS", "XUA", "XXX", "YER", "ZAR", "ZMW", "ZWG",
+];
+
+pub(crate) fn iso_namespace() -> Namespace {
+    Namespace {
+        name: ISO_VARIABLE.to_string(),
+        methods: vec![
+            ("isCountry".to_string(), lookup("iso.isCountry", &COUNTRIES)),
+            (
+                "isCurrency".to_string(),
+                lookup("iso.isCurrency", &CURRENCIES),
+            ),
+        ],
+    }
+}
+
+pub(crate) fn locale_namespace() -> Namespace {
+    let is_valid: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::String(tag)] = args else {
+            return Err(ftx.error("locale.isValid expects one string"));
+        };
+        Ok(CelValue::Bool(
+            LanguageTag::parse(tag).is_ok_and(|tag| tag.is_valid()),
+        ))
+    });
+    Namespace {
+        name: LOCALE_VARIABLE.to_string(),
+        methods: vec![("isValid".to_string(), is_valid)],
+    }
+}
+
+/// A method telling whether its argument is one of the sorted `codes`.
+fn lookup(name: &'static str, codes: &'static [&'static str]) -> Method {
+    Arc::new(move |ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::String(code)] = args else {
+            return Err(ftx.error(format!("{} expects one string", name)));
+        };
+        Ok(CelValue::Bool(codes.binary_search(&code.as_str()).is_ok()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::library::Library;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_tables_are_sorted() {
+        assert!(COUNTRIES.is_sorted());
+        assert!(CURRENCIES.is_sorted());
+    }
+
+    #[test]
+    fn test_code_validation() {
+        let evaluator = Evaluator::new()
+            .with_function_provider(Library::Iso)
+            .with_function_provider(Library::Locale);
+        let evaluate =
+            |expression: &str| evaluator.evaluate(expression, &json!({}), Profile::Default);
+        for (expression, valid) in [
+            ("iso.isCountry('JP')", true),
+            ("iso.isCountry('jp')", false),
+            ("iso.isCountry('XX')", false),
+            ("iso.isCurrency('USD')", true),
+            ("iso.isCurrency('ABC')", false),
+            ("locale.isValid('en-US')", true),
+            ("locale.isValid('zh-Hant-TW')", true),
+            ("locale.isValid('en-QQQ')", false),
+            ("locale.isValid('not a locale')", false),
+        ] {
+            assert_eq!(evaluate(expression), Ok(json!(valid)), "{}", expression);
+        }
+    }
+}
@@ -0,0 +1,528 @@
+//! Batch evaluation jobs, tracked by ID so the `job_status` and `cancel_job` tools can poll or
+//! abandon them while they run, and `get_job_result` can fetch what submitted ones produced.
+//!
+//! A job's results stay in memory until they exceed [`JobLimits::spill_threshold`], then move
+//! to a temporary JSON Lines file that is read back page by page and removed with the job.
+
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::{Deserialize, Serialize};
+use rmcp::serde_json;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// How many finished jobs stay queryable, however recent.
+pub const FINISHED_CAPACITY: usize = 256;
+/// How many submitted jobs run at once unless configured.
+pub const DEFAULT_MAX_CONCURRENT: usize = 4;
+/// How long finished jobs stay queryable unless configured.
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(3600);
+/// Bytes of results a job keeps in memory before spilling them to disk, unless configured.
+pub const DEFAULT_SPILL_THRESHOLD: usize = 64 << 20;
+
+/// Bounds on the jobs of a [`JobRegistry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobLimits {
+    /// Submitted jobs running at once; later ones are queued.
+    pub max_concurrent: usize,
+    /// How long finished jobs and their results stay queryable.
+    pub retention: Duration,
+    /// Approximate bytes of results a job holds in memory before spilling them to a file.
+    pub spill_threshold: usize,
+}
+
+impl Default for JobLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent: DEFAULT_MAX_CONCURRENT,
+            retention: DEFAULT_RETENTION,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum JobState {
+    /// Submitted, waiting for one of the concurrent job slots.
+    Queued,
+    Running,
+    /// Cancellation was requested; the job stops before its next item.
+    Cancelling,
+    Completed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+pub struct JobStatus {
+    pub job_id: String,
+    pub state: JobState,
+    /// Items in the job.
+    pub total: usize,
+    /// Items evaluated so far, including failed ones.
+    pub completed: usize,
+    pub failed: usize,
+}
+
+/// The outcome of one item of a batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+pub struct BatchItem {
+    /// The value as JSON text, as for `evaluate`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The item's position in the input, when results are recorded as they complete.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+}
+
+impl BatchItem {
+    fn size(&self) -> usize {
+        let text = |s: &Option<String>| s.as_ref().map_or(0, String::len);
+        text(&self.result) + text(&self.error) + size_of::<Self>()
+    }
+}
+
+/// Results written to a temporary file, one JSON line each.
+struct Spill {
+    path: PathBuf,
+    file: File,
+    /// Byte offset of each line.
+    offsets: Vec<u64>,
+    end: u64,
+}
+
+impl Spill {
+    fn create() -> Result<Self, String> {
+        let path = std::env::temp_dir().join(format!("cel-mcp-job-{}.jsonl", uuid::Uuid::new_v4()));
+        let file = File::create_new(&path)
+            .map_err(|e| format!("Cannot create {}: {}", path.display(), e))?;
+        Ok(Self {
+            path,
+            file,
+            offsets: Vec::new(),
+            end: 0,
+        })
+    }
+
+    fn append(&mut self, item: &BatchItem) -> Result<(), String> {
+        let mut line = serde_json::to_vec(item).map_err(|e| e.to_string())?;
+        line.push(b'\n');
+        self.file
+            .write_all(&line)
+            .map_err(|e| format!("Cannot write {}: {}", self.path.display(), e))?;
+        self.offsets.push(self.end);
+        self.end += line.len() as u64;
+        Ok(())
+    }
+
+    fn read(&self, offset: usize, limit: usize) -> Result<Vec<BatchItem>, String> {
+        let Some(&start) = self.offsets.get(offset) else {
+            return Ok(Vec::new());
+        };
+        let error = |e: std::io::Error| format!("Cannot read {}: {}", self.path.display(), e);
+        let mut file = File::open(&self.path).map_err(error)?;
+        file.seek(SeekFrom::Start(start)).map_err(error)?;
+        BufReader::new(file)
+            .lines()
+            .take(limit.min(self.offsets.len() - offset))
+            .map(|line| {
+                let line = line.map_err(error)?;
+                serde_json::from_str(&line).map_err(|e| e.to_string())
+            })
+            .collect()
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            tracing::warn!("Cannot remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// A job's results: in memory up to a threshold, then spilled to disk.
+struct ResultStore {
+    threshold: usize,
+    bytes: usize,
+    memory: Vec<BatchItem>,
+    spill: Option<Spill>,
+}
+
+impl ResultStore {
+    fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            bytes: 0,
+            memory: Vec::new(),
+            spill: None,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.spill
+            .as_ref()
+            .map_or(self.memory.len(), |spill| spill.offsets.len())
+    }
+
+    fn push(&mut self, item: BatchItem) -> Result<(), String> {
+        if let Some(spill) = &mut self.spill {
+            return spill.append(&item);
+        }
+        self.bytes += item.size();
+        self.memory.push(item);
+        if self.bytes > self.threshold {
+            // Keep the results in memory if they cannot be spilled, and stop trying.
+            self.threshold = usize::MAX;
+            let mut spill = Spill::create()?;
+            for item in &self.memory {
+                spill.append(item)?;
+            }
+            tracing::info!(
+                "Spilled {} job results ({} bytes) to {}",
+                self.memory.len(),
+                self.bytes,
+                spill.path.display()
+            );
+            self.memory = Vec::new();
+            self.spill = Some(spill);
+        }
+        Ok(())
+    }
+
+    fn page(&self, offset: usize, limit: usize) -> Result<Vec<BatchItem>, String> {
+        match &self.spill {
+            Some(spill) => spill.read(offset, limit),
+            None => Ok(self
+                .memory
+                .iter()
+                .skip(offset)
+                .take(limit)
+                .cloned()
+                .collect()),
+        }
+    }
+}
+
+/// Progress and results of one job, updated by the task running it.
+pub struct Job {
+    id: String,
+    total: usize,
+    completed: AtomicUsize,
+    failed: AtomicUsize,
+    started: AtomicBool,
+    cancel: AtomicBool,
+    finished: AtomicBool,
+    /// `None` once taken by the caller that ran the job.
+    results: Mutex<Option<ResultStore>>,
+}
+
+impl Job {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Records one evaluated item.
+    pub fn record(&self, item: BatchItem) {
+        if item.error.is_some() {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Ok(mut results) = self.results.lock()
+            && let Some(results) = results.as_mut()
+            && let Err(e) = results.push(item)
+        {
+            tracing::error!("Job {} cannot store a result: {}", self.id, e);
+        }
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Up to `limit` results recorded so far, from the `offset`th on, and the number recorded;
+    /// `None` if they were taken.
+    pub fn results(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Option<(Vec<BatchItem>, usize)>, String> {
+        let results = self
+            .results
+            .lock()
+            .map_err(|_| "Job results lock poisoned")?;
+        results
+            .as_ref()
+            .map(|store| Ok((store.page(offset, limit)?, store.len())))
+            .transpose()
+    }
+
+    /// Hands all results to the caller that ran the job; later reads find none.
+    pub fn take_results(&self) -> Result<Vec<BatchItem>, String> {
+        let store = self
+            .results
+            .lock()
+            .map_err(|_| "Job results lock poisoned")?
+            .take();
+        match store {
+            Some(store) => store.page(0, usize::MAX),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn status(&self) -> JobStatus {
+        let state = match (
+            self.started.load(Ordering::Relaxed),
+            self.finished.load(Ordering::Relaxed),
+            self.is_cancelled(),
+        ) {
+            (_, true, false) => JobState::Completed,
+            (_, true, true) => JobState::Cancelled,
+            (_, false, true) => JobState::Cancelling,
+            (false, false, false) => JobState::Queued,
+            (true, false, false) => JobState::Running,
+        };
+        JobStatus {
+            job_id: self.id.clone(),
+            state,
+            total: self.total,
+            completed: self.completed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A registered job; marks it finished when dropped, also if its caller goes away mid-run.
+pub struct RunningJob {
+    registry: Arc<JobRegistry>,
+    job: Arc<Job>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl RunningJob {
+    /// Waits for a concurrent job slot, then marks the job running.
+    pub async fn acquire_slot(&mut self) {
+        if let Ok(permit) = self.registry.slots.clone().acquire_owned().await {
+            self._permit = Some(permit);
+        }
+        self.job.started.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Deref for RunningJob {
+    type Target = Job;
+
+    fn deref(&self) -> &Job {
+        &self.job
+    }
+}
+
+impl Drop for RunningJob {
+    fn drop(&mut self) {
+        self.registry.finish(&self.job);
+    }
+}
+
+#[derive(Default)]
+struct Jobs {
+    by_id: HashMap<String, Arc<Job>>,
+    finished: VecDeque<(String, Instant)>,
+}
+
+impl Jobs {
+    fn expire(&mut self, retention: Duration) {
+        while let Some((id, finished_at)) = self.finished.front() {
+            if self.finished.len() <= FINISHED_CAPACITY && finished_at.elapsed() < retention {
+                break;
+            }
+            self.by_id.remove(id);
+            self.finished.pop_front();
+        }
+    }
+}
+
+/// The unfinished jobs and the finished ones still within their retention.
+pub struct JobRegistry {
+    limits: JobLimits,
+    slots: Arc<Semaphore>,
+    jobs: Mutex<Jobs>,
+}
+
+impl Default for JobRegistry {
+    fn default() -> Self {
+        Self::new(JobLimits::default())
+    }
+}
+
+impl JobRegistry {
+    pub fn new(limits: JobLimits) -> Self {
+        Self {
+            limits,
+            slots: Arc::new(Semaphore::new(limits.max_concurrent.max(1))),
+            jobs: Mutex::default(),
+        }
+    }
+
+    /// Registers a running job of `total` items; fails if `id` is already in use.
+    pub fn start(self: &Arc<Self>, id: String, total: usize) -> Result<RunningJob, String> {
+        self.register(id, total, true)
+    }
+
+    /// Registers a job of `total` items that stays queued until it
+    /// [acquires a slot](RunningJob::acquire_slot).
+    pub fn submit(self: &Arc<Self>, id: String, total: usize) -> Result<RunningJob, String> {
+        self.register(id, total, false)
+    }
+
+    fn register(
+        self: &Arc<Self>,
+        id: String,
+        total: usize,
+        started: bool,
+    ) -> Result<RunningJob, String> {
+        let mut jobs = self.jobs.lock().map_err(|_| "Job registry lock poisoned")?;
+        jobs.expire(self.limits.retention);
+        if jobs.by_id.contains_key(&id) {
+            return Err(format!("Job `{}` already exists", id));
+        }
+        let job = Arc::new(Job {
+            id: id.clone(),
+            total,
+            completed: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            started: AtomicBool::new(started),
+            cancel: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            results: Mutex::new(Some(ResultStore::new(self.limits.spill_threshold))),
+        });
+        jobs.by_id.insert(id, job.clone());
+        Ok(RunningJob {
+            registry: self.clone(),
+            job,
+            _permit: None,
+        })
+    }
+
+    /// Marks `job` finished, starting its retention period.
+    fn finish(&self, job: &Job) {
+        job.finished.store(true, Ordering::Relaxed);
+        let Ok(mut jobs) = self.jobs.lock() else {
+            return;
+        };
+        jobs.finished.push_back((job.id.clone(), Instant::now()));
+        jobs.expire(self.limits.retention);
+    }
+
+    pub fn status(&self, id: &str) -> Result<JobStatus, String> {
+        self.get(id).map(|job| job.status())
+    }
+
+    /// Asks a job to stop before its next item. Cancelling a finished job changes nothing.
+    pub fn cancel(&self, id: &str) -> Result<JobStatus, String> {
+        let job = self.get(id)?;
+        if !job.finished.load(Ordering::Relaxed) {
+            job.cancel.store(true, Ordering::Relaxed);
+        }
+        Ok(job.status())
+    }
+
+    pub fn get(&self, id: &str) -> Result<Arc<Job>, String> {
+        let mut jobs = self.jobs.lock().map_err(|_| "Job registry lock poisoned")?;
+        jobs.expire(self.limits.retention);
+        jobs.by_id
+            .get(id)
+            .cloned()
+            .ok_or_else(|| format!("Unknown job `{}`", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(error: bool) -> BatchItem {
+        BatchItem {
+            result: (!error).then(|| "true".to_string()),
+            error: error.then(|| "boom".to_string()),
+            index: None,
+        }
+    }
+
+    #[test]
+    fn test_job_lifecycle() {
+        let registry = Arc::new(JobRegistry::default());
+        let job = registry.start("j1".to_string(), 3).unwrap();
+        assert!(registry.start("j1".to_string(), 1).is_err());
+        job.record(item(false));
+        job.record(item(true));
+        let status = registry.cancel("j1").unwrap();
+        assert_eq!(status.state, JobState::Cancelling);
+        assert_eq!((status.completed, status.failed), (2, 1));
+        assert!(job.is_cancelled());
+        drop(job);
+        assert_eq!(registry.status("j1").unwrap().state, JobState::Cancelled);
+        let (results, count) = registry.get("j1").unwrap().results(1, 5).unwrap().unwrap();
+        assert_eq!((results, count), (vec![item(true)], 2));
+        assert_eq!(registry.status("j2").err().unwrap(), "Unknown job `j2`");
+    }
+
+    #[tokio::test]
+    async fn test_slots_and_retention() {
+        let registry = Arc::new(JobRegistry::new(JobLimits {
+            max_concurrent: 1,
+            retention: Duration::ZERO,
+            ..JobLimits::default()
+        }));
+        let mut first = registry.submit("a".to_string(), 1).unwrap();
+        let mut second = registry.submit("b".to_string(), 1).unwrap();
+        first.acquire_slot().await;
+        assert_eq!(registry.status("a").unwrap().state, JobState::Running);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), second.acquire_slot())
+                .await
+                .is_err()
+        );
+        assert_eq!(registry.status("b").unwrap().state, JobState::Queued);
+        drop(first);
+        second.acquire_slot().await;
+        assert_eq!(registry.status("b").unwrap().state, JobState::Running);
+        assert!(registry.status("a").is_err());
+    }
+
+    #[test]
+    fn test_results_spill_to_disk() {
+        let registry = Arc::new(JobRegistry::new(JobLimits {
+            spill_threshold: 200,
+            ..JobLimits::default()
+        }));
+        let job = registry.start("big".to_string(), 10).unwrap();
+        for _ in 0..10 {
+            job.record(item(false));
+        }
+        job.record(item(true));
+        let path = {
+            let results = job.results.lock().unwrap();
+            let store = results.as_ref().unwrap();
+            assert!(store.memory.is_empty());
+            store.spill.as_ref().unwrap().path.clone()
+        };
+        assert!(path.exists());
+        let (page, count) = job.results(9, 5).unwrap().unwrap();
+        assert_eq!((page, count), (vec![item(false), item(true)], 11));
+        assert_eq!(job.take_results().unwrap().len(), 11);
+        assert!(!path.exists());
+    }
+}
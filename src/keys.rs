@@ -0,0 +1,349 @@
+//! API keys for the HTTP listeners, kept in the `--keys` file and managed with
+//! `cel-mcp keys add|list|revoke`.
+//!
+//! The file holds only SHA-256 hashes of the keys' secrets; a secret is printed once, when its
+//! key is added. With `--keys`, the listeners accept only requests carrying the secret of a key
+//! with their scope as a bearer token, and charge them to the key's account and quota. The
+//! server rereads the file when it changes, so added and revoked keys take effect without a
+//! restart.
+
+use crate::quota::{self, Account, Quota};
+use axum::Router;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use chrono::{SecondsFormat, Utc};
+use rmcp::serde::{Deserialize, Serialize};
+use rmcp::serde_json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// What a key may be used for.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, clap::ValueEnum,
+)]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum Scope {
+    /// The tools and endpoints of `--http`.
+    Evaluate,
+    /// The admin tools and metrics of `--admin-http`, and everything `evaluate` allows.
+    Admin,
+}
+
+/// Whether `scopes` include `scope`; `admin` includes them all.
+pub fn allows(scopes: &BTreeSet<Scope>, scope: Scope) -> bool {
+    scopes.contains(&scope) || scopes.contains(&Scope::Admin)
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::Evaluate => "evaluate",
+            Scope::Admin => "admin",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct ApiKey {
+    /// Identifies the key in `keys list` and `keys revoke`, and its usage as `key-<id>`.
+    pub id: String,
+    pub name: String,
+    /// Hex SHA-256 of the secret.
+    hash: String,
+    pub scopes: BTreeSet<Scope>,
+    /// Replaces the server's quota for the key's requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<Quota>,
+    /// When the key was added (RFC 3339).
+    pub created_at: String,
+}
+
+impl ApiKey {
+    pub fn allows(&self, scope: Scope) -> bool {
+        allows(&self.scopes, scope)
+    }
+
+    /// The account the key's requests are charged to.
+    pub fn account(&self) -> Account {
+        Account {
+            name: format!("key-{}", self.id),
+            quota: self.quota,
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+struct KeyFile {
+    keys: Vec<ApiKey>,
+}
+
+fn hash(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+/// The keys in a file, reread when the file changes.
+pub struct KeyStore {
+    path: PathBuf,
+    /// The file's modification time when it was read, and its keys.
+    loaded: RwLock<(Option<SystemTime>, Vec<ApiKey>)>,
+}
+
+impl KeyStore {
+    /// The keys in the file at `path`, which is created when the first key is added.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let loaded = read(&path)?;
+        Ok(Self {
+            path,
+            loaded: RwLock::new(loaded),
+        })
+    }
+
+    /// Rereads the file if it changed since it was read. A file that fails to read keeps the
+    /// keys read before.
+    fn refresh(&self) {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if self.loaded.read().is_ok_and(|loaded| loaded.0 == modified) {
+            return;
+        }
+        match read(&self.path) {
+            Ok(reread) => {
+                if let Ok(mut loaded) = self.loaded.write() {
+                    *loaded = reread;
+                }
+            }
+            Err(e) => tracing::error!("Keeping the previous API keys: {}", e),
+        }
+    }
+
+    pub fn list(&self) -> Vec<ApiKey> {
+        self.refresh();
+        self.loaded
+            .read()
+            .map(|loaded| loaded.1.clone())
+            .unwrap_or_default()
+    }
+
+    /// The key whose secret is `secret`.
+    pub fn authenticate(&self, secret: &str) -> Option<ApiKey> {
+        let hash = hash(secret);
+        self.list().into_iter().find(|key| key.hash == hash)
+    }
+
+    /// Adds a key named `name`, returning it and its secret.
+    pub fn add(
+        &self,
+        name: &str,
+        scopes: BTreeSet<Scope>,
+        quota: Option<Quota>,
+    ) -> Result<(ApiKey, String), String> {
+        if scopes.is_empty() {
+            return Err("A key needs at least one scope".to_string());
+        }
+        let mut keys = self.list();
+        if keys.iter().any(|key| key.name == name) {
+            return Err(format!("A key named `{}` exists already", name));
+        }
+        let secret = format!("cel_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let key = ApiKey {
+            id: Uuid::new_v4()
+                .simple()
+                .to_string()
+                .chars()
+                .take(12)
+                .collect(),
+            name: name.to_string(),
+            hash: hash(&secret),
+            scopes,
+            quota,
+            created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        };
+        keys.push(key.clone());
+        self.write(keys)?;
+        Ok((key, secret))
+    }
+
+    /// Deletes the key with ID or name `key`, returning it; `None` if there is none.
+    pub fn revoke(&self, key: &str) -> Result<Option<ApiKey>, String> {
+        let mut keys = self.list();
+        let Some(index) = keys.iter().position(|k| k.id == key || k.name == key) else {
+            return Ok(None);
+        };
+        let revoked = keys.remove(index);
+        self.write(keys)?;
+        Ok(Some(revoked))
+    }
+
+    /// Replaces the file, through a temporary file renamed into place.
+    fn write(&self, keys: Vec<ApiKey>) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(&KeyFile { keys }).map_err(|e| e.to_string())?;
+        let temporary = self.path.with_extension("tmp");
+        std::fs::write(&temporary, text)
+            .and_then(|()| std::fs::rename(&temporary, &self.path))
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))?;
+        let reread = read(&self.path)?;
+        if let Ok(mut loaded) = self.loaded.write() {
+            *loaded = reread;
+        }
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> Result<(Option<SystemTime>, Vec<ApiKey>), String> {
+    if !path.exists() {
+        return Ok((None, Vec::new()));
+    }
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let file: KeyFile = serde_json::from_str(&text)
+        .map_err(|e| format!("Invalid key file {}: {}", path.display(), e))?;
+    Ok((modified, file.keys))
+}
+
+#[derive(Clone)]
+struct RequireKey {
+    store: Arc<KeyStore>,
+    scope: Scope,
+}
+
+impl RequireKey {
+    /// The account of the key a request with `headers` carries, or the status to refuse it
+    /// with.
+    fn authorize(&self, headers: &HeaderMap) -> Result<Account, (StatusCode, String)> {
+        let key = quota::bearer(headers)
+            .and_then(|secret| self.store.authenticate(secret))
+            .ok_or((
+                StatusCode::UNAUTHORIZED,
+                "A valid API key is required".to_string(),
+            ))?;
+        if !key.allows(self.scope) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("Key `{}` lacks the `{}` scope", key.id, self.scope),
+            ));
+        }
+        Ok(key.account())
+    }
+}
+
+/// Makes `router` refuse requests without the secret of a key with `scope`, with status 401,
+/// or 403 for a key without it. Other requests are charged to their key's account.
+pub fn require(router: Router, store: Arc<KeyStore>, scope: Scope) -> Router {
+    router.layer(middleware::from_fn_with_state(
+        RequireKey { store, scope },
+        check,
+    ))
+}
+
+async fn check(State(required): State<RequireKey>, mut request: Request, next: Next) -> Response {
+    match required.authorize(request.headers()) {
+        Ok(account) => {
+            request.extensions_mut().insert(account);
+            next.run(request).await
+        }
+        Err((status, message)) => (
+            status,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            format!("{}\n", message),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary() -> PathBuf {
+        std::env::temp_dir().join(format!("cel-keys-{}.json", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_add_authenticate_revoke() {
+        let path = temporary();
+        let store = KeyStore::open(&path).unwrap();
+        let quota = Quota {
+            max_evaluations: Some(10),
+            ..Quota::default()
+        };
+        let (key, secret) = store
+            .add("ci", BTreeSet::from([Scope::Evaluate]), Some(quota))
+            .unwrap();
+        assert!(
+            store
+                .add("ci", BTreeSet::from([Scope::Admin]), None)
+                .is_err()
+        );
+        assert!(store.add("ops", BTreeSet::new(), None).is_err());
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(!text.contains(&secret));
+
+        // A server that opened the file earlier sees the key.
+        let server = KeyStore::open(&path).unwrap();
+        let found = server.authenticate(&secret).unwrap();
+        assert_eq!(found.account().name, format!("key-{}", key.id));
+        assert_eq!(found.account().quota, Some(quota));
+        assert_eq!(server.authenticate("cel_guess"), None);
+
+        assert_eq!(store.revoke("ci").unwrap().map(|k| k.id), Some(key.id));
+        assert_eq!(store.revoke("ci"), Ok(None));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_authorize() {
+        let path = temporary();
+        let store = Arc::new(KeyStore::open(&path).unwrap());
+        let (agent, evaluate) = store
+            .add("agent", BTreeSet::from([Scope::Evaluate]), None)
+            .unwrap();
+        let (_, admin) = store
+            .add("ops", BTreeSet::from([Scope::Admin]), None)
+            .unwrap();
+        let authorize = |scope, secret: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(secret) = secret {
+                let value = format!("Bearer {}", secret);
+                headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+            }
+            let required = RequireKey {
+                store: store.clone(),
+                scope,
+            };
+            required.authorize(&headers).map_err(|(status, _)| status)
+        };
+        assert_eq!(
+            authorize(Scope::Evaluate, None),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            authorize(Scope::Evaluate, Some("cel_guess")),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            authorize(Scope::Evaluate, Some(&evaluate)).map(|account| account.name),
+            Ok(format!("key-{}", agent.id))
+        );
+        assert_eq!(
+            authorize(Scope::Admin, Some(&evaluate)),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert!(authorize(Scope::Admin, Some(&admin)).is_ok());
+        assert!(authorize(Scope::Evaluate, Some(&admin)).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
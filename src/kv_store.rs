@@ -0,0 +1,192 @@
+//! A small key-value store of reference data, such as blocklists and feature flags, that
+//! expressions read with `kv.get(key)` and `kv.exists(key)` instead of every request carrying it.
+//!
+//! Operators fill it with the `kv_put` and `kv_delete` admin tools. A store opened on a file
+//! loads it at startup and rewrites it whole after every change, through a temporary file
+//! synced to disk and renamed into place, so entries survive restarts and crashes. Rewriting
+//! the whole file keeps the format a plain JSON object operators can edit, at the cost of
+//! changes taking time proportional to the store's size: it suits reference data changed
+//! now and then, not a write-heavy workload. Expressions keep reading the previous entries
+//! while a change is written.
+
+use crate::config;
+use crate::conversion::from_json;
+use crate::provider::{FunctionProvider, Method, Namespace};
+use cel::{Context, FunctionContext, Value as CelValue};
+use rmcp::serde_json::{self, Map, Value};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// The namespace the store's functions are called on.
+pub const VARIABLE: &str = "kv";
+
+/// JSON values by key, optionally persisted to a file.
+#[derive(Default)]
+pub struct KvStore {
+    path: Option<PathBuf>,
+    entries: RwLock<Map<String, Value>>,
+    /// Held while a change is applied, so changes don't overwrite each other's.
+    writer: Mutex<()>,
+}
+
+impl KvStore {
+    /// A store kept in memory only.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// A store persisted to the JSON object file at `path`, created on the first change.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let entries = if path.exists() {
+            config::read_json_object(&path)?
+        } else {
+            Map::new()
+        };
+        Ok(Self {
+            path: Some(path),
+            entries: RwLock::new(entries),
+            writer: Mutex::default(),
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.entries.read().ok()?.get(key).cloned()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries
+            .read()
+            .is_ok_and(|entries| entries.contains_key(key))
+    }
+
+    /// The keys starting with `prefix`, in order.
+    pub fn keys(&self, prefix: &str) -> Vec<String> {
+        self.entries.read().map_or_else(
+            |_| Vec::new(),
+            |entries| {
+                entries
+                    .keys()
+                    .filter(|key| key.starts_with(prefix))
+                    .cloned()
+                    .collect()
+            },
+        )
+    }
+
+    /// Sets `key` to `value`, replacing any earlier value. Blocks while the file is written.
+    pub fn put(&self, key: String, value: Value) -> Result<(), String> {
+        self.update(|entries| {
+            entries.insert(key, value);
+        })
+    }
+
+    /// Removes `key`, reporting whether it was present. Blocks while the file is written.
+    pub fn delete(&self, key: &str) -> Result<bool, String> {
+        let mut removed = false;
+        self.update(|entries| removed = entries.remove(key).is_some())?;
+        Ok(removed)
+    }
+
+    /// Applies `change` and persists the result; on a write error nothing changes. The entries
+    /// are locked only to copy and to replace them, not while the file is written.
+    fn update(&self, change: impl FnOnce(&mut Map<String, Value>)) -> Result<(), String> {
+        let _writer = self
+            .writer
+            .lock()
+            .map_err(|_| "Key-value store lock poisoned")?;
+        let mut changed = self
+            .entries
+            .read()
+            .map_err(|_| "Key-value store lock poisoned")?
+            .clone();
+        change(&mut changed);
+        if let Some(path) = &self.path {
+            let text = serde_json::to_string_pretty(&changed).map_err(|e| e.to_string())?;
+            write_synced(path, text.as_bytes())
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        *self
+            .entries
+            .write()
+            .map_err(|_| "Key-value store lock poisoned")? = changed;
+        Ok(())
+    }
+}
+
+/// Replaces the file at `path` with `contents`: written to a temporary file, synced to disk,
+/// then renamed over it, so a crash leaves either the old or the new file.
+fn write_synced(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    let mut file = File::create(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)
+}
+
+impl FunctionProvider for Arc<KvStore> {
+    fn register(&self, _ctx: &mut Context) {}
+
+    fn names(&self) -> Vec<String> {
+        vec!["kv.get".to_string(), "kv.exists".to_string()]
+    }
+
+    fn namespace(&self) -> Option<Namespace> {
+        let store = self.clone();
+        let get: Method = Arc::new(move |ftx: &FunctionContext, args: &[CelValue]| {
+            let [CelValue::String(key)] = args else {
+                return Err(ftx.error("kv.get expects one string key"));
+            };
+            Ok(store
+                .get(key)
+                .map_or(CelValue::Null, |value| from_json(&value)))
+        });
+        let store = self.clone();
+        let exists: Method = Arc::new(move |ftx: &FunctionContext, args: &[CelValue]| {
+            let [CelValue::String(key)] = args else {
+                return Err(ftx.error("kv.exists expects one string key"));
+            };
+            Ok(CelValue::Bool(store.contains(key)))
+        });
+        Some(Namespace {
+            name: VARIABLE.to_string(),
+            methods: vec![("get".to_string(), get), ("exists".to_string(), exists)],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_persists_and_serves_expressions() {
+        let path = std::env::temp_dir().join(format!("cel-kv-{}.json", uuid::Uuid::new_v4()));
+        let store = KvStore::open(&path).unwrap();
+        store
+            .put("blocked_ips".to_string(), json!(["10.0.0.1"]))
+            .unwrap();
+        store.put("beta".to_string(), json!(true)).unwrap();
+        assert_eq!(store.delete("beta"), Ok(true));
+        assert_eq!(store.delete("beta"), Ok(false));
+
+        let store = Arc::new(KvStore::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(store.keys("blocked"), ["blocked_ips"]);
+        let evaluator = Evaluator::new().with_function_provider(store);
+        let evaluate = |expression: &str| {
+            evaluator.evaluate(expression, &json!({"ip": "10.0.0.1"}), Profile::Default)
+        };
+        assert_eq!(evaluate("ip in kv.get('blocked_ips')"), Ok(json!(true)));
+        assert_eq!(
+            evaluate("kv.exists('beta') || kv.get('beta') == null"),
+            Ok(json!(true))
+        );
+        assert!(evaluate("'beta'.exists('x')").is_err());
+    }
+}
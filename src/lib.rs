@@ -1 +1,82 @@
+pub mod admission;
+mod ast;
+pub mod audit;
+pub mod bench;
+mod bits;
+mod calendar;
 pub mod cel_tool;
+pub mod cel_type;
+mod checksum;
+pub mod completion;
+pub mod config;
+pub mod conformance;
+pub mod conversion;
+mod cost;
+#[cfg(feature = "arrow")]
+pub mod dataset;
+#[cfg(feature = "decimal")]
+mod decimal;
+pub mod deny;
+mod elicitation;
+pub mod environment;
+pub mod evaluator;
+pub mod fair_queue;
+mod formats;
+#[cfg(feature = "geoip")]
+pub mod geoip;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+#[cfg(feature = "http-function")]
+pub mod http_function;
+pub mod ingest;
+pub mod ip_filter;
+mod iso;
+pub mod job;
+pub mod keys;
+pub mod kv_store;
+pub mod library;
+pub mod literal;
+pub mod macros;
+pub mod mcp_http;
+pub mod mcp_log;
+pub mod media;
+pub mod merge;
+pub mod messages;
+#[cfg(feature = "object-store")]
+pub mod object_store;
+pub mod openapi;
+mod paths;
+pub mod pattern;
+pub mod pipe;
+pub mod policy;
+pub mod profile;
+pub mod program_cache;
+pub mod provider;
+pub mod quota;
+pub mod redact;
+pub mod registry;
+pub mod reload;
+mod repair;
+pub mod resolver;
+pub mod rest;
+pub mod result_cache;
+pub mod rewrite;
+pub mod size_limit;
+mod stats;
+mod strings;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod template;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod truncate;
+mod unparse;
+#[cfg(feature = "validation")]
+mod validation;
+#[cfg(feature = "arrow")]
+pub mod vectorized;
+#[cfg(feature = "wasm")]
+pub mod wasm;
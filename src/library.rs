@@ -0,0 +1,197 @@
+//! Optional function libraries, enabled with `--library <name>`.
+//!
+//! Each library is a namespace of functions, such as `decimal.add(a, b)`, though `checksum`'s
+//! are also plain functions. They are off by default so that their names stay free for context
+//! variables.
+
+use crate::bits;
+use crate::calendar;
+use crate::checksum;
+#[cfg(feature = "decimal")]
+use crate::decimal;
+use crate::formats;
+use crate::iso;
+use crate::paths;
+use crate::provider::{FunctionProvider, Method, Namespace};
+use crate::stats;
+use crate::strings;
+#[cfg(feature = "validation")]
+use crate::validation;
+use cel::Context;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Library {
+    /// `decimal.add`, `decimal.cmp`, ...: fixed-point decimal arithmetic. Requires the
+    /// `decimal` feature.
+    Decimal,
+    /// `stats.mean`, `stats.percentile`, ...: summary statistics over lists of numbers.
+    Stats,
+    /// `time.truncate`, `time.inZone`, `time.addBusinessDays`: calendar functions.
+    Time,
+    /// `email.isValid`: email address syntax. Requires the `validation` feature.
+    Email,
+    /// `phone.isValid`: phone numbers by region. Requires the `validation` feature.
+    Phone,
+    /// `iso.isCountry`, `iso.isCurrency`: ISO 3166-1 alpha-2 and ISO 4217 codes.
+    Iso,
+    /// `locale.isValid`: BCP 47 language tags.
+    Locale,
+    /// `xml.decode`: XML documents into maps and lists.
+    Xml,
+    /// `qs.parse`: URL query strings into maps.
+    Qs,
+    /// `csv.decode`: CSV text into lists of rows.
+    Csv,
+    /// `path.match`, `path.clean`: globs and `/`-separated paths.
+    Path,
+    /// `strings.fields`, `strings.naturalCompare`: tokenizing and comparing strings.
+    Strings,
+    /// `bits.and`, `bits.shl`, ...: bitwise operations on ints and uints.
+    Bits,
+    /// `crc32`, `adler32`: integrity checks of bytes and strings, also as `checksum.crc32` etc.
+    Checksum,
+}
+
+impl Library {
+    pub fn name(self) -> &'static str {
+        match self {
+            Library::Decimal => "decimal",
+            Library::Stats => stats::VARIABLE,
+            Library::Time => calendar::VARIABLE,
+            Library::Email => "email",
+            Library::Phone => "phone",
+            Library::Iso => iso::ISO_VARIABLE,
+            Library::Locale => iso::LOCALE_VARIABLE,
+            Library::Xml => formats::XML_VARIABLE,
+            Library::Qs => formats::QS_VARIABLE,
+            Library::Csv => formats::CSV_VARIABLE,
+            Library::Path => paths::VARIABLE,
+            Library::Strings => strings::VARIABLE,
+            Library::Bits => bits::VARIABLE,
+            Library::Checksum => checksum::VARIABLE,
+        }
+    }
+
+    /// The Cargo feature the library needs but this build lacks, if any.
+    pub fn missing_feature(self) -> Option<&'static str> {
+        match self {
+            Library::Decimal if !cfg!(feature = "decimal") => Some("decimal"),
+            Library::Email | Library::Phone if !cfg!(feature = "validation") => Some("validation"),
+            _ => None,
+        }
+    }
+
+    /// A line for the server instructions.
+    pub fn description(self) -> &'static str {
+        match self {
+            Library::Decimal => {
+                "decimal.add/sub/mul/div(a, b), decimal.cmp(a, b), decimal.round(a, places) and decimal.parse(a) do exact decimal arithmetic on decimal strings such as '19.99', returning strings (cmp returns -1, 0 or 1)"
+            }
+            Library::Stats => {
+                "stats.mean(list), stats.median(list), stats.stddev(list) (population) and stats.percentile(list, p) (p from 0 to 100, interpolated) summarize non-empty lists of numbers as doubles"
+            }
+            Library::Time => {
+                "time.truncate(ts, unit) (second, minute, hour, day, week, month or year), time.inZone(ts, 'Asia/Tokyo') (an IANA zone, so that getHours() etc. are local) and time.addBusinessDays(ts, n) (skipping weekends) work in the timestamp's UTC offset"
+            }
+            Library::Email => {
+                "email.isValid(s) tells whether s is a plain email address (no display name, with a top-level domain)"
+            }
+            Library::Phone => {
+                "phone.isValid(s, region) tells whether s is a valid phone number in the ISO 3166-1 region, such as 'JP'; without a region, s must start with + and the country code"
+            }
+            Library::Iso => {
+                "iso.isCountry(code) and iso.isCurrency(code) tell whether code is an uppercase ISO 3166-1 alpha-2 country code, such as 'JP', or ISO 4217 currency code, such as 'USD'"
+            }
+            Library::Locale => {
+                "locale.isValid(tag) tells whether tag is a valid BCP 47 language tag, such as 'en-US'"
+            }
+            Library::Xml => {
+                "xml.decode(s) decodes an XML document into {root: value}, where an element with only text is a string and others are maps of '@attribute', child names (lists when repeated) and '#text'"
+            }
+            Library::Qs => {
+                "qs.parse(s) decodes a URL query string, with or without '?', into a map of strings (lists of strings for repeated keys)"
+            }
+            Library::Csv => {
+                "csv.decode(s) decodes CSV text into a list of maps from the header row's column names to string fields; csv.decode(s, {'header': false, 'delimiter': ';'}) gives lists of fields instead, and changes the delimiter"
+            }
+            Library::Path => {
+                "path.match(glob, p) tells whether the /-separated path p matches glob, where * and ? stay within a component and ** spans components, as in 'src/**/*.rs'; path.clean(p) resolves . and .. and removes repeated slashes"
+            }
+            Library::Strings => {
+                "strings.fields(s) splits s around runs of whitespace; strings.naturalCompare(a, b) returns -1, 0 or 1, comparing digit runs by value so that 'v1.9' comes before 'v1.10'"
+            }
+            Library::Bits => {
+                "bits.and(a, b), bits.or(a, b) and bits.xor(a, b) take two ints or two uints; bits.shl(a, n) and bits.shr(a, n) shift an int or uint by 0 to 63 bits"
+            }
+            Library::Checksum => {
+                "crc32(data) and adler32(data) return the CRC-32 (IEEE) or Adler-32 checksum of bytes, or of a string's UTF-8 bytes, as a uint; checksum.crc32(data) and checksum.adler32(data) are aliases"
+            }
+        }
+    }
+}
+
+impl fmt::Display for Library {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FunctionProvider for Library {
+    fn register(&self, _ctx: &mut Context) {}
+
+    fn names(&self) -> Vec<String> {
+        match self {
+            #[cfg(feature = "decimal")]
+            Library::Decimal => decimal::names(),
+            #[cfg(not(feature = "decimal"))]
+            Library::Decimal => Vec::new(),
+            Library::Stats => stats::names(),
+            Library::Time => calendar::names(),
+            Library::Email | Library::Phone | Library::Locale => {
+                vec![format!("{}.isValid", self.name())]
+            }
+            Library::Xml => vec!["xml.decode".to_string()],
+            Library::Qs => vec!["qs.parse".to_string()],
+            Library::Csv => vec!["csv.decode".to_string()],
+            Library::Path => paths::names(),
+            Library::Strings => strings::names(),
+            Library::Bits => bits::names(),
+            Library::Checksum => checksum::names(),
+            Library::Iso => vec!["iso.isCountry".to_string(), "iso.isCurrency".to_string()],
+        }
+    }
+
+    fn namespace(&self) -> Option<Namespace> {
+        match self {
+            #[cfg(feature = "decimal")]
+            Library::Decimal => Some(decimal::namespace()),
+            #[cfg(not(feature = "decimal"))]
+            Library::Decimal => None,
+            Library::Stats => Some(stats::namespace()),
+            Library::Time => Some(calendar::namespace()),
+            Library::Iso => Some(iso::iso_namespace()),
+            Library::Locale => Some(iso::locale_namespace()),
+            Library::Xml => Some(formats::xml_namespace()),
+            Library::Qs => Some(formats::qs_namespace()),
+            Library::Csv => Some(formats::csv_namespace()),
+            Library::Path => Some(paths::namespace()),
+            Library::Strings => Some(strings::namespace()),
+            Library::Bits => Some(bits::namespace()),
+            Library::Checksum => Some(checksum::namespace()),
+            #[cfg(feature = "validation")]
+            Library::Email => Some(validation::email_namespace()),
+            #[cfg(feature = "validation")]
+            Library::Phone => Some(validation::phone_namespace()),
+            #[cfg(not(feature = "validation"))]
+            Library::Email | Library::Phone => None,
+        }
+    }
+
+    fn globals(&self) -> Vec<(String, Method)> {
+        match self {
+            Library::Checksum => checksum::functions(),
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! CEL literals for JSON values, so values put into expressions are quoted and escaped by the
+//! server instead of pasted in as text, where a stray quote could change what the expression
+//! does.
+//!
+//! A declared type picks the literal: `uint` numbers get a `u` suffix, `double` numbers a
+//! decimal point, `bytes` strings a `b` prefix, and `timestamp` and `duration` strings are
+//! wrapped in a conversion call.
+
+use crate::cel_type::CelType;
+use rmcp::serde_json::{Number, Value};
+use std::fmt::Write;
+
+/// `value` as a CEL literal of type `declared`, failing, naming `path`, if it doesn't match.
+pub fn quote(path: &str, value: &Value, declared: &CelType) -> Result<String, String> {
+    declared.check(path, value)?;
+    let mut literal = String::new();
+    write_literal(&mut literal, path, value, declared)?;
+    Ok(literal)
+}
+
+fn write_literal(
+    out: &mut String,
+    path: &str,
+    value: &Value,
+    declared: &CelType,
+) -> Result<(), String> {
+    match (value, declared) {
+        (Value::Null, _) => out.push_str("null"),
+        (Value::Bool(b), _) => out.push_str(if *b { "true" } else { "false" }),
+        (Value::Number(n), _) => write_number(out, n, declared),
+        (Value::String(s), CelType::Bytes) => {
+            out.push('b');
+            write_string(out, s);
+        }
+        (Value::String(s), CelType::Timestamp | CelType::Duration) => {
+            out.push_str(&declared.to_string());
+            out.push('(');
+            write_string(out, s);
+            out.push(')');
+        }
+        (Value::String(s), _) => write_string(out, s),
+        (Value::Array(items), _) => {
+            let element = match declared {
+                CelType::List(element) => element,
+                _ => &CelType::Dyn,
+            };
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_literal(out, &format!("{}[{}]", path, i), item, element)?;
+            }
+            out.push(']');
+        }
+        (Value::Object(entries), _) => {
+            let (key_type, value_type) = match declared {
+                CelType::Map(key, value) => (key.as_ref(), value.as_ref()),
+                _ => (&CelType::Dyn, &CelType::Dyn),
+            };
+            out.push('{');
+            for (i, (key, item)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_key(out, path, key, key_type)?;
+                out.push_str(": ");
+                write_literal(out, &format!("{}.{}", path, key), item, value_type)?;
+            }
+            out.push('}');
+        }
+    }
+    Ok(())
+}
+
+fn write_number(out: &mut String, n: &Number, declared: &CelType) {
+    match (declared, n.as_i64(), n.as_u64()) {
+        (CelType::Uint, _, Some(u)) => {
+            let _ = write!(out, "{}u", u);
+        }
+        (CelType::Double, Some(i), _) => {
+            let _ = write!(out, "{}.0", i);
+        }
+        (CelType::Double, None, Some(u)) => {
+            let _ = write!(out, "{}.0", u);
+        }
+        (_, Some(i), _) => {
+            let _ = write!(out, "{}", i);
+        }
+        // Above the int range.
+        (_, None, Some(u)) => {
+            let _ = write!(out, "{}u", u);
+        }
+        _ => {
+            let f = n.as_f64().unwrap_or_default();
+            // Debug keeps a decimal point or an exponent, so the literal stays a double.
+            let _ = write!(out, "{:?}", f);
+        }
+    }
+}
+
+/// Map keys are JSON object keys, so int, uint and bool keys are parsed from them.
+fn write_key(out: &mut String, path: &str, key: &str, declared: &CelType) -> Result<(), String> {
+    let invalid = || {
+        format!(
+            "`{}` has the key `{}`, which is not {}",
+            path, key, declared
+        )
+    };
+    match declared {
+        CelType::Int => {
+            let _ = write!(out, "{}", key.parse::<i64>().map_err(|_| invalid())?);
+        }
+        CelType::Uint => {
+            let _ = write!(out, "{}u", key.parse::<u64>().map_err(|_| invalid())?);
+        }
+        CelType::Bool => {
+            let _ = write!(out, "{}", key.parse::<bool>().map_err(|_| invalid())?);
+        }
+        _ => write_string(out, key),
+    }
+    Ok(())
+}
+
+/// `s` as a CEL string literal.
+pub(crate) fn string(s: &str) -> String {
+    let mut literal = String::with_capacity(s.len() + 2);
+    write_string(&mut literal, s);
+    literal
+}
+
+/// `bytes` as a CEL bytes literal, with bytes outside printable ASCII escaped as `\xHH`.
+pub(crate) fn bytes(bytes: &[u8]) -> String {
+    let mut literal = String::from("b\"");
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            0x20..=0x7e => literal.push(byte as char),
+            _ => {
+                let _ = write!(literal, "\\x{:02x}", byte);
+            }
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// A double-quoted string literal with quotes, backslashes and control characters escaped.
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::profile::Profile;
+    use rmcp::serde_json::{Map, json};
+
+    #[test]
+    fn test_quote() {
+        let quote = |value: Value, declared: &str| {
+            quote("value", &value, &CelType::parse(declared).unwrap())
+        };
+        assert_eq!(quote(json!(3), "dyn"), Ok("3".to_string()));
+        assert_eq!(quote(json!(3), "uint"), Ok("3u".to_string()));
+        assert_eq!(quote(json!(3), "double"), Ok("3.0".to_string()));
+        assert_eq!(quote(json!(0.5), "dyn"), Ok("0.5".to_string()));
+        assert_eq!(quote(json!("hi"), "bytes"), Ok("b\"hi\"".to_string()));
+        assert_eq!(
+            quote(json!("1h"), "duration"),
+            Ok("duration(\"1h\")".to_string())
+        );
+        assert_eq!(
+            quote(json!({"1": ["a"]}), "map<int, list<string>>"),
+            Ok("{1: [\"a\"]}".to_string())
+        );
+        assert!(quote(json!({"x": 1}), "map<int, int>").is_err());
+        assert!(quote(json!(-1), "uint").is_err());
+    }
+
+    #[test]
+    fn test_quoted_strings_evaluate_to_themselves() {
+        let evaluator = Evaluator::new();
+        for text in [
+            "it's \"quoted\"",
+            "back\\slash",
+            "line\nbreak\t\u{1}",
+            "') || true || ('",
+            "ünïcode ✓",
+        ] {
+            let literal = quote("value", &json!(text), &CelType::String).unwrap();
+            assert_eq!(
+                evaluator.evaluate(&literal, &Value::Object(Map::new()), Profile::Default),
+                Ok(json!(text)),
+                "{}",
+                literal
+            );
+        }
+    }
+}
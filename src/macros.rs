@@ -0,0 +1,159 @@
+//! CEL's macros (`has`, `all`, `exists`, `exists_one`, `map`, `filter`) in parsed expressions.
+//!
+//! The parser expands macros into field tests and comprehensions, so [`classify`] recognizes
+//! each expansion by its shape. This lets `describe_ast` report macro usage, and the server
+//! reject expressions using macros it disabled.
+
+use crate::ast;
+use cel::common::ast::{Expr, operators};
+use cel::common::value::CelVal;
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    clap::ValueEnum,
+)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum Macro {
+    Has,
+    All,
+    Exists,
+    ExistsOne,
+    Map,
+    Filter,
+}
+
+impl Macro {
+    pub fn name(self) -> &'static str {
+        match self {
+            Macro::Has => "has",
+            Macro::All => "all",
+            Macro::Exists => "exists",
+            Macro::ExistsOne => "exists_one",
+            Macro::Map => "map",
+            Macro::Filter => "filter",
+        }
+    }
+}
+
+impl fmt::Display for Macro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The macro `expr` was expanded from, if any.
+pub fn classify(expr: &Expr) -> Option<Macro> {
+    match expr {
+        Expr::Select(select) if select.test => Some(Macro::Has),
+        Expr::Comprehension(comp) => match (&comp.accu_init.expr, &comp.loop_step.expr) {
+            (Expr::Literal(CelVal::Boolean(true)), _) => Some(Macro::All),
+            (Expr::Literal(CelVal::Boolean(false)), _) => Some(Macro::Exists),
+            (Expr::Literal(CelVal::Int(0)), _) => Some(Macro::ExistsOne),
+            (Expr::List(_), Expr::Call(step)) if step.func_name == operators::CONDITIONAL => {
+                // `filter` appends the element itself; three-argument `map` a transform of it.
+                let appends_element = step.args.get(1).is_some_and(|append| {
+                    matches!(&append.expr, Expr::Call(add) if add.args.get(1).is_some_and(|list| {
+                        matches!(&list.expr, Expr::List(list) if matches!(
+                            list.elements.as_slice(),
+                            [element] if matches!(&element.expr, Expr::Ident(name) if *name == comp.iter_var)
+                        ))
+                    }))
+                });
+                Some(if appends_element {
+                    Macro::Filter
+                } else {
+                    Macro::Map
+                })
+            }
+            (Expr::List(_), _) => Some(Macro::Map),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The macros `expression` uses; none if it does not parse.
+pub fn used(expression: &str) -> BTreeSet<Macro> {
+    let mut macros = BTreeSet::new();
+    if let Ok(parsed) = ast::parse(expression) {
+        ast::walk(&parsed, &mut |node| macros.extend(classify(&node.expr)));
+    }
+    macros
+}
+
+/// Fails if `expression` uses one of the `disabled` macros.
+pub(crate) fn check(expression: &str, disabled: &BTreeSet<Macro>) -> Result<(), String> {
+    if disabled.is_empty() {
+        return Ok(());
+    }
+    let banned: Vec<String> = used(expression)
+        .intersection(disabled)
+        .map(|m| format!("`{}`", m))
+        .collect();
+    if banned.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "macro {} disabled on this server",
+            if banned.len() == 1 {
+                format!("{} is", banned[0])
+            } else {
+                format!("{} are", banned.join(", "))
+            }
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+
+    #[test]
+    fn test_classifies_expanded_macros() {
+        let all: BTreeSet<Macro> = [
+            Macro::Has,
+            Macro::All,
+            Macro::Exists,
+            Macro::ExistsOne,
+            Macro::Map,
+            Macro::Filter,
+        ]
+        .into();
+        assert_eq!(
+            used(
+                "has(a.b) && l.all(x, x > 0) && l.exists(x, x > 1) && l.exists_one(x, x > 2) \
+                 && l.map(x, x * 2) == l.filter(x, x > 3)"
+            ),
+            all
+        );
+        assert_eq!(used("l.map(x, x > 0, x * 2)"), [Macro::Map].into());
+        assert_eq!(used("l.map(x, x > 0, x)"), [Macro::Filter].into());
+        assert!(used("size(l) > 0 && a.b").is_empty());
+    }
+
+    #[test]
+    fn test_disabled_macros_fail_to_compile() {
+        let evaluator = Evaluator::new().with_disabled_macros([Macro::Map, Macro::Filter]);
+        let err = evaluator.compile("[1, 2].map(x, x * 2)").err().unwrap();
+        assert_eq!(
+            err,
+            "CEL compile error: macro `map` is disabled on this server"
+        );
+        assert!(evaluator.compile("[1, 2].all(x, x > 0)").is_ok());
+    }
+}
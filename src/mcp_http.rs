@@ -0,0 +1,137 @@
+//! The MCP Streamable HTTP endpoint (`/mcp`).
+//!
+//! The endpoint is stateful: `initialize` opens a session, identified by the `Mcp-Session-Id`
+//! header of later requests, and served by its own [`session`](CelTool::session) of the tool,
+//! so what a session sets (`logging/setLevel`, `set_context`) lasts until it ends. A session is
+//! closed after five minutes without requests, rmcp's default.
+
+use crate::cel_tool::CelTool;
+use rmcp::transport::streamable_http_server::session::local::LocalSessionManager;
+use rmcp::transport::streamable_http_server::{StreamableHttpServerConfig, StreamableHttpService};
+
+/// Serves MCP sessions of `tool` over HTTP.
+pub fn service(tool: CelTool) -> StreamableHttpService<CelTool, LocalSessionManager> {
+    StreamableHttpService::new(
+        move || Ok(tool.session()),
+        LocalSessionManager::default().into(),
+        StreamableHttpServerConfig::default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, header};
+    use http_body_util::BodyExt;
+    use rmcp::serde_json::{Value, json};
+    use std::time::Duration;
+
+    const SESSION_ID_HEADER: &str = "mcp-session-id";
+
+    /// Posts `message`, returning the session ID the server names and the JSON-RPC response to
+    /// `message`, if it is a request.
+    async fn post(
+        service: &StreamableHttpService<CelTool, LocalSessionManager>,
+        session: Option<&str>,
+        message: Value,
+    ) -> (Option<String>, Option<Value>) {
+        let mut request = Request::post("/")
+            .header(header::HOST, "localhost")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::ACCEPT, "application/json, text/event-stream");
+        if let Some(session) = session {
+            request = request.header(SESSION_ID_HEADER, session);
+        }
+        let id = message.get("id").cloned();
+        let request = request.body(Body::from(message.to_string())).unwrap();
+        let response = service.handle(request).await;
+        assert!(response.status().is_success(), "{:?}", response.status());
+        let session = response
+            .headers()
+            .get(SESSION_ID_HEADER)
+            .map(|id| id.to_str().unwrap().to_string());
+        let Some(id) = id else {
+            return (session, None);
+        };
+        let mut body = response.into_body();
+        let mut text = String::new();
+        loop {
+            let frame = tokio::time::timeout(Duration::from_secs(10), body.frame())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            if let Ok(data) = frame.into_data() {
+                text.push_str(std::str::from_utf8(&data).unwrap());
+            }
+            let reply = text
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .filter_map(|data| rmcp::serde_json::from_str::<Value>(data.trim()).ok())
+                .find(|message| message.get("id") == Some(&id));
+            if reply.is_some() {
+                return (session, reply);
+            }
+        }
+    }
+
+    async fn connect(service: &StreamableHttpService<CelTool, LocalSessionManager>) -> String {
+        let initialize = json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-06-18",
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "0"},
+            },
+        });
+        let (session, _) = post(service, None, initialize).await;
+        let session = session.unwrap();
+        let initialized = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+        post(service, Some(&session), initialized).await;
+        session
+    }
+
+    async fn call(
+        service: &StreamableHttpService<CelTool, LocalSessionManager>,
+        session: &str,
+        tool: &str,
+        arguments: Value,
+    ) -> Value {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {"name": tool, "arguments": arguments},
+        });
+        let (_, reply) = post(service, Some(session), message).await;
+        reply.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_session_context_lasts_across_requests() {
+        let service = service(CelTool::builder().build());
+        let session = connect(&service).await;
+        call(
+            &service,
+            &session,
+            "set_context",
+            json!({"context": {"tenant": "acme"}}),
+        )
+        .await;
+        let evaluate = json!({"expression": "tenant", "context": {}});
+        let reply = call(&service, &session, "evaluate", evaluate.clone()).await;
+        assert_eq!(reply["result"]["structuredContent"]["result"], "\"acme\"");
+
+        let other = connect(&service).await;
+        let reply = call(&service, &other, "evaluate", evaluate).await;
+        assert!(
+            reply["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("tenant")
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Forwards the log events of a tool call to the MCP client that asked for them with
+//! `logging/setLevel`, as `notifications/message`.
+//!
+//! [`McpLogLayer`] must be part of the process's tracing subscriber. Events are routed by the
+//! `log_sink` field of the call's `cel.request` span, so the evaluator's events, which run in
+//! that span, reach the session that made the call.
+
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam};
+use rmcp::serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex, PoisonError};
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Name of the span field holding a [`Subscription`] key.
+pub(crate) const SINK_FIELD: &str = "log_sink";
+
+type Sender = mpsc::UnboundedSender<LoggingMessageNotificationParam>;
+
+static SINKS: LazyLock<Mutex<HashMap<u64, (LoggingLevel, Sender)>>> = LazyLock::new(Mutex::default);
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+static NEXT_KEY: AtomicU64 = AtomicU64::new(1);
+
+/// Receives events at `level` or above from spans carrying its key, until dropped.
+pub(crate) struct Subscription {
+    key: u64,
+}
+
+impl Subscription {
+    pub(crate) fn key(&self) -> u64 {
+        self.key
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut sinks = SINKS.lock().unwrap_or_else(PoisonError::into_inner);
+        if sinks.remove(&self.key).is_some() {
+            ACTIVE.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+pub(crate) fn subscribe(
+    level: LoggingLevel,
+) -> (
+    Subscription,
+    mpsc::UnboundedReceiver<LoggingMessageNotificationParam>,
+) {
+    let key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+    let (sender, receiver) = mpsc::unbounded_channel();
+    SINKS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(key, (level, sender));
+    ACTIVE.fetch_add(1, Ordering::Relaxed);
+    (Subscription { key }, receiver)
+}
+
+/// MCP severities in increasing order.
+fn severity(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+fn mcp_level(level: &Level) -> LoggingLevel {
+    match *level {
+        Level::ERROR => LoggingLevel::Error,
+        Level::WARN => LoggingLevel::Warning,
+        Level::INFO => LoggingLevel::Info,
+        _ => LoggingLevel::Debug,
+    }
+}
+
+/// The sink key of a span, kept in its extensions.
+struct SinkKey(u64);
+
+struct SinkKeyVisitor(Option<u64>);
+
+impl Visit for SinkKeyVisitor {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == SINK_FIELD {
+            self.0 = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+/// Collects an event's fields as strings.
+struct FieldsVisitor(Map<String, Value>);
+
+impl Visit for FieldsVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(
+            field.name().to_string(),
+            Value::from(format!("{:?}", value)),
+        );
+    }
+}
+
+/// Routes events to the subscriptions of their enclosing spans.
+pub struct McpLogLayer;
+
+impl McpLogLayer {
+    fn remember_key<S>(values: &Record<'_>, id: &Id, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut visitor = SinkKeyVisitor(None);
+        values.record(&mut visitor);
+        if let (Some(key), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().replace(SinkKey(key));
+        }
+    }
+}
+
+impl<S> Layer<S> for McpLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        Self::remember_key(&Record::new(attrs.values()), id, &ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        Self::remember_key(values, id, &ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if ACTIVE.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let Some(key) = ctx.event_scope(event).and_then(|scope| {
+            scope
+                .into_iter()
+                .find_map(|span| span.extensions().get::<SinkKey>().map(|key| key.0))
+        }) else {
+            return;
+        };
+        let level = mcp_level(event.metadata().level());
+        let sinks = SINKS.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some((threshold, sender)) = sinks.get(&key) else {
+            return;
+        };
+        if severity(level) < severity(*threshold) {
+            return;
+        }
+        let mut fields = FieldsVisitor(Map::new());
+        event.record(&mut fields);
+        let message = LoggingMessageNotificationParam::new(level, Value::Object(fields.0))
+            .with_logger(event.metadata().target());
+        // The receiver is gone once the call has finished.
+        let _ = sender.send(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_events_reach_their_span_subscription() {
+        let subscriber = tracing_subscriber::registry().with(McpLogLayer);
+        let _default = tracing::subscriber::set_default(subscriber);
+        let (subscription, mut receiver) = subscribe(LoggingLevel::Info);
+        let span = tracing::info_span!("cel.request", log_sink = tracing::field::Empty);
+        span.record(SINK_FIELD, subscription.key());
+        span.in_scope(|| {
+            tracing::debug!("too detailed");
+            tracing::warn!(limit = 3, "slow evaluation");
+        });
+        tracing::error!("outside the call");
+        drop(subscription);
+
+        let message = receiver.try_recv().unwrap();
+        assert_eq!(message.level, LoggingLevel::Warning);
+        assert_eq!(message.data["message"], "slow evaluation");
+        assert_eq!(message.data["limit"], "3");
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,153 @@
+//! Response formats for the HTTP endpoints, negotiated from the `Accept` header: JSON, YAML,
+//! or plain text for scalar results.
+
+use rmcp::serde_json::{self, Value};
+
+pub const JSON: &str = "application/json";
+pub const YAML: &str = "application/yaml";
+pub const TEXT: &str = "text/plain; charset=utf-8";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+    /// A scalar written bare, e.g. `hello` or `42`, followed by a newline.
+    Text,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => JSON,
+            Format::Yaml => YAML,
+            Format::Text => TEXT,
+        }
+    }
+
+    /// The formats an `Accept` header allows, most preferred first; JSON when absent.
+    pub fn acceptable(accept: Option<&str>) -> Vec<Format> {
+        let Some(accept) = accept.filter(|accept| !accept.trim().is_empty()) else {
+            return vec![Format::Json];
+        };
+        let mut ranges: Vec<(f32, &[Format])> = accept
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let media = parts.next()?.to_ascii_lowercase();
+                let quality = parts
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+                let formats: &[Format] = match media.as_str() {
+                    "*/*" => &[Format::Json, Format::Yaml, Format::Text],
+                    "application/*" => &[Format::Json, Format::Yaml],
+                    "application/json" => &[Format::Json],
+                    "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => {
+                        &[Format::Yaml]
+                    }
+                    "text/*" | "text/plain" => &[Format::Text],
+                    _ => &[],
+                };
+                (quality > 0.0).then_some((quality, formats))
+            })
+            .collect();
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut formats = Vec::new();
+        for format in ranges.into_iter().flat_map(|(_, formats)| formats) {
+            if !formats.contains(format) {
+                formats.push(*format);
+            }
+        }
+        formats
+    }
+
+    /// Writes `value` in this format, or `None` if it cannot be, as for a list in plain text.
+    pub fn render(self, value: &Value) -> Option<String> {
+        match self {
+            Format::Json => serde_json::to_string(value).ok(),
+            Format::Yaml => Some(to_yaml(value)),
+            Format::Text => match value {
+                Value::Array(_) | Value::Object(_) => None,
+                Value::String(s) => Some(format!("{}\n", s)),
+                scalar => Some(format!("{}\n", scalar)),
+            },
+        }
+    }
+}
+
+/// `value` as a block-style YAML document. Strings and keys are double-quoted, with JSON's
+/// escapes, so none is read back as another type.
+pub fn to_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    write_yaml(value, 0, &mut out);
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+fn write_yaml(value: &Value, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            for item in items {
+                out.push_str(&pad);
+                out.push('-');
+                write_nested(item, indent + 2, out);
+            }
+        }
+        Value::Object(entries) if !entries.is_empty() => {
+            for (key, item) in entries {
+                out.push_str(&pad);
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_nested(item, indent + 2, out);
+            }
+        }
+        Value::Array(_) => out.push_str("[]"),
+        Value::Object(_) => out.push_str("{}"),
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// Writes a list item or map value after its `-` or `key:`.
+fn write_nested(value: &Value, indent: usize, out: &mut String) {
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            write_yaml(value, indent, out);
+        }
+        Value::Object(entries) if !entries.is_empty() => {
+            out.push('\n');
+            write_yaml(value, indent, out);
+        }
+        scalar => {
+            out.push(' ');
+            write_yaml(scalar, 0, out);
+            out.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_negotiate_and_render() {
+        assert_eq!(Format::acceptable(None), [Format::Json]);
+        assert_eq!(
+            Format::acceptable(Some("text/plain;q=0.5, application/yaml")),
+            [Format::Yaml, Format::Text]
+        );
+        assert_eq!(Format::acceptable(Some("image/png")), []);
+
+        let value = json!({"result": {"ids": [1, {"a": null}], "name": "x: y", "tags": []}});
+        assert_eq!(
+            to_yaml(&value),
+            "\"result\":\n  \"ids\":\n    - 1\n    -\n      \"a\": null\n  \"name\": \"x: y\"\n  \"tags\": []\n"
+        );
+        assert_eq!(Format::Text.render(&json!("hi")), Some("hi\n".to_string()));
+        assert_eq!(Format::Text.render(&json!([1])), None);
+    }
+}
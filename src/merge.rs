@@ -0,0 +1,149 @@
+//! How a request context is combined with the session context set with `set_context` and the
+//! default (base) context.
+
+use crate::profile;
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// A request variable replaces the default variable of the same name.
+    #[default]
+    Replace,
+    /// Objects are merged key by key, recursively; anything else (including `null`) replaces.
+    Deep,
+    /// JSON Merge Patch (RFC 7386): like `deep`, but a `null` removes the key, and a top-level
+    /// `null` unsets the variable.
+    MergePatch,
+}
+
+impl MergeStrategy {
+    /// Combines a default variable value with the request's value for the same variable.
+    pub fn apply(self, base: &Value, overlay: &Value) -> Value {
+        match (self, base, overlay) {
+            (MergeStrategy::Replace, _, _) => overlay.clone(),
+            (MergeStrategy::Deep, Value::Object(base), Value::Object(overlay)) => {
+                let mut merged = base.clone();
+                profile::deep_merge(&mut merged, overlay.clone());
+                Value::Object(merged)
+            }
+            (MergeStrategy::Deep, _, _) => overlay.clone(),
+            (MergeStrategy::MergePatch, _, _) => {
+                let mut merged = base.clone();
+                merge_patch(&mut merged, overlay);
+                merged
+            }
+        }
+    }
+
+    /// Combines the variables of `overlay` with those of `base`, as a request context is with
+    /// the session context. Under `merge_patch`, a `null` variable is kept, so that it also
+    /// unsets the variable in the layers below.
+    pub fn layer(
+        self,
+        base: &Map<String, Value>,
+        overlay: Map<String, Value>,
+    ) -> Map<String, Value> {
+        let mut layered = base.clone();
+        for (name, value) in overlay {
+            let value = match layered.get(&name) {
+                Some(base) if !(self == MergeStrategy::MergePatch && value.is_null()) => {
+                    self.apply(base, &value)
+                }
+                _ => value,
+            };
+            layered.insert(name, value);
+        }
+        layered
+    }
+}
+
+/// Applies `patch` to `target` as specified by RFC 7386.
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_layer() {
+        let session = json!({"user": {"name": "a", "role": "dev"}, "limit": 5});
+        let session = session.as_object().unwrap();
+        let request = |value: Value| value.as_object().cloned().unwrap();
+        assert_eq!(
+            Value::Object(
+                MergeStrategy::Replace.layer(session, request(json!({"user": {"role": "ops"}})))
+            ),
+            json!({"user": {"role": "ops"}, "limit": 5})
+        );
+        assert_eq!(
+            Value::Object(
+                MergeStrategy::Deep.layer(session, request(json!({"user": {"role": "ops"}})))
+            ),
+            json!({"user": {"name": "a", "role": "ops"}, "limit": 5})
+        );
+        assert_eq!(
+            Value::Object(MergeStrategy::MergePatch.layer(
+                session,
+                request(json!({"user": {"role": null}, "limit": null, "new": 1}))
+            )),
+            json!({"user": {"name": "a"}, "limit": null, "new": 1})
+        );
+    }
+
+    #[test]
+    fn test_strategies() {
+        let base = json!({"a": {"x": 1, "y": 2}, "b": [1, 2]});
+        let overlay = json!({"a": {"y": null, "z": 3}, "b": [3]});
+        assert_eq!(MergeStrategy::Replace.apply(&base, &overlay), overlay);
+        assert_eq!(
+            MergeStrategy::Deep.apply(&base, &overlay),
+            json!({"a": {"x": 1, "y": null, "z": 3}, "b": [3]})
+        );
+        assert_eq!(
+            MergeStrategy::MergePatch.apply(&base, &overlay),
+            json!({"a": {"x": 1, "z": 3}, "b": [3]})
+        );
+    }
+
+    #[test]
+    fn test_non_object_edges() {
+        let base = json!({"a": 1});
+        // A scalar replaces an object and vice versa, under every strategy.
+        for strategy in [
+            MergeStrategy::Replace,
+            MergeStrategy::Deep,
+            MergeStrategy::MergePatch,
+        ] {
+            assert_eq!(strategy.apply(&base, &json!(5)), json!(5));
+            assert_eq!(strategy.apply(&json!("s"), &base), base);
+        }
+        // Merge patch deletes nested keys even when they do not exist, and drops nested nulls
+        // from objects it creates.
+        assert_eq!(
+            MergeStrategy::MergePatch.apply(&json!("s"), &json!({"a": {"b": null, "c": 1}})),
+            json!({"a": {"c": 1}})
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Operator-defined wording for the error messages clients see, e.g. translated or branded.
+//!
+//! Errors are sorted into a few kinds by their message. A template for a kind replaces the
+//! message, with `{message}` standing for the original text and `{request_id}` for the request.
+
+use rmcp::serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The expression does not parse, compile or pass the profile's checks.
+    CompileError,
+    /// The expression failed while running.
+    ExecutionError,
+    /// The evaluation took longer than the server allows.
+    Timeout,
+    /// A size, cost or iteration limit was exceeded.
+    LimitExceeded,
+    /// A context variable does not match its declared type.
+    InvalidContext,
+}
+
+impl ErrorKind {
+    /// The kind of error `message` reports, if known.
+    pub fn of(message: &str) -> Option<Self> {
+        if message.contains("cost budget exceeded")
+            || message.contains("size limit exceeded")
+            || message.starts_with("Context too ")
+            || (message.starts_with("Expression is ") && message.contains("limit is"))
+        {
+            Some(ErrorKind::LimitExceeded)
+        } else if message.starts_with("CEL compile error")
+            || message.starts_with("CEL parse error")
+            || message.starts_with("Profile violation")
+        {
+            Some(ErrorKind::CompileError)
+        } else if message.starts_with("CEL execution error") {
+            Some(ErrorKind::ExecutionError)
+        } else if message.starts_with("Evaluation timed out") {
+            Some(ErrorKind::Timeout)
+        } else if message.contains("is declared") {
+            Some(ErrorKind::InvalidContext)
+        } else {
+            None
+        }
+    }
+}
+
+/// The `error_messages` section of the `--config` file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct ErrorMessagesConfig {
+    /// A JSON file of templates by kind, such as a translation.
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// Templates by kind, overriding those from `file`.
+    #[serde(default)]
+    pub templates: BTreeMap<ErrorKind, String>,
+}
+
+/// Message templates by error kind.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorMessages {
+    templates: BTreeMap<ErrorKind, String>,
+}
+
+impl ErrorMessages {
+    pub fn new(templates: BTreeMap<ErrorKind, String>) -> Self {
+        Self { templates }
+    }
+
+    /// Reads `config.file` and layers the inline templates over it.
+    pub fn load(config: &ErrorMessagesConfig) -> Result<Self, String> {
+        let mut templates = match &config.file {
+            Some(path) => {
+                let text = std::fs::read_to_string(path).map_err(|e| {
+                    format!("Failed to read error messages {}: {}", path.display(), e)
+                })?;
+                rmcp::serde_json::from_str(&text)
+                    .map_err(|e| format!("Invalid error messages in {}: {}", path.display(), e))?
+            }
+            None => BTreeMap::new(),
+        };
+        templates.extend(config.templates.clone());
+        Ok(Self::new(templates))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    /// `message` as worded by the template for its kind, or unchanged.
+    pub fn render(&self, message: &str, request_id: &str) -> String {
+        match ErrorKind::of(message).and_then(|kind| self.templates.get(&kind)) {
+            Some(template) => template
+                .replace("{message}", message)
+                .replace("{request_id}", request_id),
+            None => message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_by_kind() {
+        let messages = ErrorMessages::new(BTreeMap::from([
+            (
+                ErrorKind::Timeout,
+                "Die Auswertung dauerte zu lange ({request_id})".to_string(),
+            ),
+            (
+                ErrorKind::LimitExceeded,
+                "Policy too expensive: {message}".to_string(),
+            ),
+        ]));
+        assert_eq!(
+            messages.render("Evaluation timed out after 1s", "r-1"),
+            "Die Auswertung dauerte zu lange (r-1)"
+        );
+        assert_eq!(
+            messages.render(
+                "CEL execution error: cost budget exceeded: evaluation cost is over 5",
+                "r-1"
+            ),
+            "Policy too expensive: CEL execution error: cost budget exceeded: evaluation cost is over 5"
+        );
+        assert_eq!(
+            messages.render("CEL compile error: oops", "r-1"),
+            "CEL compile error: oops"
+        );
+    }
+}
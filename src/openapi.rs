@@ -0,0 +1,115 @@
+//! The OpenAPI 3.1 document for the HTTP endpoints, served at `/openapi.json`. Request and
+//! response schemas come from the same schemars types the handlers use.
+
+use crate::media;
+use crate::rest::{ErrorResponse, EvaluateRequest, EvaluateResponse};
+use axum::routing::get;
+use axum::{Json, Router};
+use rmcp::schemars::JsonSchema;
+use rmcp::schemars::generate::{SchemaGenerator, SchemaSettings};
+use rmcp::serde_json::{Value, json};
+
+/// Routes `GET /openapi.json`; `admission` tells whether `/admission` is served.
+pub fn router(admission: bool) -> Router {
+    let document = document(admission);
+    Router::new().route("/openapi.json", get(move || async move { Json(document) }))
+}
+
+/// Builds the document.
+pub fn document(admission: bool) -> Value {
+    let mut settings = SchemaSettings::draft2020_12();
+    settings.definitions_path = "/components/schemas".into();
+    settings.meta_schema = None;
+    let mut generator = SchemaGenerator::new(settings);
+    let request = subschema::<EvaluateRequest>(&mut generator);
+    let response = subschema::<EvaluateResponse>(&mut generator);
+    let error = subschema::<ErrorResponse>(&mut generator);
+    let json_content = |schema: &Value| json!({ "application/json": { "schema": schema } });
+    let negotiated = |schema: &Value| {
+        json!({
+            media::JSON: { "schema": schema },
+            media::YAML: { "schema": schema },
+            media::TEXT: { "schema": { "type": "string" } },
+        })
+    };
+
+    let mut paths = json!({
+        "/v1/evaluate": {
+            "post": {
+                "operationId": "evaluate",
+                "summary": "Evaluates a CEL expression.",
+                "parameters": [{
+                    "name": crate::cel_tool::REQUEST_ID_HEADER,
+                    "in": "header",
+                    "required": false,
+                    "schema": { "type": "string", "maxLength": 128 },
+                }],
+                "requestBody": { "required": true, "content": json_content(&request) },
+                "responses": {
+                    "200": {
+                        "description": "The expression's value; as plain text, the bare value of a scalar.",
+                        "content": negotiated(&response),
+                    },
+                    "400": { "description": "The request was rejected.", "content": negotiated(&error) },
+                    "406": { "description": "No format allowed by `Accept` can hold the result.", "content": json_content(&error) },
+                    "429": { "description": "The caller's quota is used up.", "content": negotiated(&error) },
+                    "500": { "description": "Evaluation failed.", "content": negotiated(&error) },
+                },
+            }
+        }
+    });
+    if admission {
+        let review = json!({
+            "type": "object",
+            "description": "A Kubernetes admission.k8s.io/v1 AdmissionReview.",
+            "properties": {
+                "apiVersion": { "type": "string" },
+                "kind": { "type": "string", "const": "AdmissionReview" },
+                "request": { "type": "object" },
+                "response": { "type": "object" },
+            },
+        });
+        paths["/admission"] = json!({
+            "post": {
+                "operationId": "admission",
+                "summary": "Validates a Kubernetes object as an admission webhook.",
+                "requestBody": { "required": true, "content": json_content(&review) },
+                "responses": {
+                    "200": { "description": "The review with its response.", "content": json_content(&review) },
+                },
+            }
+        });
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": { "schemas": generator.take_definitions(true) },
+    })
+}
+
+fn subschema<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    generator.subschema_for::<T>().to_value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_references_components() {
+        let document = document(false);
+        let request = &document["paths"]["/v1/evaluate"]["post"]["requestBody"]["content"]["application/json"]
+            ["schema"];
+        assert_eq!(request["$ref"], "#/components/schemas/EvaluateRequest");
+        let schemas = &document["components"]["schemas"];
+        assert!(schemas["EvaluateRequest"]["properties"]["expression"].is_object());
+        assert!(schemas["MergeStrategy"].is_object());
+        assert!(document["paths"]["/admission"].is_null());
+        assert!(super::document(true)["paths"]["/admission"].is_object());
+    }
+}
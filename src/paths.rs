@@ -0,0 +1,123 @@
+//! `path.match(glob, p)` and `path.clean(p)`, for policies over file paths such as the
+//! paths a change touches.
+//!
+//! Paths are `/`-separated strings, not paths on the server's file system. In globs, `*`
+//! and `?` stay within one path component, `**` spans any number of them, and `[ab]` and
+//! `{a,b}` match alternatives.
+
+use crate::provider::{Method, Namespace};
+use cel::{FunctionContext, Value as CelValue};
+use globset::GlobBuilder;
+use std::sync::Arc;
+
+/// The namespace the functions are called on.
+pub const VARIABLE: &str = "path";
+
+pub(crate) fn names() -> Vec<String> {
+    vec![format!("{}.match", VARIABLE), format!("{}.clean", VARIABLE)]
+}
+
+pub(crate) fn namespace() -> Namespace {
+    let matches: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::String(glob), CelValue::String(path)] = args else {
+            return Err(ftx.error("path.match expects a glob and a path"));
+        };
+        let matcher = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| ftx.error(format!("Invalid glob `{}`: {}", glob, e)))?
+            .compile_matcher();
+        Ok(CelValue::Bool(matcher.is_match(path.as_str())))
+    });
+    let clean_path: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::String(path)] = args else {
+            return Err(ftx.error("path.clean expects one string"));
+        };
+        Ok(CelValue::from(clean(path)))
+    });
+    Namespace {
+        name: VARIABLE.to_string(),
+        methods: vec![
+            ("match".to_string(), matches),
+            ("clean".to_string(), clean_path),
+        ],
+    }
+}
+
+/// The shortest path naming the same file as `path`, by lexical processing only: repeated
+/// and trailing slashes and `.` components go, and `..` removes the component before it.
+/// `..` can't climb above the root, and an empty result is `.`.
+fn clean(path: &str) -> String {
+    let rooted = path.starts_with('/');
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if components.last().is_some_and(|last| *last != "..") {
+                    components.pop();
+                } else if !rooted {
+                    components.push("..");
+                }
+            }
+            _ => components.push(component),
+        }
+    }
+    let joined = components.join("/");
+    if rooted {
+        format!("/{}", joined)
+    } else if joined.is_empty() {
+        ".".to_string()
+    } else {
+        joined
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::library::Library;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_clean() {
+        for (path, cleaned) in [
+            ("", "."),
+            ("./", "."),
+            ("src//lib.rs", "src/lib.rs"),
+            ("src/./bin/../lib.rs", "src/lib.rs"),
+            ("../a/../../b/", "../../b"),
+            ("/../etc/passwd", "/etc/passwd"),
+            ("/", "/"),
+        ] {
+            assert_eq!(clean(path), cleaned, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_path_functions() {
+        let evaluator = Evaluator::new().with_function_provider(Library::Path);
+        let evaluate = |expression: &str| {
+            evaluator.evaluate(
+                expression,
+                &json!({"changed": ["src/cel_tool.rs", "docs/README.md", "src/bin/cel-mcp.rs"]}),
+                Profile::Default,
+            )
+        };
+        assert_eq!(
+            evaluate("changed.filter(p, path.match('src/**/*.rs', p))"),
+            Ok(json!(["src/cel_tool.rs", "src/bin/cel-mcp.rs"]))
+        );
+        assert_eq!(
+            evaluate("changed.filter(p, path.match('src/*.rs', p))"),
+            Ok(json!(["src/cel_tool.rs"]))
+        );
+        assert_eq!(
+            evaluate("path.match('docs/*.{md,txt}', path.clean('docs/../docs/README.md'))"),
+            Ok(json!(true))
+        );
+        assert!(evaluate("path.match('src/[', 'src/a')").is_err());
+    }
+}
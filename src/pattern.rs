@@ -0,0 +1,126 @@
+//! Regular expressions for CEL's `matches()`, compiled under a size limit and cached.
+//!
+//! The `regex` crate matches in linear time, so the risk is in compilation: a pattern such as
+//! `(\w{100}){100}` builds a huge automaton. Such patterns fail with an error instead. Literal
+//! patterns are checked when an expression is compiled, before anything is evaluated.
+
+use crate::ast;
+use cel::common::ast::Expr;
+use cel::common::value::CelVal;
+use cel::extractors::This;
+use cel::{Context, ExecutionError, FunctionContext};
+use regex::{Regex, RegexBuilder};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// How many compiled patterns a [`RegexCache`] keeps.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Compiled patterns, evicting the oldest when full.
+pub struct RegexCache {
+    size_limit: Option<usize>,
+    compiled: Mutex<Compiled>,
+}
+
+#[derive(Default)]
+struct Compiled {
+    regexes: HashMap<String, Arc<Regex>>,
+    order: VecDeque<String>,
+}
+
+impl RegexCache {
+    /// `size_limit` bounds each compiled pattern and its lazy DFA, in bytes; `None` keeps the
+    /// `regex` crate's defaults.
+    pub fn new(size_limit: Option<usize>) -> Self {
+        Self {
+            size_limit,
+            compiled: Mutex::default(),
+        }
+    }
+
+    pub fn compile(&self, pattern: &str) -> Result<Arc<Regex>, String> {
+        if let Ok(compiled) = self.compiled.lock()
+            && let Some(regex) = compiled.regexes.get(pattern)
+        {
+            return Ok(regex.clone());
+        }
+        let mut builder = RegexBuilder::new(pattern);
+        if let Some(limit) = self.size_limit {
+            builder.size_limit(limit).dfa_size_limit(limit);
+        }
+        let regex = Arc::new(
+            builder
+                .build()
+                .map_err(|e| format!("'{}' not a valid regex:\n{}", pattern, e))?,
+        );
+        if let Ok(mut compiled) = self.compiled.lock() {
+            while compiled.order.len() >= DEFAULT_CAPACITY {
+                if let Some(oldest) = compiled.order.pop_front() {
+                    compiled.regexes.remove(&oldest);
+                }
+            }
+            if compiled
+                .regexes
+                .insert(pattern.to_string(), regex.clone())
+                .is_none()
+            {
+                compiled.order.push_back(pattern.to_string());
+            }
+        }
+        Ok(regex)
+    }
+
+    /// Compiles the string literals passed as patterns to `matches` in `expression`.
+    pub(crate) fn check_literals(&self, expression: &str) -> Result<(), String> {
+        let Ok(parsed) = ast::parse(expression) else {
+            return Ok(());
+        };
+        let mut result = Ok(());
+        ast::walk(&parsed, &mut |node| {
+            if let Expr::Call(call) = &node.expr
+                && call.func_name == "matches"
+                && result.is_ok()
+                && let Some(pattern) = call.args.last()
+                && let Expr::Literal(CelVal::String(pattern)) = &pattern.expr
+            {
+                result = self.compile(pattern).map(drop);
+            }
+        });
+        result
+    }
+
+    /// Replaces the built-in `matches` with one compiling through this cache.
+    pub(crate) fn register(self: &Arc<Self>, ctx: &mut Context) {
+        let cache = self.clone();
+        ctx.add_function(
+            "matches",
+            move |ftx: &FunctionContext,
+                  This(this): This<Arc<String>>,
+                  pattern: Arc<String>|
+                  -> Result<bool, ExecutionError> {
+                let regex = cache.compile(&pattern).map_err(|e| ftx.error(e))?;
+                Ok(regex.is_match(&this))
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_limit_rejects_huge_patterns() {
+        let cache = RegexCache::new(Some(1 << 16));
+        let first = cache.compile("^[a-z]+$").unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.compile("^[a-z]+$").unwrap()));
+        let err = cache.compile(r"(\w{100}){100}").err().unwrap();
+        assert!(err.contains("exceeds size limit"), "{}", err);
+        assert!(
+            cache
+                .check_literals(r"'a'.matches('(\\w{100}){100}')")
+                .is_err()
+        );
+        assert!(cache.check_literals("'a'.matches(p)").is_ok());
+    }
+}
@@ -0,0 +1,100 @@
+//! `cel-mcp pipe`: evaluates newline-delimited JSON from a reader and writes one JSON result
+//! line per input line, for shell pipelines.
+//!
+//! Each input line is `{"expression": ..., "context": {...}}`, or, when a fixed expression is
+//! given, just the context object. Each output line is `{"result": value}` or
+//! `{"error": message}`. Blank lines are skipped.
+
+use crate::cel_tool::{CelTool, client_request_id};
+use crate::evaluator::Query;
+use crate::merge::MergeStrategy;
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{self, Map, Value, json};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+#[derive(Deserialize)]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+struct PipeRequest {
+    expression: String,
+    #[serde(default)]
+    context: Map<String, Value>,
+}
+
+/// Processes `input` until end of file; returns how many lines failed.
+pub async fn run(
+    tool: &CelTool,
+    expression: Option<&str>,
+    input: impl AsyncBufRead + Unpin,
+    mut output: impl AsyncWrite + Unpin,
+) -> std::io::Result<usize> {
+    let mut lines = input.lines();
+    let mut failed = 0;
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let outcome = match parse(&line, expression) {
+            Ok((expression, context)) => tool
+                .query(
+                    "pipe",
+                    &client_request_id(None),
+                    Query::Expression(expression),
+                    context,
+                    MergeStrategy::Replace,
+                )
+                .await
+                .map_err(|e| e.message.to_string()),
+            Err(e) => Err(e),
+        };
+        let record = match outcome {
+            Ok(result) => json!({ "result": result }),
+            Err(e) => {
+                failed += 1;
+                json!({ "error": e })
+            }
+        };
+        output.write_all(format!("{}\n", record).as_bytes()).await?;
+    }
+    output.flush().await?;
+    Ok(failed)
+}
+
+fn parse(line: &str, expression: Option<&str>) -> Result<(String, Map<String, Value>), String> {
+    match expression {
+        Some(expression) => match serde_json::from_str(line) {
+            Ok(Value::Object(context)) => Ok((expression.to_string(), context)),
+            Ok(_) => Err("Invalid input: expected a JSON object".to_string()),
+            Err(e) => Err(format!("Invalid input: {}", e)),
+        },
+        None => serde_json::from_str::<PipeRequest>(line)
+            .map(|request| (request.expression, request.context))
+            .map_err(|e| format!("Invalid input: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pipe_lines() {
+        let tool = CelTool::builder().build();
+        let input = b"{\"n\": \"a\"}\n\n[1]\n{\"n\": \"b\"}\n";
+        let mut output = Vec::new();
+        let failed = run(&tool, Some("n + '!'"), &input[..], &mut output)
+            .await
+            .unwrap();
+        assert_eq!(failed, 1);
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], r#"{"result":"a!"}"#);
+        assert!(lines[1].starts_with(r#"{"error":"Invalid input"#));
+        assert_eq!(lines[2], r#"{"result":"b!"}"#);
+
+        let input = br#"{"expression": "x == 'y'", "context": {"x": "y"}}"#;
+        let mut output = Vec::new();
+        run(&tool, None, &input[..], &mut output).await.unwrap();
+        assert_eq!(output, b"{\"result\":true}\n");
+    }
+}
@@ -0,0 +1,204 @@
+//! Named policy sets for the `decide` tool: ordered rules, each a CEL condition with an effect
+//! and an optional reason template, combined into one decision by the set's [`Combining`]
+//! strategy.
+
+use crate::template;
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum Effect {
+    Allow,
+    #[default]
+    Deny,
+}
+
+/// How the effects of matching rules combine into a decision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum Combining {
+    /// The first matching rule decides; later rules are not evaluated.
+    #[default]
+    FirstMatch,
+    /// Any matching `deny` rule denies; otherwise any matching `allow` rule allows.
+    DenyOverrides,
+    /// Any matching `allow` rule allows; otherwise any matching `deny` rule denies.
+    AllowOverrides,
+    /// Like `deny_overrides`, but every matching rule is reported.
+    CollectAll,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct PolicyRule {
+    /// Identifies the rule in decisions; unique within its policy set.
+    pub name: String,
+    /// A CEL expression returning a bool.
+    pub condition: String,
+    pub effect: Effect,
+    /// Reason template with `${expression}` placeholders, rendered against the same context.
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct PolicySet {
+    pub rules: Vec<PolicyRule>,
+    #[serde(default)]
+    pub combining: Combining,
+    /// The effect when no rule matches.
+    #[serde(default)]
+    pub default_effect: Effect,
+    /// A candidate version evaluated alongside this one; its decision is never returned, but
+    /// disagreements are logged and audited.
+    #[serde(default)]
+    pub shadow: Option<Box<PolicySet>>,
+}
+
+impl PolicySet {
+    /// Checks that rule names are unique and message templates parse, here and in the shadow.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for rule in &self.rules {
+            if !names.insert(rule.name.as_str()) {
+                return Err(format!("Duplicate rule name `{}`", rule.name));
+            }
+            if let Some(message) = &rule.message {
+                template::parse(message).map_err(|e| format!("{} (rule `{}`)", e, rule.name))?;
+            }
+        }
+        match &self.shadow {
+            Some(shadow) => shadow.validate().map_err(|e| format!("{} (shadow)", e)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn rule(&self, name: &str) -> Option<&PolicyRule> {
+        self.rules.iter().find(|rule| rule.name == name)
+    }
+
+    /// Combines the names of the matching rules, in rule order, into a decision: its effect and
+    /// the rules to report (the deciding rule first). No rules means the default effect applied.
+    pub fn combine(&self, matched: &[&str]) -> (Effect, Vec<&PolicyRule>) {
+        let matched: Vec<&PolicyRule> = matched.iter().filter_map(|name| self.rule(name)).collect();
+        let first = |effect: Effect| matched.iter().copied().find(|rule| rule.effect == effect);
+        let deciding = match self.combining {
+            Combining::FirstMatch => matched.first().copied(),
+            Combining::DenyOverrides | Combining::CollectAll => {
+                first(Effect::Deny).or_else(|| first(Effect::Allow))
+            }
+            Combining::AllowOverrides => first(Effect::Allow).or_else(|| first(Effect::Deny)),
+        };
+        let Some(deciding) = deciding else {
+            return (self.default_effect, Vec::new());
+        };
+        let mut reported = vec![deciding];
+        if self.combining == Combining::CollectAll {
+            reported.extend(matched.iter().filter(|rule| rule.name != deciding.name));
+        }
+        (deciding.effect, reported)
+    }
+
+    /// Every condition here and in the shadow, labelled with its rule for error messages.
+    pub fn labelled_conditions(&self) -> Vec<(String, String)> {
+        let mut conditions: Vec<(String, String)> = self
+            .rules
+            .iter()
+            .map(|rule| (format!("rule `{}`", rule.name), rule.condition.clone()))
+            .collect();
+        if let Some(shadow) = &self.shadow {
+            conditions.extend(
+                shadow
+                    .labelled_conditions()
+                    .into_iter()
+                    .map(|(label, condition)| (format!("shadow {}", label), condition)),
+            );
+        }
+        conditions
+    }
+
+    /// The `(name, condition)` pairs, in order.
+    pub fn conditions(&self) -> Vec<(String, String)> {
+        self.rules
+            .iter()
+            .map(|rule| (rule.name.clone(), rule.condition.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_policy_set() {
+        let set: PolicySet = rmcp::serde_json::from_str(
+            r#"{"rules": [
+                {"name": "admin", "condition": "'admin' in user.roles", "effect": "allow"},
+                {"name": "admin", "condition": "true", "effect": "deny", "message": "no"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(set.default_effect, Effect::Deny);
+        assert_eq!(
+            set.validate(),
+            Err("Duplicate rule name `admin`".to_string())
+        );
+
+        let set = PolicySet {
+            rules: vec![PolicyRule {
+                name: "r".to_string(),
+                condition: "true".to_string(),
+                effect: Effect::Deny,
+                message: Some("${".to_string()),
+            }],
+            combining: Combining::FirstMatch,
+            default_effect: Effect::Allow,
+            shadow: None,
+        };
+        assert!(set.validate().unwrap_err().ends_with("(rule `r`)"));
+    }
+
+    #[test]
+    fn test_combining_strategies() {
+        let rule = |name: &str, effect| PolicyRule {
+            name: name.to_string(),
+            condition: "true".to_string(),
+            effect,
+            message: None,
+        };
+        let mut set = PolicySet {
+            rules: vec![
+                rule("a", Effect::Allow),
+                rule("d", Effect::Deny),
+                rule("a2", Effect::Allow),
+            ],
+            ..PolicySet::default()
+        };
+        let decide = |set: &PolicySet, matched: &[&str]| {
+            let (effect, rules) = set.combine(matched);
+            let names: Vec<&str> = rules.iter().map(|rule| rule.name.as_str()).collect();
+            (effect, names.join(","))
+        };
+        let all = ["a", "d", "a2"];
+        assert_eq!(decide(&set, &all), (Effect::Allow, "a".to_string()));
+        assert_eq!(decide(&set, &[]), (Effect::Deny, String::new()));
+        set.combining = Combining::DenyOverrides;
+        assert_eq!(decide(&set, &all), (Effect::Deny, "d".to_string()));
+        assert_eq!(decide(&set, &["a2"]), (Effect::Allow, "a2".to_string()));
+        set.combining = Combining::AllowOverrides;
+        assert_eq!(
+            decide(&set, &["d", "a2"]),
+            (Effect::Allow, "a2".to_string())
+        );
+        set.combining = Combining::CollectAll;
+        assert_eq!(decide(&set, &all), (Effect::Deny, "d,a,a2".to_string()));
+    }
+}
@@ -0,0 +1,114 @@
+pub mod envoy;
+pub mod firestore;
+pub mod iam;
+
+use cel::Context;
+use cel::Value as CelValue;
+use cel::objects::{Key, Map as CelMap};
+use chrono::DateTime;
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{Map, Value};
+use std::sync::Arc;
+
+/// A compatibility profile selecting which host-specific variables are pre-declared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum Profile {
+    /// Plain CEL: only the variables supplied in the request context exist.
+    #[default]
+    Default,
+    /// Envoy RBAC / ext_authz attributes (`request.*`, `source.*`, `destination.*`, ...).
+    Envoy,
+    /// Google Cloud IAM Conditions attributes (`request.time`, `resource.name`, ...),
+    /// with expressions checked against IAM's restrictions before evaluation.
+    Iam,
+    /// Firebase security-rules variables (`request.auth`, `resource.data`, ...) plus
+    /// `get()`/`exists()` simulated against caller-provided documents.
+    Firestore,
+}
+
+impl Profile {
+    /// Returns the variables pre-declared by this profile, with their empty defaults.
+    pub fn declared_variables(self) -> Map<String, Value> {
+        match self {
+            Profile::Default => Map::new(),
+            Profile::Envoy => envoy::declared_attributes(),
+            Profile::Iam => iam::declared_attributes(),
+            Profile::Firestore => firestore::declared_variables(),
+        }
+    }
+
+    /// Names of the functions [`register_functions`](Self::register_functions) adds.
+    pub fn function_names(self) -> &'static [&'static str] {
+        match self {
+            Profile::Firestore => &["exists", "get"],
+            Profile::Default | Profile::Envoy | Profile::Iam => &[],
+        }
+    }
+
+    /// The context variable `name` with the attributes the profile types beyond JSON, such as
+    /// `request.time`, converted.
+    pub(crate) fn type_attributes(self, name: &str, value: CelValue) -> CelValue {
+        match self {
+            Profile::Iam | Profile::Envoy | Profile::Firestore => request_time(name, value),
+            Profile::Default => value,
+        }
+    }
+
+    /// Registers the profile's extra functions on an evaluation context.
+    pub(crate) fn register_functions(self, ctx: &mut Context) {
+        if self == Profile::Firestore {
+            firestore::register_functions(ctx);
+        }
+    }
+
+    /// Lists the reasons `expression` is not acceptable under this profile.
+    pub fn violations(self, expression: &str) -> Vec<String> {
+        match self {
+            Profile::Default | Profile::Envoy | Profile::Firestore => Vec::new(),
+            Profile::Iam => iam::validate(expression),
+        }
+    }
+
+    /// Layers the request context over the profile's pre-declared variables.
+    pub fn seed_context(self, context: Map<String, Value>) -> Map<String, Value> {
+        let mut seeded = self.declared_variables();
+        deep_merge(&mut seeded, context);
+        seeded
+    }
+}
+
+/// The context variable `name` with `request.time` as a timestamp; JSON contexts carry it as
+/// an RFC 3339 string.
+fn request_time(name: &str, value: CelValue) -> CelValue {
+    if name != "request" {
+        return value;
+    }
+    let CelValue::Map(request) = value else {
+        return value;
+    };
+    let time = match request.map.get(&Key::from("time")) {
+        Some(CelValue::String(time)) => DateTime::parse_from_rfc3339(time).ok(),
+        _ => None,
+    };
+    let Some(time) = time else {
+        return CelValue::Map(request);
+    };
+    let mut map = (*request.map).clone();
+    map.insert(Key::from("time"), CelValue::Timestamp(time));
+    CelValue::Map(CelMap { map: Arc::new(map) })
+}
+
+/// Recursively merges `overlay` into `base`; objects are merged key by key, anything else replaces.
+pub(crate) fn deep_merge(base: &mut Map<String, Value>, overlay: Map<String, Value>) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Object(existing)), Value::Object(incoming)) => {
+                deep_merge(existing, incoming);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Envoy RBAC / ext_authz attribute helpers.
+//!
+//! Attribute names follow <https://www.envoyproxy.io/docs/envoy/latest/intro/arch_overview/advanced/attributes>.
+//! `request.time` is a timestamp, the time of the request unless the context or check request
+//! passes another as an RFC 3339 string.
+
+use chrono::{SecondsFormat, Utc};
+use rmcp::serde_json::{Map, Value, json};
+
+/// The attribute tree every Envoy-profile evaluation starts from.
+pub fn declared_attributes() -> Map<String, Value> {
+    let attributes = json!({
+        "request": {
+            "path": "",
+            "url_path": "",
+            "host": "",
+            "scheme": "",
+            "method": "",
+            "headers": {},
+            "referer": "",
+            "useragent": "",
+            "time": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "id": "",
+            "protocol": "",
+            "query": "",
+            "size": 0,
+        },
+        "source": {
+            "address": "",
+            "port": 0,
+            "principal": "",
+        },
+        "destination": {
+            "address": "",
+            "port": 0,
+            "principal": "",
+        },
+        "connection": {
+            "requested_server_name": "",
+        },
+        "metadata": {},
+        "context_extensions": {},
+    });
+    match attributes {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Converts an ext_authz `CheckRequest` (proto3 JSON form) into Envoy attributes.
+///
+/// Only the attributes present in the request are returned; callers layer the result
+/// over [`declared_attributes`] so absent ones keep their defaults.
+pub fn from_check_request(check_request: &Value) -> Result<Map<String, Value>, String> {
+    let attributes = check_request
+        .get("attributes")
+        .and_then(Value::as_object)
+        .ok_or_else(|| "Envoy check request error: missing `attributes` object".to_string())?;
+
+    let mut out = Map::new();
+
+    if let Some(request) = attributes.get("request") {
+        let mut req = Map::new();
+        if let Some(time) = request.get("time").and_then(Value::as_str) {
+            req.insert("time".into(), time.into());
+        }
+        if let Some(http) = request.get("http").and_then(Value::as_object) {
+            for (attr, field) in [
+                ("id", "id"),
+                ("method", "method"),
+                ("host", "host"),
+                ("scheme", "scheme"),
+                ("protocol", "protocol"),
+                ("query", "query"),
+            ] {
+                if let Some(v) = http.get(field).and_then(Value::as_str) {
+                    req.insert(attr.into(), v.into());
+                }
+            }
+            if let Some(path) = http.get("path").and_then(Value::as_str) {
+                let url_path = path.split('?').next().unwrap_or(path);
+                req.insert("path".into(), path.into());
+                req.insert("url_path".into(), url_path.into());
+            }
+            if let Some(size) = http.get("size").and_then(size_value) {
+                req.insert("size".into(), size.into());
+            }
+            if let Some(headers) = http.get("headers").and_then(Value::as_object) {
+                let headers: Map<String, Value> = headers
+                    .iter()
+                    .map(|(k, v)| (k.to_ascii_lowercase(), v.clone()))
+                    .collect();
+                for (attr, header) in [("referer", "referer"), ("useragent", "user-agent")] {
+                    if let Some(v) = headers.get(header) {
+                        req.insert(attr.into(), v.clone());
+                    }
+                }
+                req.insert("headers".into(), Value::Object(headers));
+            }
+        }
+        out.insert("request".into(), Value::Object(req));
+    }
+
+    for peer in ["source", "destination"] {
+        if let Some(p) = attributes.get(peer) {
+            out.insert(peer.into(), Value::Object(peer_attributes(p)));
+        }
+    }
+
+    if let Some(sni) = attributes
+        .get("tlsSession")
+        .and_then(|t| t.get("sni"))
+        .and_then(Value::as_str)
+    {
+        out.insert("connection".into(), json!({ "requested_server_name": sni }));
+    }
+
+    if let Some(metadata) = attributes.get("metadataContext") {
+        out.insert("metadata".into(), metadata.clone());
+    }
+    if let Some(extensions) = attributes.get("contextExtensions") {
+        out.insert("context_extensions".into(), extensions.clone());
+    }
+
+    Ok(out)
+}
+
+fn peer_attributes(peer: &Value) -> Map<String, Value> {
+    let mut out = Map::new();
+    if let Some(socket) = peer.get("address").and_then(|a| a.get("socketAddress")) {
+        if let Some(address) = socket.get("address").and_then(Value::as_str) {
+            out.insert("address".into(), address.into());
+        }
+        if let Some(port) = socket.get("portValue").and_then(size_value) {
+            out.insert("port".into(), port.into());
+        }
+    }
+    if let Some(principal) = peer.get("principal").and_then(Value::as_str) {
+        out.insert("principal".into(), principal.into());
+    }
+    out
+}
+
+/// proto3 JSON encodes 64-bit integers as strings, 32-bit ones as numbers.
+fn size_value(v: &Value) -> Option<i64> {
+    match v {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_check_request_http() {
+        let check_request = json!({
+            "attributes": {
+                "source": {
+                    "address": { "socketAddress": { "address": "10.0.0.1", "portValue": 5000 } },
+                    "principal": "spiffe://cluster.local/ns/default/sa/client"
+                },
+                "request": {
+                    "time": "2024-01-01T00:00:00Z",
+                    "http": {
+                        "method": "GET",
+                        "path": "/api/items?limit=10",
+                        "host": "example.com",
+                        "headers": { "User-Agent": "curl/8.0", ":authority": "example.com" },
+                        "size": "42"
+                    }
+                }
+            }
+        });
+        let attrs = from_check_request(&check_request).unwrap();
+        assert_eq!(attrs["request"]["url_path"], json!("/api/items"));
+        assert_eq!(attrs["request"]["useragent"], json!("curl/8.0"));
+        assert_eq!(attrs["request"]["size"], json!(42));
+        assert_eq!(attrs["source"]["port"], json!(5000));
+        assert!(!attrs.contains_key("destination"));
+    }
+
+    #[test]
+    fn test_request_time_is_a_timestamp() {
+        use crate::evaluator::Evaluator;
+        use crate::profile::Profile;
+
+        let evaluator = Evaluator::new();
+        let evaluate = |expression: &str, context: Map<String, Value>| {
+            evaluator.evaluate(
+                expression,
+                &Value::Object(Profile::Envoy.seed_context(context)),
+                Profile::Envoy,
+            )
+        };
+        assert_eq!(
+            evaluate(
+                "request.time > timestamp('2025-01-01T00:00:00Z') && request.time < timestamp('2999-01-01T00:00:00Z')",
+                Map::new()
+            ),
+            Ok(json!(true))
+        );
+        let check_request = json!({
+            "attributes": {"request": {"time": "2024-01-01T00:00:00Z"}}
+        });
+        let attrs = from_check_request(&check_request).unwrap();
+        assert_eq!(
+            evaluate(
+                "request.time == timestamp('2024-01-01T00:00:00Z') && request.time.getFullYear() == 2024",
+                attrs
+            ),
+            Ok(json!(true))
+        );
+    }
+
+    #[test]
+    fn test_from_check_request_missing_attributes() {
+        let response = from_check_request(&json!({}));
+        assert!(response.unwrap_err().contains("attributes"));
+    }
+}
@@ -0,0 +1,130 @@
+//! Firestore / Firebase security-rules-style helpers.
+//!
+//! `get(path)` and `exists(path)` are simulated against a caller-provided document map
+//! keyed by document path (`users/alice`), so rule logic can be prototyped without a
+//! database. Full paths such as `/databases/(default)/documents/users/alice` are accepted too.
+//! `request.time` is a timestamp, the time of the request unless the context passes another
+//! as an RFC 3339 string.
+
+use cel::objects::Key;
+use cel::{Context, ExecutionError, FunctionContext, Value as CelValue};
+use chrono::{SecondsFormat, Utc};
+use rmcp::serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Context variable holding the simulated documents; not meant to be referenced directly.
+pub const DOCUMENTS_VARIABLE: &str = "__firestore_documents__";
+
+/// The variables every Firestore-profile evaluation starts from.
+pub fn declared_variables() -> Map<String, Value> {
+    let variables = json!({
+        "request": {
+            "auth": null,
+            "method": "",
+            "path": "",
+            "time": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "resource": { "data": {} },
+        },
+        "resource": {
+            "id": "",
+            "data": {},
+        },
+        DOCUMENTS_VARIABLE: {},
+    });
+    match variables {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Registers `get()` and `exists()` on the evaluation context.
+pub(crate) fn register_functions(ctx: &mut Context) {
+    ctx.add_function("get", get);
+    ctx.add_function("exists", exists);
+}
+
+/// Strips `/databases/<db>/documents/` and surrounding slashes from a document path.
+fn normalize_path(path: &str) -> &str {
+    let trimmed = path.trim_matches('/');
+    match trimmed.strip_prefix("databases/") {
+        Some(rest) => match rest.split_once("/documents/") {
+            Some((_, doc)) => doc.trim_matches('/'),
+            None => trimmed,
+        },
+        None => trimmed,
+    }
+}
+
+fn lookup(ftx: &FunctionContext, path: &str) -> Result<Option<CelValue>, ExecutionError> {
+    let documents = match ftx.ptx.get_variable(DOCUMENTS_VARIABLE)? {
+        CelValue::Map(map) => map,
+        _ => return Err(ftx.error("simulated documents must be a map")),
+    };
+    let key = Key::String(Arc::new(normalize_path(path).to_string()));
+    Ok(documents.get(&key).cloned())
+}
+
+/// `get(path)` returns `{id, data}` for a simulated document, failing like Firestore when absent.
+fn get(ftx: &FunctionContext, path: Arc<String>) -> Result<CelValue, ExecutionError> {
+    let data =
+        lookup(ftx, &path)?.ok_or_else(|| ftx.error(format!("document not found: {}", path)))?;
+    let id = normalize_path(&path).rsplit('/').next().unwrap_or_default();
+    let resource = HashMap::from([
+        (Key::from("id"), CelValue::from(id)),
+        (Key::from("data"), data),
+    ]);
+    Ok(resource.into())
+}
+
+/// `exists(path)` reports whether a simulated document exists.
+fn exists(ftx: &FunctionContext, path: Arc<String>) -> Result<bool, ExecutionError> {
+    Ok(lookup(ftx, &path)?.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path("/databases/(default)/documents/users/alice"),
+            "users/alice"
+        );
+        assert_eq!(normalize_path("users/alice/"), "users/alice");
+    }
+
+    #[test]
+    fn test_request_time_is_a_timestamp() {
+        use crate::evaluator::Evaluator;
+        use crate::profile::Profile;
+
+        let evaluator = Evaluator::new();
+        let evaluate = |expression: &str, context: Value| {
+            let context = match context {
+                Value::Object(context) => context,
+                _ => Map::new(),
+            };
+            evaluator.evaluate(
+                expression,
+                &Value::Object(Profile::Firestore.seed_context(context)),
+                Profile::Firestore,
+            )
+        };
+        assert_eq!(
+            evaluate(
+                "request.time > timestamp('2025-01-01T00:00:00Z') && request.time < timestamp('2999-01-01T00:00:00Z')",
+                json!({})
+            ),
+            Ok(json!(true))
+        );
+        assert_eq!(
+            evaluate(
+                "request.time < timestamp('2025-01-01T00:00:00Z') && request.auth == null",
+                json!({"request": {"time": "2024-06-01T09:30:00Z"}})
+            ),
+            Ok(json!(true))
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Google Cloud IAM Conditions helpers.
+//!
+//! Mirrors the attributes and restrictions documented at
+//! <https://cloud.google.com/iam/docs/conditions-overview>. `request.time` is a timestamp, the
+//! time of the request unless the context passes another as an RFC 3339 string.
+
+use crate::ast;
+use cel::common::ast::{Expr, operators};
+use chrono::{SecondsFormat, Utc};
+use rmcp::serde_json::{Map, Value, json};
+
+/// IAM rejects condition expressions longer than this many characters.
+pub const MAX_EXPRESSION_LENGTH: usize = 1000;
+
+/// IAM rejects conditions combining more than this many logical operators.
+pub const MAX_LOGICAL_OPERATORS: usize = 12;
+
+/// Functions available both in IAM Conditions and in this evaluator.
+const ALLOWED_FUNCTIONS: &[&str] = &[
+    "startsWith",
+    "endsWith",
+    "timestamp",
+    "duration",
+    "getFullYear",
+    "getMonth",
+    "getDayOfYear",
+    "getDayOfMonth",
+    "getDate",
+    "getDayOfWeek",
+    "getHours",
+    "getMinutes",
+    "getSeconds",
+    "getMilliseconds",
+];
+
+/// Operators IAM accepts: logical, comparison, membership and indexing. Arithmetic is rejected.
+const ALLOWED_OPERATORS: &[&str] = &[
+    operators::LOGICAL_AND,
+    operators::LOGICAL_OR,
+    operators::LOGICAL_NOT,
+    operators::EQUALS,
+    operators::NOT_EQUALS,
+    operators::LESS,
+    operators::LESS_EQUALS,
+    operators::GREATER,
+    operators::GREATER_EQUALS,
+    operators::IN,
+    operators::INDEX,
+    // Synthesised by macro expansion, which is reported separately.
+    operators::NOT_STRICTLY_FALSE,
+];
+
+/// The attribute tree every IAM-profile evaluation starts from.
+pub fn declared_attributes() -> Map<String, Value> {
+    let attributes = json!({
+        "request": {
+            "time": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "host": "",
+            "path": "",
+        },
+        "resource": {
+            "name": "",
+            "type": "",
+            "service": "",
+        },
+        "destination": {
+            "ip": "",
+            "port": 0,
+        },
+        "origin": {
+            "ip": "",
+        },
+    });
+    match attributes {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Lists every way `expression` falls outside what IAM Conditions accept.
+///
+/// An empty list means the expression can be used as an IAM condition as far as this
+/// server can tell; a parse failure is reported as a single violation.
+pub fn validate(expression: &str) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    let length = expression.chars().count();
+    if length > MAX_EXPRESSION_LENGTH {
+        violations.push(format!(
+            "expression is {} characters long; IAM allows at most {}",
+            length, MAX_EXPRESSION_LENGTH
+        ));
+    }
+
+    let parsed = match ast::parse(expression) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            violations.push(format!("CEL compile error: {}", e));
+            return violations;
+        }
+    };
+
+    let mut logical_operators = 0;
+    let mut comprehension_reported = false;
+    ast::walk(&parsed, &mut |node| match &node.expr {
+        Expr::Call(call) if is_operator(&call.func_name) => {
+            if matches!(
+                call.func_name.as_str(),
+                operators::LOGICAL_AND | operators::LOGICAL_OR | operators::LOGICAL_NOT
+            ) {
+                logical_operators += 1;
+            }
+            if !ALLOWED_OPERATORS.contains(&call.func_name.as_str()) {
+                violations.push(format!(
+                    "operator `{}` is not allowed in IAM conditions",
+                    call.func_name.trim_matches('_')
+                ));
+            }
+        }
+        Expr::Call(call) if !ALLOWED_FUNCTIONS.contains(&call.func_name.as_str()) => {
+            violations.push(format!(
+                "function `{}` is not allowed in IAM conditions",
+                call.func_name
+            ));
+        }
+        Expr::Comprehension(_) if !comprehension_reported => {
+            comprehension_reported = true;
+            violations.push(
+                "macros `all`, `exists`, `exists_one`, `map` and `filter` are not allowed in IAM conditions"
+                    .to_string(),
+            );
+        }
+        _ => {}
+    });
+
+    if logical_operators > MAX_LOGICAL_OPERATORS {
+        violations.push(format!(
+            "expression uses {} logical operators; IAM allows at most {}",
+            logical_operators, MAX_LOGICAL_OPERATORS
+        ));
+    }
+
+    violations
+}
+
+fn is_operator(func_name: &str) -> bool {
+    func_name.starts_with('_') || func_name.ends_with('_') || func_name.starts_with('@')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_accepts_time_condition() {
+        let violations = validate(
+            "request.time < timestamp('2025-01-01T00:00:00Z') && resource.name.startsWith('projects/_/buckets/b')",
+        );
+        assert!(violations.is_empty(), "{:?}", violations);
+    }
+
+    #[test]
+    fn test_request_time_is_a_timestamp() {
+        use crate::evaluator::Evaluator;
+        use crate::profile::Profile;
+
+        let evaluator = Evaluator::new();
+        let evaluate = |expression: &str, context: Value| {
+            let context = match context {
+                Value::Object(context) => context,
+                _ => Map::new(),
+            };
+            evaluator.evaluate(
+                expression,
+                &Value::Object(Profile::Iam.seed_context(context)),
+                Profile::Iam,
+            )
+        };
+        assert_eq!(
+            evaluate(
+                "request.time > timestamp('2025-01-01T00:00:00Z') && request.time < timestamp('2999-01-01T00:00:00Z')",
+                json!({})
+            ),
+            Ok(json!(true))
+        );
+        let context = json!({"request": {"time": "2024-06-01T09:30:00Z", "host": "example.com"}});
+        assert_eq!(
+            evaluate(
+                "request.time < timestamp('2025-01-01T00:00:00Z') && request.time.getHours() == 9",
+                context.clone()
+            ),
+            Ok(json!(true))
+        );
+        assert_eq!(evaluate("request.host", context), Ok(json!("example.com")));
+    }
+
+    #[test]
+    fn test_validate_rejects_arithmetic_and_macros() {
+        let violations = validate("[1, 2].all(x, x + 1 > 0)");
+        assert_eq!(violations.len(), 2, "{:?}", violations);
+    }
+
+    #[test]
+    fn test_validate_rejects_too_many_logical_operators() {
+        let expression = vec!["a == 1"; 14].join(" || ");
+        let violations = validate(&expression);
+        assert!(violations.iter().any(|v| v.contains("logical operators")));
+    }
+}
@@ -0,0 +1,186 @@
+//! Compiled programs keyed by expression fingerprint, shareable by every evaluator of a server.
+//!
+//! Differently formatted copies of an expression share one program. Entries expire after an
+//! optional TTL and the oldest is evicted when the cache is full, so memory stays bounded.
+//! Pinned programs, such as those of policy sets compiled at startup, stay until replaced.
+
+use cel::Program;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many compiled programs a [`ProgramCache`] keeps by default.
+pub const DEFAULT_MAX_ENTRIES: usize = 256;
+
+/// How many spellings of each program are remembered without parsing them again.
+const ALIASES_PER_ENTRY: usize = 4;
+
+/// A bounded, thread-safe map from fingerprints to compiled programs.
+pub struct ProgramCache {
+    max_entries: usize,
+    ttl: Option<Duration>,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    programs: HashMap<String, (Arc<Program>, Instant)>,
+    /// Programs kept regardless of age and capacity.
+    pinned: HashMap<String, Arc<Program>>,
+    /// Expression texts seen, with their fingerprint.
+    fingerprints: HashMap<String, String>,
+    order: VecDeque<String>,
+}
+
+impl Entries {
+    fn remove(&mut self, fingerprint: &str) {
+        self.programs.remove(fingerprint);
+        self.fingerprints.retain(|_, f| f != fingerprint);
+        self.order.retain(|f| f != fingerprint);
+    }
+}
+
+impl Default for ProgramCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES, None)
+    }
+}
+
+impl ProgramCache {
+    /// Keeps up to `max_entries` programs (`0` disables caching), each for at most `ttl`.
+    pub fn new(max_entries: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            max_entries,
+            ttl,
+            entries: Mutex::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_entries > 0
+    }
+
+    /// The number of cached programs, expired ones included until they are next looked up.
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .map_or(0, |entries| entries.programs.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The program for an expression text seen before.
+    pub fn get_by_text(&self, expression: &str) -> Option<Arc<Program>> {
+        let fingerprint = self
+            .entries
+            .lock()
+            .ok()?
+            .fingerprints
+            .get(expression)?
+            .clone();
+        self.get(&fingerprint)
+    }
+
+    /// The program for `fingerprint`, remembering `expression` as one of its spellings.
+    pub fn get_aliased(&self, fingerprint: &str, expression: &str) -> Option<Arc<Program>> {
+        let program = self.get(fingerprint)?;
+        self.alias(fingerprint, expression);
+        Some(program)
+    }
+
+    fn get(&self, fingerprint: &str) -> Option<Arc<Program>> {
+        let mut entries = self.entries.lock().ok()?;
+        if let Some(program) = entries.pinned.get(fingerprint) {
+            return Some(program.clone());
+        }
+        let (program, inserted) = entries.programs.get(fingerprint)?;
+        if self.ttl.is_some_and(|ttl| inserted.elapsed() >= ttl) {
+            entries.remove(fingerprint);
+            return None;
+        }
+        Some(program.clone())
+    }
+
+    /// Caches `program` under `fingerprint`, reachable from `expression` without parsing.
+    pub fn insert(&self, fingerprint: &str, expression: &str, program: Arc<Program>) {
+        if !self.is_enabled() {
+            return;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        if !entries.programs.contains_key(fingerprint) {
+            while entries.order.len() >= self.max_entries {
+                if let Some(oldest) = entries.order.pop_front() {
+                    entries.remove(&oldest);
+                }
+            }
+            entries.order.push_back(fingerprint.to_string());
+        }
+        entries
+            .programs
+            .insert(fingerprint.to_string(), (program, Instant::now()));
+        drop(entries);
+        self.alias(fingerprint, expression);
+    }
+
+    /// Replaces the pinned programs with `programs`, given as fingerprint, expression text and
+    /// program.
+    pub fn pin(&self, programs: Vec<(String, String, Arc<Program>)>) {
+        if !self.is_enabled() {
+            return;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        entries.pinned.clear();
+        for (fingerprint, expression, program) in programs {
+            entries.pinned.insert(fingerprint.clone(), program);
+            entries.fingerprints.insert(expression, fingerprint);
+        }
+        let Entries {
+            programs,
+            pinned,
+            fingerprints,
+            ..
+        } = &mut *entries;
+        fingerprints.retain(|_, f| pinned.contains_key(f) || programs.contains_key(f));
+    }
+
+    fn alias(&self, fingerprint: &str, expression: &str) {
+        if let Ok(mut entries) = self.entries.lock()
+            && entries.fingerprints.len() < self.max_entries.saturating_mul(ALIASES_PER_ENTRY)
+        {
+            entries
+                .fingerprints
+                .insert(expression.to_string(), fingerprint.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expires_and_evicts() {
+        let program = Arc::new(Program::compile("1").unwrap());
+        let cache = ProgramCache::new(1, Some(Duration::from_millis(200)));
+        cache.insert("f1", "1", program.clone());
+        assert!(cache.get_by_text("1").is_some());
+        assert!(cache.get_aliased("f1", "(1)").is_some());
+        assert!(cache.get_by_text("(1)").is_some());
+
+        cache.insert("f2", "2", program.clone());
+        assert!(cache.get_by_text("1").is_none());
+        assert_eq!(cache.len(), 1);
+
+        cache.pin(vec![("f3".to_string(), "3".to_string(), program)]);
+        std::thread::sleep(Duration::from_millis(250));
+        assert!(cache.get_by_text("2").is_none());
+        assert!(cache.is_empty());
+        assert!(cache.get_by_text("3").is_some());
+    }
+}
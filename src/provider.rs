@@ -0,0 +1,191 @@
+//! Extension points for embedding `rs_cel_mcp` as a library.
+//!
+//! A [`FunctionProvider`] adds Rust functions to every evaluation context, and a
+//! [`VariableResolver`] supplies variables the request context does not contain. Both are
+//! registered through [`Evaluator`](crate::evaluator::Evaluator) or
+//! [`CelToolBuilder`](crate::cel_tool::CelToolBuilder).
+
+use crate::ingest::ContextBudget;
+use crate::reload::Reloadable;
+use cel::extractors::Arguments;
+use cel::objects::Key;
+use cel::{Context, ExecutionError, FunctionContext, Program, Value as CelValue};
+use rmcp::serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Registers custom functions on each evaluation context.
+pub trait FunctionProvider: Send + Sync {
+    fn register(&self, ctx: &mut Context);
+
+    /// Names of the functions `register` adds, offered as completions.
+    fn names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Whether the functions may return different results for the same arguments, as
+    /// `http.get` may. Results of expressions calling them are not cached.
+    fn is_volatile(&self) -> bool {
+        false
+    }
+
+    /// Methods called on a namespace variable, such as `kv.get(key)`, for each evaluation
+    /// context. Namespaces may share method names, which plain functions cannot.
+    fn namespace(&self) -> Option<Namespace> {
+        None
+    }
+
+    /// Functions called without a namespace, such as `crc32(data)`. Unlike functions `register`
+    /// adds, they may share a name with a namespace method, which is then called on the
+    /// namespace and this function otherwise.
+    fn globals(&self) -> Vec<(String, Method)> {
+        Vec::new()
+    }
+}
+
+/// A method of a [`Namespace`], given the call's arguments.
+pub type Method =
+    Arc<dyn Fn(&FunctionContext, &[CelValue]) -> Result<CelValue, ExecutionError> + Send + Sync>;
+
+/// Methods called as `<name>.<method>(args)`.
+pub struct Namespace {
+    pub name: String,
+    pub methods: Vec<(String, Method)>,
+}
+
+/// Key of the map bound to a namespace's variable, holding the namespace's name.
+const NAMESPACE_KEY: &str = "__namespace__";
+
+impl<F> FunctionProvider for F
+where
+    F: Fn(&mut Context) + Send + Sync,
+{
+    fn register(&self, ctx: &mut Context) {
+        self(ctx)
+    }
+}
+
+/// Resolves a top-level variable referenced by an expression but absent from its context.
+pub trait VariableResolver: Send + Sync {
+    /// Returns `Ok(None)` when this resolver does not know `name`.
+    fn resolve(&self, name: &str) -> Result<Option<Value>, String>;
+}
+
+impl<R: VariableResolver + ?Sized> VariableResolver for Box<R> {
+    fn resolve(&self, name: &str) -> Result<Option<Value>, String> {
+        (**self).resolve(name)
+    }
+}
+
+/// The functions and resolvers applied to every evaluation.
+#[derive(Clone, Default)]
+pub(crate) struct Extensions {
+    pub(crate) functions: Vec<Arc<dyn FunctionProvider>>,
+    pub(crate) resolvers: Reloadable<Vec<Arc<dyn VariableResolver>>>,
+}
+
+impl Extensions {
+    pub(crate) fn register_functions(&self, ctx: &mut Context) {
+        let mut methods: BTreeMap<String, HashMap<String, Method>> = BTreeMap::new();
+        let mut globals: BTreeMap<String, Method> = BTreeMap::new();
+        for provider in &self.functions {
+            provider.register(ctx);
+            globals.extend(provider.globals());
+            if let Some(namespace) = provider.namespace() {
+                ctx.add_variable_from_value(
+                    &namespace.name,
+                    HashMap::from([(
+                        Key::from(NAMESPACE_KEY),
+                        CelValue::from(namespace.name.as_str()),
+                    )]),
+                );
+                for (method, function) in namespace.methods {
+                    methods
+                        .entry(method)
+                        .or_default()
+                        .insert(namespace.name.clone(), function);
+                }
+            }
+        }
+        for (method, namespaces) in methods {
+            let global = globals.remove(&method);
+            let name = method.clone();
+            ctx.add_function(
+                &name,
+                move |ftx: &FunctionContext, Arguments(args): Arguments| {
+                    let namespace = match &ftx.this {
+                        Some(CelValue::Map(map)) => match map.get(&Key::from(NAMESPACE_KEY)) {
+                            Some(CelValue::String(namespace)) => namespaces.get(namespace.as_str()),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    match (namespace, &global) {
+                        (Some(function), _) => function(ftx, &args),
+                        (None, Some(global)) if ftx.this.is_none() => global(ftx, &args),
+                        (None, _) => {
+                            let mut names: Vec<&str> =
+                                namespaces.keys().map(String::as_str).collect();
+                            names.sort_unstable();
+                            Err(ftx.error(format!(
+                                "`{}` is only available on {}",
+                                method,
+                                names.join(", ")
+                            )))
+                        }
+                    }
+                },
+            );
+        }
+        for (name, global) in globals {
+            ctx.add_function(
+                &name,
+                move |ftx: &FunctionContext, Arguments(args): Arguments| global(ftx, &args),
+            );
+        }
+    }
+
+    pub(crate) fn function_names(&self) -> Vec<String> {
+        self.functions
+            .iter()
+            .flat_map(|provider| provider.names())
+            .collect()
+    }
+
+    /// Names of the functions of [volatile](FunctionProvider::is_volatile) providers.
+    pub(crate) fn volatile_function_names(&self) -> Vec<String> {
+        self.functions
+            .iter()
+            .filter(|provider| provider.is_volatile())
+            .flat_map(|provider| provider.names())
+            .collect()
+    }
+
+    /// Adds resolver-provided values for variables `program` references that are not yet bound.
+    /// The first resolver that knows a variable wins.
+    pub(crate) fn resolve_variables(
+        &self,
+        program: &Program,
+        is_bound: &dyn Fn(&str) -> bool,
+        ctx: &mut Context,
+        budget: &mut ContextBudget,
+    ) -> Result<(), String> {
+        let resolvers = self.resolvers.get();
+        if resolvers.is_empty() {
+            return Ok(());
+        }
+        for name in program.references().variables() {
+            if is_bound(name) {
+                continue;
+            }
+            for resolver in resolvers.iter() {
+                if let Some(value) = resolver.resolve(name)? {
+                    let value = budget.convert(name, &value).map_err(|e| e.to_string())?;
+                    ctx.add_variable_from_value(name, value);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,601 @@
+//! Usage accounting for shared deployments: evaluations, evaluation time and context bytes
+//! per account, checked against the `--config` file's `quota` before each evaluation.
+//!
+//! Over HTTP, a request is charged to the API key (see [`crate::keys`]) or client certificate
+//! (see `tls`) that authenticated it. Unauthenticated requests share the `anonymous` account,
+//! whatever token they carry, so that made-up tokens don't each get a fresh quota. Other
+//! sessions are charged as themselves.
+//! Usage is kept in memory, so it starts over when the server restarts.
+
+use crate::cel_tool::CelTool;
+use axum::Router;
+use axum::extract::State;
+use axum::http::{Extensions, HeaderMap, header};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+
+/// The account of unauthenticated HTTP requests.
+pub const ANONYMOUS: &str = "anonymous";
+
+/// The period usage counts against a [`Quota`] for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(
+    crate = "rmcp::serde",
+    tag = "type",
+    rename_all = "snake_case",
+    deny_unknown_fields
+)]
+pub enum QuotaWindow {
+    /// The current UTC day.
+    #[default]
+    Daily,
+    /// The last `seconds` seconds.
+    Rolling { seconds: u64 },
+}
+
+impl QuotaWindow {
+    fn start(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            QuotaWindow::Daily => floor(now, 86_400),
+            QuotaWindow::Rolling { seconds } => now - TimeDelta::seconds(clamp(*seconds)),
+        }
+    }
+
+    /// How finely usage is bucketed: a minute for a day, at least a second otherwise.
+    fn bucket_secs(&self) -> i64 {
+        match self {
+            QuotaWindow::Daily => 60,
+            QuotaWindow::Rolling { seconds } => (clamp(*seconds) / 1024).max(1),
+        }
+    }
+
+    /// When usage recorded at `oldest` stops counting.
+    fn expiry(&self, oldest: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            QuotaWindow::Daily => floor(oldest, 86_400) + TimeDelta::days(1),
+            QuotaWindow::Rolling { seconds } => oldest + TimeDelta::seconds(clamp(*seconds)),
+        }
+    }
+}
+
+impl fmt::Display for QuotaWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaWindow::Daily => write!(f, "UTC day"),
+            QuotaWindow::Rolling { seconds } => write!(f, "{} seconds", seconds),
+        }
+    }
+}
+
+fn clamp(seconds: u64) -> i64 {
+    i64::try_from(seconds)
+        .unwrap_or(i64::MAX)
+        .min(100 * 365 * 86_400)
+}
+
+/// `time` rounded down to a multiple of `secs` since the epoch.
+fn floor(time: DateTime<Utc>, secs: i64) -> DateTime<Utc> {
+    let timestamp = time.timestamp();
+    DateTime::from_timestamp(timestamp - timestamp.rem_euclid(secs), 0).unwrap_or(time)
+}
+
+/// Limits on what one account may use per window; no limit where absent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", deny_unknown_fields)]
+pub struct Quota {
+    #[serde(default)]
+    pub window: QuotaWindow,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_evaluations: Option<u64>,
+    /// Milliseconds spent evaluating, not counting time waiting for the evaluator.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_eval_ms: Option<u64>,
+    /// Context bytes, measured as for `--max-context-bytes`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+}
+
+impl Quota {
+    pub fn is_limited(&self) -> bool {
+        self.max_evaluations.is_some() || self.max_eval_ms.is_some() || self.max_bytes.is_some()
+    }
+
+    /// Describes the limits, e.g. "1000 evaluations and 60000 ms of evaluation per UTC day".
+    pub fn describe(&self) -> Option<String> {
+        let limits: Vec<String> = [
+            self.max_evaluations.map(|n| format!("{} evaluations", n)),
+            self.max_eval_ms.map(|n| format!("{} ms of evaluation", n)),
+            self.max_bytes.map(|n| format!("{} context bytes", n)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let (last, rest) = limits.split_last()?;
+        let listed = match rest {
+            [] => last.clone(),
+            _ => format!("{} and {}", rest.join(", "), last),
+        };
+        Some(format!("{} per {}", listed, self.window))
+    }
+}
+
+/// What an account has used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+pub struct Usage {
+    pub evaluations: u64,
+    pub eval_ms: f64,
+    pub bytes: u64,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.evaluations += other.evaluations;
+        self.eval_ms += other.eval_ms;
+        self.bytes += other.bytes;
+    }
+}
+
+/// An account's usage in the current window, as the `usage` tool reports it.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+pub struct AccountUsage {
+    pub account: String,
+    pub used: Usage,
+    pub quota: Quota,
+    /// When the oldest usage counted stops counting (RFC 3339); absent without usage.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resets_at: Option<String>,
+}
+
+/// A request refused because its account has used up a limit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaExceeded {
+    /// The limit's field in [`Quota`], e.g. `max_evaluations`.
+    pub limit: &'static str,
+    pub max: u64,
+    pub window: QuotaWindow,
+    pub resets_at: Option<DateTime<Utc>>,
+}
+
+impl QuotaExceeded {
+    pub fn resets_at(&self) -> Option<String> {
+        self.resets_at
+            .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+    }
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Quota exceeded: `{}` is {} per {}",
+            self.limit, self.max, self.window
+        )?;
+        match self.resets_at() {
+            Some(time) => write!(f, "; usage starts expiring at {}", time),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Who evaluations are charged to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+    pub name: String,
+    /// Holds the account to this instead of the server's quota, as an API key's quota does.
+    pub quota: Option<Quota>,
+}
+
+impl Account {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            quota: None,
+        }
+    }
+}
+
+/// An account's usage in buckets, oldest first.
+#[derive(Default)]
+struct Ledger {
+    /// The quota the account was last held to.
+    quota: Quota,
+    buckets: VecDeque<(DateTime<Utc>, Usage)>,
+    /// Requests refused since the server started.
+    rejections: u64,
+}
+
+impl Ledger {
+    /// Drops the buckets that began before the window containing `now`.
+    fn prune(&mut self, now: DateTime<Utc>) {
+        let start = self.quota.window.start(now);
+        while self.buckets.front().is_some_and(|(time, _)| *time < start) {
+            self.buckets.pop_front();
+        }
+    }
+
+    fn total(&self) -> Usage {
+        self.buckets
+            .iter()
+            .fold(Usage::default(), |mut total, (_, usage)| {
+                total.add(usage);
+                total
+            })
+    }
+
+    /// When the oldest usage counted stops counting.
+    fn resets_at(&self) -> Option<DateTime<Utc>> {
+        self.buckets
+            .front()
+            .map(|(time, _)| self.quota.window.expiry(*time))
+    }
+}
+
+/// Per-account usage, shared by every session of a server.
+#[derive(Default)]
+pub struct Quotas {
+    quota: Quota,
+    accounts: Mutex<HashMap<String, Ledger>>,
+}
+
+impl Quotas {
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            accounts: Mutex::default(),
+        }
+    }
+
+    /// The server's quota, for accounts without their own.
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// `account`'s ledger, pruned to the current window of its quota.
+    fn ledger<'a>(
+        &self,
+        accounts: &'a mut HashMap<String, Ledger>,
+        account: &Account,
+        now: DateTime<Utc>,
+    ) -> &'a mut Ledger {
+        let ledger = accounts.entry(account.name.clone()).or_default();
+        ledger.quota = account.quota.unwrap_or(self.quota);
+        ledger.prune(now);
+        ledger
+    }
+
+    /// Fails if `account` has used up a limit, or if `bytes` more context would exceed
+    /// `max_bytes`.
+    pub fn check(&self, account: &Account, bytes: u64) -> Result<(), QuotaExceeded> {
+        self.check_at(account, bytes, Utc::now())
+    }
+
+    fn check_at(
+        &self,
+        account: &Account,
+        bytes: u64,
+        now: DateTime<Utc>,
+    ) -> Result<(), QuotaExceeded> {
+        let quota = account.quota.unwrap_or(self.quota);
+        if !quota.is_limited() {
+            return Ok(());
+        }
+        let Ok(mut accounts) = self.accounts.lock() else {
+            return Ok(());
+        };
+        let ledger = self.ledger(&mut accounts, account, now);
+        let used = ledger.total();
+        let exceeded = quota
+            .max_evaluations
+            .filter(|max| used.evaluations >= *max)
+            .map(|max| ("max_evaluations", max))
+            // Evaluation time is only known afterwards, so the request crossing it still runs.
+            .or_else(|| {
+                quota
+                    .max_eval_ms
+                    .filter(|max| used.eval_ms >= *max as f64)
+                    .map(|max| ("max_eval_ms", max))
+            })
+            .or_else(|| {
+                quota
+                    .max_bytes
+                    .filter(|max| used.bytes + bytes > *max)
+                    .map(|max| ("max_bytes", max))
+            });
+        match exceeded {
+            Some((limit, max)) => {
+                ledger.rejections += 1;
+                Err(QuotaExceeded {
+                    limit,
+                    max,
+                    window: quota.window,
+                    resets_at: ledger.resets_at(),
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Adds `usage` to what `account` has used.
+    pub fn charge(&self, account: &Account, usage: Usage) {
+        self.charge_at(account, usage, Utc::now())
+    }
+
+    fn charge_at(&self, account: &Account, usage: Usage, now: DateTime<Utc>) {
+        let Ok(mut accounts) = self.accounts.lock() else {
+            return;
+        };
+        let ledger = self.ledger(&mut accounts, account, now);
+        let bucket = floor(now, ledger.quota.window.bucket_secs());
+        match ledger.buckets.back_mut() {
+            Some((time, total)) if *time == bucket => total.add(&usage),
+            _ => ledger.buckets.push_back((bucket, usage)),
+        }
+    }
+
+    /// What `account` has used in the current window.
+    pub fn usage(&self, account: &Account) -> AccountUsage {
+        self.usage_at(account, Utc::now())
+    }
+
+    fn usage_at(&self, account: &Account, now: DateTime<Utc>) -> AccountUsage {
+        let (used, resets_at) = match self.accounts.lock() {
+            Ok(mut accounts) => {
+                let ledger = self.ledger(&mut accounts, account, now);
+                (ledger.total(), ledger.resets_at())
+            }
+            Err(_) => (Usage::default(), None),
+        };
+        AccountUsage {
+            account: account.name.clone(),
+            used,
+            quota: account.quota.unwrap_or(self.quota),
+            resets_at: resets_at.map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        }
+    }
+
+    /// Every account's usage in the current window and requests refused, dropping accounts
+    /// with neither.
+    pub fn accounts(&self) -> BTreeMap<String, (Usage, u64)> {
+        let Ok(mut accounts) = self.accounts.lock() else {
+            return BTreeMap::new();
+        };
+        let now = Utc::now();
+        accounts.retain(|_, ledger| {
+            ledger.prune(now);
+            !ledger.buckets.is_empty() || ledger.rejections > 0
+        });
+        accounts
+            .iter()
+            .map(|(account, ledger)| (account.clone(), (ledger.total(), ledger.rejections)))
+            .collect()
+    }
+
+    /// The usage of every account in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        let accounts = self.accounts();
+        let mut out = String::new();
+        let mut family =
+            |name: &str, kind: &str, help: &str, value: &dyn Fn(&Usage, u64) -> f64| {
+                out.push_str(&format!(
+                    "# HELP {} {}\n# TYPE {} {}\n",
+                    name, help, name, kind
+                ));
+                for (account, (usage, rejections)) in &accounts {
+                    out.push_str(&format!(
+                        "{}{{account=\"{}\"}} {}\n",
+                        name,
+                        account,
+                        value(usage, *rejections)
+                    ));
+                }
+            };
+        family(
+            "cel_mcp_quota_evaluations",
+            "gauge",
+            "Evaluations in the current quota window.",
+            &|usage, _| usage.evaluations as f64,
+        );
+        family(
+            "cel_mcp_quota_eval_seconds",
+            "gauge",
+            "Seconds spent evaluating in the current quota window.",
+            &|usage, _| usage.eval_ms / 1000.0,
+        );
+        family(
+            "cel_mcp_quota_bytes",
+            "gauge",
+            "Context bytes evaluated in the current quota window.",
+            &|usage, _| usage.bytes as f64,
+        );
+        family(
+            "cel_mcp_quota_rejections_total",
+            "counter",
+            "Requests refused for exceeding the quota.",
+            &|_, rejections| rejections as f64,
+        );
+        out
+    }
+}
+
+/// The bearer token of the `Authorization` header.
+pub(crate) fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// The account an HTTP request is charged to: the one authentication put in its `extensions`,
+/// else [`ANONYMOUS`].
+pub fn account(extensions: &Extensions) -> Account {
+    extensions
+        .get::<Account>()
+        .cloned()
+        .unwrap_or_else(|| Account::new(ANONYMOUS))
+}
+
+/// Routes `GET /metrics`, the usage of every account and the evaluator's panics in the
+/// Prometheus text format; see [`CelTool::metrics`].
+pub fn router(tool: CelTool) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(tool)
+}
+
+async fn metrics(State(tool): State<CelTool>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        tool.metrics(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(name: &str) -> Account {
+        Account::new(name)
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    fn one(bytes: u64) -> Usage {
+        Usage {
+            evaluations: 1,
+            eval_ms: 2.5,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn test_daily_quota() {
+        let quotas = Quotas::new(Quota {
+            max_evaluations: Some(2),
+            max_bytes: Some(100),
+            ..Quota::default()
+        });
+        let morning = at("2026-03-01T09:00:00Z");
+        assert_eq!(quotas.check_at(&named("a"), 10, morning), Ok(()));
+        quotas.charge_at(&named("a"), one(10), morning);
+        assert_eq!(
+            quotas.check_at(&named("a"), 95, morning).unwrap_err().limit,
+            "max_bytes"
+        );
+        quotas.charge_at(&named("a"), one(10), morning);
+        let err = quotas.check_at(&named("a"), 10, morning).unwrap_err();
+        assert_eq!(err.limit, "max_evaluations");
+        assert_eq!(err.resets_at(), Some("2026-03-02T00:00:00Z".to_string()));
+        assert_eq!(quotas.check_at(&named("b"), 10, morning), Ok(()));
+
+        let usage = quotas.usage_at(&named("a"), morning);
+        assert_eq!(
+            usage.used,
+            Usage {
+                evaluations: 2,
+                eval_ms: 5.0,
+                bytes: 20
+            }
+        );
+        assert_eq!(
+            quotas.check_at(&named("a"), 10, at("2026-03-02T00:00:01Z")),
+            Ok(())
+        );
+        assert_eq!(
+            quotas
+                .usage_at(&named("a"), at("2026-03-02T00:00:01Z"))
+                .used,
+            Usage::default()
+        );
+    }
+
+    #[test]
+    fn test_rolling_quota() {
+        let quotas = Quotas::new(Quota {
+            window: QuotaWindow::Rolling { seconds: 60 },
+            max_eval_ms: Some(5),
+            ..Quota::default()
+        });
+        quotas.charge_at(&named("a"), one(0), at("2026-03-01T09:00:00Z"));
+        quotas.charge_at(&named("a"), one(0), at("2026-03-01T09:00:30Z"));
+        let err = quotas
+            .check_at(&named("a"), 0, at("2026-03-01T09:00:40Z"))
+            .unwrap_err();
+        assert_eq!(err.limit, "max_eval_ms");
+        assert_eq!(err.resets_at(), Some("2026-03-01T09:01:00Z".to_string()));
+        assert_eq!(
+            quotas.check_at(&named("a"), 0, at("2026-03-01T09:01:01Z")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        let quotas = Quotas::new(Quota {
+            max_evaluations: Some(1),
+            ..Quota::default()
+        });
+        quotas.charge(&named("key-1"), one(8));
+        assert!(quotas.check(&named("key-1"), 0).is_err());
+        let metrics = quotas.metrics();
+        assert!(metrics.contains("cel_mcp_quota_evaluations{account=\"key-1\"} 1\n"));
+        assert!(metrics.contains("cel_mcp_quota_bytes{account=\"key-1\"} 8\n"));
+        assert!(metrics.contains("cel_mcp_quota_rejections_total{account=\"key-1\"} 1\n"));
+    }
+
+    #[test]
+    fn test_own_quota() {
+        let quotas = Quotas::new(Quota {
+            max_evaluations: Some(1),
+            ..Quota::default()
+        });
+        let mut ci = named("key-ci");
+        ci.quota = Some(Quota {
+            max_evaluations: Some(3),
+            ..Quota::default()
+        });
+        let now = at("2026-03-01T09:00:00Z");
+        quotas.charge_at(&ci, one(0), now);
+        quotas.charge_at(&named("a"), one(0), now);
+        assert_eq!(quotas.check_at(&ci, 0, now), Ok(()));
+        assert!(quotas.check_at(&named("a"), 0, now).is_err());
+        assert_eq!(quotas.usage_at(&ci, now).quota.max_evaluations, Some(3));
+    }
+
+    #[test]
+    fn test_account() {
+        let mut extensions = Extensions::new();
+        assert_eq!(account(&extensions).name, ANONYMOUS);
+        extensions.insert(named("key-ci"));
+        assert_eq!(account(&extensions).name, "key-ci");
+    }
+
+    #[test]
+    fn test_describe() {
+        let quota = Quota {
+            max_evaluations: Some(1000),
+            max_eval_ms: Some(60_000),
+            ..Quota::default()
+        };
+        assert_eq!(
+            quota.describe(),
+            Some("1000 evaluations and 60000 ms of evaluation per UTC day".to_string())
+        );
+        assert_eq!(Quota::default().describe(), None);
+    }
+}
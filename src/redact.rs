@@ -0,0 +1,347 @@
+//! Masking of sensitive context values in logs, audit records, and error messages.
+//!
+//! A pattern is a dotted key path whose segments may contain `*` wildcards; it matches the
+//! trailing segments of a key's path. `token` matches a `token` key at any depth, `*.password`
+//! matches any `password` key that has a parent, and `user.api_*` matches `api_key` under `user`.
+
+use rmcp::serde_json::{Map, Value};
+use std::io::{self, Write};
+use std::sync::Arc;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Replacement for masked values.
+pub const MASK: &str = "***";
+
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    patterns: Vec<Vec<String>>,
+}
+
+/// Matches `text` against a glob where `*` stands for any run of characters.
+fn glob(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len())
+                .filter(|&i| text.is_char_boundary(i))
+                .any(|i| glob(rest, &text[i..]))
+        }
+    }
+}
+
+impl Redactor {
+    pub fn new<S: AsRef<str>>(patterns: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(|p| p.as_ref().split('.').map(str::to_string).collect())
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    fn matches_path(&self, path: &[&str]) -> bool {
+        self.patterns.iter().any(|pattern| {
+            pattern.len() <= path.len()
+                && pattern
+                    .iter()
+                    .rev()
+                    .zip(path.iter().rev())
+                    .all(|(p, k)| glob(p, k))
+        })
+    }
+
+    fn matches_key(&self, key: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.last().is_some_and(|p| glob(p, key)))
+    }
+
+    /// Returns a copy of `value` with every matching key's value replaced by [`MASK`].
+    pub fn redact_value(&self, value: &Value) -> Value {
+        let mut value = value.clone();
+        if !self.is_empty() {
+            self.mask(&mut value, &mut Vec::new(), &mut |_| {});
+        }
+        value
+    }
+
+    /// Collects the string forms of the values `redact_value` would mask.
+    pub fn secrets(&self, value: &Value) -> Vec<String> {
+        let mut secrets = Vec::new();
+        if !self.is_empty() {
+            self.mask(&mut value.clone(), &mut Vec::new(), &mut |secret| {
+                collect_scalars(secret, &mut secrets)
+            });
+        }
+        secrets
+    }
+
+    fn mask(&self, value: &mut Value, path: &mut Vec<String>, found: &mut impl FnMut(&Value)) {
+        match value {
+            Value::Object(map) => self.mask_map(map, path, found),
+            Value::Array(items) => {
+                for item in items {
+                    self.mask(item, path, found);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn mask_map(
+        &self,
+        map: &mut Map<String, Value>,
+        path: &mut Vec<String>,
+        found: &mut impl FnMut(&Value),
+    ) {
+        for (key, value) in map.iter_mut() {
+            path.push(key.clone());
+            let segments: Vec<&str> = path.iter().map(String::as_str).collect();
+            if self.matches_path(&segments) {
+                found(value);
+                *value = Value::String(MASK.to_string());
+            } else {
+                self.mask(value, path, found);
+            }
+            path.pop();
+        }
+    }
+
+    /// Replaces occurrences of the context's sensitive values inside a message.
+    pub fn redact_message(&self, message: &str, context: &Value) -> String {
+        mask_secrets(message, &self.secrets(context))
+    }
+
+    /// Masks `"key": value` pairs in a rendered log line, in both JSON and Rust `Debug` form
+    /// (`"key":"v"`, `"key": String("v")`, `"key": 42`).
+    pub fn redact_log_line(&self, line: &str) -> String {
+        if self.is_empty() {
+            return line.to_string();
+        }
+        let mut out = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(start) = rest.find('"') {
+            let Some(len) = rest[start + 1..].find('"') else {
+                break;
+            };
+            let key = &rest[start + 1..start + 1 + len];
+            let after_key = &rest[start + len + 2..];
+            out.push_str(&rest[..start + len + 2]);
+            rest = after_key;
+            let trimmed = after_key.trim_start();
+            let Some(value) = trimmed.strip_prefix(':') else {
+                continue;
+            };
+            if !self.matches_key(key) {
+                continue;
+            }
+            let value = value.trim_start();
+            let consumed_prefix = after_key.len() - value.len();
+            out.push_str(&after_key[..consumed_prefix]);
+            let (masked, consumed) = mask_token(value);
+            out.push_str(&masked);
+            rest = &value[consumed..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// Secrets shorter than this many characters are left in messages: masking every `1` or `ab`
+/// would garble them while hiding little.
+const MIN_SECRET_CHARS: usize = 4;
+
+/// Replaces the occurrences of the given secrets inside `message` that stand on their own,
+/// rather than as part of a longer word or number.
+pub fn mask_secrets(message: &str, secrets: &[String]) -> String {
+    let mut message = message.to_string();
+    for secret in secrets {
+        if secret.chars().count() >= MIN_SECRET_CHARS {
+            message = mask_whole(&message, secret);
+        }
+    }
+    message
+}
+
+fn mask_whole(message: &str, secret: &str) -> String {
+    let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let mut out = String::with_capacity(message.len());
+    let mut copied = 0;
+    for (start, _) in message.match_indices(secret) {
+        let end = start + secret.len();
+        let joined = (word(secret.chars().next()) && word(message[..start].chars().next_back()))
+            || (word(secret.chars().next_back()) && word(message[end..].chars().next()));
+        if !joined {
+            out.push_str(&message[copied..start]);
+            out.push_str(MASK);
+            copied = end;
+        }
+    }
+    out.push_str(&message[copied..]);
+    out
+}
+
+fn collect_scalars(value: &Value, out: &mut Vec<String>) {
+    match value {
+        Value::String(s) if !s.is_empty() => out.push(s.clone()),
+        Value::Number(n) => out.push(n.to_string()),
+        Value::Array(items) => items.iter().for_each(|v| collect_scalars(v, out)),
+        Value::Object(map) => map.values().for_each(|v| collect_scalars(v, out)),
+        _ => {}
+    }
+}
+
+/// Masks the scalar at the start of `value`, returning the replacement and the bytes consumed.
+fn mask_token(value: &str) -> (String, usize) {
+    let (open, close) = if value.starts_with('"') {
+        ("\"", "\"")
+    } else if value.starts_with("String(\"") {
+        ("String(\"", "\")")
+    } else if value.starts_with("Number(") {
+        ("Number(", ")")
+    } else {
+        let end = value
+            .find([',', '}', ')', ']', ' ', '\n'])
+            .unwrap_or(value.len());
+        return (MASK.to_string(), end);
+    };
+    let body = &value[open.len()..];
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if body[i..].starts_with(close) {
+            return (
+                format!("{}{}{}", open, MASK, close),
+                open.len() + i + close.len(),
+            );
+        }
+    }
+    (format!("{}{}", open, MASK), value.len())
+}
+
+/// A `MakeWriter` that masks sensitive `"key": value` pairs in every log event.
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+    redactor: Arc<Redactor>,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(inner: M, redactor: Arc<Redactor>) -> Self {
+        Self { inner, redactor }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            redactor: self.redactor.clone(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+/// Buffers one log event and writes it, redacted, when dropped.
+pub struct RedactingWriter<W: Write> {
+    inner: W,
+    redactor: Arc<Redactor>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let line = self
+                .redactor
+                .redact_log_line(&String::from_utf8_lossy(&self.buffer));
+            self.buffer.clear();
+            self.inner.write_all(line.as_bytes())?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for RedactingWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_redact_value_by_pattern() {
+        let redactor = Redactor::new(["*.password", "token", "user.api_*"]);
+        let value = json!({
+            "password": "top-level",
+            "db": { "password": "hunter2", "host": "x" },
+            "token": 1234,
+            "user": { "api_key": "k", "name": "alice" },
+        });
+        assert_eq!(
+            redactor.redact_value(&value),
+            json!({
+                "password": "top-level",
+                "db": { "password": MASK, "host": "x" },
+                "token": MASK,
+                "user": { "api_key": MASK, "name": "alice" },
+            })
+        );
+        assert_eq!(
+            redactor.redact_message("got 'String(\"hunter2\")'", &value),
+            "got 'String(\"***\")'"
+        );
+    }
+
+    #[test]
+    fn test_short_and_embedded_secrets_are_left() {
+        let redactor = Redactor::new(["pin", "token"]);
+        let context = json!({"pin": 7, "token": 2024});
+        assert_eq!(
+            redactor.redact_message(
+                "expected 7 args in 2024-01-07, got 20245 at line 2024",
+                &context
+            ),
+            "expected 7 args in ***-01-07, got 20245 at line ***"
+        );
+        assert_eq!(
+            mask_secrets("key=p\"w; p\"w", &["p\"w".to_string(), "key_".to_string()]),
+            "key=p\"w; p\"w"
+        );
+        assert_eq!(
+            mask_secrets("a-b-c-d, xa-b-c-dx", &["a-b-c-d".to_string()]),
+            "***, xa-b-c-dx"
+        );
+    }
+
+    #[test]
+    fn test_redact_log_line() {
+        let redactor = Redactor::new(["password", "token"]);
+        assert_eq!(
+            redactor.redact_log_line(
+                r#"arguments: Some({"password": String("p\"w"), "token": Number(42), "name": String("a")}) {"token":"abc"}"#
+            ),
+            r#"arguments: Some({"password": String("***"), "token": Number(***), "name": String("a")}) {"token":"***"}"#
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! Expressions registered by name, optionally with `{name}` placeholders for typed parameters,
+//! such as `size(items) > {threshold}` with `threshold` declared `int`.
+//!
+//! An expression is instantiated by replacing each placeholder with its argument quoted as a
+//! CEL literal (see [`literal`](crate::literal)), so arguments are always values and never
+//! change the expression's structure. Braces inside string literals are left alone.
+//!
+//! Registered expressions combine into new ones with `&&`, `||` and `!` through a
+//! [`Composition`], which parenthesizes each part and merges their parameters.
+
+use crate::ast;
+use crate::cel_type::CelType;
+use crate::literal;
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+
+/// An expression and the types of its parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registered {
+    pub expression: String,
+    pub parameters: BTreeMap<String, CelType>,
+    pub description: Option<String>,
+}
+
+impl Registered {
+    /// Fails unless `expression` parses and its placeholders are exactly `parameters`.
+    pub fn new(
+        expression: String,
+        parameters: BTreeMap<String, CelType>,
+        description: Option<String>,
+    ) -> Result<Self, String> {
+        let used: BTreeSet<&str> = placeholders(&expression).collect();
+        if let Some(name) = used.iter().find(|name| !parameters.contains_key(**name)) {
+            return Err(format!(
+                "Placeholder `{{{}}}` is not a declared parameter",
+                name
+            ));
+        }
+        if let Some(name) = parameters.keys().find(|name| !used.contains(name.as_str())) {
+            return Err(format!("Parameter `{}` has no placeholder", name));
+        }
+        // Any literal stands in for the arguments: parsing doesn't check types.
+        let stand_in = substitute(&expression, |_| Ok("null".to_string()))?;
+        ast::parse(&stand_in).map_err(|e| format!("CEL compile error: {}", e))?;
+        Ok(Self {
+            expression,
+            parameters,
+            description,
+        })
+    }
+
+    /// The expression with every placeholder replaced by its argument in `arguments`, which
+    /// must hold a value of the declared type for each parameter and nothing else.
+    pub fn instantiate(&self, arguments: &Map<String, Value>) -> Result<String, String> {
+        if let Some(name) = arguments
+            .keys()
+            .find(|name| !self.parameters.contains_key(*name))
+        {
+            return Err(format!("Unknown parameter `{}`", name));
+        }
+        substitute(&self.expression, |name| {
+            let value = arguments
+                .get(name)
+                .ok_or_else(|| format!("Missing parameter `{}`", name))?;
+            let declared = self.parameters.get(name).unwrap_or(&CelType::Dyn);
+            literal::quote(name, value, declared)
+        })
+    }
+}
+
+/// Registered expressions by name, shared by all sessions.
+#[derive(Default)]
+pub struct ExpressionRegistry {
+    expressions: RwLock<BTreeMap<String, Registered>>,
+}
+
+impl ExpressionRegistry {
+    pub fn get(&self, name: &str) -> Option<Registered> {
+        self.expressions.read().ok()?.get(name).cloned()
+    }
+
+    /// The registered expressions, by name.
+    pub fn list(&self) -> BTreeMap<String, Registered> {
+        self.expressions
+            .read()
+            .map(|expressions| expressions.clone())
+            .unwrap_or_default()
+    }
+
+    /// Registers `expression` as `name`, replacing and reporting whether one was.
+    pub fn register(&self, name: String, expression: Registered) -> Result<bool, String> {
+        let mut expressions = self
+            .expressions
+            .write()
+            .map_err(|_| "Expression registry lock poisoned")?;
+        Ok(expressions.insert(name, expression).is_some())
+    }
+}
+
+/// Registered expressions combined with boolean connectives, as in
+/// `{"and": [{"expression": "a"}, {"not": {"expression": "b"}}]}`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum Composition {
+    /// The expression registered under this name.
+    Expression(String),
+    /// True if all of these are.
+    And(Vec<Composition>),
+    /// True if any of these is.
+    Or(Vec<Composition>),
+    /// True if this isn't.
+    Not(Box<Composition>),
+}
+
+impl ExpressionRegistry {
+    /// The expression `composition` describes, with the parameters of the expressions it
+    /// names. Those that share a parameter must declare it with the same type.
+    pub fn compose(
+        &self,
+        composition: &Composition,
+        description: Option<String>,
+    ) -> Result<Registered, String> {
+        let mut parameters = BTreeMap::new();
+        let expression = self.compose_into(composition, &mut parameters)?;
+        Registered::new(expression, parameters, description)
+    }
+
+    fn compose_into(
+        &self,
+        composition: &Composition,
+        parameters: &mut BTreeMap<String, CelType>,
+    ) -> Result<String, String> {
+        let (operator, parts) = match composition {
+            Composition::Expression(name) => {
+                let registered = self
+                    .get(name)
+                    .ok_or_else(|| format!("No expression is registered as `{}`", name))?;
+                for (parameter, declared) in registered.parameters {
+                    match parameters.get(&parameter) {
+                        Some(earlier) if *earlier != declared => {
+                            return Err(format!(
+                                "Parameter `{}` is declared `{}` and `{}`",
+                                parameter, earlier, declared
+                            ));
+                        }
+                        _ => {
+                            parameters.insert(parameter, declared);
+                        }
+                    }
+                }
+                return Ok(format!("({})", registered.expression));
+            }
+            Composition::Not(operand) => {
+                return Ok(format!("!{}", self.compose_into(operand, parameters)?));
+            }
+            Composition::And(parts) => ("&&", parts),
+            Composition::Or(parts) => ("||", parts),
+        };
+        if parts.is_empty() {
+            return Err(format!("`{}` needs at least one expression", operator));
+        }
+        let parts = parts
+            .iter()
+            .map(|part| self.compose_into(part, parameters))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("({})", parts.join(&format!(" {} ", operator))))
+    }
+}
+
+/// The parameter names of the placeholders in `expression`, in order, with repeats.
+fn placeholders(expression: &str) -> impl Iterator<Item = &str> {
+    pieces(expression)
+        .into_iter()
+        .filter_map(|piece| match piece {
+            Piece::Placeholder(name) => Some(name),
+            Piece::Text(_) => None,
+        })
+}
+
+/// `expression` with each placeholder replaced by what `argument` returns for its name.
+fn substitute(
+    expression: &str,
+    mut argument: impl FnMut(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(expression.len());
+    for piece in pieces(expression) {
+        match piece {
+            Piece::Text(text) => out.push_str(text),
+            Piece::Placeholder(name) => out.push_str(&argument(name)?),
+        }
+    }
+    Ok(out)
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+/// Splits `expression` at its placeholders: an identifier in braces, outside string literals.
+/// `{name}` is never valid CEL otherwise, as map and message literals have `key: value`
+/// entries.
+fn pieces(expression: &str) -> Vec<Piece<'_>> {
+    let bytes = expression.as_bytes();
+    let mut pieces = Vec::new();
+    let (mut start, mut i) = (0, 0);
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'"' | b'\'') => i = skip_string(expression, i, quote),
+            b'{' => {
+                let close = expression[i + 1..].find('}').map(|end| i + 1 + end);
+                match close.map(|close| (close, expression[i + 1..close].trim())) {
+                    Some((close, name)) if is_identifier(name) => {
+                        pieces.push(Piece::Text(&expression[start..i]));
+                        pieces.push(Piece::Placeholder(name));
+                        start = close + 1;
+                        i = close + 1;
+                    }
+                    _ => i += 1,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    pieces.push(Piece::Text(&expression[start..]));
+    pieces
+}
+
+/// The index just past the string literal whose opening quote is at `open`. Raw strings
+/// (prefixed `r` or `R`) have no escapes, and three quotes open a multi-line string.
+fn skip_string(expression: &str, open: usize, quote: u8) -> usize {
+    let bytes = expression.as_bytes();
+    let raw = expression[..open]
+        .bytes()
+        .rev()
+        .take_while(u8::is_ascii_alphabetic)
+        .any(|c| c == b'r' || c == b'R');
+    let triple = [quote; 3];
+    let (closing, mut i): (&[u8], usize) = if bytes[open..].starts_with(&triple) {
+        (&triple, open + 3)
+    } else {
+        (&triple[..1], open + 1)
+    };
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && !raw {
+            i += 2;
+        } else if bytes[i..].starts_with(closing) {
+            return i + closing.len();
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    fn parameters(declared: &[(&str, &str)]) -> BTreeMap<String, CelType> {
+        declared
+            .iter()
+            .map(|(name, text)| (name.to_string(), CelType::parse(text).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let registered = Registered::new(
+            "size(items) > {threshold} && name != '{threshold}' && {name} in {'a': 1}".to_string(),
+            parameters(&[("threshold", "int"), ("name", "string")]),
+            None,
+        )
+        .unwrap();
+        let arguments = |value: Value| value.as_object().unwrap().clone();
+        assert_eq!(
+            registered.instantiate(&arguments(json!({"threshold": 2, "name": "x' || true"}))),
+            Ok(
+                "size(items) > 2 && name != '{threshold}' && \"x' || true\" in {'a': 1}"
+                    .to_string()
+            )
+        );
+        assert!(
+            registered
+                .instantiate(&arguments(json!({"threshold": "2", "name": "x"})))
+                .is_err()
+        );
+        assert_eq!(
+            registered.instantiate(&arguments(json!({"threshold": 2}))),
+            Err("Missing parameter `name`".to_string())
+        );
+        assert_eq!(
+            registered.instantiate(&arguments(json!({"threshold": 2, "name": "x", "y": 1}))),
+            Err("Unknown parameter `y`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_compose() {
+        let registry = ExpressionRegistry::default();
+        let register = |name: &str, expression: &str, declared: &[(&str, &str)]| {
+            let registered =
+                Registered::new(expression.to_string(), parameters(declared), None).unwrap();
+            registry.register(name.to_string(), registered).unwrap();
+        };
+        register("big", "size(items) > {max}", &[("max", "int")]);
+        register("owned", "owner == {user} || admin", &[("user", "string")]);
+        register("small", "size(items) < {max}", &[("max", "uint")]);
+        let composition: Composition = rmcp::serde_json::from_value(json!({
+            "and": [{"expression": "big"}, {"not": {"expression": "owned"}}]
+        }))
+        .unwrap();
+        let composed = registry.compose(&composition, None).unwrap();
+        assert_eq!(
+            composed.expression,
+            "((size(items) > {max}) && !(owner == {user} || admin))"
+        );
+        assert_eq!(
+            composed.parameters,
+            parameters(&[("max", "int"), ("user", "string")])
+        );
+
+        let conflicting = Composition::Or(vec![
+            Composition::Expression("big".to_string()),
+            Composition::Expression("small".to_string()),
+        ]);
+        assert!(registry.compose(&conflicting, None).is_err());
+        assert!(
+            registry
+                .compose(&Composition::And(Vec::new()), None)
+                .is_err()
+        );
+        let unknown = Composition::Expression("nope".to_string());
+        assert!(registry.compose(&unknown, None).is_err());
+    }
+
+    #[test]
+    fn test_placeholders_must_match_parameters() {
+        let register = |expression: &str, declared: &[(&str, &str)]| {
+            Registered::new(expression.to_string(), parameters(declared), None)
+        };
+        assert!(register("x > {min}", &[("min", "int")]).is_ok());
+        assert!(
+            register(
+                "r'{min}' + \"\"\"{min}\"\"\" == {min}",
+                &[("min", "string")]
+            )
+            .is_ok()
+        );
+        assert!(register("x > {min}", &[]).is_err());
+        assert!(register("x > 1", &[("min", "int")]).is_err());
+        assert!(register("x > {min} &&", &[("min", "int")]).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+//! Settings replaced at runtime by the `reload` tool or `SIGHUP`, without restarting the
+//! server. Evaluations already running keep the values they started with.
+
+use crate::policy::PolicySet;
+use crate::provider::VariableResolver;
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::Serialize;
+use rmcp::serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// A value shared by clones and replaced as a whole.
+pub struct Reloadable<T>(Arc<RwLock<Arc<T>>>);
+
+impl<T> Reloadable<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(value))))
+    }
+
+    /// The current value; later replacements do not affect it.
+    pub fn get(&self) -> Arc<T> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn set(&self, value: T) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(value);
+    }
+}
+
+impl<T> Clone for Reloadable<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Default> Default for Reloadable<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// Everything a reload replaces.
+pub struct Settings {
+    pub base_context: Map<String, Value>,
+    pub resolvers: Vec<Box<dyn VariableResolver>>,
+    pub policies: BTreeMap<String, PolicySet>,
+}
+
+/// What a reload installed.
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+pub struct ReloadSummary {
+    pub base_context_variables: usize,
+    pub resolvers: usize,
+    /// Names of the policy sets.
+    pub policies: Vec<String>,
+}
+
+/// Produces fresh settings, typically by re-reading the config and base context files.
+pub type SettingsLoader = Arc<dyn Fn() -> Result<Settings, String> + Send + Sync>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_replacements() {
+        let value = Reloadable::new(1);
+        let clone = value.clone();
+        let before = value.get();
+        clone.set(2);
+        assert_eq!((*before, *value.get()), (1, 2));
+    }
+}
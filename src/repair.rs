@@ -0,0 +1,87 @@
+//! Corrected versions of expressions that fail to compile, asked of the client's model through
+//! MCP sampling when the server runs with `--suggest-repairs`.
+//!
+//! A reply counts as a suggestion only if it compiles and differs from the expression, so
+//! clients never see a suggestion that fails the same way.
+
+use crate::ast;
+use rmcp::model::{CreateMessageRequestParams, CreateMessageResult, SamplingMessage};
+
+/// Upper bound on the length of the model's reply.
+const MAX_TOKENS: u32 = 512;
+
+const SYSTEM_PROMPT: &str = "You fix Common Expression Language (CEL) expressions. Reply with \
+    only the corrected expression, without explanation or formatting.";
+
+/// The sampling request asking for a fix of `expression`, which failed with `error`.
+pub(crate) fn request(expression: &str, error: &str) -> CreateMessageRequestParams {
+    let prompt = format!(
+        "This CEL expression fails to compile:\n\n{}\n\nError: {}",
+        expression, error
+    );
+    CreateMessageRequestParams::new(vec![SamplingMessage::user_text(prompt)], MAX_TOKENS)
+        .with_system_prompt(SYSTEM_PROMPT)
+        .with_temperature(0.0)
+}
+
+/// The expression the model replied with, unless it's no fix of `expression`. A code fence
+/// or backticks around the reply are removed.
+pub(crate) fn suggestion(expression: &str, result: &CreateMessageResult) -> Option<String> {
+    let reply = result.message.content.first()?.as_text()?.text.trim();
+    let reply = match reply.strip_prefix("```") {
+        // The first line of a fence is its language tag.
+        Some(fenced) => {
+            let fenced = fenced.trim_end_matches("```");
+            fenced.split_once('\n').map_or(fenced, |(_, body)| body)
+        }
+        None => reply.trim_matches('`'),
+    }
+    .trim();
+    (!reply.is_empty() && reply != expression.trim() && ast::parse(reply).is_ok())
+        .then(|| reply.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(text: &str) -> CreateMessageResult {
+        CreateMessageResult::new(SamplingMessage::assistant_text(text), "test".to_string())
+    }
+
+    #[test]
+    fn test_suggestion() {
+        let expression = "size(items) >";
+        assert_eq!(
+            suggestion(expression, &reply("size(items) > 0")),
+            Some("size(items) > 0".to_string())
+        );
+        assert_eq!(
+            suggestion(expression, &reply("```cel\nsize(items) > 0\n```")),
+            Some("size(items) > 0".to_string())
+        );
+        assert_eq!(
+            suggestion(expression, &reply("`size(items) > 0`")),
+            Some("size(items) > 0".to_string())
+        );
+        assert_eq!(suggestion(expression, &reply("size(items) >")), None);
+        assert_eq!(
+            suggestion(expression, &reply("Try comparing with 0.")),
+            None
+        );
+        assert_eq!(suggestion(expression, &reply("")), None);
+    }
+
+    #[test]
+    fn test_request_quotes_expression_and_error() {
+        let params = request("a +", "Syntax error: mismatched input");
+        let prompt = params.messages[0]
+            .content
+            .first()
+            .unwrap()
+            .as_text()
+            .unwrap();
+        assert!(prompt.text.contains("a +\n\nError: Syntax error"));
+        assert_eq!(params.max_tokens, MAX_TOKENS);
+    }
+}
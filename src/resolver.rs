@@ -0,0 +1,148 @@
+//! Built-in [`VariableResolver`]s: environment variables, a JSON file, and an HTTP lookup.
+
+use crate::config::{self, ResolverConfig};
+use crate::provider::VariableResolver;
+use rmcp::serde_json::{self, Map, Value};
+use std::path::Path;
+
+/// Resolves `name` from the environment variable `<prefix><name>`. Values that parse as JSON
+/// are used as such; anything else is a string.
+pub struct EnvResolver {
+    prefix: String,
+}
+
+impl EnvResolver {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+impl VariableResolver for EnvResolver {
+    fn resolve(&self, name: &str) -> Result<Option<Value>, String> {
+        Ok(std::env::var(format!("{}{}", self.prefix, name))
+            .ok()
+            .map(|raw| serde_json::from_str(&raw).unwrap_or(Value::String(raw))))
+    }
+}
+
+/// Resolves variables from the top-level keys of a JSON object file.
+pub struct FileResolver {
+    variables: Map<String, Value>,
+}
+
+impl FileResolver {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Ok(Self {
+            variables: config::read_json_object(path)?,
+        })
+    }
+}
+
+impl VariableResolver for FileResolver {
+    fn resolve(&self, name: &str) -> Result<Option<Value>, String> {
+        Ok(self.variables.get(name).cloned())
+    }
+}
+
+/// Fetches allowlisted variables as JSON with `GET <url>/<name>`; a 404 means "unknown".
+#[cfg(feature = "http-resolver")]
+pub struct HttpResolver {
+    url: String,
+    allow: Vec<String>,
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "http-resolver")]
+impl HttpResolver {
+    pub fn new(url: impl Into<String>, allow: Vec<String>, timeout: std::time::Duration) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(timeout))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Self {
+            url: url.into(),
+            allow,
+            agent,
+        }
+    }
+}
+
+#[cfg(feature = "http-resolver")]
+impl VariableResolver for HttpResolver {
+    fn resolve(&self, name: &str) -> Result<Option<Value>, String> {
+        if !self.allow.iter().any(|allowed| allowed == name) {
+            return Ok(None);
+        }
+        let url = format!("{}/{}", self.url.trim_end_matches('/'), name);
+        let mut response = self
+            .agent
+            .get(&url)
+            .call()
+            .map_err(|e| format!("Failed to resolve `{}`: {}", name, e))?;
+        match response.status().as_u16() {
+            404 => Ok(None),
+            200..=299 => {
+                let body = response
+                    .body_mut()
+                    .read_to_string()
+                    .map_err(|e| format!("Failed to resolve `{}`: {}", name, e))?;
+                serde_json::from_str(&body)
+                    .map(Some)
+                    .map_err(|e| format!("Invalid JSON for `{}`: {}", name, e))
+            }
+            status => Err(format!(
+                "Failed to resolve `{}`: HTTP status {}",
+                name, status
+            )),
+        }
+    }
+}
+
+/// Builds the resolver described by a config entry.
+pub fn from_config(config: &ResolverConfig) -> Result<Box<dyn VariableResolver>, String> {
+    match config {
+        ResolverConfig::Env { prefix } => Ok(Box::new(EnvResolver::new(prefix.clone()))),
+        ResolverConfig::File { path } => Ok(Box::new(FileResolver::load(path)?)),
+        #[cfg(feature = "http-resolver")]
+        ResolverConfig::Http {
+            url,
+            allow,
+            timeout_ms,
+        } => Ok(Box::new(HttpResolver::new(
+            url.clone(),
+            allow.clone(),
+            std::time::Duration::from_millis(*timeout_ms),
+        ))),
+        #[cfg(not(feature = "http-resolver"))]
+        ResolverConfig::Http { .. } => {
+            Err("HTTP resolvers require the `http-resolver` feature".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_file_resolver_supplies_missing_variables() {
+        let path = std::env::temp_dir().join(format!("cel-vars-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"limit": 10, "region": "eu"}"#).unwrap();
+        let resolver = FileResolver::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let evaluator = Evaluator::new().with_variable_resolver(resolver);
+        let result = evaluator.evaluate(
+            "region == 'eu' && limit > 5 && n == 1",
+            &json!({"n": 1, "region": "eu"}),
+            Profile::Default,
+        );
+        assert_eq!(result, Ok(json!(true)));
+    }
+}
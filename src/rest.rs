@@ -0,0 +1,309 @@
+//! Plain HTTP JSON endpoints for callers that don't speak MCP, served next to `/mcp` and
+//! sharing its evaluator, limits, audit log and request IDs.
+
+use crate::cel_tool::{CelTool, Overrides, REQUEST_ID_HEADER, client_request_id};
+use crate::evaluator::{NonFinite, Priority, Query};
+use crate::media::{self, Format};
+use crate::merge::MergeStrategy;
+use crate::quota;
+use axum::extract::State;
+use axum::http::{Extensions, HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use rmcp::model::ErrorCode;
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::{Deserialize, Serialize};
+use rmcp::serde_json::{self, Map, Value};
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+pub(crate) struct EvaluateRequest {
+    expression: String,
+    #[serde(default)]
+    context: Map<String, Value>,
+    /// How `context` combines with the server's base context.
+    #[serde(default)]
+    merge_strategy: MergeStrategy,
+    /// How NaN and ±Infinity results are written; defaults to the server's setting.
+    #[serde(default)]
+    non_finite: Option<NonFinite>,
+    /// Aborts the evaluation once it costs more than this; the server's limit still applies.
+    #[serde(default)]
+    cost_limit: Option<u64>,
+    /// Whether selecting a key missing from a map is an error; defaults to the server's setting.
+    #[serde(default)]
+    strict_attributes: Option<bool>,
+    /// A configured environment to evaluate in; the server's defaults when absent.
+    #[serde(default)]
+    environment: Option<String>,
+    /// `interactive` (default) or `batch`.
+    #[serde(default)]
+    priority: Option<Priority>,
+    /// Evaluates even if the result is cached.
+    #[serde(default)]
+    bypass_cache: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+pub(crate) struct EvaluateResponse {
+    result: Value,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+pub(crate) struct ErrorResponse {
+    error: String,
+    request_id: String,
+    /// Further details, e.g. `reason` and `limit` for rejected contexts.
+    #[serde(flatten)]
+    details: Map<String, Value>,
+}
+
+/// Routes `POST /v1/evaluate`.
+pub fn router(tool: CelTool) -> Router {
+    Router::new()
+        .route("/v1/evaluate", post(evaluate))
+        .with_state(tool)
+}
+
+/// Responds with the result, or with an error and status 400 for invalid requests, 429 once the
+/// caller's quota is used up and 500 for evaluation failures, in the first format `Accept`
+/// allows that can hold the result; 406 if there is none.
+async fn evaluate(
+    State(tool): State<CelTool>,
+    headers: HeaderMap,
+    extensions: Extensions,
+    Json(request): Json<EvaluateRequest>,
+) -> Response {
+    let request_id = client_request_id(
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    );
+    let formats = Format::acceptable(
+        headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok()),
+    );
+    if formats.is_empty() {
+        return not_acceptable(request_id);
+    }
+    tracing::info!(
+        "POST /v1/evaluate called with expression: {:?}",
+        request.expression
+    );
+    let outcome = tool
+        .with_account(quota::account(&extensions))
+        .query_with(
+            "rest_evaluate",
+            &request_id,
+            Query::Expression(request.expression),
+            request.context,
+            request.merge_strategy,
+            Overrides {
+                non_finite: request.non_finite,
+                cost_limit: request.cost_limit,
+                strict_attributes: request.strict_attributes,
+                environment: request.environment,
+                priority: request.priority,
+                bypass_cache: request.bypass_cache,
+            },
+        )
+        .await;
+    match outcome {
+        Ok(result) => {
+            let response = EvaluateResponse { result };
+            formats
+                .iter()
+                .find_map(|format| {
+                    let body = match format {
+                        Format::Text => format.render(&response.result),
+                        _ => format.render(&serde_json::to_value(&response).ok()?),
+                    }?;
+                    Some(respond(StatusCode::OK, *format, body))
+                })
+                .unwrap_or_else(|| not_acceptable(request_id))
+        }
+        Err(e) => {
+            let status = match e.code {
+                ErrorCode::INVALID_PARAMS => StatusCode::BAD_REQUEST,
+                // Refused for exceeding the caller's quota.
+                ErrorCode::INVALID_REQUEST => StatusCode::TOO_MANY_REQUESTS,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let mut details = match e.data {
+                Some(Value::Object(data)) => data,
+                _ => Map::new(),
+            };
+            details.remove("request_id");
+            let body = ErrorResponse {
+                error: e.message.to_string(),
+                request_id,
+                details,
+            };
+            respond_error(status, formats[0], &body)
+        }
+    }
+}
+
+/// An error in `format`; plain text carries only the message.
+fn respond_error(status: StatusCode, format: Format, body: &ErrorResponse) -> Response {
+    let text = match format {
+        Format::Text => Some(format!("{}\n", body.error)),
+        _ => serde_json::to_value(body)
+            .ok()
+            .and_then(|body| format.render(&body)),
+    };
+    respond(status, format, text.unwrap_or_else(|| body.error.clone()))
+}
+
+fn not_acceptable(request_id: String) -> Response {
+    let body = ErrorResponse {
+        error: format!(
+            "No acceptable format for the result; accept {}, {} or, for scalars, {}",
+            media::JSON,
+            media::YAML,
+            media::TEXT
+        ),
+        request_id,
+        details: Map::new(),
+    };
+    respond_error(StatusCode::NOT_ACCEPTABLE, Format::Json, &body)
+}
+
+fn respond(status: StatusCode, format: Format, body: String) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, format.content_type())],
+        body,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[tokio::test]
+    async fn test_evaluate_endpoint() {
+        let tool = CelTool::builder().build();
+        let request = |expression: &str| EvaluateRequest {
+            expression: expression.to_string(),
+            context: json!({"name": "cel"})
+                .as_object()
+                .cloned()
+                .unwrap_or_default(),
+            merge_strategy: MergeStrategy::Replace,
+            non_finite: None,
+            cost_limit: None,
+            strict_attributes: None,
+            environment: None,
+            priority: None,
+            bypass_cache: false,
+        };
+        let call = |accept: Option<&str>, expression: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(REQUEST_ID_HEADER, "req-6".parse().unwrap());
+            if let Some(accept) = accept {
+                headers.insert(header::ACCEPT, accept.parse().unwrap());
+            }
+            let response = evaluate(
+                State(tool.clone()),
+                headers,
+                Extensions::new(),
+                Json(request(expression)),
+            );
+            async move {
+                let response = response.await;
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+        assert_eq!(
+            call(None, "'hello ' + name").await,
+            (StatusCode::OK, r#"{"result":"hello cel"}"#.to_string())
+        );
+        assert_eq!(
+            call(Some("text/plain"), "'hello ' + name").await,
+            (StatusCode::OK, "hello cel\n".to_string())
+        );
+        assert_eq!(
+            call(Some("application/yaml"), "[name]").await,
+            (StatusCode::OK, "\"result\":\n  - \"cel\"\n".to_string())
+        );
+        assert_eq!(
+            call(Some("text/plain"), "[name]").await.0,
+            StatusCode::NOT_ACCEPTABLE
+        );
+
+        let (status, body) = call(None, "1 +").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["request_id"], "req-6");
+    }
+
+    #[tokio::test]
+    async fn test_quota_per_account() {
+        let tool = CelTool::builder()
+            .with_quota(quota::Quota {
+                max_evaluations: Some(1),
+                ..quota::Quota::default()
+            })
+            .build();
+        let call = |token: &str, account: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, token.parse().unwrap());
+            let mut extensions = Extensions::new();
+            if let Some(account) = account {
+                extensions.insert(quota::Account::new(account));
+            }
+            let request = EvaluateRequest {
+                expression: "1 + 1".to_string(),
+                context: Map::new(),
+                merge_strategy: MergeStrategy::Replace,
+                non_finite: None,
+                cost_limit: None,
+                strict_attributes: None,
+                environment: None,
+                priority: None,
+                bypass_cache: false,
+            };
+            let response = evaluate(State(tool.clone()), headers, extensions, Json(request));
+            async move {
+                let response = response.await;
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: Value = serde_json::from_slice(&body).unwrap();
+                (status, body)
+            }
+        };
+        // Unauthenticated tokens share the anonymous account's quota.
+        assert_eq!(call("Bearer alice", None).await.0, StatusCode::OK);
+        let (status, body) = call("Bearer bob", None).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(body["reason"], "quota_exceeded");
+        assert_eq!(body["limit"], "max_evaluations");
+
+        // Authenticated callers each have their own.
+        assert_eq!(
+            call("Bearer alice", Some("key-alice")).await.0,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call("Bearer alice", Some("key-alice")).await.0,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(call("Bearer bob", Some("key-bob")).await.0, StatusCode::OK);
+    }
+}
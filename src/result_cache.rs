@@ -0,0 +1,245 @@
+//! Evaluation results keyed by expression fingerprint and context hash, for workloads where
+//! the same checks recur on identical payloads.
+//!
+//! The cache is off unless given a capacity, and holds at most [`DEFAULT_MAX_BYTES`] of keys
+//! and results unless given another bound. Entries expire after an optional TTL. Callers leave
+//! out results that depend on more than the key, such as those of `http.get`.
+
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::Serialize;
+use rmcp::serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Bytes of keys and results a [`ResultCache`] holds unless told otherwise.
+pub const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// A bounded, thread-safe map from request keys to results.
+pub struct ResultCache {
+    max_entries: usize,
+    max_bytes: usize,
+    ttl: Option<Duration>,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for ResultCache {
+    fn default() -> Self {
+        Self {
+            max_entries: 0,
+            max_bytes: DEFAULT_MAX_BYTES,
+            ttl: None,
+            entries: Mutex::default(),
+            hits: AtomicU64::default(),
+            misses: AtomicU64::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    results: HashMap<String, Entry>,
+    order: VecDeque<String>,
+    /// Bytes of the keys and results held.
+    bytes: usize,
+    /// Bumped by every [`ResultCache::clear`].
+    generation: u64,
+}
+
+struct Entry {
+    value: Value,
+    inserted: Instant,
+    bytes: usize,
+}
+
+impl Entries {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.results.remove(key) {
+            self.bytes -= entry.bytes;
+        }
+    }
+}
+
+/// How well a [`ResultCache`] is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+pub struct ResultCacheStats {
+    pub entries: usize,
+    /// Bytes of the keys and results held.
+    pub bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl ResultCache {
+    /// Keeps up to `max_entries` results (`0` disables caching), each for at most `ttl`.
+    pub fn new(max_entries: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            max_entries,
+            ttl,
+            ..Self::default()
+        }
+    }
+
+    /// Evicts the oldest results to keep the keys and results within `max_bytes`.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_entries > 0
+    }
+
+    /// The cached result for `key`, counting the lookup as a hit or a miss.
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let result = self.lookup(key);
+        let counter = if result.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    fn lookup(&self, key: &str) -> Option<Value> {
+        let mut entries = self.entries.lock().ok()?;
+        let entry = entries.results.get(key)?;
+        if self.ttl.is_some_and(|ttl| entry.inserted.elapsed() >= ttl) {
+            entries.remove(key);
+            entries.order.retain(|k| k != key);
+            return None;
+        }
+        Some(entry.value.clone())
+    }
+
+    /// Counts [`clear`](Self::clear) calls. Take it before evaluating and pass it to
+    /// [`insert`](Self::insert), so a result computed from settings cleared meanwhile is
+    /// dropped.
+    pub fn generation(&self) -> u64 {
+        self.entries
+            .lock()
+            .map_or(u64::MAX, |entries| entries.generation)
+    }
+
+    /// Keeps `value` under `key`, unless the cache was cleared since `generation` was taken or
+    /// the pair alone exceeds the byte bound.
+    pub fn insert(&self, key: String, value: Value, generation: u64) {
+        if !self.is_enabled() {
+            return;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        let bytes = key.len() + value.to_string().len();
+        if entries.generation != generation || bytes > self.max_bytes {
+            return;
+        }
+        if entries.results.contains_key(&key) {
+            entries.remove(&key);
+        } else {
+            entries.order.push_back(key.clone());
+        }
+        while entries.order.len() > self.max_entries || entries.bytes + bytes > self.max_bytes {
+            let Some(oldest) = entries.order.pop_front() else {
+                break;
+            };
+            if oldest == key {
+                entries.order.push_back(oldest);
+                continue;
+            }
+            entries.remove(&oldest);
+        }
+        entries.bytes += bytes;
+        entries.results.insert(
+            key,
+            Entry {
+                value,
+                inserted: Instant::now(),
+                bytes,
+            },
+        );
+    }
+
+    /// Drops every result, e.g. once the base context or policies they depend on change, and
+    /// every result still being computed from them.
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            *entries = Entries {
+                generation: entries.generation + 1,
+                ..Entries::default()
+            };
+        }
+    }
+
+    pub fn stats(&self) -> ResultCacheStats {
+        let (entries, bytes) = self
+            .entries
+            .lock()
+            .map_or((0, 0), |entries| (entries.results.len(), entries.bytes));
+        ResultCacheStats {
+            entries,
+            bytes,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_counts_hits_and_evicts() {
+        let cache = ResultCache::new(1, None);
+        assert_eq!(cache.get("a"), None);
+        cache.insert("a".to_string(), json!(1), 0);
+        assert_eq!(cache.get("a"), Some(json!(1)));
+        cache.insert("b".to_string(), json!(2), 0);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(
+            cache.stats(),
+            ResultCacheStats {
+                entries: 1,
+                bytes: 2,
+                hits: 1,
+                misses: 2
+            }
+        );
+        cache.clear();
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn test_bounds_bytes() {
+        let cache = ResultCache::new(16, None).with_max_bytes(10);
+        cache.insert("a".to_string(), json!("1234"), 0);
+        cache.insert("b".to_string(), json!("1234"), 0);
+        assert_eq!(cache.stats().bytes, 7);
+        assert_eq!(
+            (cache.get("a"), cache.get("b")),
+            (None, Some(json!("1234")))
+        );
+        cache.insert("c".to_string(), json!("too long to keep"), 0);
+        assert_eq!(cache.get("c"), None);
+        assert_eq!(cache.get("b"), Some(json!("1234")));
+    }
+
+    #[test]
+    fn test_drops_results_older_than_a_clear() {
+        let cache = ResultCache::new(16, None);
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert("a".to_string(), json!(1), generation);
+        assert_eq!(cache.get("a"), None);
+        cache.insert("a".to_string(), json!(1), cache.generation());
+        assert_eq!(cache.get("a"), Some(json!(1)));
+    }
+}
@@ -0,0 +1,252 @@
+//! Rewrite rules from the `--config` file's `rewrites`, applied to the syntax tree of every
+//! expression before it is compiled, for migrations such as renaming a deprecated function.
+//!
+//! An expression a rule changes is printed back from its tree (see [`unparse`]), so the
+//! rewritten text loses comments and formatting. Expressions no rule changes are compiled as
+//! sent.
+
+use crate::ast;
+use crate::unparse::unparse;
+use cel::common::ast::{CallExpr, EntryExpr, Expr};
+use cel::parser::Expression;
+use rmcp::serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(
+    crate = "rmcp::serde",
+    tag = "type",
+    rename_all = "snake_case",
+    deny_unknown_fields
+)]
+pub enum RewriteRule {
+    /// Calls of `from`, such as `foo(x)`, `s.foo()` or, for `ns.foo`, `ns.foo(x)`, become
+    /// calls of `to`.
+    RenameFunction { from: String, to: String },
+    /// The variable `name` becomes `function(name)`, such as `timestamp(created)`, where it
+    /// isn't already.
+    WrapVariable { name: String, function: String },
+}
+
+impl RewriteRule {
+    fn check(&self) -> Result<(), String> {
+        let names = match self {
+            RewriteRule::RenameFunction { from, to } => [from, to],
+            RewriteRule::WrapVariable { name, function } => [name, function],
+        };
+        match names.into_iter().find(|name| !is_qualified_name(name)) {
+            Some(name) => Err(format!("`{}` is not a valid name in a rewrite rule", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies the rule to `expr` and its sub-expressions; `bound` holds the variables
+    /// macros bind around it. Returns whether anything changed.
+    fn apply(&self, expr: &mut Expression, bound: &mut Vec<String>) -> bool {
+        match &mut expr.expr {
+            Expr::Ident(ident) => match self {
+                RewriteRule::WrapVariable { name, function }
+                    if ident == name && !bound.contains(name) =>
+                {
+                    let variable = std::mem::take(&mut expr.expr);
+                    expr.expr = Expr::Call(CallExpr {
+                        func_name: function.clone(),
+                        target: None,
+                        args: vec![Expression {
+                            id: expr.id,
+                            expr: variable,
+                        }],
+                    });
+                    true
+                }
+                _ => false,
+            },
+            Expr::Call(call) => {
+                if let RewriteRule::WrapVariable { name, function } = self
+                    && call.target.is_none()
+                    && call.func_name == *function
+                    && let [arg] = call.args.as_slice()
+                    && matches!(&arg.expr, Expr::Ident(ident) if ident == name)
+                {
+                    return false;
+                }
+                let mut changed = match self {
+                    RewriteRule::RenameFunction { from, to } => rename(call, from, to),
+                    RewriteRule::WrapVariable { .. } => false,
+                };
+                if let Some(target) = &mut call.target {
+                    changed |= self.apply(target, bound);
+                }
+                for arg in &mut call.args {
+                    changed |= self.apply(arg, bound);
+                }
+                changed
+            }
+            Expr::Comprehension(comp) => {
+                let mut changed = self.apply(&mut comp.iter_range, bound);
+                changed |= self.apply(&mut comp.accu_init, bound);
+                let scope = bound.len();
+                bound.extend(comp.iter_var2.iter().cloned());
+                bound.push(comp.iter_var.clone());
+                bound.push(comp.accu_var.clone());
+                changed |= self.apply(&mut comp.loop_cond, bound);
+                changed |= self.apply(&mut comp.loop_step, bound);
+                changed |= self.apply(&mut comp.result, bound);
+                bound.truncate(scope);
+                changed
+            }
+            Expr::List(list) => list.elements.iter_mut().fold(false, |changed, element| {
+                self.apply(element, bound) | changed
+            }),
+            Expr::Map(map) => map
+                .entries
+                .iter_mut()
+                .fold(false, |changed, entry| match &mut entry.expr {
+                    EntryExpr::MapEntry(e) => {
+                        self.apply(&mut e.key, bound) | self.apply(&mut e.value, bound) | changed
+                    }
+                    EntryExpr::StructField(_) => changed,
+                }),
+            Expr::Struct(st) => {
+                st.entries
+                    .iter_mut()
+                    .fold(false, |changed, entry| match &mut entry.expr {
+                        EntryExpr::StructField(field) => {
+                            self.apply(&mut field.value, bound) | changed
+                        }
+                        EntryExpr::MapEntry(_) => changed,
+                    })
+            }
+            Expr::Select(select) => self.apply(&mut select.operand, bound),
+            Expr::Literal(_) | Expr::Unspecified => false,
+        }
+    }
+}
+
+/// Renames `call` if it calls `from`, which may be qualified with the namespace the function
+/// is called on.
+fn rename(call: &mut CallExpr, from: &str, to: &str) -> bool {
+    let namespaced = match (&call.target, from.rsplit_once('.')) {
+        (Some(target), Some((namespace, name))) => {
+            call.func_name == name
+                && matches!(&target.expr, Expr::Ident(ident) if ident == namespace)
+        }
+        _ => false,
+    };
+    if namespaced {
+        match (to.rsplit_once('.'), &mut call.target) {
+            (Some((namespace, name)), Some(target)) => {
+                target.expr = Expr::Ident(namespace.to_string());
+                call.func_name = name.to_string();
+            }
+            _ => {
+                call.target = None;
+                call.func_name = to.to_string();
+            }
+        }
+        true
+    } else if call.func_name == from {
+        call.func_name = to.to_string();
+        true
+    } else {
+        false
+    }
+}
+
+fn is_qualified_name(name: &str) -> bool {
+    name.split('.').all(|part| {
+        let mut chars = part.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// The configured rules, applied in order.
+#[derive(Debug, Default)]
+pub struct Rewriter {
+    rules: Vec<RewriteRule>,
+}
+
+impl Rewriter {
+    /// Fails if a rule names something that isn't a function or variable name.
+    pub fn new(rules: Vec<RewriteRule>) -> Result<Self, String> {
+        rules.iter().try_for_each(RewriteRule::check)?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// `expression` rewritten by the rules, or `None` if none changes it. An expression that
+    /// doesn't parse is left for the compiler to report.
+    pub fn rewrite(&self, expression: &str) -> Result<Option<String>, String> {
+        if self.rules.is_empty() {
+            return Ok(None);
+        }
+        let Ok(mut parsed) = ast::parse(expression) else {
+            return Ok(None);
+        };
+        let changed = self.rules.iter().fold(false, |changed, rule| {
+            rule.apply(&mut parsed, &mut Vec::new()) | changed
+        });
+        if !changed {
+            return Ok(None);
+        }
+        unparse(&parsed)
+            .map(Some)
+            .map_err(|e| format!("Failed to rewrite expression: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        let rewriter = Rewriter::new(vec![
+            RewriteRule::RenameFunction {
+                from: "legacyMatch".to_string(),
+                to: "matches".to_string(),
+            },
+            RewriteRule::RenameFunction {
+                from: "kv.lookup".to_string(),
+                to: "kv.get".to_string(),
+            },
+            RewriteRule::WrapVariable {
+                name: "created".to_string(),
+                function: "timestamp".to_string(),
+            },
+        ])
+        .unwrap();
+        assert_eq!(
+            rewriter.rewrite("legacyMatch(name, '^a') && kv.lookup(k) == name.legacyMatch('b')"),
+            Ok(Some(
+                "matches(name, \"^a\") && kv.get(k) == name.matches(\"b\")".to_string()
+            ))
+        );
+        assert_eq!(
+            rewriter.rewrite("created < now && items.all(created, created > 0)"),
+            Ok(Some(
+                "timestamp(created) < now && items.all(created, created > 0)".to_string()
+            ))
+        );
+        assert_eq!(
+            rewriter.rewrite("timestamp(created) < now // already wrapped"),
+            Ok(None)
+        );
+        assert_eq!(rewriter.rewrite("size(x) > 1"), Ok(None));
+        assert_eq!(rewriter.rewrite("size(x) >"), Ok(None));
+    }
+
+    #[test]
+    fn test_rules_are_checked() {
+        let rule = RewriteRule::RenameFunction {
+            from: "_+_".to_string(),
+            to: "add".to_string(),
+        };
+        assert!(Rewriter::new(vec![rule]).is_err());
+    }
+}
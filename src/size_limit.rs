@@ -0,0 +1,377 @@
+//! `--max-request-bytes`: the largest request the server reads, so that an oversized context
+//! is refused as it arrives rather than buffered whole. Over HTTP, a larger body gets status
+//! 413; over stdio, a larger MCP message gets an `invalid_request` error.
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{DefaultBodyLimit, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use http_body_util::LengthLimitError;
+use regex::Regex;
+use rmcp::RoleServer;
+use rmcp::model::{ClientJsonRpcMessage, ErrorData, NumberOrString, ServerJsonRpcMessage};
+use rmcp::serde_json::{self, json};
+use rmcp::transport::Transport;
+use std::sync::{Arc, LazyLock};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+
+pub const DEFAULT_MAX_REQUEST_BYTES: usize = 4 << 20;
+
+/// How much of an oversized stdio message is kept to find its ID.
+const HEAD_BYTES: usize = 1024;
+
+fn too_large(max_bytes: usize) -> String {
+    format!(
+        "The request is larger than --max-request-bytes ({} bytes)",
+        max_bytes
+    )
+}
+
+/// Makes `router` refuse request bodies larger than `max_bytes` with status 413, before its
+/// handlers see them. A body declaring a larger `Content-Length` is refused unread.
+pub fn require(router: Router, max_bytes: usize) -> Router {
+    router
+        .layer(middleware::from_fn_with_state(max_bytes, check))
+        .layer(DefaultBodyLimit::disable())
+}
+
+async fn check(State(max_bytes): State<usize>, request: Request, next: Next) -> Response {
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    let refused = || {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("{}\n", too_large(max_bytes)),
+        )
+    };
+    if declared.is_some_and(|length| length > max_bytes as u64) {
+        return refused().into_response();
+    }
+    let (parts, body) = request.into_parts();
+    match axum::body::to_bytes(body, max_bytes).await {
+        Ok(bytes) => {
+            next.run(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        }
+        Err(e) => {
+            let e = e.into_inner();
+            if e.is::<LengthLimitError>() {
+                refused().into_response()
+            } else {
+                let message = format!("Failed to read the request body: {}\n", e);
+                (StatusCode::BAD_REQUEST, message).into_response()
+            }
+        }
+    }
+}
+
+enum Line {
+    Message(Vec<u8>),
+    /// The first bytes of a line longer than the limit.
+    TooLong(Vec<u8>),
+    End,
+}
+
+/// Reads the next line of `input` without its newline. Of a line longer than `max_bytes`, only
+/// the first `HEAD_BYTES` are kept.
+async fn read_line(
+    input: &mut (impl AsyncBufRead + Unpin),
+    max_bytes: usize,
+) -> std::io::Result<Line> {
+    let finish = |mut line: Vec<u8>| {
+        if line.len() > max_bytes {
+            line.truncate(HEAD_BYTES);
+            Line::TooLong(line)
+        } else {
+            Line::Message(line)
+        }
+    };
+    let mut line = Vec::new();
+    loop {
+        let available = input.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(if line.is_empty() {
+                Line::End
+            } else {
+                finish(line)
+            });
+        }
+        let newline = available.iter().position(|byte| *byte == b'\n');
+        let chunk = &available[..newline.unwrap_or(available.len())];
+        // Once the line is known to be too long, and its head is kept, the rest is skipped.
+        if line.len() <= max_bytes.max(HEAD_BYTES) {
+            line.extend_from_slice(chunk);
+        }
+        let consumed = newline.map_or(available.len(), |newline| newline + 1);
+        input.consume(consumed);
+        if newline.is_some() {
+            return Ok(finish(line));
+        }
+    }
+}
+
+/// The ID of the request starting with `head`, if it can be found there: the `id` of the
+/// top-level object, not of one nested in its params.
+fn request_id(head: &[u8]) -> Option<NumberOrString> {
+    static VALUE: LazyLock<Option<Regex>> =
+        LazyLock::new(|| Regex::new(r#"^\s*:\s*(-?\d+|"(?:[^"\\]|\\.)*")"#).ok());
+    let head = String::from_utf8_lossy(head);
+    let mut depth = 0;
+    let mut chars = head.char_indices();
+    let id = loop {
+        let (start, c) = chars.next()?;
+        match c {
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            '"' => {
+                let mut escaped = false;
+                let end = loop {
+                    match chars.next()? {
+                        _ if escaped => escaped = false,
+                        (_, '\\') => escaped = true,
+                        (end, '"') => break end,
+                        _ => {}
+                    }
+                };
+                if depth == 1 && &head[start..=end] == r#""id""# {
+                    let value = VALUE.as_ref()?.captures(&head[end + 1..]);
+                    if let Some(value) = value.and_then(|value| value.get(1)) {
+                        break value.as_str();
+                    }
+                }
+            }
+            _ => {}
+        }
+    };
+    match id.parse::<i64>() {
+        Ok(number) => Some(NumberOrString::Number(number)),
+        Err(_) => serde_json::from_str::<String>(id)
+            .ok()
+            .map(|id| NumberOrString::String(id.into())),
+    }
+}
+
+/// MCP over newline-delimited JSON, refusing messages longer than a limit.
+pub struct LimitedTransport<R, W> {
+    input: R,
+    output: Arc<Mutex<W>>,
+    max_bytes: usize,
+}
+
+impl<R, W> LimitedTransport<R, W> {
+    pub fn new(input: R, output: W, max_bytes: usize) -> Self {
+        Self {
+            input,
+            output: Arc::new(Mutex::new(output)),
+            max_bytes,
+        }
+    }
+}
+
+/// MCP over stdin and stdout, refusing messages longer than `max_bytes`.
+pub fn stdio(max_bytes: usize) -> LimitedTransport<BufReader<tokio::io::Stdin>, tokio::io::Stdout> {
+    LimitedTransport::new(
+        BufReader::new(tokio::io::stdin()),
+        tokio::io::stdout(),
+        max_bytes,
+    )
+}
+
+async fn write<W: AsyncWrite + Unpin>(
+    output: &Mutex<W>,
+    message: &ServerJsonRpcMessage,
+) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(message).map_err(std::io::Error::other)?;
+    line.push(b'\n');
+    let mut output = output.lock().await;
+    output.write_all(&line).await?;
+    output.flush().await
+}
+
+impl<R, W> Transport<RoleServer> for LimitedTransport<R, W>
+where
+    R: AsyncBufRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    type Error = std::io::Error;
+
+    fn send(
+        &mut self,
+        item: ServerJsonRpcMessage,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let output = self.output.clone();
+        async move { write(&output, &item).await }
+    }
+
+    async fn receive(&mut self) -> Option<ClientJsonRpcMessage> {
+        loop {
+            let line = match read_line(&mut self.input, self.max_bytes).await {
+                Ok(Line::Message(line)) => line,
+                Ok(Line::TooLong(head)) => {
+                    tracing::warn!("Refused an MCP message: {}", too_large(self.max_bytes));
+                    if let Some(id) = request_id(&head) {
+                        let error = ErrorData::invalid_request(
+                            too_large(self.max_bytes),
+                            Some(json!({
+                                "reason": "message_too_large",
+                                "max_bytes": self.max_bytes,
+                            })),
+                        );
+                        let reply = ServerJsonRpcMessage::error(error, id);
+                        if let Err(e) = write(&self.output, &reply).await {
+                            tracing::error!("Failed to write to stdout: {}", e);
+                            return None;
+                        }
+                    }
+                    continue;
+                }
+                Ok(Line::End) => return None,
+                Err(e) => {
+                    tracing::error!("Failed to read from stdin: {}", e);
+                    return None;
+                }
+            };
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            match serde_json::from_slice(&line) {
+                Ok(message) => return Some(message),
+                Err(e) => tracing::error!("Ignoring a malformed MCP message: {}", e),
+            }
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        self.output.lock().await.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_read_line() {
+        let input = format!("short\n{}\nlast", "x".repeat(5000));
+        // A small buffer makes long lines span several reads.
+        let mut input = BufReader::with_capacity(64, input.as_bytes());
+        assert!(matches!(
+            read_line(&mut input, 100).await.unwrap(),
+            Line::Message(line) if line == b"short"
+        ));
+        assert!(matches!(
+            read_line(&mut input, 100).await.unwrap(),
+            Line::TooLong(head) if head.len() == HEAD_BYTES
+        ));
+        assert!(matches!(
+            read_line(&mut input, 100).await.unwrap(),
+            Line::Message(line) if line == b"last"
+        ));
+        assert!(matches!(
+            read_line(&mut input, 100).await.unwrap(),
+            Line::End
+        ));
+    }
+
+    #[test]
+    fn test_request_id() {
+        assert_eq!(
+            request_id(br#"{"jsonrpc":"2.0","id":7,"method":"tools/call","params":{"#),
+            Some(NumberOrString::Number(7))
+        );
+        assert_eq!(
+            request_id(br#"{"jsonrpc": "2.0", "id": "a\"b", "method""#),
+            Some(NumberOrString::String("a\"b".into()))
+        );
+        assert_eq!(request_id(br#"{"jsonrpc":"2.0","method":"#), None);
+        // Only the top-level object's `id` is the request's.
+        assert_eq!(
+            request_id(
+                br#"{"params":{"arguments":{"id":"x","ids":[{"id":1}]}},"method":"id","id":9}"#
+            ),
+            Some(NumberOrString::Number(9))
+        );
+        assert_eq!(
+            request_id(br#"{"jsonrpc":"2.0","params":{"context":{"id":5,"blob":"xx"#),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transport_refuses_long_messages() {
+        let call = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "tools/call",
+            "params": {"name": "evaluate", "arguments": {"context": {"blob": "x".repeat(500)}}}
+        });
+        let ping = json!({"jsonrpc": "2.0", "id": 4, "method": "ping"});
+        let input = format!("{}\n\n{}\n", call, ping);
+        let (output, mut replies) = tokio::io::duplex(4096);
+        let mut transport = LimitedTransport::new(input.as_bytes(), output, 200);
+
+        let received = transport.receive().await.unwrap();
+        assert_eq!(
+            serde_json::to_value(&received).unwrap()["method"],
+            json!("ping")
+        );
+        assert!(transport.receive().await.is_none());
+        drop(transport);
+
+        let mut written = String::new();
+        replies.read_to_string(&mut written).await.unwrap();
+        let reply: serde_json::Value = serde_json::from_str(written.trim()).unwrap();
+        assert_eq!(reply["id"], 3);
+        assert_eq!(reply["error"]["code"], -32600);
+        assert_eq!(reply["error"]["data"]["reason"], "message_too_large");
+    }
+
+    #[tokio::test]
+    async fn test_body_limit() {
+        let app = require(
+            Router::new().route(
+                "/",
+                axum::routing::post(
+                    |body: axum::body::Bytes| async move { body.len().to_string() },
+                ),
+            ),
+            16,
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let send = |request: String| async move {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+        let post = |headers: &str, body: &str| {
+            format!(
+                "POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}\r\n{}",
+                headers, body
+            )
+        };
+        let small = send(post("Content-Length: 5\r\n", "hello")).await;
+        assert!(small.starts_with("HTTP/1.1 200"), "{}", small);
+        assert!(small.ends_with("5"));
+        let declared = send(post("Content-Length: 17\r\n", &"x".repeat(17))).await;
+        assert!(declared.starts_with("HTTP/1.1 413"), "{}", declared);
+        assert!(declared.contains("--max-request-bytes (16 bytes)"));
+        let chunked = send(post(
+            "Transfer-Encoding: chunked\r\n",
+            "a\r\nxxxxxxxxxx\r\na\r\nxxxxxxxxxx\r\n0\r\n\r\n",
+        ))
+        .await;
+        assert!(chunked.starts_with("HTTP/1.1 413"), "{}", chunked);
+    }
+}
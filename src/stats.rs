@@ -0,0 +1,137 @@
+//! `stats.*`, summary statistics over lists of numbers for monitoring and alerting
+//! expressions, such as `stats.percentile(latencies, 99) > 250.0`.
+//!
+//! Every function takes a non-empty list of ints, uints and doubles and returns a double.
+
+use crate::provider::{Method, Namespace};
+use cel::{ExecutionError, FunctionContext, Value as CelValue};
+use std::sync::Arc;
+
+/// The namespace the functions are called on.
+pub const VARIABLE: &str = "stats";
+
+type Summary = (&'static str, fn(&[f64]) -> f64);
+
+const SUMMARIES: [Summary; 3] = [("mean", mean), ("median", median), ("stddev", stddev)];
+
+pub(crate) fn names() -> Vec<String> {
+    SUMMARIES
+        .iter()
+        .map(|(name, _)| *name)
+        .chain(["percentile"])
+        .map(|name| format!("{}.{}", VARIABLE, name))
+        .collect()
+}
+
+pub(crate) fn namespace() -> Namespace {
+    let mut methods: Vec<(String, Method)> = SUMMARIES
+        .iter()
+        .map(|&(name, summary)| {
+            let method: Method = Arc::new(move |ftx: &FunctionContext, args: &[CelValue]| {
+                let [list] = args else {
+                    return Err(ftx.error(format!("stats.{} expects one list", name)));
+                };
+                Ok(CelValue::Float(summary(&numbers(ftx, list)?)))
+            });
+            (name.to_string(), method)
+        })
+        .collect();
+    let percentile: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [list, p] = args else {
+            return Err(ftx.error("stats.percentile expects a list and a percentile"));
+        };
+        let p = match p {
+            CelValue::Int(p) => *p as f64,
+            CelValue::UInt(p) => *p as f64,
+            CelValue::Float(p) => *p,
+            _ => return Err(ftx.error("stats.percentile expects a numeric percentile")),
+        };
+        if !(0.0..=100.0).contains(&p) {
+            return Err(ftx.error("stats.percentile expects a percentile from 0 to 100"));
+        }
+        Ok(CelValue::Float(percentile_of(&numbers(ftx, list)?, p)))
+    });
+    methods.push(("percentile".to_string(), percentile));
+    Namespace {
+        name: VARIABLE.to_string(),
+        methods,
+    }
+}
+
+/// The list's numbers, sorted.
+fn numbers(ftx: &FunctionContext, list: &CelValue) -> Result<Vec<f64>, ExecutionError> {
+    let CelValue::List(items) = list else {
+        return Err(ftx.error(format!("expected a list, got {}", list.type_of())));
+    };
+    if items.is_empty() {
+        return Err(ftx.error("expected a non-empty list"));
+    }
+    let mut numbers = items
+        .iter()
+        .map(|item| match item {
+            CelValue::Int(i) => Ok(*i as f64),
+            CelValue::UInt(u) => Ok(*u as f64),
+            CelValue::Float(f) => Ok(*f),
+            other => Err(ftx.error(format!(
+                "expected a list of numbers, got {}",
+                other.type_of()
+            ))),
+        })
+        .collect::<Result<Vec<f64>, _>>()?;
+    numbers.sort_by(f64::total_cmp);
+    Ok(numbers)
+}
+
+fn mean(sorted: &[f64]) -> f64 {
+    sorted.iter().sum::<f64>() / sorted.len() as f64
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    percentile_of(sorted, 50.0)
+}
+
+/// The population standard deviation.
+fn stddev(sorted: &[f64]) -> f64 {
+    let mean = mean(sorted);
+    let variance = sorted.iter().map(|n| (n - mean).powi(2)).sum::<f64>() / sorted.len() as f64;
+    variance.sqrt()
+}
+
+/// Interpolates linearly between the two closest ranks.
+fn percentile_of(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::Evaluator;
+    use crate::library::Library;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_stats_functions() {
+        let evaluator = Evaluator::new().with_function_provider(Library::Stats);
+        let evaluate = |expression: &str| {
+            evaluator.evaluate(
+                expression,
+                &json!({"latencies": [40, 10, 30, 20, 50.0]}),
+                Profile::Default,
+            )
+        };
+        assert_eq!(evaluate("stats.mean(latencies)"), Ok(json!(30.0)));
+        assert_eq!(evaluate("stats.median(latencies)"), Ok(json!(30.0)));
+        assert_eq!(evaluate("stats.median([4, 1, 3, 2])"), Ok(json!(2.5)));
+        assert_eq!(
+            evaluate("stats.stddev([2, 4, 4, 4, 5, 5, 7, 9])"),
+            Ok(json!(2.0))
+        );
+        assert_eq!(evaluate("stats.percentile(latencies, 75)"), Ok(json!(40.0)));
+        assert_eq!(evaluate("stats.percentile(latencies, 0)"), Ok(json!(10.0)));
+        assert!(evaluate("stats.mean([])").is_err());
+        assert!(evaluate("stats.mean(['a'])").is_err());
+        assert!(evaluate("stats.percentile(latencies, 101)").is_err());
+    }
+}
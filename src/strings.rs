@@ -0,0 +1,125 @@
+//! `strings.fields(s)` and `strings.naturalCompare(a, b)`, for whitespace-separated data and
+//! version-like strings that `split` and `<` handle poorly.
+
+use crate::provider::{Method, Namespace};
+use cel::{FunctionContext, Value as CelValue};
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+/// The namespace the functions are called on.
+pub const VARIABLE: &str = "strings";
+
+pub(crate) fn names() -> Vec<String> {
+    vec![
+        format!("{}.fields", VARIABLE),
+        format!("{}.naturalCompare", VARIABLE),
+    ]
+}
+
+pub(crate) fn namespace() -> Namespace {
+    let fields: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::String(s)] = args else {
+            return Err(ftx.error("strings.fields expects one string"));
+        };
+        Ok(CelValue::List(Arc::new(
+            s.split_whitespace().map(CelValue::from).collect(),
+        )))
+    });
+    let natural_compare: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::String(a), CelValue::String(b)] = args else {
+            return Err(ftx.error("strings.naturalCompare expects two strings"));
+        };
+        Ok(CelValue::Int(natural_cmp(a, b) as i64))
+    });
+    Namespace {
+        name: VARIABLE.to_string(),
+        methods: vec![
+            ("fields".to_string(), fields),
+            ("naturalCompare".to_string(), natural_compare),
+        ],
+    }
+}
+
+/// Compares runs of ASCII digits by their numeric value and everything else by character,
+/// so that `v1.9` sorts before `v1.10`. Strings equal by that measure, such as `a01` and `a1`,
+/// are ordered as plain strings.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut rest_a, mut rest_b) = (a, b);
+    loop {
+        let (x, y) = match (rest_a.chars().next(), rest_b.chars().next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => (x, y),
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let (digits_a, after_a) = split_digits(rest_a);
+            let (digits_b, after_b) = split_digits(rest_b);
+            let (digits_a, digits_b) = (
+                digits_a.trim_start_matches('0'),
+                digits_b.trim_start_matches('0'),
+            );
+            let ordering = digits_a
+                .len()
+                .cmp(&digits_b.len())
+                .then_with(|| digits_a.cmp(digits_b));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            (rest_a, rest_b) = (after_a, after_b);
+        } else {
+            if x != y {
+                return x.cmp(&y);
+            }
+            (rest_a, rest_b) = (&rest_a[x.len_utf8()..], &rest_b[y.len_utf8()..]);
+        }
+    }
+}
+
+/// The leading run of ASCII digits and what follows it.
+fn split_digits(s: &str) -> (&str, &str) {
+    s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::library::Library;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_natural_cmp() {
+        for (a, b, ordering) in [
+            ("v1.9", "v1.10", Ordering::Less),
+            ("file10.txt", "file2.txt", Ordering::Greater),
+            ("a01", "a1", Ordering::Less),
+            ("1.2", "1.2", Ordering::Equal),
+            ("1.2", "1.2.1", Ordering::Less),
+            ("img12", "imgA", Ordering::Less),
+        ] {
+            assert_eq!(natural_cmp(a, b), ordering, "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_string_functions() {
+        let evaluator = Evaluator::new().with_function_provider(Library::Strings);
+        let evaluate = |expression: &str| {
+            evaluator.evaluate(
+                expression,
+                &json!({"line": "  GET /index.html\t200 \n"}),
+                Profile::Default,
+            )
+        };
+        assert_eq!(
+            evaluate("strings.fields(line)"),
+            Ok(json!(["GET", "/index.html", "200"]))
+        );
+        assert_eq!(
+            evaluate("strings.naturalCompare('1.10.0', '1.9.3') > 0"),
+            Ok(json!(true))
+        );
+    }
+}
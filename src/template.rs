@@ -0,0 +1,182 @@
+//! Text templates with `${expression}` placeholders, used by the `render_template` tool.
+//!
+//! `$${` produces a literal `${`. A placeholder ends at the first `}` outside string literals
+//! and nested braces, so expressions may contain map literals and quoted `}`.
+
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::Deserialize;
+use rmcp::serde_json::Value;
+
+/// How substituted values are escaped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde", rename_all = "snake_case")]
+pub enum Escape {
+    /// Inserted as is.
+    #[default]
+    None,
+    /// `&`, `<`, `>`, `"` and `'` become HTML entities.
+    Html,
+    /// Escaped for use inside a JSON string literal (without the surrounding quotes).
+    Json,
+    /// Wrapped in single quotes for POSIX shells.
+    Shell,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// Splits a template into literal text and placeholder expressions.
+pub fn parse(template: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        literal.push_str(&rest[..start]);
+        let after = &rest[start..];
+        if let Some(tail) = after.strip_prefix("$${") {
+            literal.push_str("${");
+            rest = tail;
+        } else if let Some(tail) = after.strip_prefix("${") {
+            let end = placeholder_end(tail).ok_or_else(|| {
+                format!(
+                    "Template error: unterminated placeholder at byte {}",
+                    template.len() - after.len()
+                )
+            })?;
+            let expression = tail[..end].trim();
+            if expression.is_empty() {
+                return Err("Template error: empty placeholder".to_string());
+            }
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(Segment::Placeholder(expression.to_string()));
+            rest = &tail[end + 1..];
+        } else {
+            literal.push('$');
+            rest = &after[1..];
+        }
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+/// Finds the `}` closing a placeholder, skipping nested braces and quoted strings.
+fn placeholder_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '{' => depth += 1,
+                '}' if depth == 0 => return Some(i),
+                '}' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+/// Joins the segments, substituting `values` (one per placeholder, in order). Strings are
+/// inserted without quotes; other values as JSON.
+pub fn render(segments: &[Segment], values: &[Value], escape: Escape) -> String {
+    let mut values = values.iter();
+    let mut out = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Literal(text) => out.push_str(text),
+            Segment::Placeholder(_) => {
+                let text = match values.next() {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(value) => value.to_string(),
+                    None => String::new(),
+                };
+                out.push_str(&apply(escape, &text));
+            }
+        }
+    }
+    out
+}
+
+fn apply(escape: Escape, text: &str) -> String {
+    match escape {
+        Escape::None => text.to_string(),
+        Escape::Html => {
+            let mut out = String::with_capacity(text.len());
+            for c in text.chars() {
+                match c {
+                    '&' => out.push_str("&amp;"),
+                    '<' => out.push_str("&lt;"),
+                    '>' => out.push_str("&gt;"),
+                    '"' => out.push_str("&quot;"),
+                    '\'' => out.push_str("&#39;"),
+                    c => out.push(c),
+                }
+            }
+            out
+        }
+        Escape::Json => {
+            let quoted = Value::String(text.to_string()).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        }
+        Escape::Shell => format!("'{}'", text.replace('\'', r"'\''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_parse_placeholders() {
+        let segments = parse("a ${ {'k': '}'}.k } $${x} $5 ${n + 1}").unwrap();
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Literal("a ".to_string()),
+                Segment::Placeholder("{'k': '}'}.k".to_string()),
+                Segment::Literal(" ${x} $5 ".to_string()),
+                Segment::Placeholder("n + 1".to_string()),
+            ]
+        );
+        assert!(parse("${'unterminated}").is_err());
+        assert!(parse("${ }").is_err());
+    }
+
+    #[test]
+    fn test_render_with_escaping() {
+        let segments = parse("<p>${a}</p> ${b}").unwrap();
+        let values = [json!("<b>Tom & 'Jerry'</b>"), json!([1, 2])];
+        assert_eq!(
+            render(&segments, &values, Escape::Html),
+            "<p>&lt;b&gt;Tom &amp; &#39;Jerry&#39;&lt;/b&gt;</p> [1,2]"
+        );
+        assert_eq!(
+            render(&segments, &values, Escape::Shell),
+            r"<p>'<b>Tom & '\''Jerry'\''</b>'</p> '[1,2]'"
+        );
+        assert_eq!(
+            render(
+                &parse("${a}").unwrap(),
+                &[json!("say \"hi\"\n")],
+                Escape::Json
+            ),
+            r#"say \"hi\"\n"#
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! An in-process server and client for exercising the full MCP path (initialize, `list_tools`,
+//! `call_tool`) without sockets. Public with the `test-util` feature.
+
+use crate::cel_tool::CelTool;
+use rmcp::handler::client::ClientHandler;
+use rmcp::model::{
+    CallToolRequestParams, ClientCapabilities, ClientInfo, CreateElicitationRequestParams,
+    CreateElicitationResult, CreateMessageRequestParams, CreateMessageResult, ElicitationAction,
+    ElicitationCapability, ErrorData, Implementation, ListRootsResult, Root, RootsCapabilities,
+    SamplingCapability, SamplingMessage,
+};
+use rmcp::serde_json::{Map, Value};
+use rmcp::service::{RequestContext, RunningService, ServiceError};
+use rmcp::{RoleClient, ServiceExt};
+
+/// Bytes buffered in each direction of the in-memory pipe.
+const BUFFER: usize = 64 * 1024;
+
+/// A client session connected to a [`CelTool`] over an in-memory pipe.
+pub struct TestClient {
+    client: RunningService<RoleClient, Responder>,
+}
+
+/// Answers the server's requests to the client. Elicitation forms are filled in from
+/// `answers`, declining forms asking for anything else, sampling requests get `reply`, and
+/// `roots` are listed as the client's roots. Without them, the client doesn't offer
+/// elicitation, sampling or roots.
+#[derive(Debug, Clone, Default)]
+struct Responder {
+    answers: Option<Map<String, Value>>,
+    reply: Option<String>,
+    roots: Option<Vec<String>>,
+}
+
+impl ClientHandler for Responder {
+    fn get_info(&self) -> ClientInfo {
+        let mut capabilities = ClientCapabilities::default();
+        if self.answers.is_some() {
+            capabilities.elicitation = Some(ElicitationCapability::default());
+        }
+        if self.reply.is_some() {
+            capabilities.sampling = Some(SamplingCapability::default());
+        }
+        if self.roots.is_some() {
+            capabilities.roots = Some(RootsCapabilities::default());
+        }
+        ClientInfo::new(capabilities, Implementation::from_build_env())
+    }
+
+    async fn create_elicitation(
+        &self,
+        request: CreateElicitationRequestParams,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<CreateElicitationResult, ErrorData> {
+        let CreateElicitationRequestParams::FormElicitationParams {
+            requested_schema, ..
+        } = request
+        else {
+            return Ok(CreateElicitationResult::new(ElicitationAction::Decline));
+        };
+        let content: Option<Map<String, Value>> = requested_schema
+            .properties
+            .keys()
+            .map(|name| {
+                let answer = self.answers.as_ref()?.get(name)?;
+                Some((name.clone(), answer.clone()))
+            })
+            .collect();
+        Ok(match content {
+            Some(content) => CreateElicitationResult::new(ElicitationAction::Accept)
+                .with_content(Value::Object(content)),
+            None => CreateElicitationResult::new(ElicitationAction::Decline),
+        })
+    }
+
+    async fn create_message(
+        &self,
+        _params: CreateMessageRequestParams,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<CreateMessageResult, ErrorData> {
+        let reply = self
+            .reply
+            .clone()
+            .ok_or_else(|| ErrorData::invalid_request("Sampling is not supported", None))?;
+        Ok(CreateMessageResult::new(
+            SamplingMessage::assistant_text(reply),
+            "test".to_string(),
+        ))
+    }
+
+    async fn list_roots(
+        &self,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<ListRootsResult, ErrorData> {
+        let roots = self.roots.iter().flatten().map(Root::new).collect();
+        Ok(ListRootsResult::new(roots))
+    }
+}
+
+impl TestClient {
+    /// Serves a [`session`](CelTool::session) of `tool` and connects to it. Sessions of one
+    /// tool share its evaluator, as sessions of the real server do.
+    pub async fn connect(tool: &CelTool) -> Result<Self, String> {
+        Self::connect_with(tool, Responder::default()).await
+    }
+
+    /// Like [`connect`](Self::connect), from a client that supports elicitation and answers
+    /// forms asking for context variables with `answers`.
+    pub async fn connect_eliciting(
+        tool: &CelTool,
+        answers: Map<String, Value>,
+    ) -> Result<Self, String> {
+        let responder = Responder {
+            answers: Some(answers),
+            ..Responder::default()
+        };
+        Self::connect_with(tool, responder).await
+    }
+
+    /// Like [`connect`](Self::connect), from a client that supports sampling and answers every
+    /// sampling request with `reply`.
+    pub async fn connect_sampling(tool: &CelTool, reply: &str) -> Result<Self, String> {
+        let responder = Responder {
+            reply: Some(reply.to_string()),
+            ..Responder::default()
+        };
+        Self::connect_with(tool, responder).await
+    }
+
+    /// Like [`connect`](Self::connect), from a client that exposes `roots`, `file:` URIs, as
+    /// its roots.
+    pub async fn connect_with_roots(tool: &CelTool, roots: Vec<String>) -> Result<Self, String> {
+        let responder = Responder {
+            roots: Some(roots),
+            ..Responder::default()
+        };
+        Self::connect_with(tool, responder).await
+    }
+
+    async fn connect_with(tool: &CelTool, responder: Responder) -> Result<Self, String> {
+        let (server_io, client_io) = tokio::io::duplex(BUFFER);
+        let server = tool.session();
+        tokio::spawn(async move {
+            match server.serve(server_io).await {
+                Ok(running) => {
+                    if let Err(e) = running.waiting().await {
+                        tracing::error!("Test server session failed: {}", e);
+                    }
+                }
+                Err(e) => tracing::error!("Test server failed to initialize: {}", e),
+            }
+        });
+        let client = responder
+            .serve(client_io)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(Self { client })
+    }
+
+    /// The instructions the server sent when the session was initialized.
+    pub fn instructions(&self) -> Option<String> {
+        self.client
+            .peer_info()
+            .and_then(|info| info.instructions.clone())
+    }
+
+    pub async fn tool_names(&self) -> Result<Vec<String>, String> {
+        let tools = self
+            .client
+            .list_all_tools()
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(tools
+            .into_iter()
+            .map(|tool| tool.name.to_string())
+            .collect())
+    }
+
+    /// Calls tool `name` with an object of `arguments`; returns its structured result, or the
+    /// error the server answered with.
+    pub async fn call(&self, name: &str, arguments: Value) -> Result<Value, ErrorData> {
+        let mut params = CallToolRequestParams::new(name.to_string());
+        if let Value::Object(arguments) = arguments {
+            params = params.with_arguments(arguments);
+        }
+        match self.client.call_tool(params).await {
+            Ok(result) => Ok(result.structured_content.unwrap_or_default()),
+            Err(ServiceError::McpError(e)) => Err(e),
+            Err(e) => Err(ErrorData::internal_error(e.to_string(), None)),
+        }
+    }
+
+    /// Ends the session.
+    pub async fn close(self) -> Result<(), String> {
+        self.client
+            .cancel()
+            .await
+            .map(drop)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[tokio::test]
+    async fn test_concurrent_sessions() {
+        let tool = CelTool::builder().build();
+        let mut sessions = tokio::task::JoinSet::new();
+        for n in 0..4u64 {
+            let tool = tool.clone();
+            sessions.spawn(async move {
+                let session = TestClient::connect(&tool).await.unwrap();
+                let result = session
+                    .call(
+                        "evaluate",
+                        json!({"expression": "n * 2u", "context": {"n": n}}),
+                    )
+                    .await;
+                session.close().await.unwrap();
+                (n, result)
+            });
+        }
+        while let Some(joined) = sessions.join_next().await {
+            let (n, result) = joined.unwrap();
+            assert_eq!(result.unwrap(), json!({"result": (n * 2).to_string()}));
+        }
+
+        let session = TestClient::connect(&tool).await.unwrap();
+        let tools = session.tool_names().await.unwrap();
+        assert!(tools.contains(&"evaluate".to_string()));
+        assert!(session.instructions().unwrap().contains("`evaluate`"));
+        let err = session
+            .call("evaluate", json!({"expression": "1 +", "context": {}}))
+            .await
+            .err()
+            .unwrap();
+        assert!(err.message.starts_with("CEL compile error"));
+        session.close().await.unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+//! HTTPS for the HTTP listeners, with `--tls-cert` and `--tls-key`, and mutual TLS with
+//! `--client-ca`: the listeners then only complete handshakes with clients presenting a
+//! certificate the CA issued, and authorize requests by the certificate instead of a bearer
+//! token.
+//!
+//! A certificate's identities are its URI names, such as a SPIFFE ID, followed by its DNS
+//! names. The `--config` file's `client_certs` grants identities scopes and quotas, as
+//! `keys add` does for API keys; a certificate is known by the first identity listed there.
+
+use crate::config::ClientCertConfig;
+use crate::keys::{self, Scope};
+use crate::quota::Account;
+use axum::Router;
+use axum::extract::connect_info::Connected;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::serve::{IncomingStream, Listener};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{self, RootCertStore};
+use tokio_rustls::server::TlsStream;
+
+pub use tokio_rustls::rustls::ServerConfig;
+
+/// Connections whose handshake takes longer are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read certificates from {}: {}", path.display(), e))
+}
+
+/// The listeners' TLS settings: the certificate chain in `cert`, its key in `key` and, for
+/// mutual TLS, the CA certificates in `client_ca` (all PEM).
+pub fn server_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> Result<Arc<ServerConfig>, String> {
+    let chain = certificates(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("Failed to read a private key from {}: {}", key.display(), e))?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in certificates(ca)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| format!("Invalid CA certificate in {}: {}", ca.display(), e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| format!("Invalid --client-ca {}: {}", ca.display(), e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
+        .with_single_cert(chain, key)
+        .map_err(|e| format!("Invalid --tls-cert or --tls-key: {}", e))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// The identities of the first certificate in `certs`, the client's own.
+fn identities(certs: Option<&[CertificateDer<'static>]>) -> Vec<String> {
+    let Some(cert) = certs.and_then(|certs| certs.first()) else {
+        return Vec::new();
+    };
+    let Ok(cert) = webpki::EndEntityCert::try_from(cert) else {
+        return Vec::new();
+    };
+    cert.valid_uri_names()
+        .chain(cert.valid_dns_names())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Accepts TLS connections, completing handshakes in the background so that a slow client
+/// doesn't hold up the others.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, config: Arc<ServerConfig>) -> std::io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(config);
+        let (sender, connections) = mpsc::channel(64);
+        tokio::spawn(async move {
+            while !sender.is_closed() {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("Failed to accept a connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, addr)).await;
+                        }
+                        Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => tracing::debug!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            local_addr,
+            connections,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accepting task only stops once the listener is dropped.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// The client end of a TLS connection, which handlers read as `ConnectInfo<Peer>` when the
+/// app is served with `into_make_service_with_connect_info::<Peer>()`.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    /// The identities of the client's certificate; none without one.
+    pub identities: Vec<String>,
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Peer {
+            addr: *stream.remote_addr(),
+            identities: identities(stream.io().get_ref().1.peer_certificates()),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct RequireCert {
+    grants: Arc<BTreeMap<String, ClientCertConfig>>,
+    scope: Scope,
+}
+
+impl RequireCert {
+    /// The account of the certificate `peer` presented, or the status to refuse its request
+    /// with.
+    fn authorize(&self, peer: Option<&Peer>) -> Result<Account, (StatusCode, String)> {
+        let identities = peer
+            .map(|peer| peer.identities.as_slice())
+            .unwrap_or_default();
+        if identities.is_empty() {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                "A client certificate is required".to_string(),
+            ));
+        }
+        let (identity, grant) = identities
+            .iter()
+            .find_map(|identity| self.grants.get(identity).map(|grant| (identity, grant)))
+            .ok_or_else(|| {
+                (
+                    StatusCode::FORBIDDEN,
+                    format!(
+                        "No `client_certs` entry names the certificate ({})",
+                        identities.join(", ")
+                    ),
+                )
+            })?;
+        if !keys::allows(&grant.scopes, self.scope) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!(
+                    "Certificate `{}` lacks the `{}` scope",
+                    identity, self.scope
+                ),
+            ));
+        }
+        Ok(Account {
+            name: format!("cert-{}", identity),
+            quota: grant.quota,
+        })
+    }
+}
+
+/// Makes `router`, served over a [`TlsListener`] with [`Peer`] connection info, refuse
+/// requests on connections without a client certificate `grants` gives `scope`, with status
+/// 401 without a certificate and 403 otherwise. Other requests are charged to the
+/// certificate's account, `cert-<identity>`.
+pub fn require(
+    router: Router,
+    grants: Arc<BTreeMap<String, ClientCertConfig>>,
+    scope: Scope,
+) -> Router {
+    router.layer(middleware::from_fn_with_state(
+        RequireCert { grants, scope },
+        check,
+    ))
+}
+
+async fn check(State(required): State<RequireCert>, mut request: Request, next: Next) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<Peer>>()
+        .map(|ConnectInfo(peer)| peer);
+    match required.authorize(peer) {
+        Ok(account) => {
+            request.extensions_mut().insert(account);
+            next.run(request).await
+        }
+        Err((status, message)) => (status, format!("{}\n", message)).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quota::Quota;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_authorize() {
+        let quota = Quota {
+            max_evaluations: Some(5),
+            ..Quota::default()
+        };
+        let required = |scope| RequireCert {
+            grants: Arc::new(BTreeMap::from([
+                (
+                    "spiffe://corp/billing".to_string(),
+                    ClientCertConfig {
+                        scopes: BTreeSet::from([Scope::Evaluate]),
+                        quota: Some(quota),
+                    },
+                ),
+                (
+                    "ops.corp.internal".to_string(),
+                    ClientCertConfig {
+                        scopes: BTreeSet::from([Scope::Admin]),
+                        quota: None,
+                    },
+                ),
+            ])),
+            scope,
+        };
+        let peer = |identities: &[&str]| Peer {
+            addr: SocketAddr::from(([10, 0, 0, 1], 50000)),
+            identities: identities.iter().map(|i| i.to_string()).collect(),
+        };
+        let authorize = |scope, peer: Option<Peer>| {
+            required(scope)
+                .authorize(peer.as_ref())
+                .map_err(|(status, _)| status)
+        };
+
+        assert_eq!(
+            authorize(Scope::Evaluate, None),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            authorize(Scope::Evaluate, Some(peer(&[]))),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            authorize(Scope::Evaluate, Some(peer(&["spiffe://corp/other"]))),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            authorize(
+                Scope::Evaluate,
+                Some(peer(&["spiffe://corp/billing", "billing.corp.internal"]))
+            ),
+            Ok(Account {
+                name: "cert-spiffe://corp/billing".to_string(),
+                quota: Some(quota),
+            })
+        );
+        assert_eq!(
+            authorize(Scope::Admin, Some(peer(&["spiffe://corp/billing"]))),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert!(authorize(Scope::Admin, Some(peer(&["ops.corp.internal"]))).is_ok());
+        assert!(authorize(Scope::Evaluate, Some(peer(&["ops.corp.internal"]))).is_ok());
+    }
+}
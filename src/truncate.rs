@@ -0,0 +1,126 @@
+//! Shrinks JSON results to a byte budget, so one huge value cannot produce a response that
+//! breaks clients. Leading list elements and map entries are kept and strings are cut short;
+//! what was left out is counted in a [`Truncation`].
+
+use rmcp::schemars::JsonSchema;
+use rmcp::serde::Serialize;
+use rmcp::serde_json::{self, Map, Value};
+
+/// What [`truncate`] left out of a result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[schemars(crate = "rmcp::schemars")]
+#[serde(crate = "rmcp::serde")]
+pub struct Truncation {
+    /// Always `true`; present so clients can test for it.
+    pub truncated: bool,
+    /// Size of the complete result as JSON.
+    pub original_bytes: usize,
+    /// List elements and map entries left out.
+    pub omitted_elements: usize,
+    /// Strings cut short.
+    pub shortened_strings: usize,
+}
+
+/// Returns `value` unchanged if its JSON takes at most `max_bytes`; otherwise a prefix of it
+/// within `max_bytes` (a lone number or bool is kept whole), and what was cut.
+pub fn truncate(value: Value, max_bytes: usize) -> (Value, Option<Truncation>) {
+    let original_bytes = serde_json::to_string(&value).map_or(0, |json| json.len());
+    if original_bytes <= max_bytes {
+        return (value, None);
+    }
+    let mut truncation = Truncation {
+        truncated: true,
+        original_bytes,
+        omitted_elements: 0,
+        shortened_strings: 0,
+    };
+    let mut budget = max_bytes;
+    let value = fit(value, &mut budget, &mut truncation);
+    (value, Some(truncation))
+}
+
+/// Keeps as much of `value` as `budget` bytes of JSON allow, spending them.
+fn fit(value: Value, budget: &mut usize, truncation: &mut Truncation) -> Value {
+    match value {
+        Value::String(text) => Value::String(fit_string(text, budget, truncation)),
+        Value::Array(items) => {
+            *budget = budget.saturating_sub(2);
+            let total = items.len();
+            let mut kept = Vec::new();
+            for item in items {
+                if *budget < 1 + min_cost(&item) {
+                    break;
+                }
+                *budget -= 1;
+                kept.push(fit(item, budget, truncation));
+            }
+            truncation.omitted_elements += total - kept.len();
+            Value::Array(kept)
+        }
+        Value::Object(entries) => {
+            *budget = budget.saturating_sub(2);
+            let total = entries.len();
+            let mut kept = Map::new();
+            for (key, item) in entries {
+                let cost = key.len() + 4;
+                if *budget < cost + min_cost(&item) {
+                    break;
+                }
+                *budget -= cost;
+                kept.insert(key, fit(item, budget, truncation));
+            }
+            truncation.omitted_elements += total - kept.len();
+            Value::Object(kept)
+        }
+        scalar => {
+            *budget = budget.saturating_sub(min_cost(&scalar));
+            scalar
+        }
+    }
+}
+
+/// Bytes `value` takes at least once fitted: all of a scalar, the delimiters of the rest.
+fn min_cost(value: &Value) -> usize {
+    match value {
+        Value::String(_) | Value::Array(_) | Value::Object(_) => 2,
+        scalar => serde_json::to_string(scalar).map_or(0, |json| json.len()),
+    }
+}
+
+fn fit_string(mut text: String, budget: &mut usize, truncation: &mut Truncation) -> String {
+    let room = budget.saturating_sub(2);
+    if text.len() > room {
+        let mut end = room;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        truncation.shortened_strings += 1;
+    }
+    *budget = budget.saturating_sub(text.len() + 2);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_truncate_to_budget() {
+        let small = json!({"a": [1, 2, 3]});
+        assert_eq!(truncate(small.clone(), 100), (small, None));
+
+        let rows: Vec<Value> = (0..100).map(|i| json!(format!("row-{:03}", i))).collect();
+        let (value, truncation) = truncate(Value::Array(rows), 50);
+        let truncation = truncation.unwrap();
+        let kept = value.as_array().unwrap().len();
+        assert_eq!(truncation.omitted_elements, 100 - kept);
+        assert!(serde_json::to_string(&value).unwrap().len() <= 50);
+        assert_eq!(truncation.original_bytes, 1001);
+
+        let (value, truncation) = truncate(json!({"text": "é".repeat(100)}), 20);
+        assert_eq!(value, json!({"text": "éééé"}));
+        assert_eq!(truncation.unwrap().shortened_strings, 1);
+    }
+}
@@ -0,0 +1,306 @@
+//! Prints a parsed expression back as CEL text, for rewrites done on the syntax tree.
+//!
+//! Parentheses are added only where precedence needs them, and macro expansions are printed
+//! as the macro calls they came from. Comments and the original formatting are lost.
+
+use crate::literal;
+use crate::macros::{self, Macro};
+use cel::common::ast::{EntryExpr, Expr, operators};
+use cel::common::value::CelVal;
+use cel::parser::Expression;
+
+/// Binary operators: function name, symbol and precedence (higher binds tighter).
+const BINARY: [(&str, &str, u8); 14] = [
+    (operators::LOGICAL_OR, "||", 2),
+    (operators::LOGICAL_AND, "&&", 3),
+    (operators::EQUALS, "==", 4),
+    (operators::NOT_EQUALS, "!=", 4),
+    (operators::LESS, "<", 4),
+    (operators::LESS_EQUALS, "<=", 4),
+    (operators::GREATER, ">", 4),
+    (operators::GREATER_EQUALS, ">=", 4),
+    (operators::IN, "in", 4),
+    (operators::ADD, "+", 5),
+    (operators::SUBSTRACT, "-", 5),
+    (operators::MULTIPLY, "*", 6),
+    (operators::DIVIDE, "/", 6),
+    (operators::MODULO, "%", 6),
+];
+
+const CONDITIONAL: u8 = 1;
+const UNARY: u8 = 7;
+const MEMBER: u8 = 8;
+
+/// `expr` as CEL text that parses back to the same tree.
+pub(crate) fn unparse(expr: &Expression) -> Result<String, String> {
+    let mut out = String::new();
+    write(&mut out, expr)?;
+    Ok(out)
+}
+
+fn binary(func_name: &str) -> Option<(&'static str, u8)> {
+    BINARY
+        .iter()
+        .find(|(name, _, _)| *name == func_name)
+        .map(|&(_, symbol, precedence)| (symbol, precedence))
+}
+
+fn precedence(expr: &Expression) -> u8 {
+    match &expr.expr {
+        Expr::Call(call) if call.target.is_none() => match call.func_name.as_str() {
+            operators::CONDITIONAL => CONDITIONAL,
+            operators::LOGICAL_NOT | operators::NEGATE => UNARY,
+            name => binary(name).map_or(MEMBER, |(_, precedence)| precedence),
+        },
+        Expr::Literal(CelVal::Int(i)) if *i < 0 => UNARY,
+        Expr::Literal(CelVal::Double(d)) if d.is_sign_negative() => UNARY,
+        _ => MEMBER,
+    }
+}
+
+/// Writes `expr`, parenthesized if it binds looser than `min`.
+fn write_operand(out: &mut String, expr: &Expression, min: u8) -> Result<(), String> {
+    if precedence(expr) < min {
+        out.push('(');
+        write(out, expr)?;
+        out.push(')');
+        Ok(())
+    } else {
+        write(out, expr)
+    }
+}
+
+fn write_list<'a>(
+    out: &mut String,
+    items: impl IntoIterator<Item = &'a Expression>,
+) -> Result<(), String> {
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write(out, item)?;
+    }
+    Ok(())
+}
+
+fn write(out: &mut String, expr: &Expression) -> Result<(), String> {
+    match &expr.expr {
+        Expr::Ident(name) if !is_identifier(name) => {
+            return Err(format!("`{}` has no CEL syntax", name));
+        }
+        Expr::Ident(name) => out.push_str(name),
+        Expr::Literal(literal) => write_literal(out, literal)?,
+        Expr::Select(select) => {
+            if select.test {
+                out.push_str("has(");
+            }
+            write_operand(out, &select.operand, MEMBER)?;
+            out.push('.');
+            out.push_str(&select.field);
+            if select.test {
+                out.push(')');
+            }
+        }
+        Expr::Call(call) => match (&call.target, call.func_name.as_str(), call.args.as_slice()) {
+            (None, operators::CONDITIONAL, [condition, then, otherwise]) => {
+                write_operand(out, condition, CONDITIONAL + 1)?;
+                out.push_str(" ? ");
+                write_operand(out, then, CONDITIONAL + 1)?;
+                out.push_str(" : ");
+                write(out, otherwise)?;
+            }
+            (None, operators::LOGICAL_NOT | operators::NEGATE, [operand]) => {
+                out.push_str(if call.func_name == operators::NEGATE {
+                    "-"
+                } else {
+                    "!"
+                });
+                write_operand(out, operand, UNARY)?;
+            }
+            (None, operators::INDEX, [operand, index]) => {
+                write_operand(out, operand, MEMBER)?;
+                out.push('[');
+                write(out, index)?;
+                out.push(']');
+            }
+            (None, name, [left, right]) if binary(name).is_some() => {
+                let (symbol, precedence) = binary(name).unwrap_or(("", MEMBER));
+                // `&&` and `||` are associative; the others group to the left.
+                let associative = precedence <= 3;
+                write_operand(out, left, precedence)?;
+                out.push(' ');
+                out.push_str(symbol);
+                out.push(' ');
+                write_operand(out, right, precedence + u8::from(!associative))?;
+            }
+            // Operators with the wrong number of arguments, and internal functions.
+            (_, name, _) if !name.split('.').all(is_identifier) => {
+                return Err(format!("`{}` has no CEL syntax", name));
+            }
+            (Some(target), name, args) => {
+                write_operand(out, target, MEMBER)?;
+                out.push('.');
+                out.push_str(name);
+                out.push('(');
+                write_list(out, args)?;
+                out.push(')');
+            }
+            (None, name, args) => {
+                out.push_str(name);
+                out.push('(');
+                write_list(out, args)?;
+                out.push(')');
+            }
+        },
+        Expr::List(list) => {
+            out.push('[');
+            write_list(out, &list.elements)?;
+            out.push(']');
+        }
+        Expr::Map(map) => {
+            out.push('{');
+            for (i, entry) in map.entries.iter().enumerate() {
+                let EntryExpr::MapEntry(entry) = &entry.expr else {
+                    return Err("A map literal with a field initializer".to_string());
+                };
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write(out, &entry.key)?;
+                out.push_str(": ");
+                write(out, &entry.value)?;
+            }
+            out.push('}');
+        }
+        Expr::Struct(st) => {
+            out.push_str(&st.type_name);
+            out.push('{');
+            for (i, entry) in st.entries.iter().enumerate() {
+                let EntryExpr::StructField(field) = &entry.expr else {
+                    return Err("A message literal with a map entry".to_string());
+                };
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(&field.field);
+                out.push_str(": ");
+                write(out, &field.value)?;
+            }
+            out.push('}');
+        }
+        Expr::Comprehension(comp) => {
+            let (found, args) = macro_args(expr)
+                .ok_or_else(|| "A comprehension no macro expands to".to_string())?;
+            write_operand(out, &comp.iter_range, MEMBER)?;
+            out.push('.');
+            out.push_str(found.name());
+            out.push('(');
+            out.push_str(&comp.iter_var);
+            for arg in args {
+                out.push_str(", ");
+                write(out, arg)?;
+            }
+            out.push(')');
+        }
+        Expr::Unspecified => return Err("An unspecified expression".to_string()),
+    }
+    Ok(())
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The macro a comprehension was expanded from and its arguments after the variable.
+fn macro_args(expr: &Expression) -> Option<(Macro, Vec<&Expression>)> {
+    let Expr::Comprehension(comp) = &expr.expr else {
+        return None;
+    };
+    if comp.iter_var2.is_some() {
+        return None;
+    }
+    let Expr::Call(step) = &comp.loop_step.expr else {
+        return None;
+    };
+    let found = macros::classify(&expr.expr)?;
+    let args = match (found, step.func_name.as_str(), step.args.as_slice()) {
+        (Macro::All | Macro::Exists, _, [_, predicate]) => vec![predicate],
+        (Macro::ExistsOne | Macro::Filter, operators::CONDITIONAL, [predicate, ..]) => {
+            vec![predicate]
+        }
+        (Macro::Map, operators::CONDITIONAL, [predicate, append, _]) => {
+            vec![predicate, appended(append)?]
+        }
+        (Macro::Map, operators::ADD, _) => vec![appended(&comp.loop_step)?],
+        _ => return None,
+    };
+    Some((found, args))
+}
+
+/// `x` in a `map` step's `accu + [x]`.
+fn appended(step: &Expression) -> Option<&Expression> {
+    let Expr::Call(add) = &step.expr else {
+        return None;
+    };
+    match add.args.get(1).map(|list| &list.expr) {
+        Some(Expr::List(list)) => list.elements.first(),
+        _ => None,
+    }
+}
+
+fn write_literal(out: &mut String, literal: &CelVal) -> Result<(), String> {
+    match literal {
+        CelVal::Null => out.push_str("null"),
+        CelVal::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        CelVal::Int(i) => out.push_str(&i.to_string()),
+        CelVal::UInt(u) => out.push_str(&format!("{}u", u)),
+        // Debug keeps a decimal point or an exponent, so the literal stays a double.
+        CelVal::Double(d) => out.push_str(&format!("{:?}", d)),
+        CelVal::String(s) => out.push_str(&literal::string(s)),
+        CelVal::Bytes(bytes) => out.push_str(&literal::bytes(bytes)),
+        other => return Err(format!("The literal {:?} has no CEL syntax", other)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast;
+
+    #[test]
+    fn test_unparse_round_trips() {
+        for expression in [
+            "a + b * c - (d - e)",
+            "(a + b) * c",
+            "!(a && b) || c && !d",
+            "a ? (b ? c : d) : e ? f : g",
+            "-(a + 1) < -1",
+            "has(request.auth.claims) && request.auth.claims.role in [\"admin\", 'x\\'y']",
+            "items.all(i, i > 0) && items.exists(i, i == 2u) && items.exists_one(i, i < 0)",
+            "items.map(i, i * 2.5).filter(j, j > 1.0).map(k, k > 0.0, string(k))",
+            "{\"a\": b\"\\x00\\xff\", c: d[0][e].f}",
+            "Msg{field: 1, other: null}.field",
+            "kv.get('x').size() + size(y)",
+        ] {
+            let parsed = ast::parse(expression).unwrap();
+            let printed = unparse(&parsed).unwrap();
+            let reparsed = ast::parse(&printed).unwrap();
+            assert_eq!(
+                ast::fingerprint(&parsed),
+                ast::fingerprint(&reparsed),
+                "{} printed as {}",
+                expression,
+                printed
+            );
+        }
+        assert_eq!(
+            unparse(&ast::parse("((a)) &&   (b || c)").unwrap()),
+            Ok("a && (b || c)".to_string())
+        );
+    }
+}
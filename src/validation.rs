@@ -0,0 +1,100 @@
+//! `email.isValid(s)` and `phone.isValid(s, region)`, input validation without hand-written
+//! regular expressions.
+//!
+//! Email addresses are checked against RFC 5322's syntax, bare addresses only, with a
+//! top-level domain. Phone numbers are checked against libphonenumber's metadata.
+
+use crate::provider::{Method, Namespace};
+use cel::{FunctionContext, Value as CelValue};
+use email_address::{EmailAddress, Options};
+use phonenumber::country;
+use std::sync::Arc;
+
+/// The namespace `email.isValid` is called on.
+pub const EMAIL_VARIABLE: &str = "email";
+
+/// The namespace `phone.isValid` is called on.
+pub const PHONE_VARIABLE: &str = "phone";
+
+pub(crate) fn email_namespace() -> Namespace {
+    let is_valid: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let [CelValue::String(address)] = args else {
+            return Err(ftx.error("email.isValid expects one string"));
+        };
+        Ok(CelValue::Bool(is_email(address)))
+    });
+    Namespace {
+        name: EMAIL_VARIABLE.to_string(),
+        methods: vec![("isValid".to_string(), is_valid)],
+    }
+}
+
+pub(crate) fn phone_namespace() -> Namespace {
+    let is_valid: Method = Arc::new(|ftx: &FunctionContext, args: &[CelValue]| {
+        let (number, region) = match args {
+            [CelValue::String(number)] => (number, None),
+            [CelValue::String(number), CelValue::String(region)] => (number, Some(region)),
+            _ => {
+                return Err(ftx.error(
+                    "phone.isValid expects a string and optionally an ISO 3166-1 region code",
+                ));
+            }
+        };
+        let region = region
+            .map(|region| {
+                region
+                    .to_ascii_uppercase()
+                    .parse::<country::Id>()
+                    .map_err(|_| ftx.error(format!("`{}` is not a region code", region)))
+            })
+            .transpose()?;
+        Ok(CelValue::Bool(is_phone(number, region)))
+    });
+    Namespace {
+        name: PHONE_VARIABLE.to_string(),
+        methods: vec![("isValid".to_string(), is_valid)],
+    }
+}
+
+fn is_email(address: &str) -> bool {
+    let options = Options::default()
+        .with_required_tld()
+        .without_domain_literal()
+        .without_display_text();
+    EmailAddress::parse_with_options(address, options).is_ok()
+}
+
+/// Without a region, `number` must be in international format (`+81 ...`).
+fn is_phone(number: &str, region: Option<country::Id>) -> bool {
+    phonenumber::parse(region, number).is_ok_and(|parsed| phonenumber::is_valid(&parsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::Evaluator;
+    use crate::library::Library;
+    use crate::profile::Profile;
+    use rmcp::serde_json::json;
+
+    #[test]
+    fn test_email_and_phone_validation() {
+        let evaluator = Evaluator::new()
+            .with_function_provider(Library::Email)
+            .with_function_provider(Library::Phone);
+        let evaluate =
+            |expression: &str| evaluator.evaluate(expression, &json!({}), Profile::Default);
+        for (expression, valid) in [
+            ("email.isValid('jane.doe@example.com')", true),
+            ("email.isValid('jane.doe@localhost')", false),
+            ("email.isValid('Jane <jane@example.com>')", false),
+            ("email.isValid('not an address')", false),
+            ("phone.isValid('03-1234-5678', 'JP')", true),
+            ("phone.isValid('+1 650-253-0000')", true),
+            ("phone.isValid('650-253-0000')", false),
+            ("phone.isValid('12', 'us')", false),
+        ] {
+            assert_eq!(evaluate(expression), Ok(json!(valid)), "{}", expression);
+        }
+        assert!(evaluate("phone.isValid('12', 'XX')").is_err());
+    }
+}
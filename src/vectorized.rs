@@ -0,0 +1,275 @@
+//! A vectorized fast path for `evaluate_dataset`: predicates built from comparisons of a
+//! column with a literal, `&&`, `||` and `!` run as Arrow kernels over whole columns instead
+//! of once per row.
+//!
+//! Anything else, such as a function call, a nullable or NaN-holding column, or a comparison
+//! whose types CEL would reject, makes [`evaluate`] return `None`, and the dataset is
+//! evaluated row by row. Results match the row-by-row ones, including CEL's numeric
+//! comparisons across `int`, `uint` and `double`.
+
+use crate::ast;
+use arrow_arith::boolean;
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Datum, Float64Array, Int64Array, RecordBatch, StringArray,
+};
+use arrow_cast::cast;
+use arrow_ord::cmp;
+use arrow_schema::DataType;
+use cel::common::ast::{Expr, operators};
+use cel::common::value::CelVal;
+use cel::parser::Expression;
+
+/// Evaluates `expression` against every row of `batches`, or `None` if it has no vectorized
+/// form.
+pub fn evaluate(expression: &str, batches: &[RecordBatch]) -> Option<Vec<bool>> {
+    let predicate = Predicate::compile(&ast::parse(expression).ok()?)?;
+    let mut results = Vec::with_capacity(batches.iter().map(RecordBatch::num_rows).sum());
+    for batch in batches {
+        let values = predicate.evaluate(batch)?;
+        if values.null_count() > 0 {
+            return None;
+        }
+        results.extend(values.values().iter());
+    }
+    Some(results)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    /// A bool column.
+    Column(String),
+    Compare(String, Comparison, Literal),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn of(function: &str) -> Option<Self> {
+        Some(match function {
+            operators::EQUALS => Comparison::Eq,
+            operators::NOT_EQUALS => Comparison::Ne,
+            operators::LESS => Comparison::Lt,
+            operators::LESS_EQUALS => Comparison::Le,
+            operators::GREATER => Comparison::Gt,
+            operators::GREATER_EQUALS => Comparison::Ge,
+            _ => return None,
+        })
+    }
+
+    /// The comparison with its operands swapped, so `1 < x` becomes `x > 1`.
+    fn flip(self) -> Self {
+        match self {
+            Comparison::Lt => Comparison::Gt,
+            Comparison::Le => Comparison::Ge,
+            Comparison::Gt => Comparison::Lt,
+            Comparison::Ge => Comparison::Le,
+            same => same,
+        }
+    }
+
+    fn apply(self, lhs: &dyn Datum, rhs: &dyn Datum) -> Option<BooleanArray> {
+        match self {
+            Comparison::Eq => cmp::eq(lhs, rhs),
+            Comparison::Ne => cmp::neq(lhs, rhs),
+            Comparison::Lt => cmp::lt(lhs, rhs),
+            Comparison::Le => cmp::lt_eq(lhs, rhs),
+            Comparison::Gt => cmp::gt(lhs, rhs),
+            Comparison::Ge => cmp::gt_eq(lhs, rhs),
+        }
+        .ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Int(i64),
+    Double(f64),
+    String(String),
+    Bool(bool),
+}
+
+impl Literal {
+    fn of(expr: &Expression) -> Option<Self> {
+        match &expr.expr {
+            Expr::Literal(CelVal::Int(i)) => Some(Literal::Int(*i)),
+            Expr::Literal(CelVal::UInt(u)) => i64::try_from(*u).ok().map(Literal::Int),
+            Expr::Literal(CelVal::Double(d)) if d.is_finite() => Some(Literal::Double(*d)),
+            Expr::Literal(CelVal::String(s)) => Some(Literal::String(s.to_string())),
+            Expr::Literal(CelVal::Boolean(b)) => Some(Literal::Bool(*b)),
+            Expr::Call(call) if call.func_name == operators::NEGATE && call.args.len() == 1 => {
+                match Literal::of(&call.args[0])? {
+                    Literal::Int(i) => i.checked_neg().map(Literal::Int),
+                    Literal::Double(d) => Some(Literal::Double(-d)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Predicate {
+    fn compile(expr: &Expression) -> Option<Self> {
+        match &expr.expr {
+            Expr::Ident(name) => Some(Predicate::Column(name.clone())),
+            Expr::Call(call) if call.target.is_none() => {
+                let function = call.func_name.as_str();
+                match (function, call.args.as_slice()) {
+                    (operators::LOGICAL_AND, [lhs, rhs]) => Some(Predicate::And(
+                        Box::new(Predicate::compile(lhs)?),
+                        Box::new(Predicate::compile(rhs)?),
+                    )),
+                    (operators::LOGICAL_OR, [lhs, rhs]) => Some(Predicate::Or(
+                        Box::new(Predicate::compile(lhs)?),
+                        Box::new(Predicate::compile(rhs)?),
+                    )),
+                    (operators::LOGICAL_NOT, [operand]) => {
+                        Some(Predicate::Not(Box::new(Predicate::compile(operand)?)))
+                    }
+                    (_, [lhs, rhs]) => {
+                        let comparison = Comparison::of(function)?;
+                        match (&lhs.expr, &rhs.expr) {
+                            (Expr::Ident(column), _) => Some(Predicate::Compare(
+                                column.clone(),
+                                comparison,
+                                Literal::of(rhs)?,
+                            )),
+                            (_, Expr::Ident(column)) => Some(Predicate::Compare(
+                                column.clone(),
+                                comparison.flip(),
+                                Literal::of(lhs)?,
+                            )),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Option<BooleanArray> {
+        match self {
+            Predicate::Column(name) => {
+                let column = column(batch, name)?;
+                column.as_any().downcast_ref::<BooleanArray>().cloned()
+            }
+            Predicate::Compare(name, comparison, literal) => {
+                compare(column(batch, name)?, *comparison, literal)
+            }
+            Predicate::And(lhs, rhs) => {
+                boolean::and(&lhs.evaluate(batch)?, &rhs.evaluate(batch)?).ok()
+            }
+            Predicate::Or(lhs, rhs) => {
+                boolean::or(&lhs.evaluate(batch)?, &rhs.evaluate(batch)?).ok()
+            }
+            Predicate::Not(operand) => boolean::not(&operand.evaluate(batch)?).ok(),
+        }
+    }
+}
+
+/// The column `name`, if it has no nulls, which CEL would compare differently.
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> Option<&'a ArrayRef> {
+    batch
+        .column_by_name(name)
+        .filter(|column| column.null_count() == 0)
+}
+
+fn compare(column: &ArrayRef, comparison: Comparison, literal: &Literal) -> Option<BooleanArray> {
+    let data_type = column.data_type();
+    match literal {
+        Literal::Int(i) if data_type.is_integer() => {
+            let column = cast_exact(column, &DataType::Int64)?;
+            comparison.apply(&column, &Int64Array::new_scalar(*i))
+        }
+        Literal::Int(i) if data_type.is_floating() => {
+            let column = finite(cast_exact(column, &DataType::Float64)?)?;
+            comparison.apply(&column, &Float64Array::new_scalar(*i as f64))
+        }
+        Literal::Double(d) if data_type.is_integer() || data_type.is_floating() => {
+            let column = finite(cast_exact(column, &DataType::Float64)?)?;
+            comparison.apply(&column, &Float64Array::new_scalar(*d))
+        }
+        Literal::String(s)
+            if matches!(
+                data_type,
+                DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+            ) =>
+        {
+            let column = cast_exact(column, &DataType::Utf8)?;
+            comparison.apply(&column, &StringArray::new_scalar(s))
+        }
+        Literal::Bool(b) if data_type == &DataType::Boolean => {
+            comparison.apply(column, &BooleanArray::new_scalar(*b))
+        }
+        _ => None,
+    }
+}
+
+/// `column` as `to`, unless a value doesn't fit, which the cast would turn into a null.
+fn cast_exact(column: &ArrayRef, to: &DataType) -> Option<ArrayRef> {
+    cast(column, to)
+        .ok()
+        .filter(|cast| cast.null_count() == column.null_count())
+}
+
+/// The column, unless it holds NaN or an infinity, which don't travel as JSON numbers.
+fn finite(column: ArrayRef) -> Option<ArrayRef> {
+    let values = column.as_any().downcast_ref::<Float64Array>()?;
+    values
+        .values()
+        .iter()
+        .all(|v| v.is_finite())
+        .then_some(column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_vectorized_predicates() {
+        let batch = RecordBatch::try_from_iter([
+            ("n", Arc::new(Int64Array::from(vec![1, 5, 9])) as ArrayRef),
+            (
+                "x",
+                Arc::new(Float64Array::from(vec![0.5, 2.0, -1.0])) as ArrayRef,
+            ),
+            (
+                "s",
+                Arc::new(StringArray::from(vec!["a", "b", "c"])) as ArrayRef,
+            ),
+            (
+                "ok",
+                Arc::new(BooleanArray::from(vec![true, false, true])) as ArrayRef,
+            ),
+            (
+                "maybe",
+                Arc::new(Int64Array::from(vec![Some(1), None, Some(2)])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+        let run = |expression: &str| evaluate(expression, std::slice::from_ref(&batch));
+        assert_eq!(run("n > 2u && s != 'c'"), Some(vec![false, true, false]));
+        assert_eq!(run("3 >= n || !ok"), Some(vec![true, true, false]));
+        assert_eq!(run("x < 1 && n >= -1"), Some(vec![true, false, true]));
+        assert_eq!(run("n == 5.0"), Some(vec![false, true, false]));
+        assert_eq!(run("maybe > 1"), None);
+        assert_eq!(run("s == 1"), None);
+        assert_eq!(run("s.startsWith('a')"), None);
+        assert_eq!(run("missing > 1"), None);
+    }
+}
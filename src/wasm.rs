@@ -0,0 +1,248 @@
+//! WebAssembly plugins exposing custom functions to CEL (enabled with the `wasm` feature).
+//!
+//! Every exported function whose parameters and single result are numeric (`i32`, `i64`, `f32`,
+//! `f64`) becomes a CEL function of the same name. Modules run without any imports, so they have
+//! no access to the host, and each call gets a fresh instance with a fuel and memory budget.
+
+use crate::provider::FunctionProvider;
+use cel::extractors::Arguments;
+use cel::{Context, ExecutionError, FunctionContext, Value as CelValue};
+use std::path::Path;
+use std::sync::Arc;
+use wasmtime::{
+    Config, Engine, ExternType, Instance, Module, Store, StoreLimits, StoreLimitsBuilder, Trap,
+    Val, ValType,
+};
+
+/// Fuel granted to each call when none is configured.
+pub const DEFAULT_FUEL: u64 = 10_000_000;
+
+/// Linear memory a single call may grow to.
+const MAX_MEMORY_BYTES: usize = 16 << 20;
+
+struct WasmFunction {
+    engine: Engine,
+    fuel: u64,
+    name: String,
+    module: Module,
+    params: Vec<ValType>,
+    result: ValType,
+}
+
+/// A set of loaded plugin modules sharing one engine.
+pub struct WasmPlugins {
+    engine: Engine,
+    fuel: u64,
+    functions: Vec<Arc<WasmFunction>>,
+}
+
+fn is_numeric(ty: &ValType) -> bool {
+    matches!(
+        ty,
+        ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64
+    )
+}
+
+impl WasmPlugins {
+    /// Creates an empty plugin set granting `fuel` units to every call.
+    pub fn new(fuel: u64) -> Result<Self, String> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| format!("WASM engine error: {}", e))?;
+        Ok(Self {
+            engine,
+            fuel,
+            functions: Vec::new(),
+        })
+    }
+
+    /// Loads a `.wasm` file.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("Failed to read WASM plugin {}: {}", path.display(), e))?;
+        self.load_bytes(&bytes)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Loads a module from its binary encoding, registering its numeric exports.
+    pub fn load_bytes(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let module =
+            Module::new(&self.engine, bytes).map_err(|e| format!("invalid module: {}", e))?;
+        if let Some(import) = module.imports().next() {
+            return Err(format!(
+                "imports are not allowed (found {}::{})",
+                import.module(),
+                import.name()
+            ));
+        }
+        for export in module.exports() {
+            let ExternType::Func(ty) = export.ty() else {
+                continue;
+            };
+            let params: Vec<ValType> = ty.params().collect();
+            let results: Vec<ValType> = ty.results().collect();
+            match results.as_slice() {
+                [result] if is_numeric(result) && params.iter().all(is_numeric) => {
+                    self.functions.push(Arc::new(WasmFunction {
+                        engine: self.engine.clone(),
+                        fuel: self.fuel,
+                        name: export.name().to_string(),
+                        module: module.clone(),
+                        params,
+                        result: result.clone(),
+                    }));
+                }
+                _ => tracing::warn!(
+                    "Skipping WASM export `{}`: only numeric parameters and a single numeric result are supported",
+                    export.name()
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of the functions made available to CEL.
+    pub fn function_names(&self) -> impl Iterator<Item = &str> {
+        self.functions.iter().map(|f| f.name.as_str())
+    }
+}
+
+impl FunctionProvider for WasmPlugins {
+    fn register(&self, ctx: &mut Context) {
+        for function in &self.functions {
+            let function = function.clone();
+            ctx.add_function(
+                &function.name.clone(),
+                move |ftx: &FunctionContext, Arguments(args): Arguments| function.call(ftx, &args),
+            );
+        }
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.function_names().map(str::to_string).collect()
+    }
+}
+
+impl WasmFunction {
+    fn call(&self, ftx: &FunctionContext, args: &[CelValue]) -> Result<CelValue, ExecutionError> {
+        if args.len() != self.params.len() {
+            return Err(ftx.error(format!(
+                "{} expects {} arguments, got {}",
+                self.name,
+                self.params.len(),
+                args.len()
+            )));
+        }
+        let params = self
+            .params
+            .iter()
+            .zip(args)
+            .map(|(ty, arg)| to_wasm(ty, arg))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ftx.error(format!("{}: {}", self.name, e)))?;
+
+        let limits: StoreLimits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY_BYTES)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store
+            .set_fuel(self.fuel)
+            .map_err(|e| ftx.error(e.to_string()))?;
+        let instance = Instance::new(&mut store, &self.module, &[])
+            .map_err(|e| ftx.error(format!("{}: {}", self.name, e)))?;
+        let func = instance
+            .get_func(&mut store, &self.name)
+            .ok_or_else(|| ftx.error(format!("{} is not exported", self.name)))?;
+
+        let mut results = [Val::I64(0)];
+        if let Err(e) = func.call(&mut store, &params, &mut results) {
+            let message = match e.downcast_ref::<Trap>() {
+                Some(Trap::OutOfFuel) => "fuel exhausted".to_string(),
+                _ => e.to_string(),
+            };
+            return Err(ftx.error(format!("{} failed: {}", self.name, message)));
+        }
+        from_wasm(&results[0]).ok_or_else(|| {
+            ftx.error(format!(
+                "{} returned an unsupported {} value",
+                self.name, self.result
+            ))
+        })
+    }
+}
+
+fn to_wasm(ty: &ValType, arg: &CelValue) -> Result<Val, String> {
+    Ok(match (ty, arg) {
+        (ValType::I64, CelValue::Int(i)) => Val::I64(*i),
+        (ValType::I64, CelValue::UInt(u)) => {
+            Val::I64(i64::try_from(*u).map_err(|_| "uint out of i64 range".to_string())?)
+        }
+        (ValType::I32, CelValue::Int(i)) => {
+            Val::I32(i32::try_from(*i).map_err(|_| "int out of i32 range".to_string())?)
+        }
+        (ValType::I32, CelValue::UInt(u)) => {
+            Val::I32(i32::try_from(*u).map_err(|_| "uint out of i32 range".to_string())?)
+        }
+        (ValType::I32, CelValue::Bool(b)) => Val::I32(i32::from(*b)),
+        (ValType::F64, CelValue::Float(f)) => Val::F64(f.to_bits()),
+        (ValType::F64, CelValue::Int(i)) => Val::F64((*i as f64).to_bits()),
+        (ValType::F32, CelValue::Float(f)) => Val::F32((*f as f32).to_bits()),
+        (ty, arg) => return Err(format!("cannot pass {:?} as {}", arg, ty)),
+    })
+}
+
+fn from_wasm(val: &Val) -> Option<CelValue> {
+    Some(match val {
+        Val::I32(i) => CelValue::Int(i64::from(*i)),
+        Val::I64(i) => CelValue::Int(*i),
+        Val::F32(bits) => CelValue::Float(f64::from(f32::from_bits(*bits))),
+        Val::F64(bits) => CelValue::Float(f64::from_bits(*bits)),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel::Program;
+
+    /// Exports `add(i64, i64) -> i64` and `spin() -> i64`, which loops forever.
+    const MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x0b, 0x02, 0x60, 0x02, 0x7e, 0x7e, 0x01, 0x7e, 0x60, 0x00, 0x01, 0x7e, // types
+        0x03, 0x03, 0x02, 0x00, 0x01, // functions
+        0x07, 0x0e, 0x02, 0x03, b'a', b'd', b'd', 0x00, 0x00, 0x04, b's', b'p', b'i', b'n', 0x00,
+        0x01, // exports
+        0x0a, 0x12, 0x02, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x7c, 0x0b, 0x08, 0x00, 0x03, 0x40,
+        0x0c, 0x00, 0x0b, 0x00, 0x0b, // code
+    ];
+
+    fn context(fuel: u64) -> Context<'static> {
+        let mut plugins = WasmPlugins::new(fuel).unwrap();
+        plugins.load_bytes(MODULE).unwrap();
+        let mut ctx = Context::default();
+        plugins.register(&mut ctx);
+        ctx
+    }
+
+    #[test]
+    fn test_call_exported_function() {
+        let ctx = context(DEFAULT_FUEL);
+        let value = Program::compile("add(40, 2)")
+            .unwrap()
+            .execute(&ctx)
+            .unwrap();
+        assert_eq!(value, CelValue::Int(42));
+    }
+
+    #[test]
+    fn test_fuel_limit_stops_runaway_function() {
+        let ctx = context(10_000);
+        let err = Program::compile("spin()")
+            .unwrap()
+            .execute(&ctx)
+            .unwrap_err();
+        assert!(err.to_string().contains("fuel exhausted"), "{}", err);
+    }
+}